@@ -1,4 +1,6 @@
 use anyhow::Result;
+use mcp_core::Config;
+use mcp_runtime::{EventBus, SessionManager, ToolExecutor};
 use std::env;
 
 // This function checks if stdin is redirected
@@ -26,6 +28,28 @@ async fn main() -> Result<()> {
     } else {
         // No args and no piped stdin, launch TUI mode
         println!("Launching TUI mode...");
-        mcpterm_tui::App::new()?.run()
+        run_tui().await
     }
 }
+
+// Answer the TUI's prompts with a session on the configured model
+async fn run_tui() -> Result<()> {
+    let config = Config::load(None, None, None).unwrap_or_else(|e| {
+        eprintln!("Warning: Could not load configuration: {}", e);
+        Config::default()
+    });
+    let model = config.get_active_model().unwrap_or_default();
+
+    // Requests waiting on a rate limit are reported on the bus rather than on stderr,
+    // which the TUI's screen would hide
+    let event_bus = EventBus::new();
+    let client =
+        mcpterm_cli::build_llm_client(&config, &model, event_bus.rate_limit_listener()).await?;
+
+    let tool_executor = ToolExecutor::new(mcpterm_cli::default_tool_manager());
+    let session_manager = SessionManager::new(client, tool_executor, event_bus);
+    mcpterm_tui::App::new()?
+        .with_model(model)
+        .with_session(session_manager)?
+        .run()
+}
@@ -136,3 +136,4 @@ pub fn process_slash_command(
 
 // Re-export sub-modules
 pub mod mcp;
+pub mod usage;
@@ -0,0 +1,67 @@
+use super::{CommandResult, SlashCommand};
+use crate::usage::{ContextUsage, SessionUsage};
+
+/// Usage slash command handler, reporting tokens and cost for the session so far
+pub struct UsageCommand {
+    model: String,
+    usage: SessionUsage,
+    context: Option<ContextUsage>,
+}
+
+impl UsageCommand {
+    /// Create a usage command from a snapshot of the session usage
+    pub fn new(model: &str, usage: SessionUsage) -> Self {
+        Self {
+            model: model.to_string(),
+            usage,
+            context: None,
+        }
+    }
+
+    /// Also report how full the model's context window is
+    pub fn with_context(mut self, context: ContextUsage) -> Self {
+        self.context = Some(context);
+        self
+    }
+}
+
+impl SlashCommand for UsageCommand {
+    fn name(&self) -> &str {
+        "usage"
+    }
+
+    fn description(&self) -> &str {
+        "Show token usage and cost for this session"
+    }
+
+    fn help(&self) -> &str {
+        r#"
+=== Usage Commands ===
+/usage        - Show token usage and estimated cost for this session
+/usage json   - Show the same information as JSON
+/usage help   - Show this help message
+"#
+    }
+
+    fn execute(&self, args: &[&str]) -> CommandResult {
+        let mut data = serde_json::to_value(&self.usage).unwrap_or_default();
+        let mut summary = self.usage.summary(&self.model);
+        if let Some(context) = &self.context {
+            data["context"] = serde_json::to_value(context).unwrap_or_default();
+            summary.push_str(&format!("{}\n", context.gauge(20)));
+        }
+
+        match args.first() {
+            None => CommandResult::success_with_data(&summary, data),
+            Some(&"json") => match serde_json::to_string_pretty(&data) {
+                Ok(json) => CommandResult::success_with_data(&json, data),
+                Err(e) => CommandResult::error(&format!("Failed to format usage: {}", e)),
+            },
+            Some(&"help") => CommandResult::success(self.help()),
+            Some(unknown) => CommandResult::error(&format!(
+                "Unknown usage command: {}. Use '/usage help' for available commands.",
+                unknown
+            )),
+        }
+    }
+}
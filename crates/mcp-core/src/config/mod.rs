@@ -1,6 +1,9 @@
+use crate::context::{ContextWindow, DropToolOutputs, HeadTail, SlidingWindow};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fs;
 use std::path::PathBuf;
+use std::sync::Arc;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Config {
@@ -10,6 +13,12 @@ pub struct Config {
     pub logging: LoggingConfig,
     #[serde(default)]
     pub mcp: McpConfig,
+    #[serde(default)]
+    pub compaction: CompactionConfig,
+    #[serde(default)]
+    pub sessions: SessionsConfig,
+    #[serde(default)]
+    pub middleware: MiddlewareConfig,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -26,30 +35,358 @@ pub struct LoggingConfig {
     pub log_dir: Option<String>,
 }
 
+/// Layers wrapped around the model client
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+#[serde(default)]
+pub struct MiddlewareConfig {
+    /// Retries after a throttled or transient failure, before giving up or falling back;
+    /// unset keeps the default
+    pub max_retries: Option<u32>,
+    /// Log a line for every request and reply
+    pub logging: bool,
+    /// Environment variables whose values are redacted from every request, e.g. API keys
+    pub redact_env: Vec<String>,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct McpConfig {
     pub enabled: bool,
     pub base_dir: Option<String>,
 }
 
+/// When older turns are summarised to keep a long conversation inside the context window
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct CompactionConfig {
+    /// Compact before sending a message once the conversation passes the threshold;
+    /// `/compact` works either way
+    pub auto: bool,
+    /// Fraction of the model's context window at which to compact
+    pub threshold: f64,
+    /// Compact at this many estimated tokens instead, e.g. for models without a known
+    /// context window
+    pub threshold_tokens: Option<usize>,
+    /// Most recent user turns, with their replies and tool calls, kept verbatim
+    pub keep_recent_turns: usize,
+    /// Characters of each tool result sent to the summariser; bulky output is cut short
+    pub max_tool_result_chars: usize,
+}
+
+impl Default for CompactionConfig {
+    fn default() -> Self {
+        Self {
+            auto: true,
+            threshold: 0.8,
+            threshold_tokens: None,
+            keep_recent_turns: 4,
+            max_tool_result_chars: 2000,
+        }
+    }
+}
+
+impl CompactionConfig {
+    /// Estimated tokens at which to compact, if there is a threshold for this model
+    pub fn threshold_for(&self, context_window: Option<usize>) -> Option<usize> {
+        self.threshold_tokens.or_else(|| {
+            context_window.map(|window| (window as f64 * self.threshold.clamp(0.0, 1.0)) as usize)
+        })
+    }
+}
+
+/// Where conversations are saved so they can be resumed later
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(default)]
+pub struct SessionsConfig {
+    /// Save each conversation as it goes
+    pub enabled: bool,
+    /// Directory for session files; defaults to `sessions` next to the config file
+    pub dir: Option<String>,
+}
+
+impl Default for SessionsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            dir: None,
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct AwsConfig {
     pub region: String,
+    /// Named profile from the shared AWS config, including SSO and assume-role profiles
     pub profile: Option<String>,
+    /// Bedrock runtime endpoint override, e.g. a VPC interface endpoint or a local stand-in
+    #[serde(default)]
+    pub endpoint_url: Option<String>,
+    /// Role to assume, using the profile or default credentials as the source
+    #[serde(default)]
+    pub role_arn: Option<String>,
+    /// External id required by the role's trust policy, if any
+    #[serde(default)]
+    pub external_id: Option<String>,
+}
+
+impl Default for AwsConfig {
+    fn default() -> Self {
+        Self {
+            region: "us-east-1".to_string(),
+            profile: None,
+            endpoint_url: None,
+            role_arn: None,
+            external_id: None,
+        }
+    }
+}
+
+/// Backend that serves a model
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ModelProvider {
+    #[default]
+    Bedrock,
+    Anthropic,
+    /// Any server exposing an OpenAI-compatible chat completions API (Ollama, vLLM, ...)
+    OpenAi,
+}
+
+impl ModelProvider {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ModelProvider::Bedrock => "bedrock",
+            ModelProvider::Anthropic => "anthropic",
+            ModelProvider::OpenAi => "openai",
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ModelConfig {
+    /// Model id, inference profile id, or inference profile ARN
     pub model_id: String,
     pub max_tokens: usize,
     pub temperature: f32,
     pub active: bool,
     pub description: Option<String>,
+    #[serde(default)]
+    pub provider: ModelProvider,
+    /// Endpoint override, e.g. `http://localhost:11434/v1` for a local Ollama server
+    #[serde(default)]
+    pub base_url: Option<String>,
+    /// Environment variable holding the API key, for providers that need one
+    #[serde(default)]
+    pub api_key_env: Option<String>,
+    /// Token prices used for session cost accounting
+    #[serde(default)]
+    pub pricing: Option<ModelPricing>,
+    /// Cache the system prompt, tools and history between turns. Unset uses the provider
+    /// default: on for Anthropic, off for Bedrock where only some models support it.
+    #[serde(default)]
+    pub prompt_caching: Option<bool>,
+    /// Token budget for extended thinking on Claude models; unset disables thinking.
+    /// Must be at least 1024 and below `max_tokens`.
+    #[serde(default)]
+    pub thinking_budget_tokens: Option<usize>,
+    /// Models or regions to try, in order, when this one is throttled, out of capacity or
+    /// not available to the account
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fallbacks: Vec<ModelFallback>,
+    /// Tokens the model accepts per request, prompt and reply together. Requests estimated
+    /// not to fit are refused before they are sent; unset skips the check.
+    #[serde(default)]
+    pub context_window: Option<usize>,
+    /// Region for this model, overriding `aws.region`
+    #[serde(default)]
+    pub region: Option<String>,
+    /// Client-side quotas; requests over them wait locally instead of being throttled
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rate_limit: Option<RateLimit>,
+    /// Which messages of the conversation to send with each request; unset sends them all
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub context_strategy: Option<ContextStrategy>,
+}
+
+/// A context window strategy for a model, e.g. `{"type": "drop_tool_outputs",
+/// "keep_recent_turns": 2}`. System messages and pinned turns are sent whatever the strategy.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ContextStrategy {
+    /// Only the most recent turns
+    SlidingWindow { max_turns: usize },
+    /// The first turns, which usually set out the task, and the most recent ones
+    HeadTail {
+        head_turns: usize,
+        tail_turns: usize,
+    },
+    /// Every turn, with tool output from all but the most recent turns left out
+    DropToolOutputs {
+        keep_recent_turns: usize,
+        /// Output up to this many characters is kept anyway
+        #[serde(default = "default_max_tool_output_chars")]
+        max_chars: usize,
+    },
+}
+
+fn default_max_tool_output_chars() -> usize {
+    500
+}
+
+impl ContextStrategy {
+    pub fn window(self) -> Arc<dyn ContextWindow> {
+        match self {
+            ContextStrategy::SlidingWindow { max_turns } => Arc::new(SlidingWindow { max_turns }),
+            ContextStrategy::HeadTail {
+                head_turns,
+                tail_turns,
+            } => Arc::new(HeadTail {
+                head_turns,
+                tail_turns,
+            }),
+            ContextStrategy::DropToolOutputs {
+                keep_recent_turns,
+                max_chars,
+            } => Arc::new(DropToolOutputs {
+                keep_recent_turns,
+                max_chars,
+            }),
+        }
+    }
+}
+
+/// Request, token and concurrency budgets for one model, shared by every client using it.
+/// Unset budgets are not enforced.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RateLimit {
+    #[serde(default)]
+    pub requests_per_minute: Option<u32>,
+    /// Prompt and reply tokens together, as Bedrock counts them
+    #[serde(default)]
+    pub tokens_per_minute: Option<u64>,
+    /// Requests in flight at once, streams included
+    #[serde(default)]
+    pub max_concurrent: Option<usize>,
+}
+
+/// A fallback for a model: another model, another region, or both.
+/// Whatever is left unset is the same as for the model it falls back from.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct ModelFallback {
+    #[serde(default)]
+    pub model_id: Option<String>,
+    #[serde(default)]
+    pub region: Option<String>,
+}
+
+/// Token prices in USD per million tokens
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub struct ModelPricing {
+    pub input_per_million: f64,
+    pub output_per_million: f64,
+    /// Price of prompt tokens read from the cache; a tenth of the input price if unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_read_per_million: Option<f64>,
+    /// Price of prompt tokens written to the cache; 1.25x the input price if unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_write_per_million: Option<f64>,
+}
+
+impl ModelPricing {
+    pub fn new(input_per_million: f64, output_per_million: f64) -> Self {
+        Self {
+            input_per_million,
+            output_per_million,
+            cache_read_per_million: None,
+            cache_write_per_million: None,
+        }
+    }
+
+    /// Cost in USD of a request with the given token counts
+    pub fn cost(&self, input_tokens: u64, output_tokens: u64) -> f64 {
+        self.cost_with_cache(input_tokens, output_tokens, 0, 0)
+    }
+
+    /// Cost in USD of a request that also read or wrote cached prompt tokens
+    pub fn cost_with_cache(
+        &self,
+        input_tokens: u64,
+        output_tokens: u64,
+        cache_read_tokens: u64,
+        cache_write_tokens: u64,
+    ) -> f64 {
+        let cache_read = self
+            .cache_read_per_million
+            .unwrap_or(self.input_per_million * 0.1);
+        let cache_write = self
+            .cache_write_per_million
+            .unwrap_or(self.input_per_million * 1.25);
+
+        (input_tokens as f64 * self.input_per_million
+            + output_tokens as f64 * self.output_per_million
+            + cache_read_tokens as f64 * cache_read
+            + cache_write_tokens as f64 * cache_write)
+            / 1_000_000.0
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ModelSettings {
     pub models: Vec<ModelConfig>,
+    /// Model id to use for each auxiliary task, e.g. `{"summarize": "<haiku model id>"}`.
+    /// Tasks without a route, and the main agent loop, use the active model.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub routing: BTreeMap<ModelTask, String>,
+}
+
+/// What a model request is for, so auxiliary work can go to a cheaper, faster model
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum ModelTask {
+    /// The agent loop: user turns, tool calls and follow-ups
+    Main,
+    /// Summarising conversation history
+    Summarize,
+    /// Naming a conversation
+    Title,
+    /// Writing commit messages
+    CommitMessage,
+    /// Condensing long tool output before it goes back to the main model
+    Condense,
+}
+
+impl ModelTask {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ModelTask::Main => "main",
+            ModelTask::Summarize => "summarize",
+            ModelTask::Title => "title",
+            ModelTask::CommitMessage => "commit_message",
+            ModelTask::Condense => "condense",
+        }
+    }
+}
+
+impl Default for ModelConfig {
+    fn default() -> Self {
+        Self {
+            model_id: "us.anthropic.claude-3-7-sonnet-20250219-v1:0".to_string(),
+            max_tokens: 4096,
+            temperature: 0.7,
+            active: false,
+            description: None,
+            provider: ModelProvider::default(),
+            base_url: None,
+            api_key_env: None,
+            pricing: None,
+            prompt_caching: None,
+            thinking_budget_tokens: None,
+            fallbacks: Vec::new(),
+            context_window: Some(200_000),
+            region: None,
+            rate_limit: None,
+            context_strategy: None,
+        }
+    }
 }
 
 impl Default for McpConfig {
@@ -64,38 +401,34 @@ impl Default for McpConfig {
 impl Default for Config {
     fn default() -> Self {
         Self {
-            aws: AwsConfig {
-                region: "us-east-1".to_string(),
-                profile: None,
-            },
+            aws: AwsConfig::default(),
             model_settings: ModelSettings {
                 models: vec![
                     ModelConfig {
-                        model_id: "us.anthropic.claude-3-7-sonnet-20250219-v1:0".to_string(),
-                        max_tokens: 4096,
-                        temperature: 0.7,
                         active: true,
                         description: Some("Claude 3.7 Sonnet - Good Coder".to_string()),
+                        pricing: Some(ModelPricing::new(3.0, 15.0)),
+                        prompt_caching: Some(true),
+                        ..ModelConfig::default()
                     },
                     ModelConfig {
                         model_id: "anthropic.claude-3-sonnet-20240229-v1:0".to_string(),
-                        max_tokens: 4096,
-                        temperature: 0.7,
-                        active: false,
                         description: Some(
                             "Claude 3 Sonnet - Balanced performance and quality".to_string(),
                         ),
+                        pricing: Some(ModelPricing::new(3.0, 15.0)),
+                        ..ModelConfig::default()
                     },
                     ModelConfig {
                         model_id: "anthropic.claude-3-opus-20240229-v1:0".to_string(),
-                        max_tokens: 4096,
-                        temperature: 0.7,
-                        active: false,
                         description: Some(
                             "Claude 3 Opus - Highest capability and quality".to_string(),
                         ),
+                        pricing: Some(ModelPricing::new(15.0, 75.0)),
+                        ..ModelConfig::default()
                     },
                 ],
+                routing: BTreeMap::new(),
             },
             ui: UiConfig {
                 emacs_mode: false,          // Default to Vi mode
@@ -106,6 +439,9 @@ impl Default for Config {
                 log_dir: None, // Ignored - all logs go to /tmp
             },
             mcp: McpConfig::default(),
+            compaction: CompactionConfig::default(),
+            sessions: SessionsConfig::default(),
+            middleware: MiddlewareConfig::default(),
         }
     }
 }
@@ -152,10 +488,8 @@ impl Config {
                     // Add the new model
                     config.model_settings.models.push(ModelConfig {
                         model_id: model_id_str.to_string(),
-                        max_tokens: 4096,
-                        temperature: 0.7,
                         active: true,
-                        description: None,
+                        ..ModelConfig::default()
                     });
                 }
             }
@@ -186,10 +520,8 @@ impl Config {
                     // Add the new model
                     config.model_settings.models.push(ModelConfig {
                         model_id: model_id_str.to_string(),
-                        max_tokens: 4096,
-                        temperature: 0.7,
                         active: true,
-                        description: None,
+                        ..ModelConfig::default()
                     });
                 }
             }
@@ -217,4 +549,80 @@ impl Config {
             .find(|model| model.active)
             .cloned()
     }
+
+    /// The model configured for an auxiliary task, if it is routed away from the active model.
+    ///
+    /// A routed model id that isn't listed in `models` inherits the active model's provider
+    /// and settings, so a cheaper model from the same provider only needs its id.
+    pub fn get_task_model(&self, task: ModelTask) -> Option<ModelConfig> {
+        let model_id = self.model_settings.routing.get(&task)?;
+        let active = self.get_active_model();
+        if active.as_ref().map(|m| &m.model_id) == Some(model_id) {
+            return None;
+        }
+
+        let model = self
+            .model_settings
+            .models
+            .iter()
+            .find(|model| &model.model_id == model_id)
+            .cloned()
+            .unwrap_or_else(|| ModelConfig {
+                model_id: model_id.clone(),
+                description: None,
+                pricing: None,
+                ..active.unwrap_or_default()
+            });
+
+        Some(ModelConfig {
+            active: false,
+            ..model
+        })
+    }
+
+    /// The region to call a model in: its own override, else the region of an inference
+    /// profile ARN, else `aws.region`
+    pub fn region_for(&self, model: &ModelConfig) -> String {
+        model
+            .region
+            .clone()
+            .or_else(|| inference_profile_region(&model.model_id).map(str::to_string))
+            .unwrap_or_else(|| self.aws.region.clone())
+    }
+
+    /// All routed auxiliary tasks with their models
+    pub fn task_models(&self) -> BTreeMap<ModelTask, ModelConfig> {
+        self.model_settings
+            .routing
+            .keys()
+            .filter_map(|&task| Some((task, self.get_task_model(task)?)))
+            .collect()
+    }
+
+    /// Rate limits of the configured models, by model id
+    pub fn rate_limits(&self) -> BTreeMap<String, RateLimit> {
+        self.model_settings
+            .models
+            .iter()
+            .filter_map(|model| Some((model.model_id.clone(), model.rate_limit?)))
+            .collect()
+    }
+
+    /// Token prices of the configured models, by model id
+    pub fn model_pricing(&self) -> BTreeMap<String, ModelPricing> {
+        self.model_settings
+            .models
+            .iter()
+            .filter_map(|model| Some((model.model_id.clone(), model.pricing?)))
+            .collect()
+    }
+}
+
+/// The region of an inference profile ARN such as
+/// `arn:aws:bedrock:us-east-1:123456789012:inference-profile/us.anthropic.claude-3-7-sonnet-20250219-v1:0`
+pub fn inference_profile_region(model_id: &str) -> Option<&str> {
+    model_id
+        .strip_prefix("arn:")
+        .and_then(|arn| arn.split(':').nth(2))
+        .filter(|region| !region.is_empty())
 }
@@ -0,0 +1,168 @@
+use anyhow::{anyhow, Context, Result};
+use regex::{Captures, Regex};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Largest file accepted as an attachment; providers reject bigger images and documents
+pub const MAX_ATTACHMENT_BYTES: usize = 5 * 1024 * 1024;
+
+// Images are scaled down to about 1.15 megapixels, which Claude counts as ~1600 tokens
+const IMAGE_TOKENS: usize = 1_600;
+// PDFs cost text plus an image per page; assume ~2000 tokens per 50KB page
+const DOCUMENT_PAGE_BYTES: usize = 50 * 1024;
+const DOCUMENT_PAGE_TOKENS: usize = 2_000;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum AttachmentKind {
+    Image,
+    Document,
+}
+
+/// A file sent to the model alongside the text of a message, stored base64-encoded
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Attachment {
+    pub kind: AttachmentKind,
+    /// MIME type, e.g. `image/png` or `application/pdf`
+    pub media_type: String,
+    /// File name without the directory, used to label documents
+    pub name: String,
+    pub data: String,
+}
+
+impl Attachment {
+    pub fn new(kind: AttachmentKind, media_type: &str, name: &str, bytes: &[u8]) -> Self {
+        Self {
+            kind,
+            media_type: media_type.to_string(),
+            name: name.to_string(),
+            data: base64::encode(bytes),
+        }
+    }
+
+    /// Read an image (PNG, JPEG, GIF, WebP) or PDF from disk, detecting the type by extension
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let (kind, media_type) = media_type_for_path(path)
+            .ok_or_else(|| anyhow!("Unsupported attachment type: {}", path.display()))?;
+
+        let bytes = std::fs::read(path)
+            .with_context(|| format!("Failed to read attachment {}", path.display()))?;
+        if bytes.len() > MAX_ATTACHMENT_BYTES {
+            return Err(anyhow!(
+                "Attachment {} is {} bytes, the limit is {} bytes",
+                path.display(),
+                bytes.len(),
+                MAX_ATTACHMENT_BYTES
+            ));
+        }
+
+        let name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "attachment".to_string());
+
+        Ok(Self::new(kind, media_type, &name, &bytes))
+    }
+
+    /// The raw file contents, for providers that take bytes rather than base64
+    pub fn bytes(&self) -> Result<Vec<u8>> {
+        base64::decode(&self.data).map_err(|e| anyhow!("Invalid attachment data: {}", e))
+    }
+
+    /// Format part of the media type, e.g. `png` or `pdf`
+    pub fn format(&self) -> &str {
+        self.media_type
+            .rsplit('/')
+            .next()
+            .unwrap_or(&self.media_type)
+    }
+
+    /// Rough token cost of sending this attachment
+    pub fn estimated_tokens(&self) -> usize {
+        match self.kind {
+            AttachmentKind::Image => IMAGE_TOKENS,
+            AttachmentKind::Document => {
+                // Base64 is four characters for every three bytes
+                let bytes = self.data.len() / 4 * 3;
+                bytes.div_ceil(DOCUMENT_PAGE_BYTES).max(1) * DOCUMENT_PAGE_TOKENS
+            }
+        }
+    }
+
+    /// The data as a `data:` URL, as OpenAI-style APIs expect
+    pub fn data_url(&self) -> String {
+        format!("data:{};base64,{}", self.media_type, self.data)
+    }
+}
+
+/// The attachment kind and MIME type for a path, or `None` if the extension isn't supported
+pub fn media_type_for_path(path: &Path) -> Option<(AttachmentKind, &'static str)> {
+    let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+    match extension.as_str() {
+        "png" => Some((AttachmentKind::Image, "image/png")),
+        "jpg" | "jpeg" => Some((AttachmentKind::Image, "image/jpeg")),
+        "gif" => Some((AttachmentKind::Image, "image/gif")),
+        "webp" => Some((AttachmentKind::Image, "image/webp")),
+        "pdf" => Some((AttachmentKind::Document, "application/pdf")),
+        _ => None,
+    }
+}
+
+/// Split `@path` references out of user input.
+///
+/// A word starting with `@` counts as a reference when it names a supported attachment
+/// type; the `@` is dropped and the path stays in the text so the model can refer to it.
+/// Anything else, such as `@someone`, is left untouched.
+pub fn extract_attachment_refs(input: &str) -> (String, Vec<String>) {
+    let pattern = Regex::new(r"(^|\s)@(\S+)").expect("valid attachment pattern");
+    let mut paths = Vec::new();
+    let text = pattern.replace_all(input, |caps: &Captures| {
+        let path = &caps[2];
+        if media_type_for_path(Path::new(path)).is_some() {
+            paths.push(path.to_string());
+            format!("{}{}", &caps[1], path)
+        } else {
+            caps[0].to_string()
+        }
+    });
+
+    (text.into_owned(), paths)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_media_type_for_path() {
+        assert_eq!(
+            media_type_for_path(Path::new("shot.PNG")),
+            Some((AttachmentKind::Image, "image/png"))
+        );
+        assert_eq!(
+            media_type_for_path(Path::new("docs/spec.pdf")),
+            Some((AttachmentKind::Document, "application/pdf"))
+        );
+        assert_eq!(media_type_for_path(Path::new("notes.txt")), None);
+        assert_eq!(media_type_for_path(Path::new("Makefile")), None);
+    }
+
+    #[test]
+    fn test_extract_attachment_refs() {
+        let (text, paths) = extract_attachment_refs("ask @alice about user@example.com");
+        assert_eq!(text, "ask @alice about user@example.com");
+        assert!(paths.is_empty());
+
+        let (text, paths) = extract_attachment_refs("Explain @screens/error.png\nand @spec.pdf");
+        assert_eq!(text, "Explain screens/error.png\nand spec.pdf");
+        assert_eq!(paths, vec!["screens/error.png", "spec.pdf"]);
+    }
+
+    #[test]
+    fn test_attachment_round_trip() {
+        let attachment = Attachment::new(AttachmentKind::Image, "image/png", "a.png", b"\x89PNG");
+        assert_eq!(attachment.format(), "png");
+        assert_eq!(attachment.bytes().unwrap(), b"\x89PNG");
+        assert!(attachment.data_url().starts_with("data:image/png;base64,"));
+    }
+}
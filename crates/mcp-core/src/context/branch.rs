@@ -0,0 +1,260 @@
+use super::{ConversationContext, Message, MessageRole};
+use mcp_metrics::count;
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+/// One path a conversation has taken. A branch shares the first `forked_at` messages of
+/// its parent and goes its own way after them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Branch {
+    pub id: usize,
+    pub parent: Option<usize>,
+    pub forked_at: usize,
+    pub messages: Vec<Message>,
+}
+
+impl Branch {
+    /// The first user message of the branch's own, to tell it apart from its siblings
+    pub fn label(&self) -> Option<&str> {
+        self.messages
+            .iter()
+            .skip(self.forked_at)
+            .find(|message| message.role == MessageRole::User)
+            .map(|message| message.content.as_str())
+    }
+}
+
+/// The outcome of `ConversationTree::rewind`
+#[derive(Debug, Clone)]
+pub struct Rewind {
+    /// The user message the conversation was rewound to before
+    pub message: Message,
+    /// Branch keeping the path rewound from, or `None` if it had nothing worth keeping
+    pub kept: Option<usize>,
+}
+
+/// Every branch of a conversation, so rewinding to an earlier message never loses the
+/// work done after it.
+///
+/// The live conversation stays in its `ConversationContext`. The tree holds a copy of the
+/// current branch as of the last `snapshot`, `rewind` or `switch`, and the other branches
+/// in full.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(try_from = "SavedTree")]
+pub struct ConversationTree {
+    branches: Vec<Branch>,
+    current: usize,
+}
+
+// A tree as read from a session file, which may have been edited or cut short, so it is
+// checked before use
+#[derive(Deserialize)]
+struct SavedTree {
+    branches: Vec<Branch>,
+    current: usize,
+}
+
+impl TryFrom<SavedTree> for ConversationTree {
+    type Error = String;
+
+    fn try_from(saved: SavedTree) -> Result<Self, Self::Error> {
+        let count = saved.branches.len();
+        if saved.current >= count {
+            return Err(format!(
+                "current branch {} is out of range ({} branches)",
+                saved.current, count
+            ));
+        }
+        for (index, branch) in saved.branches.iter().enumerate() {
+            if branch.id != index || branch.parent.is_some_and(|parent| parent >= count) {
+                return Err(format!("branch {} is out of place", branch.id));
+            }
+        }
+        Ok(Self {
+            branches: saved.branches,
+            current: saved.current,
+        })
+    }
+}
+
+impl Default for ConversationTree {
+    fn default() -> Self {
+        Self {
+            branches: vec![Branch {
+                id: 0,
+                parent: None,
+                forked_at: 0,
+                messages: Vec::new(),
+            }],
+            current: 0,
+        }
+    }
+}
+
+impl ConversationTree {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn branches(&self) -> &[Branch] {
+        &self.branches
+    }
+
+    pub fn branch(&self, id: usize) -> Option<&Branch> {
+        self.branches.get(id)
+    }
+
+    /// Id of the branch the conversation is on
+    pub fn current(&self) -> usize {
+        self.current
+    }
+
+    /// Copy the live conversation into the current branch
+    pub fn snapshot(&mut self, context: &ConversationContext) {
+        self.branches[self.current].messages = context.messages.clone();
+    }
+
+    /// Rewind `context` to just before the user message at `index`, so it can be asked
+    /// differently. The path rewound from is kept as a branch of its own. Returns `None`,
+    /// leaving everything as it was, if `index` isn't a user message.
+    pub fn rewind(&mut self, context: &mut ConversationContext, index: usize) -> Option<Rewind> {
+        let message = context
+            .messages
+            .get(index)
+            .filter(|message| message.role == MessageRole::User)?
+            .clone();
+        self.snapshot(context);
+
+        // A branch with nothing of its own yet, and no branches off it, is rewound in place
+        // rather than kept
+        let current = &self.branches[self.current];
+        let empty = current.parent.is_some()
+            && current.messages.len() <= current.forked_at
+            && !self
+                .branches
+                .iter()
+                .any(|branch| branch.parent == Some(self.current));
+        let kept = if empty {
+            self.branches[self.current].forked_at = index;
+            None
+        } else {
+            let id = self.branches.len();
+            self.branches.push(Branch {
+                id,
+                parent: Some(self.current),
+                forked_at: index,
+                messages: context.messages[..index].to_vec(),
+            });
+            Some(std::mem::replace(&mut self.current, id))
+        };
+
+        context.messages.truncate(index);
+        self.branches[self.current].messages.truncate(index);
+        debug!(
+            "Rewound to message {} on branch {}, keeping {:?}",
+            index, self.current, kept
+        );
+        count!("conversation.rewinds");
+        Some(Rewind { message, kept })
+    }
+
+    /// Put `context` on branch `id`, keeping the current branch as it stands. Returns false
+    /// if there is no such branch.
+    pub fn switch(&mut self, context: &mut ConversationContext, id: usize) -> bool {
+        if id >= self.branches.len() {
+            return false;
+        }
+        self.snapshot(context);
+        self.current = id;
+        context.messages = self.branches[id].messages.clone();
+        debug!("Switched to branch {}", id);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn conversation(turns: usize) -> ConversationContext {
+        let mut context = ConversationContext::new();
+        for turn in 1..=turns {
+            context.add_user_message(&format!("question {}", turn));
+            context.add_assistant_message(&format!("answer {}", turn));
+        }
+        context
+    }
+
+    #[test]
+    fn test_rewind_keeps_the_path_rewound_from() {
+        let mut context = conversation(3);
+        let mut tree = ConversationTree::new();
+
+        // Only user messages can be rewound to
+        assert!(tree.rewind(&mut context, 1).is_none());
+        assert_eq!(context.messages.len(), 6);
+
+        let rewind = tree.rewind(&mut context, 2).unwrap();
+        assert_eq!(rewind.message.content, "question 2");
+        assert_eq!(rewind.kept, Some(0));
+        assert_eq!(context.messages.len(), 2);
+        assert_eq!(tree.current(), 1);
+
+        let branch = tree.branch(1).unwrap();
+        assert_eq!((branch.parent, branch.forked_at), (Some(0), 2));
+        assert_eq!(tree.branch(0).unwrap().messages.len(), 6);
+        assert_eq!(tree.branch(0).unwrap().label(), Some("question 1"));
+
+        // Rewinding again before asking anything moves the new branch rather than adding one
+        let rewind = tree.rewind(&mut context, 0).unwrap();
+        assert_eq!(rewind.kept, None);
+        assert_eq!(tree.branches().len(), 2);
+        assert_eq!(tree.branch(1).unwrap().forked_at, 0);
+        assert!(context.messages.is_empty());
+    }
+
+    #[test]
+    fn test_switch_between_branches() {
+        let mut context = conversation(2);
+        let mut tree = ConversationTree::new();
+        tree.rewind(&mut context, 2).unwrap();
+        context.add_user_message("question 2, asked differently");
+        context.add_assistant_message("a better answer");
+        assert_eq!(
+            tree.branch(1).unwrap().label(),
+            None,
+            "the new branch is only copied on snapshot"
+        );
+
+        assert!(tree.switch(&mut context, 0));
+        assert_eq!(context.messages.last().unwrap().content, "answer 2");
+        assert_eq!(
+            tree.branch(1).unwrap().label(),
+            Some("question 2, asked differently")
+        );
+
+        assert!(tree.switch(&mut context, 1));
+        assert_eq!(context.messages.last().unwrap().content, "a better answer");
+        assert!(!tree.switch(&mut context, 5));
+        assert_eq!(tree.current(), 1);
+    }
+
+    #[test]
+    fn test_saved_tree_is_checked() {
+        let mut context = conversation(2);
+        let mut tree = ConversationTree::new();
+        tree.rewind(&mut context, 2).unwrap();
+        let saved = serde_json::to_value(&tree).unwrap();
+        let loaded: ConversationTree = serde_json::from_value(saved.clone()).unwrap();
+        assert_eq!((loaded.current(), loaded.branches().len()), (1, 2));
+
+        let mut edited = saved.clone();
+        edited["current"] = 2.into();
+        let error = serde_json::from_value::<ConversationTree>(edited).unwrap_err();
+        assert!(error.to_string().contains("out of range"));
+
+        let mut edited = saved;
+        edited["branches"][1]["parent"] = 7.into();
+        assert!(serde_json::from_value::<ConversationTree>(edited).is_err());
+    }
+}
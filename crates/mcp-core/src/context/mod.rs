@@ -3,6 +3,16 @@ use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use tracing::debug;
 
+mod attachment;
+mod branch;
+mod window;
+
+pub use attachment::{
+    extract_attachment_refs, media_type_for_path, Attachment, AttachmentKind, MAX_ATTACHMENT_BYTES,
+};
+pub use branch::{Branch, ConversationTree, Rewind};
+pub use window::{ContextWindow, DropToolOutputs, HeadTail, SlidingWindow};
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub enum MessageRole {
     System,
@@ -17,18 +27,102 @@ pub struct Message {
     pub content: String,
     pub tool_calls: Option<Vec<ToolCall>>,
     pub tool_results: Option<Vec<ToolResult>>,
+    /// Images and documents sent along with the text of a user message
+    #[serde(default)]
+    pub attachments: Option<Vec<Attachment>>,
+    /// Extended thinking that preceded an assistant's tool calls
+    #[serde(default)]
+    pub thinking: Option<Vec<ThinkingBlock>>,
+    /// Always sent, with the rest of its turn, whatever the context window strategy
+    #[serde(default)]
+    pub pinned: bool,
+}
+
+/// A block of extended thinking, kept verbatim so it can be sent back to the provider.
+///
+/// When a turn ends in tool use, its thinking blocks have to be returned unchanged (with
+/// their signatures) ahead of the tool calls in the next request.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ThinkingBlock {
+    Thinking {
+        thinking: String,
+        #[serde(default)]
+        signature: String,
+    },
+    /// Reasoning the provider encrypted; `data` is opaque and only meant to be sent back
+    RedactedThinking { data: String },
+}
+
+impl ThinkingBlock {
+    /// The readable reasoning, if it wasn't redacted
+    pub fn text(&self) -> Option<&str> {
+        match self {
+            ThinkingBlock::Thinking { thinking, .. } => Some(thinking),
+            ThinkingBlock::RedactedThinking { .. } => None,
+        }
+    }
+}
+
+/// Rough token count of a piece of text, at about four characters per token.
+///
+/// Real tokenizers differ by model, so this only has to be close enough to warn before a
+/// request overflows the context window.
+pub fn estimate_tokens(text: &str) -> usize {
+    text.len().div_ceil(4)
+}
+
+// Role markers and message framing the providers add around each message
+const MESSAGE_OVERHEAD_TOKENS: usize = 4;
+
+/// Opening line of the system message that replaces compacted turns
+pub const SUMMARY_HEADING: &str = "Summary of the earlier conversation:";
+
+impl Message {
+    /// Estimated tokens this message takes up in a request
+    pub fn estimated_tokens(&self) -> usize {
+        let mut tokens = MESSAGE_OVERHEAD_TOKENS + estimate_tokens(&self.content);
+        for call in self.tool_calls.iter().flatten() {
+            tokens +=
+                estimate_tokens(&call.tool_id) + estimate_tokens(&call.parameters.to_string());
+        }
+        for result in self.tool_results.iter().flatten() {
+            tokens += estimate_tokens(&result.result.to_string());
+        }
+        for attachment in self.attachments.iter().flatten() {
+            tokens += attachment.estimated_tokens();
+        }
+        for block in self.thinking.iter().flatten() {
+            tokens += match block {
+                ThinkingBlock::Thinking { thinking, .. } => estimate_tokens(thinking),
+                ThinkingBlock::RedactedThinking { data } => estimate_tokens(data),
+            };
+        }
+        tokens
+    }
+
+    /// Whether this is the summary of compacted turns
+    pub fn is_summary(&self) -> bool {
+        self.role == MessageRole::System && self.content.starts_with(SUMMARY_HEADING)
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ToolCall {
     pub tool_id: String,
     pub parameters: Value,
+    /// Provider-assigned id linking this call to its result (empty for JSON-RPC calls)
+    #[serde(default)]
+    pub call_id: String,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ToolResult {
     pub tool_id: String,
     pub result: Value,
+    /// Id of the tool call this result answers (empty for JSON-RPC calls)
+    #[serde(default)]
+    pub call_id: String,
 }
 
 #[derive(Debug, Clone)]
@@ -48,6 +142,103 @@ impl ConversationContext {
         }
     }
 
+    /// Estimated prompt tokens for the system prompt and all messages
+    pub fn estimated_tokens(&self) -> usize {
+        estimate_tokens(&self.system_prompt)
+            + self
+                .messages
+                .iter()
+                .map(Message::estimated_tokens)
+                .sum::<usize>()
+    }
+
+    /// Index of the first message of the `turns` most recent user turns, or zero if there
+    /// are no more turns than that. A turn starts at a user message, so everything before
+    /// the index can be summarised without parting tool calls from their results.
+    pub fn recent_turns_start(&self, turns: usize) -> usize {
+        if turns == 0 {
+            return self.messages.len();
+        }
+        self.messages
+            .iter()
+            .enumerate()
+            .rev()
+            .filter(|(_, message)| message.role == MessageRole::User)
+            .nth(turns - 1)
+            .map_or(0, |(index, _)| index)
+    }
+
+    /// Pin the message at `index` so it is always sent, along with the rest of its turn.
+    /// Returns false if there is no such message.
+    pub fn pin(&mut self, index: usize) -> bool {
+        match self.messages.get_mut(index) {
+            Some(message) => {
+                message.pinned = true;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// A copy of the conversation holding only the messages `window` chooses to send
+    pub fn windowed(&self, window: &dyn ContextWindow) -> ConversationContext {
+        let messages = window.apply(&self.messages);
+        let dropped = self.messages.len().saturating_sub(messages.len());
+        if dropped > 0 {
+            count!("conversation.window.dropped", dropped as u64);
+        }
+        ConversationContext {
+            system_prompt: self.system_prompt.clone(),
+            messages,
+            current_request_id: self.current_request_id.clone(),
+        }
+    }
+
+    /// The messages before `end` that compaction folds into a summary: all of them except
+    /// turns with a pinned message, which are kept as they are
+    pub fn compactable(&self, end: usize) -> Vec<&Message> {
+        let end = end.min(self.messages.len());
+        window::turns(&self.messages[..end])
+            .into_iter()
+            .map(|turn| &self.messages[turn])
+            .filter(|turn| !turn.iter().any(|message| message.pinned))
+            .flatten()
+            .collect()
+    }
+
+    /// Replace the messages before `end` with one system message holding their summary,
+    /// followed by any pinned turns among them
+    pub fn replace_with_summary(&mut self, end: usize, summary: &str) {
+        let end = end.min(self.messages.len());
+        let pinned: Vec<Message> = window::turns(&self.messages[..end])
+            .into_iter()
+            .map(|turn| &self.messages[turn])
+            .filter(|turn| turn.iter().any(|message| message.pinned))
+            .flatten()
+            .cloned()
+            .collect();
+        debug!(
+            "Replacing {} messages with a summary, keeping {} pinned",
+            end - pinned.len(),
+            pinned.len()
+        );
+
+        let summary = Message {
+            role: MessageRole::System,
+            content: format!("{}\n\n{}", SUMMARY_HEADING, summary.trim()),
+            tool_calls: None,
+            tool_results: None,
+            attachments: None,
+            thinking: None,
+            pinned: false,
+        };
+        self.messages
+            .splice(..end, std::iter::once(summary).chain(pinned));
+
+        count!("conversation.compactions");
+        debug!("Conversation now has {} messages", self.messages.len());
+    }
+
     // Helper methods to add messages of different types
     pub fn add_user_message(&mut self, content: &str) {
         debug!("Adding user message to conversation:: {}", content);
@@ -56,6 +247,37 @@ impl ConversationContext {
             content: content.to_string(),
             tool_calls: None,
             tool_results: None,
+            attachments: None,
+            thinking: None,
+            pinned: false,
+        });
+
+        // Count message metrics
+        count!("conversation.messages.total");
+        count!("conversation.messages.user");
+
+        debug!("Conversation now has {} messages", self.messages.len());
+    }
+
+    /// Add a user message carrying image or document attachments
+    pub fn add_user_message_with_attachments(
+        &mut self,
+        content: &str,
+        attachments: Vec<Attachment>,
+    ) {
+        debug!(
+            "Adding user message with {} attachments to conversation: {}",
+            attachments.len(),
+            content
+        );
+        self.messages.push(Message {
+            role: MessageRole::User,
+            content: content.to_string(),
+            tool_calls: None,
+            tool_results: None,
+            attachments: (!attachments.is_empty()).then_some(attachments),
+            thinking: None,
+            pinned: false,
         });
 
         // Count message metrics
@@ -72,6 +294,9 @@ impl ConversationContext {
             content: content.to_string(),
             tool_calls: None,
             tool_results: None,
+            attachments: None,
+            thinking: None,
+            pinned: false,
         });
 
         // Count message metrics
@@ -88,6 +313,66 @@ impl ConversationContext {
             content: content.to_string(),
             tool_calls: None,
             tool_results: None,
+            attachments: None,
+            thinking: None,
+            pinned: false,
+        });
+
+        // Count message metrics
+        count!("conversation.messages.total");
+        count!("conversation.messages.tool");
+
+        debug!("Conversation now has {} messages", self.messages.len());
+    }
+
+    /// Add an assistant message that requested one or more tool calls
+    pub fn add_assistant_tool_calls(&mut self, content: &str, tool_calls: Vec<ToolCall>) {
+        self.add_assistant_tool_calls_with_thinking(content, tool_calls, Vec::new());
+    }
+
+    /// Add an assistant message that requested tool calls after some extended thinking
+    pub fn add_assistant_tool_calls_with_thinking(
+        &mut self,
+        content: &str,
+        tool_calls: Vec<ToolCall>,
+        thinking: Vec<ThinkingBlock>,
+    ) {
+        debug!(
+            "Adding assistant message with {} tool calls and {} thinking blocks to conversation",
+            tool_calls.len(),
+            thinking.len()
+        );
+        self.messages.push(Message {
+            role: MessageRole::Assistant,
+            content: content.to_string(),
+            tool_calls: Some(tool_calls),
+            tool_results: None,
+            attachments: None,
+            thinking: (!thinking.is_empty()).then_some(thinking),
+            pinned: false,
+        });
+
+        // Count message metrics
+        count!("conversation.messages.total");
+        count!("conversation.messages.assistant");
+
+        debug!("Conversation now has {} messages", self.messages.len());
+    }
+
+    /// Add a tool message carrying the structured result of a tool call
+    pub fn add_tool_result(&mut self, content: &str, result: ToolResult) {
+        debug!(
+            "Adding tool result for {} ({}) to conversation",
+            result.tool_id, result.call_id
+        );
+        self.messages.push(Message {
+            role: MessageRole::Tool,
+            content: content.to_string(),
+            tool_calls: None,
+            tool_results: Some(vec![result]),
+            attachments: None,
+            thinking: None,
+            pinned: false,
         });
 
         // Count message metrics
@@ -104,6 +389,9 @@ impl ConversationContext {
             content: content.to_string(),
             tool_calls: None,
             tool_results: None,
+            attachments: None,
+            thinking: None,
+            pinned: false,
         });
 
         // Count message metrics
@@ -123,6 +411,9 @@ impl ConversationContext {
             content: content.to_string(),
             tool_calls: None,
             tool_results: None,
+            attachments: None,
+            thinking: None,
+            pinned: false,
         });
 
         // Count message metrics
@@ -0,0 +1,227 @@
+use super::{Message, MessageRole};
+use serde_json::Value;
+use std::ops::Range;
+
+/// Chooses which messages of a conversation go out with each request, so a long session
+/// can carry on without resending everything it has accumulated.
+///
+/// The strategies here work in whole turns, a user message and everything up to the next
+/// one, so tool calls are never parted from their results. They always keep system
+/// messages, such as the summary of compacted turns, and every turn with a pinned message.
+pub trait ContextWindow: Send + Sync {
+    fn apply(&self, messages: &[Message]) -> Vec<Message>;
+}
+
+/// Sends only the last `max_turns` turns
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SlidingWindow {
+    pub max_turns: usize,
+}
+
+impl ContextWindow for SlidingWindow {
+    fn apply(&self, messages: &[Message]) -> Vec<Message> {
+        keep_turns(messages, |index, count| index + self.max_turns >= count)
+    }
+}
+
+/// Sends the first `head_turns` turns, which usually set out the task, and the last
+/// `tail_turns`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HeadTail {
+    pub head_turns: usize,
+    pub tail_turns: usize,
+}
+
+impl ContextWindow for HeadTail {
+    fn apply(&self, messages: &[Message]) -> Vec<Message> {
+        keep_turns(messages, |index, count| {
+            index < self.head_turns || index + self.tail_turns >= count
+        })
+    }
+}
+
+/// Sends every turn, but replaces tool output from before the last `keep_recent_turns`
+/// turns with a note of its size. Output of up to `max_chars` characters is left alone.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DropToolOutputs {
+    pub keep_recent_turns: usize,
+    pub max_chars: usize,
+}
+
+impl ContextWindow for DropToolOutputs {
+    fn apply(&self, messages: &[Message]) -> Vec<Message> {
+        let turns = turns(messages);
+        let recent = turns.len().saturating_sub(self.keep_recent_turns);
+
+        let mut kept = Vec::with_capacity(messages.len());
+        for (index, range) in turns.into_iter().enumerate() {
+            let turn = &messages[range];
+            if index >= recent || turn.iter().any(|message| message.pinned) {
+                kept.extend_from_slice(turn);
+                continue;
+            }
+            kept.extend(turn.iter().map(|message| self.drop_output(message)));
+        }
+        kept
+    }
+}
+
+impl DropToolOutputs {
+    fn drop_output(&self, message: &Message) -> Message {
+        let mut message = message.clone();
+        if message.role != MessageRole::Tool {
+            return message;
+        }
+        if message.content.chars().count() > self.max_chars {
+            message.content = elided(&message.content);
+        }
+        for result in message.tool_results.iter_mut().flatten() {
+            let output = result.result.to_string();
+            if output.chars().count() > self.max_chars {
+                result.result = Value::String(elided(&output));
+            }
+        }
+        message
+    }
+}
+
+fn elided(output: &str) -> String {
+    format!(
+        "[Tool output of {} characters left out to save context]",
+        output.chars().count()
+    )
+}
+
+// The turns of a conversation as ranges of message indexes. Anything before the first
+// user message, such as a compaction summary, counts as a turn of its own.
+pub(super) fn turns(messages: &[Message]) -> Vec<Range<usize>> {
+    let mut turns = Vec::new();
+    let mut start = 0;
+    for (index, message) in messages.iter().enumerate().skip(1) {
+        if message.role == MessageRole::User {
+            turns.push(start..index);
+            start = index;
+        }
+    }
+    if start < messages.len() {
+        turns.push(start..messages.len());
+    }
+    turns
+}
+
+// The turns `keep` picks, by index and turn count, along with pinned turns and every
+// system message
+fn keep_turns(messages: &[Message], keep: impl Fn(usize, usize) -> bool) -> Vec<Message> {
+    let turns = turns(messages);
+    let count = turns.len();
+    let mut kept = Vec::new();
+    for (index, range) in turns.into_iter().enumerate() {
+        let turn = &messages[range];
+        if keep(index, count) || turn.iter().any(|message| message.pinned) {
+            kept.extend_from_slice(turn);
+        } else {
+            kept.extend(
+                turn.iter()
+                    .filter(|message| message.role == MessageRole::System)
+                    .cloned(),
+            );
+        }
+    }
+    kept
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::{ConversationContext, ToolResult};
+    use serde_json::json;
+
+    // Four turns, each a question, a tool result and an answer
+    fn conversation() -> ConversationContext {
+        let mut context = ConversationContext::new();
+        for turn in 1..=4 {
+            context.add_user_message(&format!("question {}", turn));
+            context.add_tool_result(
+                &"x".repeat(1000),
+                ToolResult {
+                    tool_id: "read_file".to_string(),
+                    result: json!({"content": "x".repeat(1000)}),
+                    call_id: format!("call-{}", turn),
+                },
+            );
+            context.add_assistant_message(&format!("answer {}", turn));
+        }
+        context
+    }
+
+    fn contents(messages: &[Message]) -> Vec<&str> {
+        messages
+            .iter()
+            .filter(|message| message.role != MessageRole::Tool)
+            .map(|message| message.content.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn test_sliding_window_keeps_summary_and_pinned_turns() {
+        let mut context = conversation();
+        context.replace_with_summary(0, "Earlier work");
+        assert!(context.pin(1));
+
+        let window = SlidingWindow { max_turns: 1 };
+        let messages = window.apply(&context.messages);
+        assert!(messages[0].is_summary());
+        assert_eq!(
+            contents(&messages[1..]),
+            vec!["question 1", "answer 1", "question 4", "answer 4"]
+        );
+    }
+
+    #[test]
+    fn test_head_tail() {
+        let context = conversation();
+        let window = HeadTail {
+            head_turns: 1,
+            tail_turns: 2,
+        };
+        assert_eq!(
+            contents(&window.apply(&context.messages)),
+            vec![
+                "question 1",
+                "answer 1",
+                "question 3",
+                "answer 3",
+                "question 4",
+                "answer 4"
+            ]
+        );
+
+        // Overlapping head and tail send everything once
+        let window = HeadTail {
+            head_turns: 3,
+            tail_turns: 3,
+        };
+        assert_eq!(window.apply(&context.messages).len(), 12);
+    }
+
+    #[test]
+    fn test_drop_old_tool_outputs() {
+        let context = conversation();
+        let window = DropToolOutputs {
+            keep_recent_turns: 1,
+            max_chars: 100,
+        };
+        let messages = window.apply(&context.messages);
+        assert_eq!(messages.len(), 12);
+
+        let old = &messages[1];
+        assert!(old.content.starts_with("[Tool output of 1000 characters"));
+        assert!(old.tool_results.as_ref().unwrap()[0]
+            .result
+            .as_str()
+            .unwrap()
+            .contains("left out"));
+        // The last turn keeps its output
+        assert_eq!(messages[10].content.len(), 1000);
+    }
+}
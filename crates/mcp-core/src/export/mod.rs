@@ -0,0 +1,374 @@
+// Renders a conversation as a transcript to read, share or analyse
+use crate::context::{ConversationContext, Message, MessageRole, SUMMARY_HEADING};
+use serde_json::{json, Map, Value};
+use std::fmt::Write;
+
+const DEFAULT_TITLE: &str = "Conversation";
+const TITLE_CHARS: usize = 80;
+
+/// What a `Transcript` can be rendered as
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// Readable Markdown, with tool calls and results in collapsible `<details>` blocks
+    Markdown,
+    /// A single self-contained HTML page, with tool calls and results collapsed
+    Html,
+    /// One JSON object per message, for analysis or fine-tuning datasets
+    Jsonl,
+}
+
+impl ExportFormat {
+    /// Parse a format name or file extension, e.g. `markdown`, `md`, `html` or `jsonl`
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "markdown" | "md" => Some(ExportFormat::Markdown),
+            "html" | "htm" => Some(ExportFormat::Html),
+            "jsonl" | "json" => Some(ExportFormat::Jsonl),
+            _ => None,
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Markdown => "md",
+            ExportFormat::Html => "html",
+            ExportFormat::Jsonl => "jsonl",
+        }
+    }
+}
+
+/// A conversation, with its tool calls and results, ready to export
+#[derive(Debug, Clone)]
+pub struct Transcript<'a> {
+    context: &'a ConversationContext,
+    title: Option<String>,
+    model: Option<String>,
+}
+
+impl<'a> Transcript<'a> {
+    pub fn new(context: &'a ConversationContext) -> Self {
+        Self {
+            context,
+            title: None,
+            model: None,
+        }
+    }
+
+    /// Heading for the transcript; the first user message is used otherwise
+    pub fn with_title(mut self, title: impl Into<String>) -> Self {
+        self.title = Some(title.into());
+        self
+    }
+
+    /// Model the conversation was held with, noted under the heading
+    pub fn with_model(mut self, model: impl Into<String>) -> Self {
+        self.model = Some(model.into());
+        self
+    }
+
+    pub fn render(&self, format: ExportFormat) -> String {
+        match format {
+            ExportFormat::Markdown => self.markdown(),
+            ExportFormat::Html => self.html(),
+            ExportFormat::Jsonl => self.jsonl(),
+        }
+    }
+
+    pub fn markdown(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "# {}\n", self.title());
+        if let Some(model) = &self.model {
+            let _ = writeln!(out, "_Model: {}_\n", model);
+        }
+        if !self.context.system_prompt.trim().is_empty() {
+            markdown_details(&mut out, "System prompt", &self.context.system_prompt, "");
+        }
+
+        for message in &self.context.messages {
+            match message.role {
+                MessageRole::System => {
+                    let (heading, content) = system_heading(message);
+                    let _ = writeln!(out, "### {}\n\n{}\n", heading, content.trim());
+                }
+                MessageRole::User => {
+                    let _ = writeln!(out, "### User\n\n{}\n", message.content.trim());
+                    for attachment in message.attachments.iter().flatten() {
+                        let _ = writeln!(
+                            out,
+                            "_Attached: {} ({})_\n",
+                            attachment.name, attachment.media_type
+                        );
+                    }
+                }
+                MessageRole::Assistant => {
+                    for thinking in thinking(message) {
+                        markdown_details(&mut out, "Thinking", thinking, "");
+                    }
+                    if !message.content.trim().is_empty() {
+                        let _ = writeln!(out, "### Assistant\n\n{}\n", message.content.trim());
+                    }
+                    for call in message.tool_calls.iter().flatten() {
+                        markdown_details(
+                            &mut out,
+                            &format!("Tool call: {}", call.tool_id),
+                            &pretty(&call.parameters),
+                            "json",
+                        );
+                    }
+                }
+                MessageRole::Tool => {
+                    for (summary, output, language) in tool_outputs(message) {
+                        markdown_details(&mut out, &summary, &output, language);
+                    }
+                }
+            }
+        }
+        out.truncate(out.trim_end().len());
+        out.push('\n');
+        out
+    }
+
+    pub fn html(&self) -> String {
+        let title = escape_html(&self.title());
+        let mut out = String::new();
+        let _ = write!(
+            out,
+            "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n\
+             <title>{}</title>\n<style>{}</style>\n</head>\n<body>\n<h1>{}</h1>\n",
+            title, STYLE, title
+        );
+        if let Some(model) = &self.model {
+            let _ = writeln!(out, "<p class=\"meta\">Model: {}</p>", escape_html(model));
+        }
+        if !self.context.system_prompt.trim().is_empty() {
+            html_details(&mut out, "System prompt", &self.context.system_prompt);
+        }
+
+        for message in &self.context.messages {
+            match message.role {
+                MessageRole::System => {
+                    let (heading, content) = system_heading(message);
+                    html_message(&mut out, "system", heading, content.trim());
+                }
+                MessageRole::User => {
+                    html_message(&mut out, "user", "User", message.content.trim());
+                    for attachment in message.attachments.iter().flatten() {
+                        let _ = writeln!(
+                            out,
+                            "<p class=\"meta\">Attached: {} ({})</p>",
+                            escape_html(&attachment.name),
+                            escape_html(&attachment.media_type)
+                        );
+                    }
+                }
+                MessageRole::Assistant => {
+                    for thinking in thinking(message) {
+                        html_details(&mut out, "Thinking", thinking);
+                    }
+                    if !message.content.trim().is_empty() {
+                        html_message(&mut out, "assistant", "Assistant", message.content.trim());
+                    }
+                    for call in message.tool_calls.iter().flatten() {
+                        html_details(
+                            &mut out,
+                            &format!("Tool call: {}", call.tool_id),
+                            &pretty(&call.parameters),
+                        );
+                    }
+                }
+                MessageRole::Tool => {
+                    for (summary, output, _) in tool_outputs(message) {
+                        html_details(&mut out, &summary, &output);
+                    }
+                }
+            }
+        }
+        out.push_str("</body>\n</html>\n");
+        out
+    }
+
+    /// One line per message, numbered by `step`. The system prompt, if any, is step 0;
+    /// attachments are listed by name rather than carrying their data.
+    pub fn jsonl(&self) -> String {
+        let mut steps = Vec::new();
+        if !self.context.system_prompt.trim().is_empty() {
+            steps.push(json!({"role": "system", "content": self.context.system_prompt}));
+        }
+        steps.extend(self.context.messages.iter().map(jsonl_step));
+
+        let mut out = String::new();
+        for (step, mut value) in steps.into_iter().enumerate() {
+            if let Value::Object(fields) = &mut value {
+                fields.insert("step".to_string(), json!(step));
+            }
+            let _ = writeln!(out, "{}", value);
+        }
+        out
+    }
+
+    fn title(&self) -> String {
+        if let Some(title) = &self.title {
+            return title.clone();
+        }
+        let Some(first) = self
+            .context
+            .messages
+            .iter()
+            .find(|message| message.role == MessageRole::User)
+            .and_then(|message| message.content.lines().find(|line| !line.trim().is_empty()))
+        else {
+            return DEFAULT_TITLE.to_string();
+        };
+        let first = first.trim();
+        match first.char_indices().nth(TITLE_CHARS) {
+            Some((at, _)) => format!("{}...", &first[..at]),
+            None => first.to_string(),
+        }
+    }
+}
+
+const STYLE: &str = "body{font-family:system-ui,sans-serif;max-width:860px;margin:2em auto;\
+padding:0 1em;line-height:1.5;color:#1f2328}h1{font-size:1.5em}h2{font-size:1em;margin:0 0 .4em}\
+.meta{color:#656d76;font-size:.9em}.message{border-left:4px solid #d0d7de;padding:.2em 1em;\
+margin:1em 0}.user{border-color:#bf8700}.assistant{border-color:#1a7f37}.system{border-color:#0969da}\
+.content{white-space:pre-wrap}details{margin:.5em 0 .5em 1.25em}summary{cursor:pointer;\
+color:#656d76}pre{background:#f6f8fa;padding:.75em;overflow-x:auto;white-space:pre-wrap}";
+
+// The heading for a system message, and its text without any heading of its own
+fn system_heading(message: &Message) -> (&'static str, &str) {
+    match message.content.strip_prefix(SUMMARY_HEADING) {
+        Some(summary) if message.is_summary() => ("Summary of the earlier conversation", summary),
+        _ => ("System", message.content.as_str()),
+    }
+}
+
+fn thinking(message: &Message) -> impl Iterator<Item = &str> {
+    message
+        .thinking
+        .iter()
+        .flatten()
+        .filter_map(|block| block.text())
+}
+
+// Each tool result as (summary, output, code block language)
+fn tool_outputs(message: &Message) -> Vec<(String, String, &'static str)> {
+    match &message.tool_results {
+        Some(results) => results
+            .iter()
+            .map(|result| {
+                let (output, language) = match &result.result {
+                    Value::String(text) => (text.clone(), ""),
+                    value => (pretty(value), "json"),
+                };
+                (format!("Tool result: {}", result.tool_id), output, language)
+            })
+            .collect(),
+        // JSON-RPC results travel as the message text
+        None => vec![("Tool result".to_string(), message.content.clone(), "")],
+    }
+}
+
+fn pretty(value: &Value) -> String {
+    serde_json::to_string_pretty(value).unwrap_or_else(|_| value.to_string())
+}
+
+fn markdown_details(out: &mut String, summary: &str, body: &str, language: &str) {
+    // A fence longer than any run of backticks in the body, so the body can't close it
+    let mut longest = 0;
+    let mut run = 0;
+    for c in body.chars() {
+        run = if c == '`' { run + 1 } else { 0 };
+        longest = longest.max(run);
+    }
+    let fence = "`".repeat((longest + 1).max(3));
+    let _ = writeln!(
+        out,
+        "<details>\n<summary>{}</summary>\n\n{}{}\n{}\n{}\n\n</details>\n",
+        escape_html(summary),
+        fence,
+        language,
+        body.trim_end(),
+        fence
+    );
+}
+
+fn html_message(out: &mut String, class: &str, heading: &str, content: &str) {
+    let _ = writeln!(
+        out,
+        "<section class=\"message {}\">\n<h2>{}</h2>\n<div class=\"content\">{}</div>\n</section>",
+        class,
+        heading,
+        escape_html(content)
+    );
+}
+
+fn html_details(out: &mut String, summary: &str, body: &str) {
+    let _ = writeln!(
+        out,
+        "<details>\n<summary>{}</summary>\n<pre>{}</pre>\n</details>",
+        escape_html(summary),
+        escape_html(body.trim_end())
+    );
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+// A message as a trajectory step, leaving out fields it doesn't use
+fn jsonl_step(message: &Message) -> Value {
+    let role = match message.role {
+        MessageRole::System => "system",
+        MessageRole::User => "user",
+        MessageRole::Assistant => "assistant",
+        MessageRole::Tool => "tool",
+    };
+    let mut step = Map::new();
+    step.insert("role".to_string(), json!(role));
+    step.insert("content".to_string(), json!(message.content));
+
+    let thinking: Vec<&str> = thinking(message).collect();
+    if !thinking.is_empty() {
+        step.insert("thinking".to_string(), json!(thinking));
+    }
+    if let Some(calls) = message
+        .tool_calls
+        .as_ref()
+        .filter(|calls| !calls.is_empty())
+    {
+        let calls: Vec<Value> = calls
+            .iter()
+            .map(|call| json!({"id": call.call_id, "name": call.tool_id, "arguments": call.parameters}))
+            .collect();
+        step.insert("tool_calls".to_string(), json!(calls));
+    }
+    if let Some(results) = message.tool_results.as_ref().filter(|r| !r.is_empty()) {
+        let results: Vec<Value> = results
+            .iter()
+            .map(|result| json!({"id": result.call_id, "name": result.tool_id, "output": result.result}))
+            .collect();
+        step.insert("tool_results".to_string(), json!(results));
+    }
+    if let Some(attachments) = message.attachments.as_ref().filter(|a| !a.is_empty()) {
+        let attachments: Vec<Value> = attachments
+            .iter()
+            .map(|attachment| json!({"name": attachment.name, "media_type": attachment.media_type}))
+            .collect();
+        step.insert("attachments".to_string(), json!(attachments));
+    }
+    if message.pinned {
+        step.insert("pinned".to_string(), json!(true));
+    }
+    Value::Object(step)
+}
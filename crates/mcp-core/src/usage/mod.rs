@@ -0,0 +1,245 @@
+use crate::config::ModelPricing;
+use serde::{Deserialize, Serialize};
+
+/// Token usage and cost accumulated over a session
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct SessionUsage {
+    /// Number of requests that reported usage
+    pub requests: u64,
+    /// Uncached prompt tokens
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    /// Prompt tokens served from the provider's prompt cache
+    #[serde(default)]
+    pub cache_read_tokens: u64,
+    /// Prompt tokens written to the provider's prompt cache
+    #[serde(default)]
+    pub cache_write_tokens: u64,
+    /// Cost in USD of the requests made with a priced model, if there were any
+    pub cost_usd: Option<f64>,
+    /// Requests made with a model that has no pricing configured
+    pub unpriced_requests: u64,
+}
+
+impl SessionUsage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add one request's usage, returning its cost when the model is priced
+    pub fn record(
+        &mut self,
+        input_tokens: u64,
+        output_tokens: u64,
+        pricing: Option<&ModelPricing>,
+    ) -> Option<f64> {
+        self.record_with_cache(input_tokens, output_tokens, 0, 0, pricing)
+    }
+
+    /// Add one request's usage including prompt cache reads and writes
+    pub fn record_with_cache(
+        &mut self,
+        input_tokens: u64,
+        output_tokens: u64,
+        cache_read_tokens: u64,
+        cache_write_tokens: u64,
+        pricing: Option<&ModelPricing>,
+    ) -> Option<f64> {
+        self.requests += 1;
+        self.input_tokens += input_tokens;
+        self.output_tokens += output_tokens;
+        self.cache_read_tokens += cache_read_tokens;
+        self.cache_write_tokens += cache_write_tokens;
+
+        match pricing {
+            Some(pricing) => {
+                let cost = pricing.cost_with_cache(
+                    input_tokens,
+                    output_tokens,
+                    cache_read_tokens,
+                    cache_write_tokens,
+                );
+                *self.cost_usd.get_or_insert(0.0) += cost;
+                Some(cost)
+            }
+            None => {
+                self.unpriced_requests += 1;
+                None
+            }
+        }
+    }
+
+    pub fn total_tokens(&self) -> u64 {
+        self.input_tokens + self.cache_read_tokens + self.cache_write_tokens + self.output_tokens
+    }
+
+    /// Share of prompt tokens served from the cache, once anything has been cached
+    pub fn cache_hit_rate(&self) -> Option<f64> {
+        let prompt_tokens = self.input_tokens + self.cache_read_tokens + self.cache_write_tokens;
+        if self.cache_read_tokens + self.cache_write_tokens == 0 {
+            return None;
+        }
+        Some(self.cache_read_tokens as f64 / prompt_tokens as f64)
+    }
+
+    /// Human-readable summary for the given model
+    pub fn summary(&self, model: &str) -> String {
+        let mut summary = format!(
+            "\n=== Session Usage ===\nModel: {}\nRequests: {}\nInput tokens: {}\nOutput tokens: {}\nTotal tokens: {}\n",
+            model,
+            self.requests,
+            self.input_tokens,
+            self.output_tokens,
+            self.total_tokens()
+        );
+
+        if let Some(hit_rate) = self.cache_hit_rate() {
+            summary.push_str(&format!(
+                "Cache read tokens: {}\nCache write tokens: {}\nCache hit rate: {:.1}%\n",
+                self.cache_read_tokens,
+                self.cache_write_tokens,
+                hit_rate * 100.0
+            ));
+        }
+
+        match self.cost_usd {
+            Some(cost) => summary.push_str(&format!("Estimated cost: ${:.4}\n", cost)),
+            None => summary.push_str("Estimated cost: unknown (no pricing configured)\n"),
+        }
+        if self.cost_usd.is_some() && self.unpriced_requests > 0 {
+            summary.push_str(&format!(
+                "Note: {} request(s) used a model without pricing and are not included in the cost\n",
+                self.unpriced_requests
+            ));
+        }
+
+        summary
+    }
+}
+
+/// How much of a model's context window a conversation takes up
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ContextUsage {
+    /// Estimated prompt tokens, including the system prompt and tool descriptions
+    pub prompt_tokens: usize,
+    /// Tokens set aside for the reply (`max_tokens`)
+    pub reply_tokens: usize,
+    pub context_window: usize,
+}
+
+impl ContextUsage {
+    pub fn new(prompt_tokens: usize, reply_tokens: usize, context_window: usize) -> Self {
+        Self {
+            prompt_tokens,
+            reply_tokens,
+            context_window,
+        }
+    }
+
+    /// Whether the prompt still leaves room for a full reply
+    pub fn fits(&self) -> bool {
+        self.prompt_tokens + self.reply_tokens <= self.context_window
+    }
+
+    /// Share of the window taken by the prompt, from 0.0 upwards
+    pub fn fraction(&self) -> f64 {
+        if self.context_window == 0 {
+            return 1.0;
+        }
+        self.prompt_tokens as f64 / self.context_window as f64
+    }
+
+    /// One-line gauge, e.g. `Context: [####················] 21% (42k of 200k tokens)`
+    pub fn gauge(&self, width: usize) -> String {
+        let filled = ((self.fraction() * width as f64).round() as usize).min(width);
+        format!(
+            "Context: [{}{}] {:.0}% ({} of {} tokens)",
+            "#".repeat(filled),
+            "·".repeat(width - filled),
+            self.fraction() * 100.0,
+            short_count(self.prompt_tokens),
+            short_count(self.context_window)
+        )
+    }
+}
+
+// Token counts in thousands once they are large enough
+fn short_count(tokens: usize) -> String {
+    if tokens >= 10_000 {
+        format!("{}k", tokens / 1000)
+    } else {
+        tokens.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_accumulates_tokens_and_cost() {
+        let pricing = ModelPricing {
+            input_per_million: 3.0,
+            output_per_million: 15.0,
+            cache_read_per_million: None,
+            cache_write_per_million: None,
+        };
+        let mut usage = SessionUsage::new();
+
+        let cost = usage.record(1_000_000, 100_000, Some(&pricing)).unwrap();
+        assert!((cost - 4.5).abs() < 1e-9);
+
+        usage.record(500_000, 0, Some(&pricing));
+        usage.record(10, 10, None);
+
+        assert_eq!(usage.requests, 3);
+        assert_eq!(usage.input_tokens, 1_500_010);
+        assert_eq!(usage.output_tokens, 100_010);
+        assert!((usage.cost_usd.unwrap() - 6.0).abs() < 1e-9);
+        assert_eq!(usage.unpriced_requests, 1);
+        assert!(usage.summary("test-model").contains("$6.0000"));
+    }
+
+    #[test]
+    fn test_record_prices_cache_tokens() {
+        let pricing = ModelPricing::new(3.0, 15.0);
+        let mut usage = SessionUsage::new();
+
+        // 1M cache writes at 3.75, then 1M cache reads at 0.30
+        let write = usage
+            .record_with_cache(0, 0, 0, 1_000_000, Some(&pricing))
+            .unwrap();
+        let read = usage
+            .record_with_cache(0, 0, 1_000_000, 0, Some(&pricing))
+            .unwrap();
+        assert!((write - 3.75).abs() < 1e-9);
+        assert!((read - 0.3).abs() < 1e-9);
+
+        assert_eq!(usage.total_tokens(), 2_000_000);
+        assert_eq!(usage.cache_hit_rate(), Some(0.5));
+        assert!(usage
+            .summary("test-model")
+            .contains("Cache hit rate: 50.0%"));
+    }
+
+    #[test]
+    fn test_context_usage_gauge() {
+        let usage = ContextUsage::new(42_000, 4096, 200_000);
+        assert!(usage.fits());
+        assert_eq!(
+            usage.gauge(20),
+            "Context: [####················] 21% (42k of 200k tokens)"
+        );
+
+        // A prompt that fits but leaves no room for the reply doesn't
+        assert!(!ContextUsage::new(198_000, 4096, 200_000).fits());
+    }
+
+    #[test]
+    fn test_cost_unknown_without_pricing() {
+        let mut usage = SessionUsage::new();
+        assert_eq!(usage.record(100, 20, None), None);
+        assert_eq!(usage.cost_usd, None);
+        assert!(usage.summary("local").contains("unknown"));
+    }
+}
@@ -0,0 +1,291 @@
+use mcp_core::{
+    AwsConfig, CompactionConfig, Config, ContextStrategy, MiddlewareConfig, ModelConfig,
+    ModelFallback, ModelPricing, ModelProvider, ModelTask, RateLimit,
+};
+use serde_json::json;
+
+#[test]
+fn test_model_provider_defaults_to_bedrock() {
+    // Model entries written before providers existed keep working
+    let model: ModelConfig = serde_json::from_value(json!({
+        "model_id": "anthropic.claude-3-sonnet-20240229-v1:0",
+        "max_tokens": 4096,
+        "temperature": 0.7,
+        "active": true,
+        "description": null
+    }))
+    .unwrap();
+
+    assert_eq!(model.provider, ModelProvider::Bedrock);
+    assert!(model.base_url.is_none());
+    assert!(model.api_key_env.is_none());
+    assert!(model.thinking_budget_tokens.is_none());
+}
+
+#[test]
+fn test_openai_compatible_model_config() {
+    let model: ModelConfig = serde_json::from_value(json!({
+        "model_id": "qwen2.5-coder:32b",
+        "max_tokens": 8192,
+        "temperature": 0.2,
+        "active": true,
+        "description": "On-prem model served by Ollama",
+        "provider": "openai",
+        "base_url": "http://gpu-box:11434/v1"
+    }))
+    .unwrap();
+
+    assert_eq!(model.provider, ModelProvider::OpenAi);
+    assert_eq!(model.base_url.as_deref(), Some("http://gpu-box:11434/v1"));
+
+    let value = serde_json::to_value(&model).unwrap();
+    assert_eq!(value["provider"], "openai");
+}
+
+#[test]
+fn test_model_pricing() {
+    let model: ModelConfig = serde_json::from_value(json!({
+        "model_id": "anthropic.claude-3-haiku-20240307-v1:0",
+        "max_tokens": 4096,
+        "temperature": 0.7,
+        "active": true,
+        "description": null,
+        "pricing": {"input_per_million": 0.25, "output_per_million": 1.25}
+    }))
+    .unwrap();
+
+    let pricing = model.pricing.unwrap();
+    assert!((pricing.cost(2_000_000, 400_000) - 1.0).abs() < 1e-9);
+
+    // Models added on the command line have no pricing until configured
+    assert!(ModelConfig::default().pricing.is_none());
+}
+
+#[test]
+fn test_task_routing() {
+    let model_settings = serde_json::from_value(json!({
+        "models": [
+            {
+                "model_id": "claude-3-7-sonnet-latest",
+                "max_tokens": 8192,
+                "temperature": 0.7,
+                "active": true,
+                "description": null,
+                "provider": "anthropic",
+                "api_key_env": "WORK_ANTHROPIC_KEY"
+            },
+            {
+                "model_id": "local-small",
+                "max_tokens": 2048,
+                "temperature": 0.2,
+                "active": false,
+                "description": null,
+                "provider": "openai",
+                "base_url": "http://localhost:11434/v1"
+            }
+        ],
+        "routing": {
+            "summarize": "claude-3-5-haiku-latest",
+            "commit_message": "local-small",
+            "title": "claude-3-7-sonnet-latest"
+        }
+    }))
+    .unwrap();
+    let config = Config {
+        model_settings,
+        ..Config::default()
+    };
+
+    // An unlisted model inherits the active model's provider and credentials
+    let summarize = config.get_task_model(ModelTask::Summarize).unwrap();
+    assert_eq!(summarize.model_id, "claude-3-5-haiku-latest");
+    assert_eq!(summarize.provider, ModelProvider::Anthropic);
+    assert_eq!(summarize.api_key_env.as_deref(), Some("WORK_ANTHROPIC_KEY"));
+    assert!(!summarize.active);
+
+    // A listed model keeps its own settings
+    let commit = config.get_task_model(ModelTask::CommitMessage).unwrap();
+    assert_eq!(commit.provider, ModelProvider::OpenAi);
+    assert_eq!(commit.max_tokens, 2048);
+
+    // Routing to the active model, or not routing at all, leaves the task on the main client
+    assert!(config.get_task_model(ModelTask::Title).is_none());
+    assert!(config.get_task_model(ModelTask::Condense).is_none());
+    assert_eq!(config.task_models().len(), 2);
+
+    // Configs without routing don't write an empty section
+    let value = serde_json::to_value(Config::default()).unwrap();
+    assert!(value["model_settings"].get("routing").is_none());
+}
+
+#[test]
+fn test_model_fallbacks() {
+    let model: ModelConfig = serde_json::from_value(json!({
+        "model_id": "us.anthropic.claude-3-7-sonnet-20250219-v1:0",
+        "max_tokens": 4096,
+        "temperature": 0.7,
+        "active": true,
+        "description": null,
+        "fallbacks": [
+            {"region": "us-west-2"},
+            {"model_id": "us.anthropic.claude-3-5-sonnet-20241022-v2:0", "region": "us-east-2"}
+        ]
+    }))
+    .unwrap();
+
+    assert_eq!(
+        model.fallbacks[0],
+        ModelFallback {
+            model_id: None,
+            region: Some("us-west-2".to_string()),
+        }
+    );
+    assert_eq!(model.fallbacks[1].region.as_deref(), Some("us-east-2"));
+
+    // Models without fallbacks don't write an empty list
+    let value = serde_json::to_value(ModelConfig::default()).unwrap();
+    assert!(value.get("fallbacks").is_none());
+}
+
+#[test]
+fn test_aws_endpoint_role_and_model_region() {
+    // Configs written before these settings existed keep working
+    let aws: AwsConfig = serde_json::from_value(json!({
+        "region": "us-east-1",
+        "profile": "work-sso"
+    }))
+    .unwrap();
+    assert!(aws.endpoint_url.is_none());
+    assert!(aws.role_arn.is_none());
+
+    let aws: AwsConfig = serde_json::from_value(json!({
+        "region": "eu-west-1",
+        "profile": null,
+        "endpoint_url": "https://vpce-0abc.bedrock-runtime.eu-west-1.vpce.amazonaws.com",
+        "role_arn": "arn:aws:iam::123456789012:role/BedrockInvoke",
+        "external_id": "mcpterm"
+    }))
+    .unwrap();
+    assert_eq!(
+        aws.endpoint_url.as_deref(),
+        Some("https://vpce-0abc.bedrock-runtime.eu-west-1.vpce.amazonaws.com")
+    );
+    assert_eq!(aws.external_id.as_deref(), Some("mcpterm"));
+
+    let model: ModelConfig = serde_json::from_value(json!({
+        "model_id": "arn:aws:bedrock:us-west-2:123456789012:inference-profile/us.meta.llama3-3-70b-instruct-v1:0",
+        "max_tokens": 2048,
+        "temperature": 0.5,
+        "active": true,
+        "description": null,
+        "region": "us-west-2"
+    }))
+    .unwrap();
+    assert_eq!(model.region.as_deref(), Some("us-west-2"));
+    assert!(ModelConfig::default().region.is_none());
+}
+
+#[test]
+fn test_model_rate_limits() {
+    let mut config = Config::default();
+    config.model_settings.models[1].rate_limit = Some(RateLimit {
+        requests_per_minute: Some(50),
+        tokens_per_minute: Some(200_000),
+        max_concurrent: None,
+    });
+
+    let limits = config.rate_limits();
+    assert_eq!(limits.len(), 1);
+    assert_eq!(
+        limits["anthropic.claude-3-sonnet-20240229-v1:0"].requests_per_minute,
+        Some(50)
+    );
+
+    // Partial limits leave the other budgets unenforced
+    let limit: RateLimit = serde_json::from_value(json!({"max_concurrent": 2})).unwrap();
+    assert_eq!(limit.max_concurrent, Some(2));
+    assert!(limit.tokens_per_minute.is_none());
+}
+
+#[test]
+fn test_model_pricing_by_model_id() {
+    let mut config = Config::default();
+    config.model_settings.models[1].pricing = None;
+
+    // A fallback to another configured model is priced at that model's rates
+    let pricing = config.model_pricing();
+    assert_eq!(pricing.len(), 2);
+    assert_eq!(
+        pricing["anthropic.claude-3-opus-20240229-v1:0"],
+        ModelPricing::new(15.0, 75.0)
+    );
+    assert!(!pricing.contains_key("anthropic.claude-3-sonnet-20240229-v1:0"));
+}
+
+#[test]
+fn test_compaction_config() {
+    // Configs written before compaction existed get the defaults
+    let mut value = serde_json::to_value(Config::default()).unwrap();
+    value.as_object_mut().unwrap().remove("compaction");
+    let config: Config = serde_json::from_value(value).unwrap();
+    assert_eq!(config.compaction, CompactionConfig::default());
+    assert_eq!(
+        config.compaction.threshold_for(Some(200_000)),
+        Some(160_000)
+    );
+    assert_eq!(config.compaction.threshold_for(None), None);
+
+    let compaction: CompactionConfig =
+        serde_json::from_value(json!({"threshold_tokens": 50_000, "keep_recent_turns": 2}))
+            .unwrap();
+    assert!(compaction.auto);
+    assert_eq!(compaction.keep_recent_turns, 2);
+    assert_eq!(compaction.threshold_for(Some(200_000)), Some(50_000));
+}
+
+#[test]
+fn test_middleware_config() {
+    // Configs written before middleware existed get the defaults
+    let mut value = serde_json::to_value(Config::default()).unwrap();
+    value.as_object_mut().unwrap().remove("middleware");
+    let config: Config = serde_json::from_value(value).unwrap();
+    assert_eq!(config.middleware, MiddlewareConfig::default());
+
+    let middleware: MiddlewareConfig =
+        serde_json::from_value(json!({"max_retries": 1, "redact_env": ["GITHUB_TOKEN"]})).unwrap();
+    assert_eq!(middleware.max_retries, Some(1));
+    assert!(!middleware.logging);
+    assert_eq!(middleware.redact_env, ["GITHUB_TOKEN"]);
+}
+
+#[test]
+fn test_context_strategy_per_model() {
+    let model: ModelConfig = serde_json::from_value(json!({
+        "model_id": "anthropic.claude-3-haiku-20240307-v1:0",
+        "max_tokens": 4096,
+        "temperature": 0.7,
+        "active": true,
+        "description": null,
+        "context_strategy": {"type": "drop_tool_outputs", "keep_recent_turns": 2}
+    }))
+    .unwrap();
+    assert_eq!(
+        model.context_strategy,
+        Some(ContextStrategy::DropToolOutputs {
+            keep_recent_turns: 2,
+            max_chars: 500
+        })
+    );
+    assert!(ModelConfig::default().context_strategy.is_none());
+
+    let strategy: ContextStrategy =
+        serde_json::from_value(json!({"type": "head_tail", "head_turns": 1, "tail_turns": 5}))
+            .unwrap();
+    assert_eq!(
+        strategy,
+        ContextStrategy::HeadTail {
+            head_turns: 1,
+            tail_turns: 5
+        }
+    );
+}
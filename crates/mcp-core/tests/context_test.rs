@@ -1,6 +1,7 @@
 #[cfg(test)]
 mod tests {
-    use mcp_core::context::{ConversationContext, MessageRole};
+    use mcp_core::context::{ConversationContext, MessageRole, ToolCall, ToolResult};
+    use serde_json::json;
 
     #[test]
     fn test_add_user_message() {
@@ -11,4 +12,98 @@ mod tests {
         assert!(matches!(context.messages[0].role, MessageRole::User));
         assert_eq!(context.messages[0].content, "Hello, world!");
     }
+
+    #[test]
+    fn test_add_tool_call_and_result() {
+        let mut context = ConversationContext::new();
+        context.add_assistant_tool_calls(
+            "Let me look",
+            vec![ToolCall {
+                tool_id: "shell".to_string(),
+                parameters: json!({"command": "ls"}),
+                call_id: "call-1".to_string(),
+            }],
+        );
+        context.add_tool_result(
+            "{\"status\": \"Success\"}",
+            ToolResult {
+                tool_id: "shell".to_string(),
+                result: json!({"status": "Success"}),
+                call_id: "call-1".to_string(),
+            },
+        );
+
+        assert_eq!(context.messages.len(), 2);
+        let calls = context.messages[0].tool_calls.as_ref().unwrap();
+        assert_eq!(calls[0].call_id, "call-1");
+        assert!(matches!(context.messages[1].role, MessageRole::Tool));
+        let results = context.messages[1].tool_results.as_ref().unwrap();
+        assert_eq!(results[0].call_id, "call-1");
+    }
+
+    #[test]
+    fn test_replace_older_turns_with_summary() {
+        let mut context = ConversationContext::new();
+        context.add_user_message("List the files");
+        context.add_assistant_tool_calls(
+            "",
+            vec![ToolCall {
+                tool_id: "shell".to_string(),
+                parameters: json!({"command": "ls"}),
+                call_id: "call-1".to_string(),
+            }],
+        );
+        context.add_tool_result(
+            "",
+            ToolResult {
+                tool_id: "shell".to_string(),
+                result: json!({"stdout": "Cargo.toml"}),
+                call_id: "call-1".to_string(),
+            },
+        );
+        context.add_assistant_message("There is a Cargo.toml");
+        context.add_user_message("What is in it?");
+        context.add_assistant_message("A workspace");
+
+        assert_eq!(context.recent_turns_start(1), 4);
+        assert_eq!(context.recent_turns_start(2), 0);
+        assert_eq!(context.recent_turns_start(3), 0);
+        assert_eq!(context.recent_turns_start(0), 6);
+
+        context.replace_with_summary(4, "The user listed the files; there is a Cargo.toml.\n");
+        assert_eq!(context.messages.len(), 3);
+        assert!(context.messages[0].is_summary());
+        assert!(context.messages[0]
+            .content
+            .ends_with("there is a Cargo.toml."));
+        assert_eq!(context.messages[1].content, "What is in it?");
+        assert!(!context.messages[1].is_summary());
+    }
+
+    #[test]
+    fn test_pinned_turns_survive_compaction() {
+        let mut context = ConversationContext::new();
+        context.add_user_message("Always use tabs");
+        context.add_assistant_message("Noted");
+        context.add_user_message("Fix the build");
+        context.add_assistant_message("Done");
+        context.add_user_message("Now the tests");
+        assert!(context.pin(0));
+        assert!(!context.pin(10));
+
+        let end = context.recent_turns_start(1);
+        let compactable: Vec<_> = context
+            .compactable(end)
+            .iter()
+            .map(|message| message.content.clone())
+            .collect();
+        assert_eq!(compactable, vec!["Fix the build", "Done"]);
+
+        context.replace_with_summary(end, "The build was fixed.");
+        assert!(context.messages[0].is_summary());
+        assert_eq!(context.messages[1].content, "Always use tabs");
+        assert!(context.messages[1].pinned);
+        assert_eq!(context.messages[2].content, "Noted");
+        assert_eq!(context.messages[3].content, "Now the tests");
+    }
 }
@@ -0,0 +1,94 @@
+#[cfg(test)]
+mod tests {
+    use mcp_core::context::{ConversationContext, ToolCall, ToolResult};
+    use mcp_core::{ExportFormat, Transcript};
+    use serde_json::{json, Value};
+
+    fn conversation() -> ConversationContext {
+        let mut context = ConversationContext::new();
+        context.add_user_message("Why does <Foo> fail?\nIt used to pass.");
+        context.add_assistant_tool_calls(
+            "Let me run it.",
+            vec![ToolCall {
+                tool_id: "shell".to_string(),
+                parameters: json!({"command": "cargo test"}),
+                call_id: "call-1".to_string(),
+            }],
+        );
+        context.add_tool_result(
+            "",
+            ToolResult {
+                tool_id: "shell".to_string(),
+                result: json!({"stdout": "```\ntest foo ... FAILED\n```"}),
+                call_id: "call-1".to_string(),
+            },
+        );
+        context.add_assistant_message("The fixture file is missing.");
+        context
+    }
+
+    #[test]
+    fn test_format_names() {
+        assert_eq!(ExportFormat::from_name("md"), Some(ExportFormat::Markdown));
+        assert_eq!(ExportFormat::from_name("HTML"), Some(ExportFormat::Html));
+        assert_eq!(ExportFormat::from_name("jsonl"), Some(ExportFormat::Jsonl));
+        assert_eq!(ExportFormat::from_name("pdf"), None);
+        assert_eq!(ExportFormat::Markdown.extension(), "md");
+    }
+
+    #[test]
+    fn test_markdown_transcript() {
+        let context = conversation();
+        let markdown = Transcript::new(&context)
+            .with_model("claude-sonnet")
+            .markdown();
+
+        assert!(markdown.starts_with("# Why does <Foo> fail?\n\n_Model: claude-sonnet_\n"));
+        assert!(markdown.contains("### User\n\nWhy does <Foo> fail?\nIt used to pass."));
+        assert!(markdown.contains("### Assistant\n\nLet me run it."));
+        assert!(markdown.contains("<summary>Tool call: shell</summary>\n\n```json\n{\n  \"command\": \"cargo test\"\n}\n```"));
+        // The result holds a fence of its own, so it gets a longer one
+        assert!(markdown.contains("<summary>Tool result: shell</summary>\n\n````json\n"));
+        assert!(markdown.ends_with("The fixture file is missing.\n"));
+    }
+
+    #[test]
+    fn test_html_transcript_is_escaped_and_collapsible() {
+        let context = conversation();
+        let html = Transcript::new(&context).with_title("Incident 42").html();
+
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains("<title>Incident 42</title>"));
+        assert!(html.contains("<style>"));
+        assert!(html.contains("Why does &lt;Foo&gt; fail?"));
+        assert!(!html.contains("<Foo>"));
+        assert!(html.contains("<details>\n<summary>Tool result: shell</summary>\n<pre>"));
+        assert!(html.trim_end().ends_with("</html>"));
+    }
+
+    #[test]
+    fn test_jsonl_trajectory() {
+        let mut context = conversation();
+        context.system_prompt = "Be brief.".to_string();
+        let jsonl = Transcript::new(&context).render(ExportFormat::Jsonl);
+
+        let steps: Vec<Value> = jsonl
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(steps.len(), 5);
+        assert_eq!(
+            steps[0],
+            json!({"step": 0, "role": "system", "content": "Be brief."})
+        );
+        assert_eq!(steps[1]["role"], "user");
+        assert_eq!(
+            steps[2]["tool_calls"],
+            json!([{"id": "call-1", "name": "shell", "arguments": {"command": "cargo test"}}])
+        );
+        assert_eq!(steps[3]["role"], "tool");
+        assert_eq!(steps[3]["tool_results"][0]["id"], "call-1");
+        assert_eq!(steps[4]["step"], 4);
+        assert!(steps[4].get("tool_calls").is_none());
+    }
+}
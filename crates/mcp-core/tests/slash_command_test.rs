@@ -1,5 +1,7 @@
 use mcp_core::commands::mcp::{McpCommand, ToolInfo, ToolProvider};
-use mcp_core::{CommandStatus, SlashCommand};
+use mcp_core::{
+    CommandStatus, ContextUsage, ModelPricing, SessionUsage, SlashCommand, UsageCommand,
+};
 use serde_json::json;
 
 // A simple mock tool provider for testing
@@ -108,3 +110,33 @@ fn test_mcp_command_unknown() {
     let error = result.error.unwrap();
     assert!(error.contains("Unknown MCP command"));
 }
+
+#[test]
+fn test_usage_command() {
+    let mut usage = SessionUsage::new();
+    usage.record(1200, 300, Some(&ModelPricing::new(3.0, 15.0)));
+
+    let command = UsageCommand::new("claude-test", usage);
+    assert_eq!(command.name(), "usage");
+
+    let result = command.execute(&[]);
+    assert!(matches!(result.status, CommandStatus::Success));
+    let content = result.content.unwrap();
+    assert!(content.contains("claude-test"));
+    assert!(content.contains("Total tokens: 1500"));
+    assert!(content.contains("$0.0081"));
+    assert_eq!(result.data.unwrap()["input_tokens"], 1200);
+
+    let result = command.execute(&["bogus"]);
+    assert!(matches!(result.status, CommandStatus::Error));
+}
+
+#[test]
+fn test_usage_command_shows_context_gauge() {
+    let command = UsageCommand::new("claude-test", SessionUsage::new())
+        .with_context(ContextUsage::new(50_000, 4096, 200_000));
+
+    let result = command.execute(&[]);
+    assert!(result.content.unwrap().contains("25% (50k of 200k tokens)"));
+    assert_eq!(result.data.unwrap()["context"]["context_window"], 200_000);
+}
@@ -0,0 +1,538 @@
+use crate::client_trait::{
+    LlmResponse, ThinkingBlock, TokenUsage, ToolCall as ClientToolCall, ToolDefinition,
+};
+use crate::prompt_cache::{self, CacheControl};
+use crate::thinking::ThinkingConfig;
+use mcp_core::context::{Attachment, AttachmentKind, ConversationContext, MessageRole};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Request body for `POST /v1/messages`
+#[derive(Debug, Serialize)]
+pub struct MessagesRequest<'a> {
+    pub model: &'a str,
+    pub max_tokens: usize,
+    pub messages: Vec<AnthropicMessage>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub system: Vec<SystemBlock>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub thinking: Option<ThinkingConfig>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tools: Vec<ToolSpec<'a>>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub stream: bool,
+}
+
+/// Text block of the top-level system prompt
+#[derive(Debug, Serialize)]
+pub struct SystemBlock {
+    #[serde(rename = "type")]
+    pub block_type: &'static str,
+    pub text: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cache_control: Option<CacheControl>,
+}
+
+/// Native tool spec; the last one may carry a cache breakpoint covering all tools
+#[derive(Debug, Serialize)]
+pub struct ToolSpec<'a> {
+    pub name: &'a str,
+    pub description: &'a str,
+    pub input_schema: &'a Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cache_control: Option<CacheControl>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct AnthropicMessage {
+    pub role: String,
+    pub content: Vec<ContentBlock>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ContentBlock {
+    Text {
+        text: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        cache_control: Option<CacheControl>,
+    },
+    Image {
+        source: MediaSource,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        cache_control: Option<CacheControl>,
+    },
+    Document {
+        source: MediaSource,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        title: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        cache_control: Option<CacheControl>,
+    },
+    ToolUse {
+        id: String,
+        name: String,
+        input: Value,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        cache_control: Option<CacheControl>,
+    },
+    ToolResult {
+        tool_use_id: String,
+        content: String,
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        is_error: bool,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        cache_control: Option<CacheControl>,
+    },
+    Thinking {
+        thinking: String,
+        #[serde(default)]
+        signature: String,
+    },
+    RedactedThinking {
+        data: String,
+    },
+    // Block types we don't handle
+    #[serde(other)]
+    Unsupported,
+}
+
+/// Inline base64 data for image and document blocks
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct MediaSource {
+    #[serde(rename = "type")]
+    pub source_type: String,
+    pub media_type: String,
+    pub data: String,
+}
+
+/// Non-streaming response body
+#[derive(Debug, Deserialize)]
+pub struct MessagesResponse {
+    pub id: String,
+    pub content: Vec<ContentBlock>,
+    #[serde(default)]
+    pub stop_reason: Option<String>,
+    #[serde(default)]
+    pub usage: Option<Usage>,
+}
+
+impl ContentBlock {
+    // Mark the end of a cacheable prefix; thinking and unsupported blocks can't be marked
+    fn set_cache_control(&mut self, control: CacheControl) {
+        match self {
+            ContentBlock::Text { cache_control, .. }
+            | ContentBlock::Image { cache_control, .. }
+            | ContentBlock::Document { cache_control, .. }
+            | ContentBlock::ToolUse { cache_control, .. }
+            | ContentBlock::ToolResult { cache_control, .. } => *cache_control = Some(control),
+            ContentBlock::Thinking { .. }
+            | ContentBlock::RedactedThinking { .. }
+            | ContentBlock::Unsupported => {}
+        }
+    }
+}
+
+/// Token counts reported with each response
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct Usage {
+    #[serde(default)]
+    pub input_tokens: u64,
+    #[serde(default)]
+    pub output_tokens: u64,
+    #[serde(default)]
+    pub cache_read_input_tokens: u64,
+    #[serde(default)]
+    pub cache_creation_input_tokens: u64,
+}
+
+impl From<ThinkingBlock> for ContentBlock {
+    fn from(block: ThinkingBlock) -> Self {
+        match block {
+            ThinkingBlock::Thinking {
+                thinking,
+                signature,
+            } => ContentBlock::Thinking {
+                thinking,
+                signature,
+            },
+            ThinkingBlock::RedactedThinking { data } => ContentBlock::RedactedThinking { data },
+        }
+    }
+}
+
+impl From<Usage> for TokenUsage {
+    fn from(usage: Usage) -> Self {
+        TokenUsage::new(usage.input_tokens, usage.output_tokens).with_cache(
+            usage.cache_read_input_tokens,
+            usage.cache_creation_input_tokens,
+        )
+    }
+}
+
+/// Error body returned with non-2xx statuses
+#[derive(Debug, Deserialize)]
+pub struct ErrorResponse {
+    pub error: ErrorDetail,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ErrorDetail {
+    #[serde(rename = "type")]
+    pub error_type: String,
+    pub message: String,
+}
+
+/// Convert the conversation into Messages API turns.
+///
+/// System messages are returned separately since the API takes them as a top-level field.
+/// Tool results with a call id become `tool_result` blocks, and the thinking that led to
+/// the tool calls is sent back first, as the API requires. Consecutive messages with the
+/// same role are merged since the API requires alternating roles.
+pub fn build_messages(context: &ConversationContext) -> (Vec<String>, Vec<AnthropicMessage>) {
+    let mut system = Vec::new();
+    let mut messages: Vec<AnthropicMessage> = Vec::new();
+
+    for message in &context.messages {
+        let (role, blocks) = match message.role {
+            MessageRole::System => {
+                system.push(message.content.clone());
+                continue;
+            }
+            MessageRole::User => {
+                // Attachments go before the text that asks about them
+                let mut blocks: Vec<_> = message
+                    .attachments
+                    .iter()
+                    .flatten()
+                    .map(attachment_block)
+                    .collect();
+                blocks.extend(text_blocks(&message.content));
+                ("user", blocks)
+            }
+            MessageRole::Assistant => {
+                let mut blocks: Vec<_> = message
+                    .thinking
+                    .iter()
+                    .flatten()
+                    .cloned()
+                    .map(ContentBlock::from)
+                    .collect();
+                blocks.extend(text_blocks(&message.content));
+                for call in message.tool_calls.iter().flatten() {
+                    if call.call_id.is_empty() {
+                        continue;
+                    }
+                    blocks.push(ContentBlock::ToolUse {
+                        id: call.call_id.clone(),
+                        name: call.tool_id.clone(),
+                        input: call.parameters.clone(),
+                        cache_control: None,
+                    });
+                }
+                ("assistant", blocks)
+            }
+            MessageRole::Tool => {
+                let blocks: Vec<_> = message
+                    .tool_results
+                    .iter()
+                    .flatten()
+                    .filter(|r| !r.call_id.is_empty())
+                    .map(|r| ContentBlock::ToolResult {
+                        tool_use_id: r.call_id.clone(),
+                        content: r.result.to_string(),
+                        is_error: matches!(
+                            r.result.get("status").and_then(|s| s.as_str()),
+                            Some("Failure") | Some("Timeout")
+                        ),
+                        cache_control: None,
+                    })
+                    .collect();
+
+                if blocks.is_empty() {
+                    // A JSON-RPC era tool message without a call id, pass it along as text
+                    (
+                        "user",
+                        text_blocks(&format!("Tool result:\n{}", message.content)),
+                    )
+                } else {
+                    ("user", blocks)
+                }
+            }
+        };
+
+        if blocks.is_empty() {
+            continue;
+        }
+
+        match messages.last_mut() {
+            Some(last) if last.role == role => last.content.extend(blocks),
+            _ => messages.push(AnthropicMessage {
+                role: role.to_string(),
+                content: blocks,
+            }),
+        }
+    }
+
+    (system, messages)
+}
+
+/// Build the system prompt blocks, with a cache breakpoint after the last one if requested
+pub fn build_system(parts: &[String], cache: bool) -> Vec<SystemBlock> {
+    let text = parts
+        .iter()
+        .filter(|s| !s.trim().is_empty())
+        .cloned()
+        .collect::<Vec<_>>()
+        .join("\n\n");
+    if text.is_empty() {
+        return Vec::new();
+    }
+
+    vec![SystemBlock {
+        block_type: "text",
+        text,
+        cache_control: cache.then(CacheControl::ephemeral),
+    }]
+}
+
+/// Describe tool definitions as native tool specs, caching them as one prefix if requested
+pub fn build_tools(tools: &[ToolDefinition], cache: bool) -> Vec<ToolSpec<'_>> {
+    let mut specs: Vec<_> = tools
+        .iter()
+        .map(|tool| ToolSpec {
+            name: &tool.name,
+            description: &tool.description,
+            input_schema: &tool.input_schema,
+            cache_control: None,
+        })
+        .collect();
+    if let Some(last) = specs.last_mut().filter(|_| cache) {
+        last.cache_control = Some(CacheControl::ephemeral());
+    }
+    specs
+}
+
+/// Place cache breakpoints on the last block of the history prefix messages
+pub fn apply_cache_breakpoints(messages: &mut [AnthropicMessage]) {
+    for index in prompt_cache::history_breakpoints(messages.len()) {
+        if let Some(block) = messages[index].content.last_mut() {
+            block.set_cache_control(CacheControl::ephemeral());
+        }
+    }
+}
+
+/// Convert a Messages API response into an `LlmResponse`
+pub fn parse_response(response: MessagesResponse) -> LlmResponse {
+    let mut text = Vec::new();
+    let mut tool_calls = Vec::new();
+    let mut thinking = Vec::new();
+
+    for block in response.content {
+        match block {
+            ContentBlock::Text { text: t, .. } => text.push(t),
+            ContentBlock::Thinking {
+                thinking: t,
+                signature,
+            } => thinking.push(ThinkingBlock::Thinking {
+                thinking: t,
+                signature,
+            }),
+            ContentBlock::RedactedThinking { data } => {
+                thinking.push(ThinkingBlock::RedactedThinking { data })
+            }
+            ContentBlock::ToolUse {
+                id, name, input, ..
+            } => tool_calls.push(ClientToolCall {
+                id,
+                tool: name,
+                params: input,
+            }),
+            _ => {}
+        }
+    }
+
+    LlmResponse {
+        id: response.id,
+        content: text.join("\n"),
+        tool_calls,
+        usage: response.usage.map(TokenUsage::from),
+        thinking,
+    }
+}
+
+// Map an attachment to an image or document block carrying its base64 data
+fn attachment_block(attachment: &Attachment) -> ContentBlock {
+    let source = MediaSource {
+        source_type: "base64".to_string(),
+        media_type: attachment.media_type.clone(),
+        data: attachment.data.clone(),
+    };
+    match attachment.kind {
+        AttachmentKind::Image => ContentBlock::Image {
+            source,
+            cache_control: None,
+        },
+        AttachmentKind::Document => ContentBlock::Document {
+            source,
+            title: Some(attachment.name.clone()),
+            cache_control: None,
+        },
+    }
+}
+
+// Wrap non-empty text in a content block
+fn text_blocks(text: &str) -> Vec<ContentBlock> {
+    if text.trim().is_empty() {
+        Vec::new()
+    } else {
+        vec![ContentBlock::Text {
+            text: text.to_string(),
+            cache_control: None,
+        }]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mcp_core::context::{ToolCall, ToolResult};
+    use serde_json::json;
+
+    #[test]
+    fn test_build_messages_with_tool_turns() {
+        let mut context = ConversationContext::new();
+        context.add_system_message("Be brief.");
+        context.add_user_message("List files");
+        context.add_assistant_tool_calls(
+            "Checking.",
+            vec![ToolCall {
+                tool_id: "shell".to_string(),
+                parameters: json!({"command": "ls"}),
+                call_id: "toolu_1".to_string(),
+            }],
+        );
+        context.add_tool_result(
+            "{}",
+            ToolResult {
+                tool_id: "shell".to_string(),
+                result: json!({"status": "Failure", "error": "denied"}),
+                call_id: "toolu_1".to_string(),
+            },
+        );
+
+        let (system, messages) = build_messages(&context);
+
+        assert_eq!(system, vec!["Be brief.".to_string()]);
+        assert_eq!(messages.len(), 3);
+        assert_eq!(messages[1].content.len(), 2);
+        assert!(matches!(
+            &messages[2].content[0],
+            ContentBlock::ToolResult { tool_use_id, is_error: true, .. } if tool_use_id == "toolu_1"
+        ));
+    }
+
+    #[test]
+    fn test_attachments_become_image_and_document_blocks() {
+        let mut context = ConversationContext::new();
+        context.add_user_message_with_attachments(
+            "What does this error mean?",
+            vec![
+                Attachment::new(AttachmentKind::Image, "image/png", "error.png", b"png"),
+                Attachment::new(
+                    AttachmentKind::Document,
+                    "application/pdf",
+                    "spec.pdf",
+                    b"pdf",
+                ),
+            ],
+        );
+
+        let (_, messages) = build_messages(&context);
+        let body = serde_json::to_value(&messages[0]).unwrap();
+
+        assert_eq!(body["content"][0]["type"], "image");
+        assert_eq!(body["content"][0]["source"]["type"], "base64");
+        assert_eq!(body["content"][0]["source"]["media_type"], "image/png");
+        assert_eq!(body["content"][1]["type"], "document");
+        assert_eq!(body["content"][1]["title"], "spec.pdf");
+        assert_eq!(body["content"][2]["type"], "text");
+    }
+
+    #[test]
+    fn test_cache_breakpoints_on_history_prefix() {
+        let mut context = ConversationContext::new();
+        context.add_user_message("First question");
+        context.add_assistant_message("First answer");
+        context.add_user_message("Second question");
+
+        let (_, mut messages) = build_messages(&context);
+        apply_cache_breakpoints(&mut messages);
+
+        let cached: Vec<_> = messages
+            .iter()
+            .map(|m| {
+                matches!(
+                    m.content.last(),
+                    Some(ContentBlock::Text {
+                        cache_control: Some(_),
+                        ..
+                    })
+                )
+            })
+            .collect();
+        assert_eq!(cached, vec![true, false, true]);
+
+        let body = serde_json::to_value(&messages[0]).unwrap();
+        assert_eq!(body["content"][0]["cache_control"]["type"], "ephemeral");
+        let body = serde_json::to_value(&messages[1]).unwrap();
+        assert!(body["content"][0].get("cache_control").is_none());
+    }
+
+    #[test]
+    fn test_parse_response_with_unknown_blocks() {
+        let response: MessagesResponse = serde_json::from_value(json!({
+            "id": "msg_1",
+            "content": [
+                {"type": "thinking", "thinking": "hmm", "signature": "x"},
+                {"type": "server_tool_use", "id": "srvtoolu_1", "name": "web_search"},
+                {"type": "text", "text": "Listing."},
+                {"type": "tool_use", "id": "toolu_1", "name": "shell", "input": {"command": "ls"}}
+            ],
+            "stop_reason": "tool_use"
+        }))
+        .unwrap();
+
+        let response = parse_response(response);
+        assert_eq!(response.content, "Listing.");
+        assert_eq!(response.tool_calls[0].tool, "shell");
+        assert_eq!(response.thinking[0].text(), Some("hmm"));
+    }
+
+    #[test]
+    fn test_thinking_sent_back_before_tool_use() {
+        let mut context = ConversationContext::new();
+        context.add_user_message("Refactor the parser");
+        context.add_assistant_tool_calls_with_thinking(
+            "",
+            vec![ToolCall {
+                tool_id: "file_read".to_string(),
+                parameters: json!({"path": "src/parser.rs"}),
+                call_id: "toolu_1".to_string(),
+            }],
+            vec![ThinkingBlock::Thinking {
+                thinking: "Read the parser first.".to_string(),
+                signature: "sig".to_string(),
+            }],
+        );
+
+        let (_, messages) = build_messages(&context);
+        let body = serde_json::to_value(&messages[1]).unwrap();
+
+        assert_eq!(body["content"][0]["type"], "thinking");
+        assert_eq!(body["content"][0]["signature"], "sig");
+        assert_eq!(body["content"][1]["type"], "tool_use");
+    }
+}
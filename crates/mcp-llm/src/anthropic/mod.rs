@@ -1,11 +1,56 @@
-use crate::client_trait::{LlmClient, LlmResponse, StreamChunk};
-use anyhow::Result;
+use crate::cancel::ActiveRequests;
+use crate::client_trait::{LlmClient, LlmResponse, StreamChunk, TokenUsage, ToolDefinition};
+use crate::retry::ErrorClass;
+use crate::streaming::{ClaudeStreamDecoder, SseParser};
+use crate::thinking::ThinkingConfig;
+use anyhow::{anyhow, Result};
 use async_trait::async_trait;
-use futures::Stream;
+use futures::{Stream, StreamExt};
 use mcp_core::context::ConversationContext;
-use mcp_metrics::{count, time};
+use mcp_metrics::count;
 use serde::{Deserialize, Serialize};
-use tracing::{debug, error, info, warn};
+use tokio_stream::wrappers::ReceiverStream;
+use tracing::{debug, error, trace, warn};
+
+mod messages;
+
+use messages::{ErrorResponse, MessagesRequest, MessagesResponse};
+
+pub const DEFAULT_BASE_URL: &str = "https://api.anthropic.com";
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+
+// Anthropic specific errors
+#[derive(Debug, thiserror::Error)]
+pub enum AnthropicError {
+    #[error("Anthropic API error ({status}): {message}")]
+    ApiError { status: u16, message: String },
+
+    #[error("Failed to parse Anthropic response: {0}")]
+    ResponseParseError(String),
+
+    #[error("HTTP request to Anthropic failed: {0}")]
+    Http(String),
+
+    #[error("Request cancelled")]
+    Cancelled,
+}
+
+impl AnthropicError {
+    /// Classify the error for retry decisions and user-facing messages
+    pub fn class(&self) -> ErrorClass {
+        match self {
+            AnthropicError::ApiError { status, message } => {
+                ErrorClass::from_http_status(*status, message)
+            }
+            AnthropicError::Http(_) => ErrorClass::Transient,
+            _ => ErrorClass::Other,
+        }
+    }
+}
+
+fn default_base_url() -> String {
+    DEFAULT_BASE_URL.to_string()
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AnthropicConfig {
@@ -13,6 +58,21 @@ pub struct AnthropicConfig {
     pub model: String,
     pub max_tokens: usize,
     pub temperature: f32,
+    /// Base URL of the API, override to point at a proxy or a local mock server
+    #[serde(default = "default_base_url")]
+    pub base_url: String,
+    #[serde(default)]
+    pub system_prompt: Option<String>,
+    /// Place cache breakpoints on the tools, system prompt and conversation history
+    #[serde(default = "default_prompt_caching")]
+    pub prompt_caching: bool,
+    /// Token budget for extended thinking; unset leaves thinking off
+    #[serde(default)]
+    pub thinking_budget_tokens: Option<usize>,
+}
+
+fn default_prompt_caching() -> bool {
+    true
 }
 
 impl AnthropicConfig {
@@ -22,83 +82,312 @@ impl AnthropicConfig {
             model,
             max_tokens: 4096,
             temperature: 0.7,
+            base_url: default_base_url(),
+            system_prompt: None,
+            prompt_caching: default_prompt_caching(),
+            thinking_budget_tokens: None,
         }
     }
+
+    /// Build a config from `ANTHROPIC_API_KEY` and, if set, `ANTHROPIC_BASE_URL`
+    pub fn from_env(model: String) -> Result<Self> {
+        let api_key = std::env::var("ANTHROPIC_API_KEY")
+            .map_err(|_| anyhow!("ANTHROPIC_API_KEY is not set"))?;
+        let mut config = Self::new(api_key, model);
+        if let Ok(base_url) = std::env::var("ANTHROPIC_BASE_URL") {
+            config = config.with_base_url(base_url);
+        }
+        Ok(config)
+    }
+
+    pub fn with_base_url(mut self, base_url: String) -> Self {
+        self.base_url = base_url.trim_end_matches('/').to_string();
+        self
+    }
+
+    pub fn with_max_tokens(mut self, max_tokens: usize) -> Self {
+        self.max_tokens = max_tokens;
+        self
+    }
+
+    pub fn with_prompt_caching(mut self, prompt_caching: bool) -> Self {
+        self.prompt_caching = prompt_caching;
+        self
+    }
+
+    pub fn with_temperature(mut self, temperature: f32) -> Self {
+        self.temperature = temperature;
+        self
+    }
+
+    pub fn with_thinking_budget(mut self, budget_tokens: usize) -> Self {
+        self.thinking_budget_tokens = Some(budget_tokens);
+        self
+    }
+
+    pub fn with_system_prompt(mut self, system_prompt: String) -> Self {
+        self.system_prompt = Some(system_prompt);
+        self
+    }
 }
 
-#[allow(dead_code)]
 pub struct AnthropicClient {
     config: AnthropicConfig,
-    // HTTP client will be added here
+    http: reqwest::Client,
+    active_requests: ActiveRequests,
+    tool_definitions: Vec<ToolDefinition>,
 }
 
 impl AnthropicClient {
     pub fn new(config: AnthropicConfig) -> Self {
         debug!("Creating new Anthropic client for model: {}", config.model);
-        // Keeping trace output minimal
-        Self { config }
+        Self {
+            config,
+            http: reqwest::Client::new(),
+            active_requests: ActiveRequests::new(),
+            tool_definitions: Vec::new(),
+        }
+    }
+
+    /// Attach tool definitions that are sent as native tool specs
+    pub fn with_tools(mut self, tools: Vec<ToolDefinition>) -> Self {
+        self.tool_definitions = tools;
+        self
+    }
+
+    // Serialize the conversation into a Messages API request body
+    fn build_request(&self, context: &ConversationContext, stream: bool) -> Result<Vec<u8>> {
+        let (system_messages, mut messages) = messages::build_messages(context);
+        let cache = self.config.prompt_caching;
+        if cache {
+            messages::apply_cache_breakpoints(&mut messages);
+        }
+
+        let system_parts: Vec<String> = self
+            .config
+            .system_prompt
+            .iter()
+            .cloned()
+            .chain(system_messages)
+            .collect();
+
+        // Thinking doesn't accept a temperature, so leave it at the default
+        let thinking = self
+            .config
+            .thinking_budget_tokens
+            .map(|budget| ThinkingConfig::enabled(budget, self.config.max_tokens));
+
+        let request = MessagesRequest {
+            model: &self.config.model,
+            max_tokens: self.config.max_tokens,
+            messages,
+            system: messages::build_system(&system_parts, cache),
+            temperature: thinking.is_none().then_some(self.config.temperature),
+            thinking,
+            tools: messages::build_tools(&self.tool_definitions, cache),
+            stream,
+        };
+
+        trace!(
+            ">>> RAW REQUEST TO LLM >>>\n{}",
+            serde_json::to_string_pretty(&request).unwrap_or_default()
+        );
+
+        Ok(serde_json::to_vec(&request)?)
+    }
+
+    // POST the request and turn non-success statuses into API errors
+    async fn post_messages(&self, body: Vec<u8>) -> Result<reqwest::Response> {
+        let url = format!("{}/v1/messages", self.config.base_url);
+        debug!("Sending request to Anthropic: {}", url);
+
+        let response = self
+            .http
+            .post(&url)
+            .header("x-api-key", &self.config.api_key)
+            .header("anthropic-version", ANTHROPIC_VERSION)
+            .header("content-type", "application/json")
+            .body(body)
+            .send()
+            .await
+            .map_err(|e| anyhow!(AnthropicError::Http(e.to_string())))?;
+
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response);
+        }
+
+        // Prefer the structured error message when the body has one
+        let body = response.text().await.unwrap_or_default();
+        let message = serde_json::from_str::<ErrorResponse>(&body)
+            .map(|e| format!("{}: {}", e.error.error_type, e.error.message))
+            .unwrap_or(body);
+
+        Err(anyhow!(AnthropicError::ApiError {
+            status: status.as_u16(),
+            message,
+        }))
     }
 }
 
 #[async_trait]
 impl LlmClient for AnthropicClient {
-    async fn send_message(&self, _context: &ConversationContext) -> Result<LlmResponse> {
+    async fn send_message(&self, context: &ConversationContext) -> Result<LlmResponse> {
         debug!("Sending message to Anthropic API");
-        // Keeping trace output minimal
+        let request = self.active_requests.register();
 
-        // Count API calls
-        count!("llm.calls.total");
-        count!("llm.calls.anthropic");
+        let body = self.build_request(context, false)?;
+        let result = request
+            .run(async {
+                match self.post_messages(body).await {
+                    Ok(response) => response
+                        .text()
+                        .await
+                        .map_err(|e| anyhow!(AnthropicError::Http(e.to_string()))),
+                    Err(e) => Err(e),
+                }
+            })
+            .await;
 
-        // Count tokens (placeholder logic - in real implementation would count actual tokens)
-        let input_tokens = _context.messages.len() * 100; // Rough estimate
-        count!("llm.tokens.input", input_tokens as u64);
+        // A cancelled request has already been dropped, closing the connection
+        let Some(result) = result else {
+            debug!("Request {} was cancelled", request.id());
+            count!("llm.cancelled");
+            return Err(anyhow!(AnthropicError::Cancelled));
+        };
 
-        // Time the API call
-        let response = time!("llm.response_time.anthropic", {
-            // Placeholder implementation
-            info!("Using placeholder Anthropic implementation");
-
-            LlmResponse {
-                id: "resp_123".to_string(),
-                content: "This is a placeholder response from Anthropic.".to_string(),
-                tool_calls: Vec::new(),
-            }
-        });
-
-        // Count output tokens (placeholder logic)
-        let output_tokens = response.content.len() / 4; // Very rough estimate
-        count!("llm.tokens.output", output_tokens as u64);
+        let response_str = result.inspect_err(|e| error!("Anthropic API error: {}", e))?;
+        trace!("<<< RAW RESPONSE FROM LLM <<<\n{}", response_str);
 
+        let response = serde_json::from_str::<MessagesResponse>(&response_str).map_err(|e| {
+            count!("llm.errors.parsing");
+            anyhow!(AnthropicError::ResponseParseError(e.to_string()))
+        })?;
         debug!(
-            "Received response from Anthropic API with ID: {}",
-            response.id
+            "Received response from Anthropic API with ID: {} (stop reason: {:?})",
+            response.id, response.stop_reason
         );
-        // Keeping trace output minimal
+
+        let response = messages::parse_response(response);
+
+        response
+            .usage
+            .unwrap_or_else(|| TokenUsage::estimate(context, &response.content))
+            .record_metrics();
 
         Ok(response)
     }
 
     async fn stream_message(
         &self,
-        _context: &ConversationContext,
+        context: &ConversationContext,
     ) -> Result<Box<dyn Stream<Item = Result<StreamChunk>> + Unpin + Send>> {
-        debug!("Attempting to stream message from Anthropic API");
-        // Keeping trace output minimal
+        debug!("Streaming message from Anthropic API");
+        let request = self.active_requests.register();
 
-        // Placeholder implementation
-        warn!("Streaming not yet implemented for Anthropic client");
-        error!("Streaming API call will fail with unimplemented error");
+        let body = self.build_request(context, true)?;
+        let Some(response) = request.run(self.post_messages(body)).await else {
+            count!("llm.cancelled");
+            return Err(anyhow!(AnthropicError::Cancelled));
+        };
+        let response = response?;
 
-        unimplemented!("Streaming not yet implemented for Anthropic")
-    }
+        // Create a channel for the stream
+        let (tx, rx) = tokio::sync::mpsc::channel::<Result<StreamChunk>>(100);
+
+        // Spawn a task to read server-sent events and forward chunks as they arrive
+        tokio::spawn(async move {
+            let mut decoder = ClaudeStreamDecoder::new(request.id());
+            let mut parser = SseParser::new();
+            let mut body = response.bytes_stream();
+            let mut failed = false;
+
+            'events: loop {
+                // Dropping the body on cancellation closes the connection
+                let bytes = tokio::select! {
+                    biased;
+                    _ = request.cancelled() => {
+                        debug!("Request {} was cancelled", request.id());
+                        count!("llm.cancelled");
+                        let _ = tx.send(Err(anyhow!(AnthropicError::Cancelled))).await;
+                        failed = true;
+                        break;
+                    }
+                    _ = tx.closed() => {
+                        debug!("Stream receiver dropped, stopping");
+                        break;
+                    }
+                    bytes = body.next() => match bytes {
+                        Some(bytes) => bytes,
+                        None => break,
+                    },
+                };
 
-    fn cancel_request(&self, _request_id: &str) -> Result<()> {
-        debug!("Attempting to cancel Anthropic request: {}", _request_id);
+                let bytes = match bytes {
+                    Ok(bytes) => bytes,
+                    Err(e) => {
+                        error!("Anthropic stream error: {}", e);
+                        let _ = tx
+                            .send(Err(anyhow!(AnthropicError::Http(e.to_string()))))
+                            .await;
+                        failed = true;
+                        break;
+                    }
+                };
 
-        // Placeholder implementation
-        info!("Request cancellation for Anthropic is a no-op in this placeholder implementation");
+                for data in parser.push(&bytes) {
+                    trace!("<<< RAW STREAM EVENT FROM LLM <<<\n{}", data);
+                    match decoder.decode(data.as_bytes()) {
+                        Ok(chunks) => {
+                            for chunk in chunks {
+                                if let Some(usage) = &chunk.usage {
+                                    usage.record_metrics();
+                                }
+                                if tx.send(Ok(chunk)).await.is_err() {
+                                    debug!("Stream receiver dropped, stopping");
+                                    break 'events;
+                                }
+                            }
+                        }
+                        Err(e) => {
+                            warn!("Anthropic stream reported an error: {}", e);
+                            let _ = tx
+                                .send(Err(anyhow!(AnthropicError::ResponseParseError(
+                                    e.to_string()
+                                ))))
+                                .await;
+                            failed = true;
+                            break 'events;
+                        }
+                    }
+                }
 
-        Ok(())
+                if decoder.is_complete() {
+                    break;
+                }
+            }
+
+            // Make sure consumers always see a final chunk
+            if !failed {
+                for chunk in decoder.finish() {
+                    if let Some(usage) = &chunk.usage {
+                        usage.record_metrics();
+                    }
+                    let _ = tx.send(Ok(chunk)).await;
+                }
+            }
+        });
+
+        // Return the receiver as a stream
+        Ok(Box::new(ReceiverStream::new(rx)))
+    }
+
+    fn cancel_request(&self, request_id: &str) -> Result<()> {
+        if self.active_requests.cancel(request_id) {
+            debug!("Cancelled request {}", request_id);
+            Ok(())
+        } else {
+            Err(anyhow!("Request ID not found: {}", request_id))
+        }
     }
 }
@@ -0,0 +1,570 @@
+use super::converse::document_name;
+use super::{BedrockClient, ClaudeResponse, ClaudeResponseContent};
+use crate::client_trait::{ThinkingBlock, TokenUsage};
+use anyhow::{anyhow, Result};
+use mcp_core::context::{Attachment, AttachmentKind, ConversationContext, MessageRole};
+use serde_json::{json, Value};
+use tracing::{debug, warn};
+
+/// Model family on Bedrock, which decides the InvokeModel request and response format
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModelFamily {
+    /// Anthropic Claude, the default for model ids that aren't recognised
+    Anthropic,
+    /// Amazon Nova
+    Nova,
+    /// Meta Llama 3 and later
+    Llama,
+    /// Mistral and Mixtral
+    Mistral,
+}
+
+// Model id prefixes, without the geography of cross-region inference profiles
+const FAMILY_PREFIXES: &[(&str, ModelFamily)] = &[
+    ("anthropic.", ModelFamily::Anthropic),
+    ("amazon.nova", ModelFamily::Nova),
+    ("meta.llama", ModelFamily::Llama),
+    ("mistral.", ModelFamily::Mistral),
+];
+
+impl ModelFamily {
+    /// Pick the family from a model id such as `amazon.nova-pro-v1:0`, an inference profile
+    /// such as `us.meta.llama3-3-70b-instruct-v1:0`, or an ARN ending in either
+    pub fn from_model_id(model_id: &str) -> Self {
+        let id = model_id.rsplit('/').next().unwrap_or(model_id);
+        let without_geography = id.split_once('.').map_or("", |(_, rest)| rest);
+
+        [id, without_geography]
+            .iter()
+            .find_map(|id| {
+                FAMILY_PREFIXES
+                    .iter()
+                    .find(|(prefix, _)| id.starts_with(prefix))
+                    .map(|(_, family)| *family)
+            })
+            .unwrap_or(ModelFamily::Anthropic)
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ModelFamily::Anthropic => "anthropic",
+            ModelFamily::Nova => "nova",
+            ModelFamily::Llama => "llama",
+            ModelFamily::Mistral => "mistral",
+        }
+    }
+
+    pub(super) fn adapter(self) -> Box<dyn PayloadAdapter> {
+        match self {
+            ModelFamily::Anthropic => Box::new(AnthropicAdapter),
+            ModelFamily::Nova => Box::new(NovaAdapter),
+            ModelFamily::Llama => Box::new(LlamaAdapter),
+            ModelFamily::Mistral => Box::new(MistralAdapter),
+        }
+    }
+}
+
+/// A reply taken apart by an adapter; its text goes through the same JSON-RPC tool call
+/// extraction for every family
+#[derive(Debug, Default)]
+pub(super) struct ModelReply {
+    pub id: Option<String>,
+    pub text: String,
+    pub usage: Option<TokenUsage>,
+    pub thinking: Vec<ThinkingBlock>,
+}
+
+/// The InvokeModel request and response formats of one model family.
+///
+/// Stream events are translated into Claude streaming events, so that text, tool calls
+/// and usage are all decoded by `ClaudeStreamDecoder` whatever the model.
+pub(super) trait PayloadAdapter: Send + Sync {
+    fn build_payload(&self, client: &BedrockClient, context: &ConversationContext)
+        -> Result<Value>;
+
+    fn parse_response(&self, body: &str) -> Result<ModelReply>;
+
+    fn stream_events(&self, payload: &[u8]) -> Result<Vec<Vec<u8>>>;
+}
+
+/// One turn of the conversation as plain text, with any attachments of the message
+pub(super) struct Turn<'a> {
+    pub role: &'static str,
+    pub text: String,
+    pub attachments: &'a [Attachment],
+}
+
+/// The conversation as user and assistant turns. JSON-RPC tool results become assistant
+/// turns describing the result, as the text-based tool protocol expects.
+pub(super) fn conversation_turns(context: &ConversationContext) -> Vec<Turn<'_>> {
+    let mut turns = Vec::new();
+    for message in &context.messages {
+        let role = match message.role {
+            MessageRole::User => "user",
+            MessageRole::Assistant => "assistant",
+            // System messages are handled separately
+            MessageRole::System => continue,
+            MessageRole::Tool => {
+                // Parse the tool message if it's in JSON-RPC format
+                let result = serde_json::from_str::<Value>(&message.content)
+                    .ok()
+                    .and_then(|value| value.get("result").cloned());
+                if let Some(result) = result {
+                    turns.push(Turn {
+                        role: "assistant",
+                        text: format!(
+                            "I've received the following tool result:\n```json\n{}\n```\n\nNow I need to provide a direct answer based on this result.",
+                            serde_json::to_string_pretty(&result).unwrap_or_else(|_| result.to_string())
+                        ),
+                        attachments: &[],
+                    });
+                    continue;
+                }
+
+                // Fallback for non-JSON tool results
+                "assistant"
+            }
+        };
+
+        turns.push(Turn {
+            role,
+            text: message.content.clone(),
+            attachments: message.attachments.as_deref().unwrap_or_default(),
+        });
+    }
+    turns
+}
+
+// Join consecutive turns of the same role, for formats that require roles to alternate
+fn alternating_turns(turns: Vec<Turn<'_>>) -> Vec<Turn<'_>> {
+    let mut merged: Vec<Turn> = Vec::with_capacity(turns.len());
+    for turn in turns {
+        match merged.last_mut() {
+            Some(last) if last.role == turn.role && turn.attachments.is_empty() => {
+                last.text = format!("{}\n\n{}", last.text, turn.text);
+            }
+            _ => merged.push(turn),
+        }
+    }
+    merged
+}
+
+// Text-only formats can't carry attachments, so say what was left out
+fn warn_dropped_attachments(family: ModelFamily, turns: &[Turn]) {
+    let dropped: usize = turns.iter().map(|turn| turn.attachments.len()).sum();
+    if dropped > 0 {
+        warn!(
+            "Dropping {} attachment(s): {} models on Bedrock only accept text",
+            dropped,
+            family.as_str()
+        );
+    }
+}
+
+// Usage Bedrock appends to the last event of every InvokeModel stream
+fn invocation_usage(event: &Value) -> Option<TokenUsage> {
+    let metrics = event.get("amazon-bedrock-invocationMetrics")?;
+    Some(TokenUsage::new(
+        metrics["inputTokenCount"].as_u64().unwrap_or_default(),
+        metrics["outputTokenCount"].as_u64().unwrap_or_default(),
+    ))
+}
+
+// Claude events for a text delta and, on the last event, the usage and end of message
+fn claude_events(text: &str, event: &Value) -> Result<Vec<Vec<u8>>> {
+    let mut events = Vec::new();
+    if !text.is_empty() {
+        events.push(serde_json::to_vec(&json!({
+            "type": "content_block_delta",
+            "index": 0,
+            "delta": { "type": "text_delta", "text": text }
+        }))?);
+    }
+    if let Some(usage) = invocation_usage(event) {
+        events.push(serde_json::to_vec(&json!({
+            "type": "message_delta",
+            "delta": {},
+            "usage": {
+                "input_tokens": usage.input_tokens,
+                "output_tokens": usage.output_tokens
+            }
+        }))?);
+        events.push(serde_json::to_vec(&json!({ "type": "message_stop" }))?);
+    }
+    Ok(events)
+}
+
+// Anthropic's messages format, which Bedrock passes through unchanged
+struct AnthropicAdapter;
+
+impl PayloadAdapter for AnthropicAdapter {
+    fn build_payload(
+        &self,
+        client: &BedrockClient,
+        context: &ConversationContext,
+    ) -> Result<Value> {
+        Ok(serde_json::to_value(
+            client.prepare_claude_payload(context),
+        )?)
+    }
+
+    fn parse_response(&self, body: &str) -> Result<ModelReply> {
+        let response: ClaudeResponse = serde_json::from_str(body)?;
+        debug!("Successfully parsed Claude response: {:?}", response.id);
+
+        let text = response
+            .content
+            .iter()
+            .filter(|c| c.content_type == "text")
+            .map(|c| c.text.clone())
+            .collect::<Vec<String>>()
+            .join("\n");
+        let usage = TokenUsage::new(
+            response.usage.input_tokens as u64,
+            response.usage.output_tokens as u64,
+        )
+        .with_cache(
+            response.usage.cache_read_input_tokens as u64,
+            response.usage.cache_creation_input_tokens as u64,
+        );
+
+        Ok(ModelReply {
+            thinking: response
+                .content
+                .iter()
+                .filter_map(ClaudeResponseContent::thinking_block)
+                .collect(),
+            id: Some(response.id),
+            text,
+            usage: Some(usage),
+        })
+    }
+
+    fn stream_events(&self, payload: &[u8]) -> Result<Vec<Vec<u8>>> {
+        Ok(vec![payload.to_vec()])
+    }
+}
+
+// Amazon Nova's messages-v1 format
+struct NovaAdapter;
+
+impl NovaAdapter {
+    fn content(turn: &Turn) -> Result<Vec<Value>> {
+        let mut content = Vec::new();
+        for attachment in turn.attachments {
+            let block = match attachment.kind {
+                AttachmentKind::Image => json!({
+                    "image": {
+                        "format": attachment.format(),
+                        "source": { "bytes": attachment.data }
+                    }
+                }),
+                AttachmentKind::Document => json!({
+                    "document": {
+                        "format": attachment.format(),
+                        "name": document_name(&attachment.name),
+                        "source": { "bytes": attachment.data }
+                    }
+                }),
+            };
+            content.push(block);
+        }
+        if !turn.text.is_empty() {
+            content.push(json!({ "text": turn.text }));
+        }
+        if content.is_empty() {
+            return Err(anyhow!("Empty {} message in conversation", turn.role));
+        }
+        Ok(content)
+    }
+}
+
+impl PayloadAdapter for NovaAdapter {
+    fn build_payload(
+        &self,
+        client: &BedrockClient,
+        context: &ConversationContext,
+    ) -> Result<Value> {
+        let config = &client.config;
+        let messages = alternating_turns(conversation_turns(context))
+            .iter()
+            .map(|turn| Ok(json!({ "role": turn.role, "content": Self::content(turn)? })))
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(json!({
+            "schemaVersion": "messages-v1",
+            "system": [{ "text": client.build_system_prompt(context, true) }],
+            "messages": messages,
+            "inferenceConfig": {
+                "maxTokens": config.max_tokens,
+                "temperature": config.temperature,
+                "topP": config.top_p
+            }
+        }))
+    }
+
+    fn parse_response(&self, body: &str) -> Result<ModelReply> {
+        let response: Value = serde_json::from_str(body)?;
+        let content = response["output"]["message"]["content"]
+            .as_array()
+            .ok_or_else(|| anyhow!("Nova response has no output message"))?;
+
+        let text = content
+            .iter()
+            .filter_map(|block| block["text"].as_str())
+            .collect::<Vec<_>>()
+            .join("\n");
+        let usage = response.get("usage").map(|usage| {
+            TokenUsage::new(
+                usage["inputTokens"].as_u64().unwrap_or_default(),
+                usage["outputTokens"].as_u64().unwrap_or_default(),
+            )
+            .with_cache(
+                usage["cacheReadInputTokenCount"]
+                    .as_u64()
+                    .unwrap_or_default(),
+                usage["cacheWriteInputTokenCount"]
+                    .as_u64()
+                    .unwrap_or_default(),
+            )
+        });
+
+        Ok(ModelReply {
+            text,
+            usage,
+            ..Default::default()
+        })
+    }
+
+    fn stream_events(&self, payload: &[u8]) -> Result<Vec<Vec<u8>>> {
+        let event: Value = serde_json::from_slice(payload)?;
+        let text = event["contentBlockDelta"]["delta"]["text"]
+            .as_str()
+            .unwrap_or_default();
+        claude_events(text, &event)
+    }
+}
+
+// Meta's Llama 3 prompt format, rendered into a single prompt string
+struct LlamaAdapter;
+
+// Llama on Bedrock rejects longer generations
+const LLAMA_MAX_GEN_LEN: usize = 2048;
+
+impl PayloadAdapter for LlamaAdapter {
+    fn build_payload(
+        &self,
+        client: &BedrockClient,
+        context: &ConversationContext,
+    ) -> Result<Value> {
+        let config = &client.config;
+        let turns = conversation_turns(context);
+        warn_dropped_attachments(ModelFamily::Llama, &turns);
+
+        let mut prompt = format!(
+            "<|begin_of_text|><|start_header_id|>system<|end_header_id|>\n\n{}<|eot_id|>",
+            client.build_system_prompt(context, true)
+        );
+        for turn in &turns {
+            prompt.push_str(&format!(
+                "<|start_header_id|>{}<|end_header_id|>\n\n{}<|eot_id|>",
+                turn.role, turn.text
+            ));
+        }
+        prompt.push_str("<|start_header_id|>assistant<|end_header_id|>\n\n");
+
+        Ok(json!({
+            "prompt": prompt,
+            "max_gen_len": config.max_tokens.min(LLAMA_MAX_GEN_LEN),
+            "temperature": config.temperature,
+            "top_p": config.top_p
+        }))
+    }
+
+    fn parse_response(&self, body: &str) -> Result<ModelReply> {
+        let response: Value = serde_json::from_str(body)?;
+        let text = response["generation"]
+            .as_str()
+            .ok_or_else(|| anyhow!("Llama response has no generation"))?;
+
+        Ok(ModelReply {
+            text: text.trim().to_string(),
+            usage: Some(TokenUsage::new(
+                response["prompt_token_count"].as_u64().unwrap_or_default(),
+                response["generation_token_count"]
+                    .as_u64()
+                    .unwrap_or_default(),
+            )),
+            ..Default::default()
+        })
+    }
+
+    fn stream_events(&self, payload: &[u8]) -> Result<Vec<Vec<u8>>> {
+        let event: Value = serde_json::from_slice(payload)?;
+        claude_events(event["generation"].as_str().unwrap_or_default(), &event)
+    }
+}
+
+// Mistral's instruction format, which has no system role, so the system prompt leads the
+// first instruction
+struct MistralAdapter;
+
+impl PayloadAdapter for MistralAdapter {
+    fn build_payload(
+        &self,
+        client: &BedrockClient,
+        context: &ConversationContext,
+    ) -> Result<Value> {
+        let config = &client.config;
+        let turns = alternating_turns(conversation_turns(context));
+        warn_dropped_attachments(ModelFamily::Mistral, &turns);
+
+        let mut system = Some(client.build_system_prompt(context, true));
+        let mut prompt = String::from("<s>");
+        for turn in &turns {
+            if turn.role == "user" {
+                match system.take() {
+                    Some(system) => {
+                        prompt.push_str(&format!("[INST] {}\n\n{} [/INST]", system, turn.text))
+                    }
+                    None => prompt.push_str(&format!("[INST] {} [/INST]", turn.text)),
+                }
+            } else {
+                prompt.push_str(&format!("{}</s>", turn.text));
+            }
+        }
+
+        Ok(json!({
+            "prompt": prompt,
+            "max_tokens": config.max_tokens,
+            "temperature": config.temperature,
+            "top_p": config.top_p
+        }))
+    }
+
+    fn parse_response(&self, body: &str) -> Result<ModelReply> {
+        let response: Value = serde_json::from_str(body)?;
+        let outputs = response["outputs"]
+            .as_array()
+            .ok_or_else(|| anyhow!("Mistral response has no outputs"))?;
+
+        // Usage only comes back in HTTP headers, so it is left to be estimated
+        Ok(ModelReply {
+            text: outputs
+                .iter()
+                .filter_map(|output| output["text"].as_str())
+                .collect::<String>()
+                .trim()
+                .to_string(),
+            ..Default::default()
+        })
+    }
+
+    fn stream_events(&self, payload: &[u8]) -> Result<Vec<Vec<u8>>> {
+        let event: Value = serde_json::from_slice(payload)?;
+        let text = event["outputs"][0]["text"].as_str().unwrap_or_default();
+        claude_events(text, &event)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bedrock::BedrockConfig;
+    use crate::cancel::ActiveRequests;
+    use crate::schema::McpSchemaManager;
+    use crate::streaming::ClaudeStreamDecoder;
+    use mcp_core::prompts::PromptManager;
+
+    #[test]
+    fn test_family_from_model_id() {
+        let cases = [
+            ("anthropic.claude-3-sonnet-20240229-v1:0", ModelFamily::Anthropic),
+            ("us.anthropic.claude-3-7-sonnet-20250219-v1:0", ModelFamily::Anthropic),
+            ("amazon.nova-pro-v1:0", ModelFamily::Nova),
+            ("us.amazon.nova-lite-v1:0", ModelFamily::Nova),
+            ("meta.llama3-1-70b-instruct-v1:0", ModelFamily::Llama),
+            (
+                "arn:aws:bedrock:us-east-1:123456789012:inference-profile/us.meta.llama3-3-70b-instruct-v1:0",
+                ModelFamily::Llama,
+            ),
+            ("mistral.mistral-large-2407-v1:0", ModelFamily::Mistral),
+            ("some.unknown-model", ModelFamily::Anthropic),
+        ];
+        for (model_id, family) in cases {
+            assert_eq!(ModelFamily::from_model_id(model_id), family, "{}", model_id);
+        }
+    }
+
+    #[test]
+    fn test_nova_response() {
+        let reply = NovaAdapter
+            .parse_response(
+                r#"{"output":{"message":{"role":"assistant","content":[{"text":"Hello"}]}},"stopReason":"end_turn","usage":{"inputTokens":12,"outputTokens":3}}"#,
+            )
+            .unwrap();
+        assert_eq!(reply.text, "Hello");
+        assert_eq!(reply.usage, Some(TokenUsage::new(12, 3)));
+    }
+
+    // Stream events of every family end up as text, tool calls and usage from one decoder
+    #[test]
+    fn test_llama_stream_decodes_tool_calls() {
+        let events = [
+            json!({"generation": "{\"jsonrpc\": \"2.0\", \"method\": \"mcp.tool_call\", "}),
+            json!({"generation": "\"params\": {\"name\": \"shell\", \"parameters\": {\"command\": \"ls\"}}, \"id\": \"call_1\"}"}),
+            json!({
+                "generation": "",
+                "stop_reason": "stop",
+                "amazon-bedrock-invocationMetrics": {"inputTokenCount": 40, "outputTokenCount": 25}
+            }),
+        ];
+
+        let mut decoder = ClaudeStreamDecoder::new("request");
+        let mut chunks = Vec::new();
+        for event in events {
+            let payload = serde_json::to_vec(&event).unwrap();
+            for claude_event in LlamaAdapter.stream_events(&payload).unwrap() {
+                chunks.extend(decoder.decode(&claude_event).unwrap());
+            }
+        }
+
+        let tool_call = chunks
+            .iter()
+            .find_map(|chunk| chunk.tool_call.as_ref())
+            .unwrap();
+        assert_eq!(tool_call.tool, "shell");
+        assert_eq!(tool_call.params["command"], "ls");
+
+        let last = chunks.last().unwrap();
+        assert!(last.is_complete);
+        assert_eq!(last.usage, Some(TokenUsage::new(40, 25)));
+    }
+
+    // The system prompt leads the first instruction and assistant turns close with </s>
+    #[test]
+    fn test_mistral_prompt() {
+        let client = BedrockClient {
+            client: None,
+            config: BedrockConfig::new("mistral.mistral-large-2407-v1:0"),
+            schema_manager: McpSchemaManager::new(),
+            prompt_manager: PromptManager::new(),
+            active_requests: ActiveRequests::new(),
+            tools_documentation: None,
+            tool_definitions: Vec::new(),
+        };
+
+        let mut context = ConversationContext::new();
+        context.add_user_message("First question");
+        context.add_assistant_message("First answer");
+        context.add_user_message("Second question");
+
+        let payload = MistralAdapter.build_payload(&client, &context).unwrap();
+        let prompt = payload["prompt"].as_str().unwrap();
+        assert!(prompt.starts_with("<s>[INST] "));
+        assert!(prompt.contains("Model Context Protocol (MCP)"));
+        assert!(prompt
+            .ends_with("\n\nFirst question [/INST]First answer</s>[INST] Second question [/INST]"));
+        assert_eq!(prompt.matches("[INST]").count(), 2);
+    }
+}
@@ -0,0 +1,820 @@
+use crate::client_trait::{
+    LlmResponse, StreamChunk, ThinkingBlock, TokenUsage, ToolCall as ClientToolCall, ToolDefinition,
+};
+use crate::prompt_cache;
+use anyhow::{anyhow, Result};
+use aws_sdk_bedrockruntime::operation::converse::ConverseOutput;
+use aws_sdk_bedrockruntime::types::{
+    CachePointBlock, CachePointType, ContentBlock, ContentBlockDelta, ContentBlockDeltaEvent,
+    ContentBlockStart, ConversationRole, ConverseOutput as ConverseOutputMessage,
+    ConverseStreamOutput, DocumentBlock, DocumentFormat, DocumentSource, ImageBlock, ImageFormat,
+    ImageSource, Message, ReasoningContentBlock, ReasoningContentBlockDelta, ReasoningTextBlock,
+    Tool, ToolConfiguration, ToolInputSchema, ToolResultBlock, ToolResultContentBlock,
+    ToolResultStatus, ToolSpecification, ToolUseBlock,
+};
+use aws_smithy_types::{Blob, Document, Number};
+use mcp_core::context::{Attachment, AttachmentKind, ConversationContext, MessageRole};
+use serde_json::Value;
+use std::collections::HashMap;
+use tracing::debug;
+
+/// Convert a JSON value into the document type used by the Converse API
+pub fn json_to_document(value: &Value) -> Document {
+    match value {
+        Value::Null => Document::Null,
+        Value::Bool(b) => Document::Bool(*b),
+        Value::Number(n) => {
+            if let Some(u) = n.as_u64() {
+                Document::Number(Number::PosInt(u))
+            } else if let Some(i) = n.as_i64() {
+                Document::Number(Number::NegInt(i))
+            } else {
+                Document::Number(Number::Float(n.as_f64().unwrap_or_default()))
+            }
+        }
+        Value::String(s) => Document::String(s.clone()),
+        Value::Array(items) => Document::Array(items.iter().map(json_to_document).collect()),
+        Value::Object(map) => Document::Object(
+            map.iter()
+                .map(|(k, v)| (k.clone(), json_to_document(v)))
+                .collect::<HashMap<_, _>>(),
+        ),
+    }
+}
+
+/// Convert a Converse API document back into a JSON value
+pub fn document_to_json(document: &Document) -> Value {
+    match document {
+        Document::Null => Value::Null,
+        Document::Bool(b) => Value::Bool(*b),
+        Document::Number(Number::PosInt(u)) => Value::from(*u),
+        Document::Number(Number::NegInt(i)) => Value::from(*i),
+        Document::Number(Number::Float(f)) => serde_json::Number::from_f64(*f)
+            .map(Value::Number)
+            .unwrap_or(Value::Null),
+        Document::String(s) => Value::String(s.clone()),
+        Document::Array(items) => Value::Array(items.iter().map(document_to_json).collect()),
+        Document::Object(map) => Value::Object(
+            map.iter()
+                .map(|(k, v)| (k.clone(), document_to_json(v)))
+                .collect(),
+        ),
+    }
+}
+
+/// A `cachePoint` block; everything before it in the request is cached as one prefix
+pub fn cache_point() -> Result<CachePointBlock> {
+    CachePointBlock::builder()
+        .r#type(CachePointType::Default)
+        .build()
+        .map_err(|e| anyhow!("Invalid cache point: {}", e))
+}
+
+/// Build the `toolConfig` block from tool definitions, ending with a cache point if requested
+pub fn build_tool_config(tools: &[ToolDefinition], cache: bool) -> Result<ToolConfiguration> {
+    let mut specs = Vec::with_capacity(tools.len());
+    for tool in tools {
+        let spec = ToolSpecification::builder()
+            .name(&tool.name)
+            .description(&tool.description)
+            .input_schema(ToolInputSchema::Json(json_to_document(&tool.input_schema)))
+            .build()
+            .map_err(|e| anyhow!("Invalid tool specification for {}: {}", tool.name, e))?;
+        specs.push(Tool::ToolSpec(spec));
+    }
+    if cache {
+        specs.push(Tool::CachePoint(cache_point()?));
+    }
+
+    ToolConfiguration::builder()
+        .set_tools(Some(specs))
+        .build()
+        .map_err(|e| anyhow!("Invalid tool configuration: {}", e))
+}
+
+/// Convert the conversation into Converse API messages.
+///
+/// Tool results are sent back as `toolResult` blocks in a user turn, and consecutive
+/// messages with the same role are merged since the API requires alternating roles.
+/// With `cache` set, cache points end the history prefix messages.
+pub fn build_converse_messages(context: &ConversationContext, cache: bool) -> Result<Vec<Message>> {
+    let mut turns: Vec<(ConversationRole, Vec<ContentBlock>)> = Vec::new();
+
+    for message in &context.messages {
+        let (role, blocks) = match message.role {
+            // System messages are handled separately, as in the invoke_model payload
+            MessageRole::System => continue,
+            MessageRole::User => {
+                // Attachments go before the text that asks about them
+                let mut blocks = Vec::new();
+                for attachment in message.attachments.iter().flatten() {
+                    blocks.push(attachment_block(attachment)?);
+                }
+                blocks.extend(text_blocks(&message.content));
+                (ConversationRole::User, blocks)
+            }
+            MessageRole::Assistant => {
+                // Thinking has to come back first, unchanged, for tool use to continue
+                let mut blocks = Vec::new();
+                for thinking in message.thinking.iter().flatten() {
+                    blocks.push(reasoning_block(thinking)?);
+                }
+                blocks.extend(text_blocks(&message.content));
+                for call in message.tool_calls.iter().flatten() {
+                    if call.call_id.is_empty() {
+                        continue;
+                    }
+                    let tool_use = ToolUseBlock::builder()
+                        .tool_use_id(&call.call_id)
+                        .name(&call.tool_id)
+                        .input(json_to_document(&call.parameters))
+                        .build()
+                        .map_err(|e| anyhow!("Invalid tool use block: {}", e))?;
+                    blocks.push(ContentBlock::ToolUse(tool_use));
+                }
+                (ConversationRole::Assistant, blocks)
+            }
+            MessageRole::Tool => {
+                let results: Vec<_> = message
+                    .tool_results
+                    .iter()
+                    .flatten()
+                    .filter(|r| !r.call_id.is_empty())
+                    .collect();
+
+                if results.is_empty() {
+                    // A JSON-RPC era tool message without a call id, pass it along as text
+                    (
+                        ConversationRole::User,
+                        text_blocks(&format!("Tool result:\n{}", message.content)),
+                    )
+                } else {
+                    let mut blocks = Vec::with_capacity(results.len());
+                    for result in results {
+                        blocks.push(ContentBlock::ToolResult(tool_result_block(
+                            &result.call_id,
+                            &result.result,
+                        )?));
+                    }
+                    (ConversationRole::User, blocks)
+                }
+            }
+        };
+
+        if blocks.is_empty() {
+            continue;
+        }
+
+        match turns.last_mut() {
+            Some((last_role, last_blocks)) if *last_role == role => last_blocks.extend(blocks),
+            _ => turns.push((role, blocks)),
+        }
+    }
+
+    if cache {
+        for index in prompt_cache::history_breakpoints(turns.len()) {
+            turns[index]
+                .1
+                .push(ContentBlock::CachePoint(cache_point()?));
+        }
+    }
+
+    turns
+        .into_iter()
+        .map(|(role, blocks)| {
+            Message::builder()
+                .role(role)
+                .set_content(Some(blocks))
+                .build()
+                .map_err(|e| anyhow!("Invalid Converse message: {}", e))
+        })
+        .collect()
+}
+
+/// Convert a Converse response into an `LlmResponse`, mapping `toolUse` blocks to tool calls
+pub fn parse_converse_output(output: &ConverseOutput, fallback_id: &str) -> Result<LlmResponse> {
+    let message = match output.output() {
+        Some(ConverseOutputMessage::Message(message)) => message,
+        _ => return Err(anyhow!("Converse response did not contain a message")),
+    };
+
+    let mut text = Vec::new();
+    let mut tool_calls = Vec::new();
+    let mut thinking = Vec::new();
+
+    for block in message.content() {
+        match block {
+            ContentBlock::Text(t) => text.push(t.clone()),
+            ContentBlock::ReasoningContent(reasoning) => match reasoning {
+                ReasoningContentBlock::ReasoningText(reasoning_text) => {
+                    thinking.push(ThinkingBlock::Thinking {
+                        thinking: reasoning_text.text().to_string(),
+                        signature: reasoning_text.signature().unwrap_or_default().to_string(),
+                    })
+                }
+                ReasoningContentBlock::RedactedContent(data) => {
+                    thinking.push(ThinkingBlock::RedactedThinking {
+                        data: base64::encode(data.as_ref()),
+                    })
+                }
+                other => debug!("Ignoring unsupported reasoning block: {:?}", other),
+            },
+            ContentBlock::ToolUse(tool_use) => {
+                debug!("Received native tool use: {}", tool_use.name());
+                tool_calls.push(ClientToolCall {
+                    id: tool_use.tool_use_id().to_string(),
+                    tool: tool_use.name().to_string(),
+                    params: document_to_json(tool_use.input()),
+                });
+            }
+            other => debug!("Ignoring unsupported Converse content block: {:?}", other),
+        }
+    }
+
+    let usage = output.usage().map(token_usage);
+
+    Ok(LlmResponse {
+        id: fallback_id.to_string(),
+        content: text.join("\n"),
+        tool_calls,
+        usage,
+        thinking,
+    })
+}
+
+// Usage as reported by Converse and ConverseStream
+fn token_usage(usage: &aws_sdk_bedrockruntime::types::TokenUsage) -> TokenUsage {
+    TokenUsage::new(
+        usage.input_tokens().max(0) as u64,
+        usage.output_tokens().max(0) as u64,
+    )
+    .with_cache(
+        usage.cache_read_input_tokens().unwrap_or(0).max(0) as u64,
+        usage.cache_write_input_tokens().unwrap_or(0).max(0) as u64,
+    )
+}
+
+// A toolUse block whose input is still arriving
+struct PendingToolUse {
+    id: String,
+    name: String,
+    input_json: String,
+}
+
+/// Turns ConverseStream events into `StreamChunk`s.
+///
+/// Text and reasoning deltas are emitted as they arrive. A `toolUse` block is reported when
+/// it stops and its streamed input is complete, and each finished reasoning block is reported
+/// with its signature the same way. Usage arrives in the metadata event after the message
+/// stops, so the final chunk is only emitted by `finish`, once the stream has ended.
+pub struct ConverseStreamDecoder {
+    request_id: String,
+    pending_tool_uses: HashMap<i32, PendingToolUse>,
+    pending_thinking: HashMap<i32, ThinkingBlock>,
+    usage: Option<TokenUsage>,
+    complete: bool,
+}
+
+impl ConverseStreamDecoder {
+    pub fn new(request_id: &str) -> Self {
+        Self {
+            request_id: request_id.to_string(),
+            pending_tool_uses: HashMap::new(),
+            pending_thinking: HashMap::new(),
+            usage: None,
+            complete: false,
+        }
+    }
+
+    /// Decode one stream event, returning the chunks it produces
+    pub fn decode(&mut self, event: &ConverseStreamOutput) -> Result<Vec<StreamChunk>> {
+        let mut chunks = Vec::new();
+        match event {
+            ConverseStreamOutput::ContentBlockStart(start) => {
+                if let Some(ContentBlockStart::ToolUse(tool_use)) = start.start() {
+                    self.pending_tool_uses.insert(
+                        start.content_block_index(),
+                        PendingToolUse {
+                            id: tool_use.tool_use_id().to_string(),
+                            name: tool_use.name().to_string(),
+                            input_json: String::new(),
+                        },
+                    );
+                }
+            }
+            ConverseStreamOutput::ContentBlockDelta(delta) => {
+                let index = delta.content_block_index();
+                match delta.delta() {
+                    Some(ContentBlockDelta::Text(text)) if !text.is_empty() => {
+                        chunks.push(self.chunk(text.clone(), None));
+                    }
+                    Some(ContentBlockDelta::ToolUse(tool_use)) => {
+                        if let Some(pending) = self.pending_tool_uses.get_mut(&index) {
+                            pending.input_json.push_str(tool_use.input());
+                        }
+                    }
+                    Some(ContentBlockDelta::ReasoningContent(reasoning)) => {
+                        if let Some(chunk) = self.reasoning_delta(index, reasoning) {
+                            chunks.push(chunk);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            ConverseStreamOutput::ContentBlockStop(stop) => {
+                let index = stop.content_block_index();
+                if let Some(pending) = self.pending_tool_uses.remove(&index) {
+                    chunks.push(self.tool_call(pending)?);
+                } else if let Some(block) = self.pending_thinking.remove(&index) {
+                    let mut chunk = self.chunk(String::new(), None);
+                    chunk.thinking_block = Some(block);
+                    chunks.push(chunk);
+                }
+            }
+            ConverseStreamOutput::MessageStop(stop) => {
+                debug!("Converse stream stopped: {}", stop.stop_reason().as_str());
+            }
+            ConverseStreamOutput::Metadata(metadata) => {
+                self.usage = metadata.usage().map(token_usage);
+            }
+            _ => {}
+        }
+        Ok(chunks)
+    }
+
+    /// Emit the final chunk, with the usage for the request, if not already emitted
+    pub fn finish(&mut self) -> Vec<StreamChunk> {
+        if self.complete {
+            return Vec::new();
+        }
+        self.complete = true;
+        let mut last = self.chunk(String::new(), None);
+        last.is_complete = true;
+        last.usage = self.usage;
+        vec![last]
+    }
+
+    // Accumulate a reasoning block, returning the text to show as it streams in
+    fn reasoning_delta(
+        &mut self,
+        index: i32,
+        delta: &ReasoningContentBlockDelta,
+    ) -> Option<StreamChunk> {
+        let block = self
+            .pending_thinking
+            .entry(index)
+            .or_insert_with(|| ThinkingBlock::Thinking {
+                thinking: String::new(),
+                signature: String::new(),
+            });
+        match (delta, block) {
+            (ReasoningContentBlockDelta::Text(text), ThinkingBlock::Thinking { thinking, .. }) => {
+                thinking.push_str(text);
+                let mut chunk = self.chunk(String::new(), None);
+                chunk.thinking = text.clone();
+                return Some(chunk);
+            }
+            (
+                ReasoningContentBlockDelta::Signature(part),
+                ThinkingBlock::Thinking { signature, .. },
+            ) => signature.push_str(part),
+            (ReasoningContentBlockDelta::RedactedContent(data), block) => {
+                *block = ThinkingBlock::RedactedThinking {
+                    data: base64::encode(data.as_ref()),
+                };
+            }
+            (other, _) => debug!("Ignoring unsupported reasoning delta: {:?}", other),
+        }
+        None
+    }
+
+    fn tool_call(&self, pending: PendingToolUse) -> Result<StreamChunk> {
+        let params = if pending.input_json.trim().is_empty() {
+            serde_json::json!({})
+        } else {
+            serde_json::from_str(&pending.input_json)
+                .map_err(|e| anyhow!("Invalid input JSON for tool {}: {}", pending.name, e))?
+        };
+
+        debug!("Received native tool use: {}", pending.name);
+        Ok(self.chunk(
+            String::new(),
+            Some(ClientToolCall {
+                id: pending.id,
+                tool: pending.name,
+                params,
+            }),
+        ))
+    }
+
+    fn chunk(&self, content: String, tool_call: Option<ClientToolCall>) -> StreamChunk {
+        StreamChunk {
+            id: self.request_id.clone(),
+            content,
+            is_tool_call: tool_call.is_some(),
+            tool_call,
+            is_complete: false,
+            usage: None,
+            thinking: String::new(),
+            thinking_block: None,
+        }
+    }
+}
+
+/// A ConverseStream text delta, as sent for the text content block at `index`
+pub fn text_delta_event(index: i32, text: &str) -> ConverseStreamOutput {
+    ConverseStreamOutput::ContentBlockDelta(
+        ContentBlockDeltaEvent::builder()
+            .content_block_index(index)
+            .delta(ContentBlockDelta::Text(text.to_string()))
+            .build()
+            .expect("content block index and delta are set"),
+    )
+}
+
+// Map a thinking block back to the reasoning block it came from
+fn reasoning_block(thinking: &ThinkingBlock) -> Result<ContentBlock> {
+    let reasoning = match thinking {
+        ThinkingBlock::Thinking {
+            thinking,
+            signature,
+        } => ReasoningContentBlock::ReasoningText(
+            ReasoningTextBlock::builder()
+                .text(thinking)
+                .set_signature((!signature.is_empty()).then(|| signature.clone()))
+                .build()
+                .map_err(|e| anyhow!("Invalid reasoning block: {}", e))?,
+        ),
+        ThinkingBlock::RedactedThinking { data } => {
+            ReasoningContentBlock::RedactedContent(Blob::new(
+                base64::decode(data).map_err(|e| anyhow!("Invalid redacted thinking: {}", e))?,
+            ))
+        }
+    };
+    Ok(ContentBlock::ReasoningContent(reasoning))
+}
+
+// Wrap non-empty text in a content block
+fn text_blocks(text: &str) -> Vec<ContentBlock> {
+    if text.trim().is_empty() {
+        Vec::new()
+    } else {
+        vec![ContentBlock::Text(text.to_string())]
+    }
+}
+
+// Map an attachment to an image or document block carrying its raw bytes
+fn attachment_block(attachment: &Attachment) -> Result<ContentBlock> {
+    let bytes = Blob::new(attachment.bytes()?);
+    match attachment.kind {
+        AttachmentKind::Image => ImageBlock::builder()
+            .format(ImageFormat::from(attachment.format()))
+            .source(ImageSource::Bytes(bytes))
+            .build()
+            .map(ContentBlock::Image)
+            .map_err(|e| anyhow!("Invalid image block for {}: {}", attachment.name, e)),
+        AttachmentKind::Document => DocumentBlock::builder()
+            .format(DocumentFormat::from(attachment.format()))
+            .name(document_name(&attachment.name))
+            .source(DocumentSource::Bytes(bytes))
+            .build()
+            .map(ContentBlock::Document)
+            .map_err(|e| anyhow!("Invalid document block for {}: {}", attachment.name, e)),
+    }
+}
+
+/// Document names may only contain alphanumerics, single spaces, hyphens, parentheses and
+/// square brackets, so replace anything else (such as the extension dot) with a hyphen
+pub fn document_name(file_name: &str) -> String {
+    let mut name = String::with_capacity(file_name.len());
+    for c in file_name.chars() {
+        let c = if c.is_ascii_alphanumeric() || "-()[] ".contains(c) {
+            c
+        } else {
+            '-'
+        };
+        if !(c == ' ' && name.ends_with(' ')) {
+            name.push(c);
+        }
+    }
+    name
+}
+
+// Build a toolResult block, flagging failed tool executions as errors
+fn tool_result_block(call_id: &str, result: &Value) -> Result<ToolResultBlock> {
+    let failed = matches!(
+        result.get("status").and_then(|s| s.as_str()),
+        Some("Failure") | Some("Timeout")
+    );
+
+    // The JSON content block must be an object
+    let content = if result.is_object() {
+        result.clone()
+    } else {
+        serde_json::json!({ "result": result })
+    };
+
+    ToolResultBlock::builder()
+        .tool_use_id(call_id)
+        .content(ToolResultContentBlock::Json(json_to_document(&content)))
+        .status(if failed {
+            ToolResultStatus::Error
+        } else {
+            ToolResultStatus::Success
+        })
+        .build()
+        .map_err(|e| anyhow!("Invalid tool result block: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mcp_core::context::{ToolCall, ToolResult};
+    use serde_json::json;
+
+    #[test]
+    fn test_document_round_trip() {
+        let value = json!({
+            "command": "ls -la",
+            "timeout_ms": 5000,
+            "offset": -3,
+            "ratio": 0.5,
+            "flags": [true, null, "x"]
+        });
+
+        assert_eq!(document_to_json(&json_to_document(&value)), value);
+    }
+
+    #[test]
+    fn test_tool_turns_map_to_tool_use_and_result_blocks() {
+        let mut context = ConversationContext::new();
+        context.add_user_message("List files");
+        context.add_assistant_tool_calls(
+            "",
+            vec![ToolCall {
+                tool_id: "shell".to_string(),
+                parameters: json!({"command": "ls"}),
+                call_id: "tooluse_1".to_string(),
+            }],
+        );
+        context.add_tool_result(
+            "{}",
+            ToolResult {
+                tool_id: "shell".to_string(),
+                result: json!({"status": "Success", "output": {"stdout": "a.txt"}}),
+                call_id: "tooluse_1".to_string(),
+            },
+        );
+        context.add_user_message("Please continue with your response based on the tool results.");
+
+        let messages = build_converse_messages(&context, false).unwrap();
+
+        // user, assistant(toolUse), user(toolResult + follow-up text merged)
+        assert_eq!(messages.len(), 3);
+        assert_eq!(messages[1].role(), &ConversationRole::Assistant);
+        match &messages[1].content()[0] {
+            ContentBlock::ToolUse(tool_use) => {
+                assert_eq!(tool_use.tool_use_id(), "tooluse_1");
+                assert_eq!(tool_use.name(), "shell");
+            }
+            other => panic!("Expected tool use block, got {:?}", other),
+        }
+        assert_eq!(messages[2].role(), &ConversationRole::User);
+        assert_eq!(messages[2].content().len(), 2);
+        assert!(matches!(
+            &messages[2].content()[0],
+            ContentBlock::ToolResult(result) if result.tool_use_id() == "tooluse_1"
+        ));
+    }
+
+    #[test]
+    fn test_thinking_sent_back_as_reasoning_before_tool_use() {
+        let mut context = ConversationContext::new();
+        context.add_user_message("Refactor the parser");
+        context.add_assistant_tool_calls_with_thinking(
+            "",
+            vec![ToolCall {
+                tool_id: "shell".to_string(),
+                parameters: json!({"command": "ls"}),
+                call_id: "tooluse_1".to_string(),
+            }],
+            vec![
+                ThinkingBlock::Thinking {
+                    thinking: "Look at the files first".to_string(),
+                    signature: "sig".to_string(),
+                },
+                ThinkingBlock::RedactedThinking {
+                    data: base64::encode(b"opaque"),
+                },
+            ],
+        );
+
+        let messages = build_converse_messages(&context, false).unwrap();
+        let content = messages[1].content();
+        assert_eq!(content.len(), 3);
+        match &content[0] {
+            ContentBlock::ReasoningContent(ReasoningContentBlock::ReasoningText(text)) => {
+                assert_eq!(text.text(), "Look at the files first");
+                assert_eq!(text.signature(), Some("sig"));
+            }
+            other => panic!("Expected reasoning block, got {:?}", other),
+        }
+        match &content[1] {
+            ContentBlock::ReasoningContent(ReasoningContentBlock::RedactedContent(data)) => {
+                assert_eq!(data.as_ref(), b"opaque");
+            }
+            other => panic!("Expected redacted reasoning block, got {:?}", other),
+        }
+        assert!(matches!(&content[2], ContentBlock::ToolUse(_)));
+    }
+
+    #[test]
+    fn test_attachments_become_image_and_document_blocks() {
+        let mut context = ConversationContext::new();
+        context.add_user_message_with_attachments(
+            "What does this error mean?",
+            vec![
+                Attachment::new(AttachmentKind::Image, "image/jpeg", "error.jpg", b"jpg"),
+                Attachment::new(
+                    AttachmentKind::Document,
+                    "application/pdf",
+                    "design  notes.v2.pdf",
+                    b"pdf",
+                ),
+            ],
+        );
+
+        let messages = build_converse_messages(&context, false).unwrap();
+        let content = messages[0].content();
+
+        assert_eq!(content.len(), 3);
+        match &content[0] {
+            ContentBlock::Image(image) => {
+                assert_eq!(image.format(), &ImageFormat::Jpeg);
+                assert!(
+                    matches!(image.source(), Some(ImageSource::Bytes(b)) if b.as_ref() == b"jpg")
+                );
+            }
+            other => panic!("Expected image block, got {:?}", other),
+        }
+        match &content[1] {
+            ContentBlock::Document(document) => {
+                assert_eq!(document.format(), &DocumentFormat::Pdf);
+                assert_eq!(document.name(), "design notes-v2-pdf");
+            }
+            other => panic!("Expected document block, got {:?}", other),
+        }
+        assert!(matches!(&content[2], ContentBlock::Text(_)));
+    }
+
+    #[test]
+    fn test_cache_points_end_history_prefix() {
+        let mut context = ConversationContext::new();
+        context.add_user_message("First question");
+        context.add_assistant_message("First answer");
+        context.add_user_message("Second question");
+
+        let messages = build_converse_messages(&context, true).unwrap();
+        let ends_with_cache_point: Vec<_> = messages
+            .iter()
+            .map(|m| matches!(m.content().last(), Some(ContentBlock::CachePoint(_))))
+            .collect();
+        assert_eq!(ends_with_cache_point, vec![true, false, true]);
+    }
+
+    #[test]
+    fn test_build_tool_config() {
+        let tools = vec![ToolDefinition {
+            name: "shell".to_string(),
+            description: "Execute shell commands".to_string(),
+            input_schema: json!({
+                "type": "object",
+                "properties": {"command": {"type": "string"}},
+                "required": ["command"]
+            }),
+        }];
+
+        let config = build_tool_config(&tools, false).unwrap();
+        assert_eq!(config.tools().len(), 1);
+        assert!(matches!(&config.tools()[0], Tool::ToolSpec(spec) if spec.name() == "shell"));
+    }
+
+    #[test]
+    fn test_stream_decoder_reports_thinking_text_and_tool_use() {
+        use aws_sdk_bedrockruntime::types::{
+            ContentBlockStartEvent, ContentBlockStopEvent, ConverseStreamMetadataEvent,
+            TokenUsage as ConverseUsage, ToolUseBlockDelta, ToolUseBlockStart,
+        };
+
+        let delta = |index: i32, delta: ContentBlockDelta| {
+            ConverseStreamOutput::ContentBlockDelta(
+                ContentBlockDeltaEvent::builder()
+                    .content_block_index(index)
+                    .delta(delta)
+                    .build()
+                    .unwrap(),
+            )
+        };
+        let stop = |index: i32| {
+            ConverseStreamOutput::ContentBlockStop(
+                ContentBlockStopEvent::builder()
+                    .content_block_index(index)
+                    .build()
+                    .unwrap(),
+            )
+        };
+        let events = vec![
+            delta(
+                0,
+                ContentBlockDelta::ReasoningContent(ReasoningContentBlockDelta::Text(
+                    "List first.".to_string(),
+                )),
+            ),
+            delta(
+                0,
+                ContentBlockDelta::ReasoningContent(ReasoningContentBlockDelta::Signature(
+                    "sig-1".to_string(),
+                )),
+            ),
+            stop(0),
+            text_delta_event(1, "Let me "),
+            text_delta_event(1, "look."),
+            stop(1),
+            ConverseStreamOutput::ContentBlockStart(
+                ContentBlockStartEvent::builder()
+                    .content_block_index(2)
+                    .start(ContentBlockStart::ToolUse(
+                        ToolUseBlockStart::builder()
+                            .tool_use_id("tooluse_1")
+                            .name("shell")
+                            .build()
+                            .unwrap(),
+                    ))
+                    .build()
+                    .unwrap(),
+            ),
+            delta(
+                2,
+                ContentBlockDelta::ToolUse(
+                    ToolUseBlockDelta::builder()
+                        .input("{\"command\": ")
+                        .build()
+                        .unwrap(),
+                ),
+            ),
+            delta(
+                2,
+                ContentBlockDelta::ToolUse(
+                    ToolUseBlockDelta::builder()
+                        .input("\"ls\"}")
+                        .build()
+                        .unwrap(),
+                ),
+            ),
+            stop(2),
+            ConverseStreamOutput::Metadata(
+                ConverseStreamMetadataEvent::builder()
+                    .usage(
+                        ConverseUsage::builder()
+                            .input_tokens(12)
+                            .output_tokens(30)
+                            .total_tokens(42)
+                            .build()
+                            .unwrap(),
+                    )
+                    .build(),
+            ),
+        ];
+
+        let mut decoder = ConverseStreamDecoder::new("req-1");
+        let mut chunks = Vec::new();
+        for event in &events {
+            chunks.extend(decoder.decode(event).unwrap());
+        }
+        assert!(chunks.iter().all(|c| !c.is_complete));
+        chunks.extend(decoder.finish());
+        assert!(decoder.finish().is_empty());
+
+        let thinking: String = chunks.iter().map(|c| c.thinking.as_str()).collect();
+        assert_eq!(thinking, "List first.");
+        let block = chunks.iter().find_map(|c| c.thinking_block.clone());
+        assert_eq!(
+            block,
+            Some(ThinkingBlock::Thinking {
+                thinking: "List first.".to_string(),
+                signature: "sig-1".to_string(),
+            })
+        );
+
+        let text: String = chunks.iter().map(|c| c.content.as_str()).collect();
+        assert_eq!(text, "Let me look.");
+
+        let tool_call = chunks.iter().find_map(|c| c.tool_call.clone()).unwrap();
+        assert_eq!(tool_call.id, "tooluse_1");
+        assert_eq!(tool_call.params, json!({"command": "ls"}));
+
+        let last = chunks.last().unwrap();
+        assert!(last.is_complete);
+        assert_eq!(last.usage, Some(TokenUsage::new(12, 30)));
+    }
+}
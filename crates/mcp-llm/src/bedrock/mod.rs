@@ -1,21 +1,41 @@
-use crate::client_trait::{LlmClient, LlmResponse, StreamChunk, ToolCall as ClientToolCall};
+use crate::cancel::{ActiveRequests, RequestGuard};
+use crate::client_trait::{
+    LlmClient, LlmResponse, StreamChunk, ThinkingBlock, TokenUsage, ToolCall as ClientToolCall,
+    ToolDefinition,
+};
+use crate::prompt_cache::{self, CacheControl};
+use crate::retry::{self, ErrorClass};
 use crate::schema::McpSchemaManager;
+use crate::streaming::{ClaudeStreamDecoder, ClaudeStreamError};
+use crate::thinking::ThinkingConfig;
 use anyhow::{anyhow, Result};
 use async_trait::async_trait;
+use aws_config::retry::RetryConfig;
+use aws_config::sts::AssumeRoleProvider;
+use aws_config::Region;
+use aws_sdk_bedrockruntime::error::{DisplayErrorContext, ProvideErrorMetadata, SdkError};
+use aws_sdk_bedrockruntime::operation::converse::builders::ConverseFluentBuilder;
+use aws_sdk_bedrockruntime::operation::converse::ConverseOutput;
+use aws_sdk_bedrockruntime::operation::converse_stream::builders::ConverseStreamFluentBuilder;
+use aws_sdk_bedrockruntime::types::{InferenceConfiguration, SystemContentBlock};
 use aws_sdk_bedrockruntime::Client as BedrockRuntimeClient;
 use aws_smithy_types::Blob;
 use futures::Stream;
-use mcp_core::context::{ConversationContext, MessageRole};
+use mcp_core::config::inference_profile_region;
+use mcp_core::context::{Attachment, AttachmentKind, ConversationContext, MessageRole};
 use mcp_core::prompts::{PromptManager, TemplateEngine};
 use mcp_core::protocol::{Request as McpRequest, Response as McpResponse};
-use mcp_metrics::{count, time};
+use mcp_metrics::count;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::sync::{Arc, Mutex};
 use tokio_stream::wrappers::ReceiverStream;
 use tracing::{debug, error, trace, warn};
 use uuid::Uuid;
 
+mod adapter;
+mod converse;
+
+pub use adapter::ModelFamily;
+
 // Bedrock specific errors
 #[derive(Debug, thiserror::Error)]
 pub enum BedrockError {
@@ -28,27 +48,124 @@ pub enum BedrockError {
     #[error("Bedrock API error: {0}")]
     ApiError(String),
 
+    #[error("Bedrock request throttled: {0}")]
+    Throttled(String),
+
+    #[error("Transient Bedrock failure: {0}")]
+    Transient(String),
+
+    #[error("Access to Bedrock denied: {0}")]
+    AccessDenied(String),
+
+    #[error("Model not available: {0}")]
+    ModelNotAvailable(String),
+
+    #[error("Request exceeds the model's context window: {0}")]
+    ContextTooLong(String),
+
     #[error("Request cancelled")]
     Cancelled,
 }
 
-// Map of active requests that can be cancelled
-type RequestMap = Arc<Mutex<HashMap<String, bool>>>;
+impl BedrockError {
+    /// Classify an AWS SDK error by its error code
+    pub fn from_sdk_error<E, R>(err: &SdkError<E, R>) -> Self
+    where
+        E: ProvideErrorMetadata + std::error::Error + 'static,
+        R: std::fmt::Debug,
+    {
+        let message = DisplayErrorContext(err).to_string();
+        match err {
+            SdkError::TimeoutError(_)
+            | SdkError::DispatchFailure(_)
+            | SdkError::ResponseError(_) => {
+                return BedrockError::Transient(message);
+            }
+            SdkError::ServiceError(_) => {}
+            _ => return BedrockError::ApiError(message),
+        }
+
+        match err.code().unwrap_or_default() {
+            "ThrottlingException"
+            | "ServiceQuotaExceededException"
+            | "TooManyRequestsException" => BedrockError::Throttled(message),
+            "InternalServerException"
+            | "ServiceUnavailableException"
+            | "ModelNotReadyException"
+            | "ModelTimeoutException"
+            | "ModelStreamErrorException" => BedrockError::Transient(message),
+            "AccessDeniedException"
+            | "UnrecognizedClientException"
+            | "ExpiredTokenException"
+            | "InvalidSignatureException" => BedrockError::AccessDenied(message),
+            "ResourceNotFoundException" => BedrockError::ModelNotAvailable(message),
+            "ValidationException" if retry::is_context_overflow(&message) => {
+                BedrockError::ContextTooLong(message)
+            }
+            _ => BedrockError::ApiError(message),
+        }
+    }
+
+    /// Classify an error the model reported part way through a response stream
+    pub fn from_stream_error(err: &ClaudeStreamError) -> Self {
+        let message = err.to_string();
+        match err.class() {
+            ErrorClass::Throttled => BedrockError::Throttled(message),
+            ErrorClass::Transient => BedrockError::Transient(message),
+            ErrorClass::Auth => BedrockError::AccessDenied(message),
+            ErrorClass::ModelNotAvailable => BedrockError::ModelNotAvailable(message),
+            ErrorClass::ContextTooLong => BedrockError::ContextTooLong(message),
+            ErrorClass::Other => BedrockError::ApiError(message),
+        }
+    }
+
+    pub fn class(&self) -> ErrorClass {
+        match self {
+            BedrockError::Throttled(_) => ErrorClass::Throttled,
+            BedrockError::Transient(_) => ErrorClass::Transient,
+            BedrockError::AccessDenied(_) => ErrorClass::Auth,
+            BedrockError::ModelNotAvailable(_) => ErrorClass::ModelNotAvailable,
+            BedrockError::ContextTooLong(_) => ErrorClass::ContextTooLong,
+            BedrockError::ResponseParseError(_)
+            | BedrockError::InvalidMcpFormat(_)
+            | BedrockError::ApiError(_)
+            | BedrockError::Cancelled => ErrorClass::Other,
+        }
+    }
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BedrockConfig {
+    /// Model id, inference profile id, or inference profile ARN
     pub model_id: String,
     pub max_tokens: usize,
     pub temperature: f32,
     pub region: Option<String>,
     pub system_prompt: Option<String>,
     pub top_p: f32,
-}
-
-// Create a separate struct for the AWS region that will be used as a static reference
-#[derive(Debug)]
-struct AwsRegionHolder {
-    region_string: String,
+    /// Use the Converse API with native tool specs instead of JSON-RPC in text
+    #[serde(default)]
+    pub native_tools: bool,
+    /// Place cache points on the system prompt, tool specs and conversation history.
+    /// Only some Bedrock models support prompt caching, so this is off by default.
+    #[serde(default)]
+    pub prompt_caching: bool,
+    /// Token budget for extended thinking on Claude models; unset leaves thinking off.
+    /// While thinking, temperature and top_p are left at the model defaults.
+    #[serde(default)]
+    pub thinking_budget_tokens: Option<usize>,
+    /// Bedrock runtime endpoint override, e.g. a VPC interface endpoint or a local stand-in
+    #[serde(default)]
+    pub endpoint_url: Option<String>,
+    /// Named profile from the shared AWS config, including SSO and assume-role profiles
+    #[serde(default)]
+    pub profile: Option<String>,
+    /// Role to assume, using the profile or default credentials as the source
+    #[serde(default)]
+    pub role_arn: Option<String>,
+    /// External id required by the role's trust policy
+    #[serde(default)]
+    pub external_id: Option<String>,
 }
 
 impl BedrockConfig {
@@ -60,6 +177,13 @@ impl BedrockConfig {
             region: None,
             system_prompt: None,
             top_p: 0.9,
+            native_tools: false,
+            prompt_caching: false,
+            thinking_budget_tokens: None,
+            endpoint_url: None,
+            profile: None,
+            role_arn: None,
+            external_id: None,
         }
     }
 
@@ -86,6 +210,57 @@ impl BedrockConfig {
         self.system_prompt = Some(system_prompt);
         self
     }
+
+    pub fn with_native_tools(mut self, native_tools: bool) -> Self {
+        self.native_tools = native_tools;
+        self
+    }
+
+    pub fn with_prompt_caching(mut self, prompt_caching: bool) -> Self {
+        self.prompt_caching = prompt_caching;
+        self
+    }
+
+    pub fn with_thinking_budget(mut self, budget_tokens: usize) -> Self {
+        self.thinking_budget_tokens = Some(budget_tokens);
+        self
+    }
+
+    pub fn with_endpoint_url(mut self, endpoint_url: String) -> Self {
+        self.endpoint_url = Some(endpoint_url);
+        self
+    }
+
+    pub fn with_profile(mut self, profile: String) -> Self {
+        self.profile = Some(profile);
+        self
+    }
+
+    /// Assume `role_arn`, optionally with the external id its trust policy requires
+    pub fn with_assume_role(mut self, role_arn: String, external_id: Option<String>) -> Self {
+        self.role_arn = Some(role_arn);
+        self.external_id = external_id;
+        self
+    }
+
+    /// The region requests go to: the configured one, else the region of an inference
+    /// profile ARN, else the default from the AWS config
+    pub fn request_region(&self) -> Option<String> {
+        self.region
+            .clone()
+            .or_else(|| inference_profile_region(&self.model_id).map(str::to_string))
+    }
+
+    /// The model family, which decides the request format outside the Converse API
+    pub fn family(&self) -> ModelFamily {
+        ModelFamily::from_model_id(&self.model_id)
+    }
+
+    // The thinking request settings, if thinking is enabled
+    fn thinking(&self) -> Option<ThinkingConfig> {
+        self.thinking_budget_tokens
+            .map(|budget| ThinkingConfig::enabled(budget, self.max_tokens))
+    }
 }
 
 // Request Payload for Claude on Bedrock
@@ -94,9 +269,13 @@ struct ClaudePayload {
     anthropic_version: String,
     max_tokens: usize,
     messages: Vec<ClaudeMessage>,
-    system: String,
-    temperature: f32,
-    top_p: f32,
+    system: Vec<ClaudeContent>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    thinking: Option<ThinkingConfig>,
 }
 
 // Claude Message Format
@@ -106,12 +285,54 @@ struct ClaudeMessage {
     content: Vec<ClaudeContent>,
 }
 
-// Claude Message Content
+// Claude Message Content: text, or an image or document with a base64 source
 #[derive(Debug, Serialize, Deserialize)]
 struct ClaudeContent {
     #[serde(rename = "type")]
     content_type: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     text: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    source: Option<ClaudeSource>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    cache_control: Option<CacheControl>,
+}
+
+// Inline base64 data for image and document content
+#[derive(Debug, Serialize, Deserialize)]
+struct ClaudeSource {
+    #[serde(rename = "type")]
+    source_type: String,
+    media_type: String,
+    data: String,
+}
+
+impl ClaudeContent {
+    fn text(text: String) -> Self {
+        Self {
+            content_type: "text".to_string(),
+            text,
+            source: None,
+            cache_control: None,
+        }
+    }
+
+    fn attachment(attachment: &Attachment) -> Self {
+        let content_type = match attachment.kind {
+            AttachmentKind::Image => "image",
+            AttachmentKind::Document => "document",
+        };
+        Self {
+            content_type: content_type.to_string(),
+            text: String::new(),
+            source: Some(ClaudeSource {
+                source_type: "base64".to_string(),
+                media_type: attachment.media_type.clone(),
+                data: attachment.data.clone(),
+            }),
+            cache_control: None,
+        }
+    }
 }
 
 // Claude Response Format
@@ -130,49 +351,47 @@ struct ClaudeResponse {
     type_: Option<String>,
 }
 
-// Claude Response Content
+// Claude Response Content: text, or a thinking block that is kept apart from the text
 #[derive(Debug, Serialize, Deserialize)]
 struct ClaudeResponseContent {
     #[serde(rename = "type")]
     content_type: String,
-    text: String,
-}
-
-// Claude Streaming Response
-#[derive(Debug, Serialize, Deserialize)]
-struct ClaudeStreamingResponse {
-    #[serde(default)]
-    delta: Option<ClaudeDelta>,
     #[serde(default)]
-    message: Option<ClaudeResponse>,
-    #[serde(default)]
-    content_block: Option<ClaudeResponseContent>,
-    #[serde(default)]
-    usage: Option<ClaudeUsage>,
+    text: String,
     #[serde(default)]
-    stop_reason: Option<String>,
+    thinking: String,
     #[serde(default)]
-    stop_sequence: Option<String>,
+    signature: String,
     #[serde(default)]
-    type_: Option<String>,
+    data: String,
 }
 
-// Claude Delta for streaming
-#[derive(Debug, Serialize, Deserialize)]
-struct ClaudeDelta {
-    #[serde(default)]
-    text: String,
-    #[serde(rename = "type", default)]
-    type_: Option<String>,
-    #[serde(default)]
-    index: Option<usize>,
+impl ClaudeResponseContent {
+    fn thinking_block(&self) -> Option<ThinkingBlock> {
+        match self.content_type.as_str() {
+            "thinking" => Some(ThinkingBlock::Thinking {
+                thinking: self.thinking.clone(),
+                signature: self.signature.clone(),
+            }),
+            "redacted_thinking" => Some(ThinkingBlock::RedactedThinking {
+                data: self.data.clone(),
+            }),
+            _ => None,
+        }
+    }
 }
 
 // Claude Usage Statistics
 #[derive(Debug, Serialize, Deserialize)]
 struct ClaudeUsage {
+    #[serde(default)]
     input_tokens: usize,
+    #[serde(default)]
     output_tokens: usize,
+    #[serde(default)]
+    cache_read_input_tokens: usize,
+    #[serde(default)]
+    cache_creation_input_tokens: usize,
 }
 
 pub struct BedrockClient {
@@ -180,86 +399,75 @@ pub struct BedrockClient {
     config: BedrockConfig,
     schema_manager: McpSchemaManager,
     prompt_manager: PromptManager,
-    active_requests: RequestMap,
+    active_requests: ActiveRequests,
     tools_documentation: Option<String>,
+    tool_definitions: Vec<ToolDefinition>,
 }
 
 impl BedrockClient {
     pub async fn new(config: BedrockConfig) -> Result<Self> {
-        // Configure AWS SDK
-        let aws_config = if let Some(region) = &config.region {
-            // Create a static region holder that will outlive the config
-            let region_holder = Box::new(AwsRegionHolder {
-                region_string: region.clone(),
-            });
-            // Leak this box to create a 'static reference
-            // This is safe in this context as we need the region string to live for
-            // the entire duration of the application
-            let region_static = Box::leak(region_holder);
-
-            aws_config::defaults(aws_config::BehaviorVersion::latest())
-                .region(region_static.region_string.as_str())
-                .load()
-                .await
-        } else {
-            aws_config::defaults(aws_config::BehaviorVersion::latest())
-                .load()
-                .await
-        };
-
-        // Create Bedrock runtime client
-        let client = BedrockRuntimeClient::new(&aws_config);
+        let client = Self::sdk_client(&config).await;
 
         Ok(Self {
             client: Some(client),
             config,
             schema_manager: McpSchemaManager::new(),
             prompt_manager: PromptManager::new(),
-            active_requests: Arc::new(Mutex::new(HashMap::new())),
+            active_requests: ActiveRequests::new(),
             tools_documentation: None,
+            tool_definitions: Vec::new(),
         })
     }
 
     /// Create a new client with custom tool documentation
     pub async fn with_tool_documentation(config: BedrockConfig, tools_doc: String) -> Result<Self> {
-        // Configure AWS SDK
-        let aws_config = if let Some(region) = &config.region {
-            // Create a static region holder that will outlive the config
-            let region_holder = Box::new(AwsRegionHolder {
-                region_string: region.clone(),
-            });
-            // Leak this box to create a 'static reference
-            // This is safe in this context as we need the region string to live for
-            // the entire duration of the application
-            let region_static = Box::leak(region_holder);
-
-            aws_config::defaults(aws_config::BehaviorVersion::latest())
-                .region(region_static.region_string.as_str())
-                .load()
-                .await
-        } else {
-            aws_config::defaults(aws_config::BehaviorVersion::latest())
-                .load()
-                .await
-        };
+        let mut client = Self::new(config).await?;
+        client.tools_documentation = Some(tools_doc);
+        Ok(client)
+    }
 
-        // Create Bedrock runtime client
-        let client = BedrockRuntimeClient::new(&aws_config);
+    // Create the Bedrock runtime client from the AWS config, with the region, profile,
+    // role and endpoint overrides applied. The SDK's own retries are off; wrap the client in
+    // a RetryLayer to retry.
+    async fn sdk_client(config: &BedrockConfig) -> BedrockRuntimeClient {
+        let mut loader = aws_config::defaults(aws_config::BehaviorVersion::latest())
+            .retry_config(RetryConfig::disabled());
+        if let Some(region) = config.request_region() {
+            loader = loader.region(Region::new(region));
+        }
+        if let Some(profile) = &config.profile {
+            loader = loader.profile_name(profile);
+        }
+        let aws_config = loader.load().await;
+
+        let mut builder = aws_sdk_bedrockruntime::config::Builder::from(&aws_config);
+        if let Some(role_arn) = &config.role_arn {
+            debug!("Assuming role {} for Bedrock", role_arn);
+            let mut role = AssumeRoleProvider::builder(role_arn)
+                .session_name("mcpterm")
+                .configure(&aws_config);
+            if let Some(external_id) = &config.external_id {
+                role = role.external_id(external_id);
+            }
+            builder = builder.credentials_provider(role.build().await);
+        }
+        // Only Bedrock goes to the override, so STS and SSO keep their usual endpoints
+        if let Some(endpoint_url) = &config.endpoint_url {
+            debug!("Using Bedrock endpoint {}", endpoint_url);
+            builder = builder.endpoint_url(endpoint_url);
+        }
 
-        Ok(Self {
-            client: Some(client),
-            config,
-            schema_manager: McpSchemaManager::new(),
-            prompt_manager: PromptManager::new(),
-            active_requests: Arc::new(Mutex::new(HashMap::new())),
-            tools_documentation: Some(tools_doc),
-        })
+        BedrockRuntimeClient::from_conf(builder.build())
     }
 
-    // Helper method to convert conversation context to Claude format
-    fn prepare_claude_payload(&self, context: &ConversationContext) -> ClaudePayload {
-        let mut claude_messages = Vec::new();
+    /// Attach tool definitions that are sent as native tool specs in Converse mode
+    pub fn with_tools(mut self, tools: Vec<ToolDefinition>) -> Self {
+        self.tool_definitions = tools;
+        self
+    }
 
+    // Build the system prompt, optionally including the JSON-RPC tool instructions
+    fn build_system_prompt(&self, context: &ConversationContext, include_mcp: bool) -> String {
         // Create a template engine with variables for the system prompt
         let mut engine = TemplateEngine::new();
 
@@ -278,6 +486,21 @@ impl BedrockClient {
             system_prompt = format!("{}\n\n{}", system_prompt, custom_prompt);
         }
 
+        // System messages in the conversation, such as the summary of compacted turns, have
+        // no turn of their own in Bedrock requests
+        for message in context
+            .messages
+            .iter()
+            .filter(|message| message.role == MessageRole::System)
+        {
+            system_prompt = format!("{}\n\n{}", system_prompt, message.content);
+        }
+
+        // Native tool use carries the tool specs in the request instead of the prompt
+        if !include_mcp {
+            return system_prompt;
+        }
+
         // Add MCP system prompt - use dynamic tool documentation if available
         if let Some(tools_doc) = &self.tools_documentation {
             // Use the custom tool documentation with MCP system prompt
@@ -296,78 +519,56 @@ impl BedrockClient {
             system_prompt.len()
         );
 
-        // Convert conversation messages to Claude format
-        for message in &context.messages {
-            let role = match message.role {
-                MessageRole::User => "user",
-                MessageRole::Assistant => "assistant",
-                // System messages are handled separately in Claude
-                MessageRole::System => continue,
-                // Tool results require special handling for Claude
-                MessageRole::Tool => {
-                    // Parse the tool message if it's in JSON-RPC format
-                    if let Ok(json_value) =
-                        serde_json::from_str::<serde_json::Value>(&message.content)
-                    {
-                        // Check if it has the JSON-RPC result field
-                        if let Some(result) = json_value.get("result") {
-                            // Format as a special tool result message for Claude
-                            let tool_msg = format!(
-                                "I've received the following tool result:\n```json\n{}\n```\n\nNow I need to provide a direct answer based on this result.",
-                                serde_json::to_string_pretty(result).unwrap_or_else(|_| result.to_string())
-                            );
-
-                            let content = ClaudeContent {
-                                content_type: "text".to_string(),
-                                text: tool_msg,
-                            };
-
-                            claude_messages.push(ClaudeMessage {
-                                role: "assistant".to_string(),
-                                content: vec![content],
-                            });
-
-                            continue;
-                        }
-                    }
-
-                    // Fallback for non-JSON tool results
-                    "assistant"
-                }
-            };
-
-            // For regular messages (or fallback for tool messages)
-            let content = ClaudeContent {
-                content_type: "text".to_string(),
-                text: message.content.clone(),
-            };
+        system_prompt
+    }
 
+    // Helper method to convert conversation context to Claude format
+    fn prepare_claude_payload(&self, context: &ConversationContext) -> ClaudePayload {
+        let mut claude_messages = Vec::new();
+        let system_prompt = self.build_system_prompt(context, true);
+
+        // Convert conversation messages to Claude format, attachments first
+        for turn in adapter::conversation_turns(context) {
+            let mut content: Vec<_> = turn
+                .attachments
+                .iter()
+                .map(ClaudeContent::attachment)
+                .collect();
+            content.push(ClaudeContent::text(turn.text));
             claude_messages.push(ClaudeMessage {
-                role: role.to_string(),
-                content: vec![content],
+                role: turn.role.to_string(),
+                content,
             });
         }
 
+        // The system prompt carries the tool documentation, so caching it covers both
+        let mut system = ClaudeContent::text(system_prompt);
+        if self.config.prompt_caching {
+            system.cache_control = Some(CacheControl::ephemeral());
+            for index in prompt_cache::history_breakpoints(claude_messages.len()) {
+                if let Some(content) = claude_messages[index].content.last_mut() {
+                    content.cache_control = Some(CacheControl::ephemeral());
+                }
+            }
+        }
+
+        // Thinking doesn't accept sampling settings, so leave them at the defaults
+        let thinking = self.config.thinking();
+        let sampling = thinking.is_none();
+
         ClaudePayload {
             anthropic_version: "bedrock-2023-05-31".to_string(),
             max_tokens: self.config.max_tokens,
             messages: claude_messages,
-            system: system_prompt,
-            temperature: self.config.temperature,
-            top_p: self.config.top_p,
+            system: vec![system],
+            temperature: sampling.then_some(self.config.temperature),
+            top_p: sampling.then_some(self.config.top_p),
+            thinking,
         }
     }
 
-    // Parse a response from Claude into an MCP response
-    fn parse_claude_response(&self, response: &ClaudeResponse) -> Result<LlmResponse> {
-        let content = response
-            .content
-            .iter()
-            .filter(|c| c.content_type == "text")
-            .map(|c| c.text.clone())
-            .collect::<Vec<String>>()
-            .join("\n");
-
+    // Parse the text of a reply into an MCP response, whichever model family it came from
+    fn parse_text_response(&self, id: &str, content: String) -> Result<LlmResponse> {
         // Attempt to parse as JSON
         match serde_json::from_str::<serde_json::Value>(&content) {
             Ok(json_value) => {
@@ -388,21 +589,24 @@ impl BedrockClient {
                         };
 
                         Ok(LlmResponse {
-                            id: response.id.clone(),
+                            id: id.to_string(),
                             content,
                             tool_calls: Vec::new(),
+                            usage: None,
+                            thinking: Vec::new(),
                         })
-                    } else if mcp_response.error.is_some() {
+                    } else if let Some(error) = mcp_response.error {
                         // Error response
-                        let error = mcp_response.error.unwrap();
                         Err(anyhow!("LLM returned error: {}", error.message))
                     } else {
                         // Must be a tool call
                         match self.extract_tool_calls(&json_value) {
                             Ok(tool_calls) => Ok(LlmResponse {
-                                id: response.id.clone(),
+                                id: id.to_string(),
                                 content: String::new(), // Empty content for tool calls
                                 tool_calls,
+                                usage: None,
+                                thinking: Vec::new(),
                             }),
                             Err(e) => Err(e),
                         }
@@ -413,9 +617,11 @@ impl BedrockClient {
 
                     // Fallback - treat as regular text
                     Ok(LlmResponse {
-                        id: response.id.clone(),
+                        id: id.to_string(),
                         content,
                         tool_calls: Vec::new(),
+                        usage: None,
+                        thinking: Vec::new(),
                     })
                 }
             }
@@ -423,9 +629,11 @@ impl BedrockClient {
                 // Not JSON, treat as regular text response
                 debug!("Response is not JSON, treating as regular text");
                 Ok(LlmResponse {
-                    id: response.id.clone(),
+                    id: id.to_string(),
                     content,
                     tool_calls: Vec::new(),
+                    usage: None,
+                    thinking: Vec::new(),
                 })
             }
         }
@@ -452,33 +660,259 @@ impl BedrockClient {
 
         Err(anyhow!("Unable to extract tool call from response"))
     }
+
+    // Build a Converse API request with native tool specs for the given context
+    fn prepare_converse_request(
+        &self,
+        client: &BedrockRuntimeClient,
+        context: &ConversationContext,
+    ) -> Result<ConverseFluentBuilder> {
+        let cache = self.config.prompt_caching;
+        let messages = converse::build_converse_messages(context, cache)?;
+        let system_prompt = self.build_system_prompt(context, false);
+
+        // Thinking doesn't accept sampling settings, so leave them at the defaults
+        let thinking = self.config.thinking();
+        let mut inference_config =
+            InferenceConfiguration::builder().max_tokens(self.config.max_tokens as i32);
+        if thinking.is_none() {
+            inference_config = inference_config
+                .temperature(self.config.temperature)
+                .top_p(self.config.top_p);
+        }
+
+        let mut request = client
+            .converse()
+            .model_id(&self.config.model_id)
+            .set_messages(Some(messages))
+            .system(SystemContentBlock::Text(system_prompt))
+            .inference_config(inference_config.build());
+        if let Some(thinking) = thinking {
+            // Converse passes model-specific settings through as a document
+            let fields = serde_json::json!({ "thinking": thinking });
+            request = request.additional_model_request_fields(converse::json_to_document(&fields));
+        }
+        if cache {
+            request = request.system(SystemContentBlock::CachePoint(converse::cache_point()?));
+        }
+
+        if !self.tool_definitions.is_empty() {
+            request =
+                request.tool_config(converse::build_tool_config(&self.tool_definitions, cache)?);
+        }
+
+        trace!(
+            ">>> RAW CONVERSE REQUEST TO LLM >>>\n{:#?}",
+            request.get_messages()
+        );
+
+        Ok(request)
+    }
+
+    // Send a request through the Converse API and map toolUse blocks to tool calls
+    async fn send_converse(
+        &self,
+        context: &ConversationContext,
+        guard: &RequestGuard,
+    ) -> Result<LlmResponse> {
+        debug!(
+            "Sending Converse request to Bedrock: {} with {} tools",
+            self.config.model_id,
+            self.tool_definitions.len()
+        );
+
+        let Some(client) = &self.client else {
+            // This is a test-only path, return a mock response
+            debug!("Using mock Converse response for tests");
+            return Ok(LlmResponse {
+                id: guard.id().to_string(),
+                content: "This is a mock response for testing".to_string(),
+                tool_calls: Vec::new(),
+                usage: None,
+                thinking: Vec::new(),
+            });
+        };
+
+        let request = self.prepare_converse_request(client, context)?;
+
+        let output = match guard.run(send_converse_request(request)).await {
+            Some(result) => result?,
+            None => return Err(cancelled(guard)),
+        };
+        trace!("<<< RAW CONVERSE RESPONSE FROM LLM <<<\n{:#?}", output);
+
+        let response = converse::parse_converse_output(&output, guard.id())
+            .map_err(|e| anyhow!(BedrockError::ResponseParseError(e.to_string())))?;
+
+        response
+            .usage
+            .unwrap_or_else(|| TokenUsage::estimate(context, &response.content))
+            .record_metrics();
+
+        Ok(response)
+    }
+
+    // Converse equivalent of stream_message, streaming native tool calls through ConverseStream
+    async fn stream_converse(
+        &self,
+        context: &ConversationContext,
+        guard: RequestGuard,
+    ) -> Result<Box<dyn Stream<Item = Result<StreamChunk>> + Unpin + Send>> {
+        debug!(
+            "Sending ConverseStream request to Bedrock: {} with {} tools",
+            self.config.model_id,
+            self.tool_definitions.len()
+        );
+
+        // Open the stream before returning it, so a failure to open can be retried
+        // For tests, we skip the actual API call
+        let output = match &self.client {
+            Some(client) => {
+                let request = converse_stream_request(
+                    client,
+                    self.prepare_converse_request(client, context)?,
+                );
+                match guard.run(request.send()).await {
+                    Some(result) => Some(result.map_err(|err| {
+                        error!("Bedrock ConverseStream API error: {:?}", err);
+                        anyhow!(BedrockError::from_sdk_error(&err))
+                    })?),
+                    None => return Err(cancelled(&guard)),
+                }
+            }
+            None => None,
+        };
+
+        // Create a channel for the stream
+        let (tx, rx) = tokio::sync::mpsc::channel::<Result<StreamChunk>>(100);
+
+        // Spawn a task to read the event stream and forward chunks as they arrive
+        tokio::spawn(async move {
+            let mut decoder = converse::ConverseStreamDecoder::new(guard.id());
+
+            let Some(output) = output else {
+                // This is a test-only path, stream a mock response
+                debug!("Using mock ConverseStream response for tests");
+                let text = converse::text_delta_event(0, "This is a mock response for testing");
+                let chunks = decoder.decode(&text).unwrap_or_default();
+                for chunk in chunks.into_iter().chain(decoder.finish()) {
+                    let _ = tx.send(Ok(chunk)).await;
+                }
+                return;
+            };
+
+            debug!("Bedrock ConverseStream opened");
+            let mut stream = output.stream;
+
+            loop {
+                // Dropping the event stream on cancellation closes the connection
+                let event = tokio::select! {
+                    biased;
+                    _ = guard.cancelled() => {
+                        let _ = tx.send(Err(cancelled(&guard))).await;
+                        return;
+                    }
+                    _ = tx.closed() => {
+                        debug!("Stream receiver dropped, stopping");
+                        return;
+                    }
+                    event = stream.recv() => event,
+                };
+                let event = match event {
+                    Ok(Some(event)) => event,
+                    Ok(None) => break,
+                    Err(err) => {
+                        error!("Bedrock ConverseStream error: {:?}", err);
+                        let _ = tx
+                            .send(Err(anyhow!(BedrockError::from_sdk_error(&err))))
+                            .await;
+                        return;
+                    }
+                };
+                trace!("<<< RAW CONVERSE STREAM EVENT FROM LLM <<<\n{:#?}", event);
+
+                match decoder.decode(&event) {
+                    Ok(chunks) => {
+                        count!("llm.stream_events", 1);
+                        for chunk in chunks {
+                            if tx.send(Ok(chunk)).await.is_err() {
+                                debug!("Stream receiver dropped, stopping");
+                                return;
+                            }
+                        }
+                    }
+                    Err(e) => {
+                        error!("Failed to parse ConverseStream event: {}", e);
+                        count!("llm.errors.parsing");
+                        let _ = tx
+                            .send(Err(anyhow!(BedrockError::ResponseParseError(
+                                e.to_string()
+                            ))))
+                            .await;
+                        return;
+                    }
+                }
+            }
+
+            // Usage arrives after the message stops, so the final chunk goes out last
+            for chunk in decoder.finish() {
+                if let Some(usage) = &chunk.usage {
+                    usage.record_metrics();
+                }
+                let _ = tx.send(Ok(chunk)).await;
+            }
+        });
+
+        // Return the receiver as a stream
+        Ok(Box::new(ReceiverStream::new(rx)))
+    }
+}
+
+// Converse and ConverseStream take the same request, so build one and copy it to the other
+fn converse_stream_request(
+    client: &BedrockRuntimeClient,
+    request: ConverseFluentBuilder,
+) -> ConverseStreamFluentBuilder {
+    client
+        .converse_stream()
+        .set_model_id(request.get_model_id().clone())
+        .set_messages(request.get_messages().clone())
+        .set_system(request.get_system().clone())
+        .set_inference_config(request.get_inference_config().clone())
+        .set_additional_model_request_fields(request.get_additional_model_request_fields().clone())
+        .set_tool_config(request.get_tool_config().clone())
+}
+
+// Note a request that was dropped because it was cancelled
+fn cancelled(guard: &RequestGuard) -> anyhow::Error {
+    debug!("Request {} was cancelled", guard.id());
+    count!("llm.cancelled");
+    anyhow!(BedrockError::Cancelled)
+}
+
+// Send one Converse request, classifying any failure
+async fn send_converse_request(request: ConverseFluentBuilder) -> Result<ConverseOutput> {
+    request.send().await.map_err(|err| {
+        error!("Bedrock Converse API error: {:?}", err);
+        anyhow!(BedrockError::from_sdk_error(&err))
+    })
 }
 
 #[async_trait]
 impl LlmClient for BedrockClient {
     async fn send_message(&self, context: &ConversationContext) -> Result<LlmResponse> {
-        // Generate a request ID and register it for possible cancellation
-        let request_id = Uuid::new_v4().to_string();
-        {
-            let mut active_requests = self.active_requests.lock().unwrap();
-            active_requests.insert(request_id.clone(), false);
-        }
-
-        // Count API calls
-        count!("llm.calls.total");
-        count!("llm.calls.bedrock");
+        // Register the request so it can be cancelled while in flight
+        let guard = self.active_requests.register();
+        let request_id = guard.id().to_string();
 
-        // Prepare the Claude-specific payload
-        let claude_payload = self.prepare_claude_payload(context);
-        let payload_bytes = serde_json::to_vec(&claude_payload)?;
+        if self.config.native_tools {
+            return self.send_converse(context, &guard).await;
+        }
 
-        // Count tokens (approximation)
-        let input_tokens = context
-            .messages
-            .iter()
-            .map(|m| m.content.len() / 4)
-            .sum::<usize>();
-        count!("llm.tokens.input", input_tokens as u64);
+        // Prepare the payload in the model family's format
+        let adapter = self.config.family().adapter();
+        let payload = adapter.build_payload(self, context)?;
+        let payload_bytes = serde_json::to_vec(&payload)?;
 
         debug!("Sending request to Bedrock: {}", self.config.model_id);
         debug!(
@@ -489,40 +923,32 @@ impl LlmClient for BedrockClient {
         // Only log the raw request at TRACE level, keeping just this critical logging point
         trace!(
             ">>> RAW REQUEST TO LLM >>>\n{}",
-            serde_json::to_string_pretty(&claude_payload).unwrap_or_default()
+            serde_json::to_string_pretty(&payload).unwrap_or_default()
         );
 
-        // Send the request to Bedrock with timing
+        // Send the request to Bedrock
         // For tests, we skip the actual API call
         let output = if let Some(client) = &self.client {
-            time!("llm.response_time.bedrock", {
-                match client
-                    .invoke_model()
-                    .body(Blob::new(payload_bytes))
-                    .model_id(&self.config.model_id)
-                    .send()
-                    .await
-                {
-                    Ok(response) => response,
-                    Err(err) => {
-                        error!("Bedrock API error: {:?}", err);
-                        // Count error
-                        count!("llm.errors");
-                        count!("llm.errors.bedrock");
-
-                        // Remove from active requests
-                        let mut active_requests = self.active_requests.lock().unwrap();
-                        active_requests.remove(&request_id);
-                        return Err(anyhow!(BedrockError::ApiError(err.to_string())));
-                    }
-                }
-            })
+            let result = guard
+                .run(
+                    client
+                        .invoke_model()
+                        .body(Blob::new(payload_bytes))
+                        .model_id(&self.config.model_id)
+                        .send(),
+                )
+                .await;
+            match result {
+                Some(result) => result.map_err(|err| {
+                    error!("Bedrock API error: {:?}", err);
+                    anyhow!(BedrockError::from_sdk_error(&err))
+                })?,
+                // Dropping the SDK future aborts the HTTP request
+                None => return Err(cancelled(&guard)),
+            }
         } else {
             // This is a test-only path, create a mock response
             debug!("Using mock response for tests");
-            // Remove request from tracking
-            let mut active_requests = self.active_requests.lock().unwrap();
-            active_requests.remove(&request_id);
 
             // We're in test mode, return a mock response that parseClaudeResponse can handle
             aws_sdk_bedrockruntime::operation::invoke_model::InvokeModelOutput::builder()
@@ -538,48 +964,33 @@ impl LlmClient for BedrockClient {
         // Log full raw response at TRACE level (only shown with LOG_LEVEL=trace)
         trace!("<<< RAW RESPONSE FROM LLM <<<\n{}", response_str);
 
-        // Check if request was cancelled
-        {
-            let active_requests = self.active_requests.lock().unwrap();
-            if let Some(cancelled) = active_requests.get(&request_id) {
-                if *cancelled {
-                    return Err(anyhow!(BedrockError::Cancelled));
-                }
-            }
-        }
-
-        // Remove from active requests
-        {
-            let mut active_requests = self.active_requests.lock().unwrap();
-            active_requests.remove(&request_id);
-        }
-
-        // Parse the Claude response
-        match serde_json::from_str::<ClaudeResponse>(&response_str) {
-            Ok(claude_response) => {
-                debug!(
-                    "Successfully parsed Claude response: {:?}",
-                    claude_response.id
-                );
-
-                let response = self.parse_claude_response(&claude_response)?;
-
-                // Count output tokens (rough approximation)
-                let output_tokens = response.content.len() / 4;
-                count!("llm.tokens.output", output_tokens as u64);
-
-                // Count successful completion
-                count!("llm.completions.success");
-
-                // Count tool calls if any
-                if !response.tool_calls.is_empty() {
-                    count!("llm.tool_calls", response.tool_calls.len() as u64);
-                }
+        // Parse the response in the model family's format
+        match adapter.parse_response(&response_str) {
+            Ok(reply) => {
+                let id = reply.id.unwrap_or_else(|| request_id.clone());
+                let mut response = match reply.usage {
+                    Some(usage) => {
+                        usage.record_metrics();
+                        let mut response = self.parse_text_response(&id, reply.text)?;
+                        response.usage = Some(usage);
+                        response
+                    }
+                    None => {
+                        // Count tokens (rough approximation)
+                        TokenUsage::estimate(context, &reply.text).record_metrics();
+                        self.parse_text_response(&id, reply.text)?
+                    }
+                };
+                response.thinking = reply.thinking;
 
                 Ok(response)
             }
             Err(err) => {
-                warn!("Failed to parse Claude response: {}", err);
+                warn!(
+                    "Failed to parse {} response: {}",
+                    self.config.family().as_str(),
+                    err
+                );
                 warn!("Response string: {}", response_str);
 
                 // Count parsing error
@@ -595,14 +1006,15 @@ impl LlmClient for BedrockClient {
                         if let Some(text) = content.as_str() {
                             debug!("Extracted content from non-standard response");
 
-                            // Count output tokens (rough approximation)
-                            let output_tokens = text.len() / 4;
-                            count!("llm.tokens.output", output_tokens as u64);
+                            // Count tokens (rough approximation)
+                            TokenUsage::estimate(context, text).record_metrics();
 
                             return Ok(LlmResponse {
                                 id: request_id,
                                 content: text.to_string(),
                                 tool_calls: Vec::new(),
+                                usage: None,
+                                thinking: Vec::new(),
                             });
                         }
                     }
@@ -610,14 +1022,15 @@ impl LlmClient for BedrockClient {
                     // Return the raw JSON as content as a last resort
                     debug!("Returning raw JSON as content");
 
-                    // Count output tokens (rough approximation)
-                    let output_tokens = response_str.len() / 4;
-                    count!("llm.tokens.output", output_tokens as u64);
+                    // Count tokens (rough approximation)
+                    TokenUsage::estimate(context, &response_str).record_metrics();
 
                     return Ok(LlmResponse {
                         id: request_id,
                         content: response_str,
                         tool_calls: Vec::new(),
+                        usage: None,
+                        thinking: Vec::new(),
                     });
                 }
 
@@ -630,16 +1043,17 @@ impl LlmClient for BedrockClient {
         &self,
         context: &ConversationContext,
     ) -> Result<Box<dyn Stream<Item = Result<StreamChunk>> + Unpin + Send>> {
-        // Generate a request ID and register it for possible cancellation
-        let request_id = Uuid::new_v4().to_string();
-        {
-            let mut active_requests = self.active_requests.lock().unwrap();
-            active_requests.insert(request_id.clone(), false);
+        // Register the request so it can be cancelled while in flight
+        let guard = self.active_requests.register();
+
+        if self.config.native_tools {
+            return self.stream_converse(context, guard).await;
         }
 
-        // Prepare the Claude-specific payload
-        let claude_payload = self.prepare_claude_payload(context);
-        let payload_bytes = serde_json::to_vec(&claude_payload)?;
+        // Prepare the payload in the model family's format
+        let adapter = self.config.family().adapter();
+        let payload = adapter.build_payload(self, context)?;
+        let payload_bytes = serde_json::to_vec(&payload)?;
 
         debug!(
             "Sending streaming request to Bedrock: {}",
@@ -653,205 +1067,147 @@ impl LlmClient for BedrockClient {
         // Only log the raw request at TRACE level, keeping just this critical logging point
         trace!(
             ">>> RAW STREAMING REQUEST TO LLM >>>\n{}",
-            serde_json::to_string_pretty(&claude_payload).unwrap_or_default()
+            serde_json::to_string_pretty(&payload).unwrap_or_default()
         );
 
-        // Create a channel for the stream
-        let (tx, rx) = tokio::sync::mpsc::channel::<Result<StreamChunk>>(100);
-
-        // Clone needed data for the async task
-        let client = self.client.clone();
-        let model_id = self.config.model_id.clone();
-        let active_requests = self.active_requests.clone();
-        let request_id_clone = request_id.clone();
-        let is_test_mode = client.is_none();
-
-        // We need to ensure we don't use self in the async block
-        // Extract what we need from the response to avoid borrowing issues
-        let extract_tool_calls = |json_value: &serde_json::Value| -> Option<ClientToolCall> {
-            if let Ok(mcp_request) = serde_json::from_value::<McpRequest>(json_value.clone()) {
-                if mcp_request.method == "mcp.tool_call" {
-                    if let Some(name) = mcp_request.params.get("name") {
-                        if let Some(name_str) = name.as_str() {
-                            if let Some(parameters) = mcp_request.params.get("parameters") {
-                                let tool_call = ClientToolCall {
-                                    id: Uuid::new_v4().to_string(),
-                                    tool: name_str.to_string(),
-                                    params: parameters.clone(),
-                                };
-                                return Some(tool_call);
-                            }
-                        }
-                    }
+        // Open the stream before returning it, so a failure to open can be retried; events
+        // already forwarded can't be taken back
+        // For tests, we skip the actual API call
+        let output = match &self.client {
+            Some(client) => {
+                let result = guard
+                    .run(
+                        client
+                            .invoke_model_with_response_stream()
+                            .body(Blob::new(payload_bytes))
+                            .model_id(&self.config.model_id)
+                            .send(),
+                    )
+                    .await;
+                match result {
+                    Some(result) => Some(result.map_err(|err| {
+                        error!("Bedrock API error: {:?}", err);
+                        anyhow!(BedrockError::from_sdk_error(&err))
+                    })?),
+                    None => return Err(cancelled(&guard)),
                 }
             }
-            None
+            None => None,
         };
 
-        // Spawn a task to run the request and process the response
+        // Create a channel for the stream
+        let (tx, rx) = tokio::sync::mpsc::channel::<Result<StreamChunk>>(100);
+
+        // Spawn a task to read the event stream and forward chunks as they arrive
         tokio::spawn(async move {
-            debug!("We're not using streaming for now, using regular invoke_model");
-            debug!("In AWS SDK 1.x, the streaming APIs are difficult to work with");
+            let mut decoder = ClaudeStreamDecoder::new(guard.id());
 
-            // Send a regular request instead of streaming
-            let output = if is_test_mode {
-                // This is a test-only path, create a mock response
+            let Some(output) = output else {
+                // This is a test-only path, replay a canned event sequence
                 debug!("Using mock streaming response for tests");
-
-                // We're in test mode, return a mock response
-                aws_sdk_bedrockruntime::operation::invoke_model::InvokeModelOutput::builder()
-                    .content_type("application/json")
-                    .body(Blob::new(r#"{"id":"test-id","content":[{"type":"text","text":"This is a mock streaming response for testing"}],"type":"message","role":"assistant","model":"claude-3-sonnet-20240229-v1:0"}"#))
-                    .build()
-                    .expect("Failed to build mock streaming response")
-            } else {
-                // This is the normal operation path
-                match client
-                    .unwrap()
-                    .invoke_model()
-                    .body(Blob::new(payload_bytes))
-                    .model_id(&model_id)
-                    .send()
-                    .await
-                {
-                    Ok(response) => response,
-                    Err(err) => {
-                        error!("Bedrock API error: {:?}", err);
-
-                        // Send error through the channel first
-                        let _ = tx
-                            .send(Err(anyhow!(BedrockError::ApiError(err.to_string()))))
-                            .await;
-
-                        // Remove from active requests
-                        {
-                            let mut active_requests = active_requests.lock().unwrap();
-                            active_requests.remove(&request_id_clone);
+                let events = [
+                    r#"{"type":"message_start","message":{"id":"test-id","type":"message","role":"assistant","content":[],"model":"claude-3-sonnet-20240229-v1:0","usage":{"input_tokens":0,"output_tokens":0}}}"#,
+                    r#"{"type":"content_block_delta","index":0,"delta":{"type":"text_delta","text":"This is a mock "}}"#,
+                    r#"{"type":"content_block_delta","index":0,"delta":{"type":"text_delta","text":"streaming response for testing"}}"#,
+                    r#"{"type":"message_stop"}"#,
+                ];
+                for event in events {
+                    for chunk in decoder.decode(event.as_bytes()).unwrap_or_default() {
+                        if tx.send(Ok(chunk)).await.is_err() {
+                            break;
                         }
-                        return;
                     }
                 }
+                return;
             };
 
-            debug!("Received response from Bedrock");
-
-            // Get response body
-            let response_bytes = output.body;
-            let response_str = String::from_utf8_lossy(response_bytes.as_ref()).to_string();
-            // Log full raw response at TRACE level (only shown with LOG_LEVEL=trace)
-            trace!("<<< RAW STREAMING RESPONSE FROM LLM <<<\n{}", response_str);
+            debug!("Bedrock response stream opened");
+            let mut body = output.body;
 
-            // Check if request was cancelled
-            {
-                let active_requests = active_requests.lock().unwrap();
-                if let Some(cancelled) = active_requests.get(&request_id_clone) {
-                    if *cancelled {
-                        debug!("Request {} was cancelled", request_id_clone);
+            loop {
+                // Dropping the event stream on cancellation closes the connection
+                let event = tokio::select! {
+                    biased;
+                    _ = guard.cancelled() => {
+                        let _ = tx.send(Err(cancelled(&guard))).await;
                         return;
                     }
-                }
-            }
-
-            // Parse the Claude response
-            match serde_json::from_str::<ClaudeResponse>(&response_str) {
-                Ok(claude_response) => {
-                    debug!(
-                        "Successfully parsed Claude response: {:?}",
-                        claude_response.id
-                    );
-
-                    // Get the text content from Claude
-                    let content = claude_response
-                        .content
-                        .iter()
-                        .filter(|c| c.content_type == "text")
-                        .map(|c| c.text.clone())
-                        .collect::<Vec<String>>()
-                        .join("\n");
-
-                    // Emit the content as a stream
-                    let stream_chunk = StreamChunk {
-                        id: request_id_clone.clone(),
-                        content: content.clone(),
-                        is_tool_call: false,
-                        tool_call: None,
-                        is_complete: false,
-                    };
-
-                    if let Err(e) = tx.send(Ok(stream_chunk)).await {
-                        error!("Failed to send content chunk to stream: {}", e);
+                    _ = tx.closed() => {
+                        debug!("Stream receiver dropped, stopping");
+                        return;
                     }
+                    event = body.recv() => event,
+                };
+                let event = match event {
+                    Ok(Some(event)) => event,
+                    Ok(None) => break,
+                    Err(err) => {
+                        error!("Bedrock stream error: {:?}", err);
+                        let _ = tx
+                            .send(Err(anyhow!(BedrockError::from_sdk_error(&err))))
+                            .await;
+                        break;
+                    }
+                };
+
+                let Some(bytes) = event.as_chunk().ok().and_then(|part| part.bytes()) else {
+                    debug!("Ignoring unknown stream event: {:?}", event);
+                    continue;
+                };
+                trace!(
+                    "<<< RAW STREAM EVENT FROM LLM <<<\n{}",
+                    String::from_utf8_lossy(bytes.as_ref())
+                );
 
-                    // Try to parse as MCP
-                    let is_mcp_response = is_valid_json(&content);
-
-                    if is_mcp_response {
-                        debug!("Content appears to be valid JSON, checking for tool calls");
-
-                        // Try to extract tool calls
-                        if let Ok(json_value) = serde_json::from_str::<serde_json::Value>(&content)
-                        {
-                            if let Some(tool_call) = extract_tool_calls(&json_value) {
-                                debug!("Found tool call in response");
-
-                                // Send a tool call chunk
-                                let final_chunk = StreamChunk {
-                                    id: request_id_clone.clone(),
-                                    content: String::new(), // Already sent in previous chunk
-                                    is_tool_call: true,
-                                    tool_call: Some(tool_call),
-                                    is_complete: true,
-                                };
-
-                                if let Err(e) = tx.send(Ok(final_chunk)).await {
-                                    error!("Failed to send tool call chunk to stream: {}", e);
-                                }
-
-                                // Clean up and exit
-                                {
-                                    let mut active_requests = active_requests.lock().unwrap();
-                                    active_requests.remove(&request_id_clone);
-                                }
+                // Other families' events are translated into Claude events first
+                let decoded = adapter.stream_events(bytes.as_ref()).and_then(|events| {
+                    events.iter().try_fold(Vec::new(), |mut chunks, event| {
+                        chunks.extend(decoder.decode(event)?);
+                        Ok(chunks)
+                    })
+                });
+                match decoded {
+                    Ok(chunks) => {
+                        count!("llm.stream_events", 1);
+                        for chunk in chunks {
+                            if let Some(usage) = &chunk.usage {
+                                usage.record_metrics();
+                            }
+                            if tx.send(Ok(chunk)).await.is_err() {
+                                debug!("Stream receiver dropped, stopping");
                                 return;
                             }
                         }
                     }
-
-                    // Send completion
-                    let final_chunk = StreamChunk {
-                        id: request_id_clone.clone(),
-                        content: String::new(), // Already sent in previous chunk
-                        is_tool_call: false,
-                        tool_call: None,
-                        is_complete: true,
-                    };
-
-                    if let Err(e) = tx.send(Ok(final_chunk)).await {
-                        error!("Failed to send completion chunk to stream: {}", e);
-                    }
+                    Err(e) => match e.downcast_ref::<ClaudeStreamError>() {
+                        // The model gave up part way through, so the reply so far is incomplete
+                        Some(error) => {
+                            error!("Bedrock stream reported an error: {}", error);
+                            let _ = tx
+                                .send(Err(anyhow!(BedrockError::from_stream_error(error))))
+                                .await;
+                            return;
+                        }
+                        None => {
+                            warn!("Failed to parse stream event: {}", e);
+                            count!("llm.errors.parsing");
+                        }
+                    },
                 }
-                Err(e) => {
-                    error!("Failed to parse Claude response: {}", e);
 
-                    // Send the raw content anyway
-                    let stream_chunk = StreamChunk {
-                        id: request_id_clone.clone(),
-                        content: response_str,
-                        is_tool_call: false,
-                        tool_call: None,
-                        is_complete: true,
-                    };
-
-                    if let Err(send_err) = tx.send(Ok(stream_chunk)).await {
-                        error!("Failed to send raw content: {}", send_err);
-                    }
+                if decoder.is_complete() {
+                    break;
                 }
             }
 
-            // Remove from active requests
-            {
-                let mut active_requests = active_requests.lock().unwrap();
-                active_requests.remove(&request_id_clone);
+            // Make sure consumers always see a final chunk
+            if !decoder.is_complete() {
+                for chunk in decoder.finish() {
+                    if let Some(usage) = &chunk.usage {
+                        usage.record_metrics();
+                    }
+                    let _ = tx.send(Ok(chunk)).await;
+                }
             }
         });
 
@@ -860,10 +1216,8 @@ impl LlmClient for BedrockClient {
     }
 
     fn cancel_request(&self, request_id: &str) -> Result<()> {
-        let mut active_requests = self.active_requests.lock().unwrap();
-        if let Some(cancelled) = active_requests.get_mut(request_id) {
-            *cancelled = true;
-            debug!("Marked request {} as cancelled", request_id);
+        if self.active_requests.cancel(request_id) {
+            debug!("Cancelled request {}", request_id);
             Ok(())
         } else {
             Err(anyhow!("Request ID not found: {}", request_id))
@@ -871,15 +1225,10 @@ impl LlmClient for BedrockClient {
     }
 }
 
-// Helper function to check if a string is valid JSON
-fn is_valid_json(s: &str) -> bool {
-    serde_json::from_str::<serde_json::Value>(s).is_ok()
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
-    use mcp_core::context::{ConversationContext, Message, MessageRole};
+    use mcp_core::context::{ConversationContext, Message};
 
     // Test the dynamic tool documentation generation
     #[test]
@@ -896,8 +1245,9 @@ mod tests {
             config,
             schema_manager: McpSchemaManager::new(),
             prompt_manager: PromptManager::new(),
-            active_requests: Arc::new(Mutex::new(HashMap::new())),
+            active_requests: ActiveRequests::new(),
             tools_documentation: Some(tools_doc.to_string()),
+            tool_definitions: Vec::new(),
         };
 
         let context = ConversationContext {
@@ -907,6 +1257,9 @@ mod tests {
                 content: "Hello".to_string(),
                 tool_calls: None,
                 tool_results: None,
+                attachments: None,
+                thinking: None,
+                pinned: false,
             }],
             current_request_id: None,
         };
@@ -914,10 +1267,12 @@ mod tests {
         let payload = client.prepare_claude_payload(&context);
 
         // Check that the system prompt contains our tool documentation
-        assert!(payload.system.contains("test_tool"));
-        assert!(payload.system.contains("This is a test tool"));
-        assert!(payload.system.contains("Required parameter"));
-        assert!(payload.system.contains("Optional: Another parameter"));
+        assert!(payload.system[0].text.contains("test_tool"));
+        assert!(payload.system[0].text.contains("This is a test tool"));
+        assert!(payload.system[0].text.contains("Required parameter"));
+        assert!(payload.system[0]
+            .text
+            .contains("Optional: Another parameter"));
     }
 
     // Test that regular (non-dynamic) tool documentation is used when no custom docs provided
@@ -934,8 +1289,9 @@ mod tests {
             config,
             schema_manager: McpSchemaManager::new(),
             prompt_manager: PromptManager::new(),
-            active_requests: Arc::new(Mutex::new(HashMap::new())),
+            active_requests: ActiveRequests::new(),
             tools_documentation: None,
+            tool_definitions: Vec::new(),
         };
 
         let context = ConversationContext {
@@ -945,6 +1301,9 @@ mod tests {
                 content: "Hello".to_string(),
                 tool_calls: None,
                 tool_results: None,
+                attachments: None,
+                thinking: None,
+                pinned: false,
             }],
             current_request_id: None,
         };
@@ -952,8 +1311,198 @@ mod tests {
         let payload = client.prepare_claude_payload(&context);
 
         // Check that the system prompt contains the default tools
-        assert!(payload.system.contains("\"shell\""));
-        assert!(payload.system.contains("\"file_read\""));
-        assert!(payload.system.contains("Model Context Protocol (MCP)"));
+        assert!(payload.system[0].text.contains("\"shell\""));
+        assert!(payload.system[0].text.contains("\"file_read\""));
+        assert!(payload.system[0]
+            .text
+            .contains("Model Context Protocol (MCP)"));
+    }
+
+    // Native tool mode should leave the JSON-RPC instructions out of the system prompt
+    #[test]
+    fn test_native_tools_system_prompt() {
+        let config = BedrockConfig::claude().with_native_tools(true);
+
+        let client = BedrockClient {
+            client: None,
+            config,
+            schema_manager: McpSchemaManager::new(),
+            prompt_manager: PromptManager::new(),
+            active_requests: ActiveRequests::new(),
+            tools_documentation: Some("1. \"test_tool\": This is a test tool".to_string()),
+            tool_definitions: Vec::new(),
+        };
+
+        let context = ConversationContext::new();
+        let native_prompt = client.build_system_prompt(&context, false);
+        let mcp_prompt = client.build_system_prompt(&context, true);
+
+        assert!(!native_prompt.contains("test_tool"));
+        assert!(mcp_prompt.contains("test_tool"));
+    }
+
+    // Conversation system messages, such as a compaction summary, join the system prompt
+    #[test]
+    fn test_system_messages_in_system_prompt() {
+        let client = BedrockClient {
+            client: None,
+            config: BedrockConfig::claude(),
+            schema_manager: McpSchemaManager::new(),
+            prompt_manager: PromptManager::new(),
+            active_requests: ActiveRequests::new(),
+            tools_documentation: None,
+            tool_definitions: Vec::new(),
+        };
+
+        let mut context = ConversationContext::new();
+        context.add_user_message("Hello");
+        context.replace_with_summary(1, "The user said hello.");
+        context.add_user_message("Again");
+
+        let payload = client.prepare_claude_payload(&context);
+        assert!(payload.system[0].text.contains("The user said hello."));
+        assert_eq!(payload.messages.len(), 1);
+    }
+
+    // Attachments are sent as base64 image and document content ahead of the text
+    #[test]
+    fn test_attachments_in_payload() {
+        let client = BedrockClient {
+            client: None,
+            config: BedrockConfig::claude(),
+            schema_manager: McpSchemaManager::new(),
+            prompt_manager: PromptManager::new(),
+            active_requests: ActiveRequests::new(),
+            tools_documentation: None,
+            tool_definitions: Vec::new(),
+        };
+
+        let mut context = ConversationContext::new();
+        context.add_user_message_with_attachments(
+            "What does this dialog say?",
+            vec![Attachment::new(
+                AttachmentKind::Image,
+                "image/png",
+                "dialog.png",
+                b"png",
+            )],
+        );
+
+        let payload = serde_json::to_value(client.prepare_claude_payload(&context)).unwrap();
+        let content = &payload["messages"][0]["content"];
+        assert_eq!(content[0]["type"], "image");
+        assert_eq!(content[0]["source"]["media_type"], "image/png");
+        assert!(content[0].get("text").is_none());
+        assert_eq!(content[1]["text"], "What does this dialog say?");
+    }
+
+    // Thinking adds the budget and drops the sampling settings Claude rejects with it
+    #[test]
+    fn test_thinking_payload() {
+        let config = BedrockConfig::claude()
+            .with_max_tokens(16000)
+            .with_thinking_budget(8000);
+
+        let client = BedrockClient {
+            client: None,
+            config,
+            schema_manager: McpSchemaManager::new(),
+            prompt_manager: PromptManager::new(),
+            active_requests: ActiveRequests::new(),
+            tools_documentation: None,
+            tool_definitions: Vec::new(),
+        };
+
+        let mut context = ConversationContext::new();
+        context.add_user_message("Plan the refactor");
+
+        let payload = serde_json::to_value(client.prepare_claude_payload(&context)).unwrap();
+        assert_eq!(payload["thinking"]["type"], "enabled");
+        assert_eq!(payload["thinking"]["budget_tokens"], 8000);
+        assert!(payload.get("temperature").is_none());
+        assert!(payload.get("top_p").is_none());
+    }
+
+    // Thinking blocks in the response are kept out of the text used for JSON-RPC parsing
+    #[test]
+    fn test_thinking_response_content() {
+        let response: ClaudeResponse = serde_json::from_value(serde_json::json!({
+            "id": "msg_1",
+            "model": "claude",
+            "role": "assistant",
+            "content": [
+                {"type": "thinking", "thinking": "Call {\"jsonrpc\": \"2.0\"}?", "signature": "sig"},
+                {"type": "text", "text": "Here is the plan."}
+            ],
+            "usage": {"input_tokens": 10, "output_tokens": 20}
+        }))
+        .unwrap();
+
+        assert_eq!(
+            response.content[0].thinking_block(),
+            Some(ThinkingBlock::Thinking {
+                thinking: "Call {\"jsonrpc\": \"2.0\"}?".to_string(),
+                signature: "sig".to_string(),
+            })
+        );
+        assert!(response.content[1].thinking_block().is_none());
+    }
+
+    // Inference profile ARNs carry their region, which an explicit region overrides
+    #[test]
+    fn test_inference_profile_arn_region() {
+        let arn = "arn:aws:bedrock:eu-central-1:123456789012:inference-profile/eu.anthropic.claude-3-7-sonnet-20250219-v1:0";
+        assert_eq!(
+            BedrockConfig::new(arn).request_region().as_deref(),
+            Some("eu-central-1")
+        );
+        assert_eq!(
+            BedrockConfig::new(arn)
+                .with_region("eu-west-1".to_string())
+                .request_region()
+                .as_deref(),
+            Some("eu-west-1")
+        );
+        assert!(BedrockConfig::claude().request_region().is_none());
+        assert_eq!(BedrockConfig::new(arn).family(), ModelFamily::Anthropic);
+    }
+
+    // Caching marks the system prompt (with the tool docs) and the history prefix
+    #[test]
+    fn test_prompt_caching_payload() {
+        let config = BedrockConfig::claude().with_prompt_caching(true);
+
+        let client = BedrockClient {
+            client: None,
+            config,
+            schema_manager: McpSchemaManager::new(),
+            prompt_manager: PromptManager::new(),
+            active_requests: ActiveRequests::new(),
+            tools_documentation: Some("1. \"test_tool\": This is a test tool".to_string()),
+            tool_definitions: Vec::new(),
+        };
+
+        let mut context = ConversationContext::new();
+        context.add_user_message("First question");
+        context.add_assistant_message("First answer");
+        context.add_user_message("Second question");
+
+        let payload = serde_json::to_value(client.prepare_claude_payload(&context)).unwrap();
+        assert_eq!(payload["system"][0]["cache_control"]["type"], "ephemeral");
+        assert!(payload["system"][0]["text"]
+            .as_str()
+            .unwrap()
+            .contains("test_tool"));
+        assert_eq!(
+            payload["messages"][0]["content"][0]["cache_control"]["type"],
+            "ephemeral"
+        );
+        assert!(payload["messages"][1]["content"][0]
+            .get("cache_control")
+            .is_none());
+        assert_eq!(
+            payload["messages"][2]["content"][0]["cache_control"]["type"],
+            "ephemeral"
+        );
     }
 }
@@ -0,0 +1,151 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use tokio::sync::watch;
+use uuid::Uuid;
+
+/// Requests in flight, each with a signal that stops it when cancelled.
+///
+/// Clients run their HTTP calls and stream readers through the [`RequestGuard`] returned by
+/// `register`, so cancelling drops the in-flight future and closes the connection instead of
+/// only being noticed once the next chunk or the full response arrives.
+#[derive(Clone, Default)]
+pub struct ActiveRequests {
+    requests: Arc<Mutex<HashMap<String, watch::Sender<bool>>>>,
+}
+
+impl ActiveRequests {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start tracking a request under a fresh id
+    pub fn register(&self) -> RequestGuard {
+        self.register_id(Uuid::new_v4().to_string())
+    }
+
+    /// Start tracking a request under an id chosen by the caller
+    pub fn register_id(&self, id: impl Into<String>) -> RequestGuard {
+        let id = id.into();
+        let (tx, rx) = watch::channel(false);
+        self.requests.lock().unwrap().insert(id.clone(), tx);
+        RequestGuard {
+            id,
+            requests: self.clone(),
+            cancelled: rx,
+        }
+    }
+
+    /// Signal a request to stop, returning false if it isn't in flight
+    pub fn cancel(&self, id: &str) -> bool {
+        match self.requests.lock().unwrap().get(id) {
+            Some(tx) => {
+                tx.send_replace(true);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Signal every request in flight to stop, returning how many there were
+    pub fn cancel_all(&self) -> usize {
+        let requests = self.requests.lock().unwrap();
+        for tx in requests.values() {
+            tx.send_replace(true);
+        }
+        requests.len()
+    }
+
+    /// Ids of the requests in flight
+    pub fn ids(&self) -> Vec<String> {
+        self.requests.lock().unwrap().keys().cloned().collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.requests.lock().unwrap().is_empty()
+    }
+}
+
+/// A tracked request; it stops being tracked when the guard is dropped
+pub struct RequestGuard {
+    id: String,
+    requests: ActiveRequests,
+    cancelled: watch::Receiver<bool>,
+}
+
+impl RequestGuard {
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        *self.cancelled.borrow()
+    }
+
+    /// Resolves once the request has been cancelled
+    pub async fn cancelled(&self) {
+        let mut rx = self.cancelled.clone();
+        if rx.wait_for(|cancelled| *cancelled).await.is_err() {
+            // No longer tracked, so nothing can cancel it any more
+            std::future::pending::<()>().await;
+        }
+    }
+
+    /// Drive `future` to completion, or drop it and return None if the request is
+    /// cancelled first
+    pub async fn run<F: Future>(&self, future: F) -> Option<F::Output> {
+        tokio::select! {
+            biased;
+            _ = self.cancelled() => None,
+            output = future => Some(output),
+        }
+    }
+}
+
+impl Drop for RequestGuard {
+    fn drop(&mut self) {
+        self.requests.requests.lock().unwrap().remove(&self.id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_cancel_drops_in_flight_future() {
+        let requests = ActiveRequests::new();
+        let guard = requests.register();
+        let id = guard.id().to_string();
+
+        let handle =
+            tokio::spawn(
+                async move { guard.run(tokio::time::sleep(Duration::from_secs(60))).await },
+            );
+
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert!(requests.cancel(&id));
+
+        let output = tokio::time::timeout(Duration::from_secs(1), handle)
+            .await
+            .expect("cancelled request should stop promptly")
+            .unwrap();
+        assert!(output.is_none());
+        assert!(requests.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_finished_requests_are_untracked() {
+        let requests = ActiveRequests::new();
+        {
+            let guard = requests.register_id("request-1");
+            assert_eq!(requests.ids(), vec!["request-1".to_string()]);
+            assert_eq!(guard.run(async { 42 }).await, Some(42));
+            assert!(!guard.is_cancelled());
+        }
+
+        assert!(!requests.cancel("request-1"));
+        assert_eq!(requests.cancel_all(), 0);
+    }
+}
@@ -0,0 +1,578 @@
+use crate::client_trait::{LlmClient, LlmResponse, StreamChunk};
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use futures::{Stream, StreamExt};
+use mcp_core::context::{ConversationContext, MessageRole};
+use mcp_core::ModelTask;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tracing::{debug, warn};
+
+/// Version written to new cassettes
+pub const CASSETTE_VERSION: u32 = 1;
+
+/// Recorded request/response pairs, stored as JSON.
+///
+/// A cassette is captured once against a real provider with [`RecordingClient`] and served
+/// back by [`ReplayClient`], so multi-turn tool sessions can run offline.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Cassette {
+    pub version: u32,
+    pub interactions: Vec<Interaction>,
+}
+
+impl Default for Cassette {
+    fn default() -> Self {
+        Self {
+            version: CASSETTE_VERSION,
+            interactions: Vec::new(),
+        }
+    }
+}
+
+impl Cassette {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let data = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read cassette {}", path.display()))?;
+        serde_json::from_str(&data)
+            .with_context(|| format!("Failed to parse cassette {}", path.display()))
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let data = serde_json::to_string_pretty(self)?;
+        std::fs::write(path, data)
+            .with_context(|| format!("Failed to write cassette {}", path.display()))
+    }
+}
+
+/// One request and what the provider sent back for it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Interaction {
+    pub request: RequestKey,
+    pub response: RecordedResponse,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum RecordedResponse {
+    /// Reply to `send_message`
+    Message { response: LlmResponse },
+    /// Chunks from `stream_message`, and the error that ended the stream if there was one
+    Stream {
+        chunks: Vec<StreamChunk>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        error: Option<String>,
+    },
+    /// The request failed outright
+    Error { error: String },
+}
+
+impl RecordedResponse {
+    // The response as send_message returns it, assembling streamed chunks if need be
+    fn to_response(&self) -> Result<LlmResponse> {
+        match self {
+            RecordedResponse::Message { response } => Ok(response.clone()),
+            RecordedResponse::Stream { error: Some(e), .. } => Err(anyhow!("{}", e)),
+            RecordedResponse::Stream { chunks, .. } => {
+                let mut response = LlmResponse {
+                    id: chunks.first().map(|c| c.id.clone()).unwrap_or_default(),
+                    content: String::new(),
+                    tool_calls: Vec::new(),
+                    usage: None,
+                    thinking: Vec::new(),
+                };
+                for chunk in chunks {
+                    response.content.push_str(&chunk.content);
+                    response.tool_calls.extend(chunk.tool_call.clone());
+                    response.thinking.extend(chunk.thinking_block.clone());
+                    response.usage = chunk.usage.or(response.usage);
+                }
+                Ok(response)
+            }
+            RecordedResponse::Error { error } => Err(anyhow!("{}", error)),
+        }
+    }
+
+    // The response as stream_message yields it, splitting a whole reply into chunks
+    fn to_stream_items(&self) -> Result<Vec<Result<StreamChunk>>> {
+        match self {
+            RecordedResponse::Message { response } => {
+                let chunk = |content: String| StreamChunk {
+                    id: response.id.clone(),
+                    content,
+                    is_tool_call: false,
+                    tool_call: None,
+                    is_complete: false,
+                    usage: None,
+                    thinking: String::new(),
+                    thinking_block: None,
+                };
+
+                let mut chunks = Vec::new();
+                for block in &response.thinking {
+                    chunks.push(StreamChunk {
+                        thinking: block.text().unwrap_or_default().to_string(),
+                        thinking_block: Some(block.clone()),
+                        ..chunk(String::new())
+                    });
+                }
+                if !response.content.is_empty() {
+                    chunks.push(chunk(response.content.clone()));
+                }
+                for tool_call in &response.tool_calls {
+                    chunks.push(StreamChunk {
+                        is_tool_call: true,
+                        tool_call: Some(tool_call.clone()),
+                        ..chunk(String::new())
+                    });
+                }
+                chunks.push(StreamChunk {
+                    is_complete: true,
+                    usage: response.usage,
+                    ..chunk(String::new())
+                });
+                Ok(chunks.into_iter().map(Ok).collect())
+            }
+            RecordedResponse::Stream { chunks, error } => {
+                let mut items: Vec<Result<StreamChunk>> = chunks.iter().cloned().map(Ok).collect();
+                if let Some(e) = error {
+                    items.push(Err(anyhow!("{}", e)));
+                }
+                Ok(items)
+            }
+            RecordedResponse::Error { error } => Err(anyhow!("{}", error)),
+        }
+    }
+}
+
+/// The part of a request that replay matches on.
+///
+/// The system prompt and tool output are left out: they carry tool documentation, paths
+/// and timings that differ from one machine to the next, while the turns the model wrote
+/// and the tools it called stay the same.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RequestKey {
+    pub messages: Vec<KeyMessage>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyMessage {
+    pub role: MessageRole,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub content: String,
+    /// Tools called by an assistant message, or answered by a tool message
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tools: Vec<String>,
+    /// Names of attached files
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub attachments: Vec<String>,
+}
+
+impl RequestKey {
+    pub fn from_context(context: &ConversationContext) -> Self {
+        let messages = context
+            .messages
+            .iter()
+            .map(|message| {
+                let content = match message.role {
+                    MessageRole::Tool => String::new(),
+                    _ => message.content.clone(),
+                };
+                let called = message.tool_calls.iter().flatten().map(|c| &c.tool_id);
+                let answered = message.tool_results.iter().flatten().map(|r| &r.tool_id);
+                KeyMessage {
+                    role: message.role.clone(),
+                    content,
+                    tools: called.chain(answered).cloned().collect(),
+                    attachments: message
+                        .attachments
+                        .iter()
+                        .flatten()
+                        .map(|a| a.name.clone())
+                        .collect(),
+                }
+            })
+            .collect();
+
+        Self { messages }
+    }
+
+    // Short description for error messages
+    fn describe(&self) -> String {
+        match self.messages.last() {
+            Some(last) => format!(
+                "{} messages, last {:?}: {:.60}",
+                self.messages.len(),
+                last.role,
+                last.content
+            ),
+            None => "an empty conversation".to_string(),
+        }
+    }
+}
+
+/// Wraps a real client and writes every request/response pair to a cassette file.
+///
+/// The file is rewritten as each interaction completes, so an interrupted session keeps
+/// everything recorded up to that point.
+pub struct RecordingClient {
+    inner: Arc<dyn LlmClient>,
+    path: PathBuf,
+    cassette: Arc<Mutex<Cassette>>,
+}
+
+impl RecordingClient {
+    pub fn new(inner: Arc<dyn LlmClient>, path: impl Into<PathBuf>) -> Self {
+        Self {
+            inner,
+            path: path.into(),
+            cassette: Arc::new(Mutex::new(Cassette::default())),
+        }
+    }
+
+    // Add an interaction and write out the cassette
+    fn record(
+        cassette: &Mutex<Cassette>,
+        path: &Path,
+        request: RequestKey,
+        response: RecordedResponse,
+    ) {
+        let mut cassette = cassette.lock().unwrap();
+        cassette
+            .interactions
+            .push(Interaction { request, response });
+        if let Err(e) = cassette.save(path) {
+            warn!("{:#}", e);
+        } else {
+            debug!(
+                "Recorded interaction {} to {}",
+                cassette.interactions.len(),
+                path.display()
+            );
+        }
+    }
+
+    // Record the outcome of a whole-message request
+    fn record_reply(&self, request: RequestKey, result: &Result<LlmResponse>) {
+        let response = match result {
+            Ok(response) => RecordedResponse::Message {
+                response: response.clone(),
+            },
+            Err(e) => RecordedResponse::Error {
+                error: format!("{:#}", e),
+            },
+        };
+        Self::record(&self.cassette, &self.path, request, response);
+    }
+}
+
+#[async_trait]
+impl LlmClient for RecordingClient {
+    async fn send_message(&self, context: &ConversationContext) -> Result<LlmResponse> {
+        let request = RequestKey::from_context(context);
+        let result = self.inner.send_message(context).await;
+
+        self.record_reply(request, &result);
+        result
+    }
+
+    async fn stream_message(
+        &self,
+        context: &ConversationContext,
+    ) -> Result<Box<dyn Stream<Item = Result<StreamChunk>> + Unpin + Send>> {
+        let request = RequestKey::from_context(context);
+        let mut stream = match self.inner.stream_message(context).await {
+            Ok(stream) => stream,
+            Err(e) => {
+                let response = RecordedResponse::Error {
+                    error: format!("{:#}", e),
+                };
+                Self::record(&self.cassette, &self.path, request, response);
+                return Err(e);
+            }
+        };
+
+        // Pass the chunks through, keeping a copy to record once the stream ends
+        let (tx, rx) = mpsc::channel::<Result<StreamChunk>>(32);
+        let cassette = self.cassette.clone();
+        let path = self.path.clone();
+        tokio::spawn(async move {
+            let mut chunks = Vec::new();
+            let mut error = None;
+            while let Some(item) = stream.next().await {
+                match &item {
+                    Ok(chunk) => chunks.push(chunk.clone()),
+                    Err(e) => error = Some(format!("{:#}", e)),
+                }
+                if tx.send(item).await.is_err() {
+                    debug!("Stream receiver dropped, recording what was received");
+                    break;
+                }
+            }
+
+            // Record before the sender drops, so the stream ends only once this is saved
+            let response = RecordedResponse::Stream { chunks, error };
+            Self::record(&cassette, &path, request, response);
+            drop(tx);
+        });
+
+        Ok(Box::new(ReceiverStream::new(rx)))
+    }
+
+    fn cancel_request(&self, request_id: &str) -> Result<()> {
+        self.inner.cancel_request(request_id)
+    }
+
+    async fn send_task(
+        &self,
+        task: ModelTask,
+        context: &ConversationContext,
+    ) -> Result<LlmResponse> {
+        // Forward the task so routing still applies while recording
+        let request = RequestKey::from_context(context);
+        let result = self.inner.send_task(task, context).await;
+
+        self.record_reply(request, &result);
+        result
+    }
+}
+
+/// Serves the responses from a cassette instead of calling a provider.
+///
+/// Each request is answered by the first unused interaction whose conversation matches,
+/// so a session that asks the same thing twice gets the two recorded answers in order.
+/// Recorded messages can be replayed as streams and vice versa.
+pub struct ReplayClient {
+    interactions: Vec<Interaction>,
+    used: Mutex<Vec<bool>>,
+}
+
+impl ReplayClient {
+    pub fn new(cassette: Cassette) -> Self {
+        let used = vec![false; cassette.interactions.len()];
+        Self {
+            interactions: cassette.interactions,
+            used: Mutex::new(used),
+        }
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        Ok(Self::new(Cassette::load(path)?))
+    }
+
+    // Find and use up the recorded response for this conversation
+    fn take(&self, context: &ConversationContext) -> Result<&RecordedResponse> {
+        let request = RequestKey::from_context(context);
+        let mut used = self.used.lock().unwrap();
+        let index = self
+            .interactions
+            .iter()
+            .enumerate()
+            .position(|(i, interaction)| !used[i] && interaction.request == request)
+            .ok_or_else(|| {
+                anyhow!(
+                    "No recorded response matches the request ({})",
+                    request.describe()
+                )
+            })?;
+
+        used[index] = true;
+        debug!("Replaying recorded interaction {}", index + 1);
+        Ok(&self.interactions[index].response)
+    }
+}
+
+#[async_trait]
+impl LlmClient for ReplayClient {
+    async fn send_message(&self, context: &ConversationContext) -> Result<LlmResponse> {
+        self.take(context)?.to_response()
+    }
+
+    async fn stream_message(
+        &self,
+        context: &ConversationContext,
+    ) -> Result<Box<dyn Stream<Item = Result<StreamChunk>> + Unpin + Send>> {
+        let items = self.take(context)?.to_stream_items()?;
+        Ok(Box::new(futures::stream::iter(items)))
+    }
+
+    fn cancel_request(&self, _request_id: &str) -> Result<()> {
+        // Replayed responses are already complete
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client_trait::{ThinkingBlock, TokenUsage, ToolCall};
+    use mcp_core::context::{ToolCall as ContextToolCall, ToolResult};
+    use serde_json::json;
+
+    // Answers every request with the number of messages it was sent
+    struct CountingClient;
+
+    #[async_trait]
+    impl LlmClient for CountingClient {
+        async fn send_message(&self, context: &ConversationContext) -> Result<LlmResponse> {
+            Ok(LlmResponse {
+                id: "msg".to_string(),
+                content: format!("{} messages", context.messages.len()),
+                tool_calls: Vec::new(),
+                usage: Some(TokenUsage::new(10, 2)),
+                thinking: Vec::new(),
+            })
+        }
+
+        async fn stream_message(
+            &self,
+            context: &ConversationContext,
+        ) -> Result<Box<dyn Stream<Item = Result<StreamChunk>> + Unpin + Send>> {
+            let response = RecordedResponse::Message {
+                response: self.send_message(context).await?,
+            };
+            Ok(Box::new(futures::stream::iter(response.to_stream_items()?)))
+        }
+
+        fn cancel_request(&self, _request_id: &str) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    fn cassette_path() -> PathBuf {
+        std::env::temp_dir().join(format!("cassette-{}.json", uuid::Uuid::new_v4()))
+    }
+
+    #[tokio::test]
+    async fn test_record_then_replay() {
+        let path = cassette_path();
+        let recorder = RecordingClient::new(Arc::new(CountingClient), &path);
+
+        let mut context = ConversationContext::new();
+        context.add_user_message("hello");
+        let recorded = recorder.send_message(&context).await.unwrap();
+
+        context.add_assistant_message(&recorded.content);
+        context.add_user_message("again");
+        let mut stream = recorder.stream_message(&context).await.unwrap();
+        let mut streamed = String::new();
+        while let Some(chunk) = stream.next().await {
+            streamed.push_str(&chunk.unwrap().content);
+        }
+        // The stream only ends once its interaction has been recorded
+
+        let replay = ReplayClient::from_file(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let mut context = ConversationContext::new();
+        context.add_user_message("hello");
+        let replayed = replay.send_message(&context).await.unwrap();
+        assert_eq!(replayed.content, "1 messages");
+        assert_eq!(replayed.usage, Some(TokenUsage::new(10, 2)));
+
+        context.add_assistant_message(&replayed.content);
+        context.add_user_message("again");
+        let chunks: Vec<_> = replay
+            .stream_message(&context)
+            .await
+            .unwrap()
+            .collect()
+            .await;
+        let content: String = chunks
+            .iter()
+            .map(|c| c.as_ref().unwrap().content.clone())
+            .collect();
+        assert_eq!(content, streamed);
+
+        // Each recorded response is only served once
+        assert!(replay.send_message(&context).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_unmatched_request_fails() {
+        let replay = ReplayClient::new(Cassette::default());
+        let mut context = ConversationContext::new();
+        context.add_user_message("never recorded");
+
+        let error = replay.send_message(&context).await.unwrap_err();
+        assert!(error.to_string().contains("No recorded response"));
+    }
+
+    #[test]
+    fn test_key_ignores_tool_output() {
+        let session = |output: &str| {
+            let mut context = ConversationContext::new();
+            context.add_user_message("List files");
+            context.add_assistant_tool_calls(
+                "",
+                vec![ContextToolCall {
+                    tool_id: "shell".to_string(),
+                    parameters: json!({"command": "ls"}),
+                    call_id: "tooluse_1".to_string(),
+                }],
+            );
+            context.add_tool_result(
+                output,
+                ToolResult {
+                    tool_id: "shell".to_string(),
+                    result: json!({"stdout": output}),
+                    call_id: "tooluse_1".to_string(),
+                },
+            );
+            RequestKey::from_context(&context)
+        };
+
+        let key = session("a.txt");
+        assert_eq!(key, session("b.txt"));
+        assert_eq!(key.messages[1].tools, vec!["shell"]);
+        assert_eq!(key.messages[2].tools, vec!["shell"]);
+    }
+
+    #[test]
+    fn test_message_replays_as_stream() {
+        let response = RecordedResponse::Message {
+            response: LlmResponse {
+                id: "msg".to_string(),
+                content: "Let me look.".to_string(),
+                tool_calls: vec![ToolCall {
+                    id: "tooluse_1".to_string(),
+                    tool: "shell".to_string(),
+                    params: json!({"command": "ls"}),
+                }],
+                usage: Some(TokenUsage::new(5, 1)),
+                thinking: vec![ThinkingBlock::Thinking {
+                    thinking: "Check the files".to_string(),
+                    signature: "sig".to_string(),
+                }],
+            },
+        };
+
+        let chunks: Vec<_> = response
+            .to_stream_items()
+            .unwrap()
+            .into_iter()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(chunks.len(), 4);
+        assert_eq!(chunks[0].thinking, "Check the files");
+        assert_eq!(chunks[1].content, "Let me look.");
+        assert!(chunks[2].is_tool_call);
+        assert!(chunks[3].is_complete);
+
+        // And back again
+        let stream = RecordedResponse::Stream {
+            chunks,
+            error: None,
+        };
+        let assembled = stream.to_response().unwrap();
+        assert_eq!(assembled.content, "Let me look.");
+        assert_eq!(assembled.tool_calls.len(), 1);
+        assert_eq!(assembled.thinking.len(), 1);
+        assert_eq!(assembled.usage, Some(TokenUsage::new(5, 1)));
+    }
+}
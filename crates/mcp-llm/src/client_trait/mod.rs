@@ -1,23 +1,103 @@
+use crate::structured::{self, OutputSchema};
 use anyhow::Result;
 use async_trait::async_trait;
 use futures::Stream;
-use mcp_core::context::ConversationContext;
+use mcp_core::context::{estimate_tokens, ConversationContext};
+use mcp_core::ModelTask;
+use mcp_metrics::count;
 use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+pub use mcp_core::context::ThinkingBlock;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LlmResponse {
     pub id: String,
     pub content: String,
     pub tool_calls: Vec<ToolCall>,
+    /// Token usage reported by the provider, if it reports any
+    #[serde(default)]
+    pub usage: Option<TokenUsage>,
+    /// Extended thinking blocks, kept out of `content` so they never reach JSON-RPC parsing
+    #[serde(default)]
+    pub thinking: Vec<ThinkingBlock>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Token counts for a single request, as reported by the provider.
+///
+/// With prompt caching, `input_tokens` only counts the uncached part of the prompt; the
+/// cached prefix is reported separately as read from or written to the cache.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TokenUsage {
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    #[serde(default)]
+    pub cache_read_tokens: u64,
+    #[serde(default)]
+    pub cache_write_tokens: u64,
+}
+
+impl TokenUsage {
+    pub fn new(input_tokens: u64, output_tokens: u64) -> Self {
+        Self {
+            input_tokens,
+            output_tokens,
+            cache_read_tokens: 0,
+            cache_write_tokens: 0,
+        }
+    }
+
+    pub fn with_cache(mut self, cache_read_tokens: u64, cache_write_tokens: u64) -> Self {
+        self.cache_read_tokens = cache_read_tokens;
+        self.cache_write_tokens = cache_write_tokens;
+        self
+    }
+
+    /// All prompt tokens, whether cached or not
+    pub fn prompt_tokens(&self) -> u64 {
+        self.input_tokens + self.cache_read_tokens + self.cache_write_tokens
+    }
+
+    pub fn total_tokens(&self) -> u64 {
+        self.prompt_tokens() + self.output_tokens
+    }
+
+    /// Rough estimate (about four characters per token) for responses without usage
+    pub fn estimate(context: &ConversationContext, output: &str) -> Self {
+        Self::new(
+            context.estimated_tokens() as u64,
+            estimate_tokens(output) as u64,
+        )
+    }
+
+    /// Add these counts to the `llm.tokens.*` metrics
+    pub fn record_metrics(&self) {
+        count!("llm.tokens.input", self.input_tokens);
+        count!("llm.tokens.output", self.output_tokens);
+        if self.cache_read_tokens > 0 {
+            count!("llm.tokens.cache_read", self.cache_read_tokens);
+        }
+        if self.cache_write_tokens > 0 {
+            count!("llm.tokens.cache_write", self.cache_write_tokens);
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ToolCall {
     pub id: String,
     pub tool: String,
     pub params: serde_json::Value,
 }
 
+/// Tool description handed to providers that support native tool use
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolDefinition {
+    pub name: String,
+    pub description: String,
+    pub input_schema: serde_json::Value,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct StreamChunk {
     pub id: String,
@@ -25,6 +105,15 @@ pub struct StreamChunk {
     pub is_tool_call: bool,
     pub tool_call: Option<ToolCall>,
     pub is_complete: bool,
+    /// Token usage for the whole request, set on the completion chunk
+    #[serde(default)]
+    pub usage: Option<TokenUsage>,
+    /// Extended thinking text as it streams in, for display only
+    #[serde(default)]
+    pub thinking: String,
+    /// A finished thinking block, to be kept with the tool calls of this turn
+    #[serde(default)]
+    pub thinking_block: Option<ThinkingBlock>,
 }
 
 #[async_trait]
@@ -37,4 +126,62 @@ pub trait LlmClient: Send + Sync {
     ) -> Result<Box<dyn Stream<Item = Result<StreamChunk>> + Unpin + Send>>;
 
     fn cancel_request(&self, request_id: &str) -> Result<()>;
+
+    /// Send a one-off request for an auxiliary task such as summarising or titling.
+    ///
+    /// Clients that route tasks to other models override this; otherwise it is the same
+    /// as `send_message`.
+    async fn send_task(
+        &self,
+        task: ModelTask,
+        context: &ConversationContext,
+    ) -> Result<LlmResponse> {
+        let _ = task;
+        self.send_message(context).await
+    }
+
+    /// Request a reply that conforms to a JSON Schema, re-prompting with the validation
+    /// errors up to the schema's retry limit when it doesn't
+    async fn send_structured(
+        &self,
+        context: &ConversationContext,
+        schema: &OutputSchema,
+    ) -> Result<serde_json::Value> {
+        structured::send_structured(self, context, schema).await
+    }
+}
+
+/// A shared client is a client, so a built stack can be handed to anything taking `impl LlmClient`
+#[async_trait]
+impl<T: LlmClient + ?Sized> LlmClient for Arc<T> {
+    async fn send_message(&self, context: &ConversationContext) -> Result<LlmResponse> {
+        (**self).send_message(context).await
+    }
+
+    async fn stream_message(
+        &self,
+        context: &ConversationContext,
+    ) -> Result<Box<dyn Stream<Item = Result<StreamChunk>> + Unpin + Send>> {
+        (**self).stream_message(context).await
+    }
+
+    fn cancel_request(&self, request_id: &str) -> Result<()> {
+        (**self).cancel_request(request_id)
+    }
+
+    async fn send_task(
+        &self,
+        task: ModelTask,
+        context: &ConversationContext,
+    ) -> Result<LlmResponse> {
+        (**self).send_task(task, context).await
+    }
+
+    async fn send_structured(
+        &self,
+        context: &ConversationContext,
+        schema: &OutputSchema,
+    ) -> Result<serde_json::Value> {
+        (**self).send_structured(context, schema).await
+    }
 }
@@ -0,0 +1,312 @@
+// Summarises the older turns of a long conversation so it keeps fitting in the context window
+use crate::client_trait::{LlmClient, TokenUsage};
+use anyhow::{anyhow, Result};
+use mcp_core::context::{ConversationContext, Message, MessageRole};
+use mcp_core::{CompactionConfig, ModelTask};
+use mcp_metrics::{count, time};
+use std::fmt::Write;
+use tracing::debug;
+
+const INSTRUCTIONS: &str = "Summarise the conversation below so it can continue without it. \
+Keep the user's goals and requests, decisions made, file paths, commands and their outcomes, \
+errors still unresolved, and anything left to do. Leave out pleasantries and tool output that \
+no longer matters. Reply with the summary only, as plain text.";
+
+/// What a compaction did to a conversation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Compaction {
+    /// Messages replaced by the summary; pinned turns are kept
+    pub messages: usize,
+    pub tokens_before: usize,
+    pub tokens_after: usize,
+    /// Tokens spent on the summarisation request
+    pub usage: Option<TokenUsage>,
+}
+
+/// Replaces all but the most recent turns of a conversation with a summary of them.
+///
+/// The summary is requested as a `Summarize` task, so a routing client can send it to a
+/// cheaper model. Tool calls and results are cut short in the request, since bulky output
+/// is usually what filled the context in the first place.
+#[derive(Debug, Clone)]
+pub struct Compactor {
+    keep_recent_turns: usize,
+    max_tool_result_chars: usize,
+}
+
+impl Compactor {
+    pub fn new(config: &CompactionConfig) -> Self {
+        Self {
+            // The turn being answered is always kept
+            keep_recent_turns: config.keep_recent_turns.max(1),
+            max_tool_result_chars: config.max_tool_result_chars,
+        }
+    }
+
+    /// Compact `context` in place, or return `None` if no turns are old enough to be
+    /// summarised. `focus` is passed on to the summariser, e.g. what to keep in detail.
+    pub async fn compact<C: LlmClient + ?Sized>(
+        &self,
+        client: &C,
+        context: &mut ConversationContext,
+        focus: Option<&str>,
+    ) -> Result<Option<Compaction>> {
+        let end = context.recent_turns_start(self.keep_recent_turns);
+        let older = context.compactable(end);
+        // An earlier summary on its own has nothing new to fold in
+        if older.iter().all(|message| message.is_summary()) {
+            debug!("Nothing old enough to compact");
+            return Ok(None);
+        }
+        let messages = older.len();
+
+        let tokens_before = context.estimated_tokens();
+        let mut request = ConversationContext::new();
+        request.add_user_message(&self.prompt(&older, focus));
+
+        let response = time!("llm.compaction.time", {
+            client.send_task(ModelTask::Summarize, &request).await
+        })?;
+        let summary = response.content.trim();
+        if summary.is_empty() {
+            count!("llm.compaction.errors");
+            return Err(anyhow!("The model returned an empty summary"));
+        }
+
+        context.replace_with_summary(end, summary);
+        let compaction = Compaction {
+            messages,
+            tokens_before,
+            tokens_after: context.estimated_tokens(),
+            usage: response.usage,
+        };
+        debug!(
+            "Compacted {} messages, about {} tokens down to {}",
+            compaction.messages, compaction.tokens_before, compaction.tokens_after
+        );
+        count!("llm.compactions");
+        Ok(Some(compaction))
+    }
+
+    // The summarisation request: instructions, then the turns as a plain transcript
+    fn prompt(&self, messages: &[&Message], focus: Option<&str>) -> String {
+        let mut prompt = INSTRUCTIONS.to_string();
+        if let Some(focus) = focus.map(str::trim).filter(|focus| !focus.is_empty()) {
+            let _ = write!(prompt, "\n\nPay particular attention to: {}", focus);
+        }
+        prompt.push_str("\n\n<conversation>\n");
+        for message in messages {
+            self.write_message(&mut prompt, message);
+        }
+        prompt.push_str("</conversation>");
+        prompt
+    }
+
+    fn write_message(&self, out: &mut String, message: &Message) {
+        let max = self.max_tool_result_chars;
+        match message.role {
+            MessageRole::System if message.is_summary() => {
+                let _ = writeln!(out, "[earlier summary]\n{}", message.content);
+            }
+            MessageRole::System => {
+                let _ = writeln!(out, "[system]\n{}", message.content);
+            }
+            MessageRole::User => {
+                let _ = writeln!(out, "[user]\n{}", message.content);
+                for attachment in message.attachments.iter().flatten() {
+                    let _ = writeln!(out, "(attached {})", attachment.name);
+                }
+            }
+            MessageRole::Assistant => {
+                if !message.content.trim().is_empty() {
+                    let _ = writeln!(out, "[assistant]\n{}", message.content);
+                }
+                for call in message.tool_calls.iter().flatten() {
+                    let _ = writeln!(
+                        out,
+                        "[assistant called {}]\n{}",
+                        call.tool_id,
+                        truncate(&call.parameters.to_string(), max)
+                    );
+                }
+            }
+            MessageRole::Tool => match &message.tool_results {
+                Some(results) => {
+                    for result in results {
+                        let _ = writeln!(
+                            out,
+                            "[{} result]\n{}",
+                            result.tool_id,
+                            truncate(&result.result.to_string(), max)
+                        );
+                    }
+                }
+                // JSON-RPC results travel as the message text
+                None => {
+                    let _ = writeln!(out, "[tool result]\n{}", truncate(&message.content, max));
+                }
+            },
+        }
+    }
+}
+
+// The first `max` characters of `text`, noting how much was left out
+fn truncate(text: &str, max: usize) -> String {
+    match text.char_indices().nth(max) {
+        Some((at, _)) => format!(
+            "{} ... [{} more characters]",
+            &text[..at],
+            text[at..].chars().count()
+        ),
+        None => text.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client_trait::{LlmResponse, StreamChunk};
+    use async_trait::async_trait;
+    use futures::Stream;
+    use mcp_core::context::{ToolCall, ToolResult};
+    use serde_json::json;
+    use std::sync::Mutex;
+
+    // Replies with a fixed summary and keeps the requests it was sent
+    #[derive(Default)]
+    struct SummarisingClient {
+        requests: Mutex<Vec<(ModelTask, ConversationContext)>>,
+    }
+
+    #[async_trait]
+    impl LlmClient for SummarisingClient {
+        async fn send_message(&self, _context: &ConversationContext) -> Result<LlmResponse> {
+            Err(anyhow!("compaction should use send_task"))
+        }
+
+        async fn stream_message(
+            &self,
+            _context: &ConversationContext,
+        ) -> Result<Box<dyn Stream<Item = Result<StreamChunk>> + Unpin + Send>> {
+            Ok(Box::new(futures::stream::empty()))
+        }
+
+        fn cancel_request(&self, _request_id: &str) -> Result<()> {
+            Ok(())
+        }
+
+        async fn send_task(
+            &self,
+            task: ModelTask,
+            context: &ConversationContext,
+        ) -> Result<LlmResponse> {
+            self.requests.lock().unwrap().push((task, context.clone()));
+            Ok(LlmResponse {
+                id: "msg".to_string(),
+                content: "  The user is exploring a Rust workspace.\n".to_string(),
+                tool_calls: Vec::new(),
+                usage: None,
+                thinking: Vec::new(),
+            })
+        }
+    }
+
+    fn conversation() -> ConversationContext {
+        let mut context = ConversationContext::new();
+        context.add_user_message("Show me the manifest");
+        context.add_assistant_tool_calls(
+            "",
+            vec![ToolCall {
+                tool_id: "shell".to_string(),
+                parameters: json!({"command": "cat Cargo.toml"}),
+                call_id: "call-1".to_string(),
+            }],
+        );
+        context.add_tool_result(
+            "",
+            ToolResult {
+                tool_id: "shell".to_string(),
+                result: json!({"stdout": "x".repeat(10_000)}),
+                call_id: "call-1".to_string(),
+            },
+        );
+        context.add_assistant_message("It is a workspace with nine crates.");
+        context.add_user_message("Which one has the CLI?");
+        context.add_assistant_message("mcpterm-cli");
+        context
+    }
+
+    #[tokio::test]
+    async fn test_compacts_older_turns() {
+        let client = SummarisingClient::default();
+        let compactor = Compactor::new(&CompactionConfig {
+            keep_recent_turns: 1,
+            max_tool_result_chars: 100,
+            ..CompactionConfig::default()
+        });
+
+        let mut context = conversation();
+        let compaction = compactor
+            .compact(&client, &mut context, Some("crate names"))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(compaction.messages, 4);
+        assert!(compaction.tokens_after < compaction.tokens_before);
+
+        assert_eq!(context.messages.len(), 3);
+        assert!(context.messages[0].is_summary());
+        assert!(context.messages[0]
+            .content
+            .ends_with("The user is exploring a Rust workspace."));
+        assert_eq!(context.messages[1].content, "Which one has the CLI?");
+
+        let requests = client.requests.lock().unwrap();
+        let (task, request) = &requests[0];
+        assert_eq!(*task, ModelTask::Summarize);
+        let prompt = &request.messages[0].content;
+        assert!(prompt.contains("Pay particular attention to: crate names"));
+        assert!(prompt.contains("[assistant called shell]"));
+        assert!(prompt.contains("more characters]"));
+        assert!(prompt.len() < 2000);
+        // The kept turn isn't summarised
+        assert!(!prompt.contains("Which one has the CLI?"));
+    }
+
+    #[tokio::test]
+    async fn test_nothing_to_compact() {
+        let client = SummarisingClient::default();
+        let compactor = Compactor::new(&CompactionConfig::default());
+
+        let mut context = conversation();
+        assert!(compactor
+            .compact(&client, &mut context, None)
+            .await
+            .unwrap()
+            .is_none());
+        assert_eq!(context.messages.len(), 6);
+
+        // A summary followed only by recent turns is already compact
+        let compactor = Compactor::new(&CompactionConfig {
+            keep_recent_turns: 1,
+            ..CompactionConfig::default()
+        });
+        compactor
+            .compact(&client, &mut context, None)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(compactor
+            .compact(&client, &mut context, None)
+            .await
+            .unwrap()
+            .is_none());
+        assert_eq!(client.requests.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_truncate_on_char_boundary() {
+        assert_eq!(truncate("héllo", 10), "héllo");
+        assert_eq!(truncate("héllo", 2), "hé ... [3 more characters]");
+    }
+}
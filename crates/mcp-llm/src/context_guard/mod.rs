@@ -0,0 +1,187 @@
+use crate::client_trait::{LlmClient, LlmResponse, StreamChunk};
+use crate::retry::ErrorClass;
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use futures::Stream;
+use mcp_core::context::{ContextWindow, ConversationContext};
+use mcp_core::{ContextUsage, ModelTask};
+use mcp_metrics::count;
+use std::sync::Arc;
+use tracing::warn;
+
+/// A request that was refused before sending because it would not fit in the context window
+#[derive(Debug, thiserror::Error)]
+#[error(
+    "The conversation is about {} tokens, which with {} tokens reserved for the reply exceeds the {}-token context window",
+    .0.prompt_tokens,
+    .0.reply_tokens,
+    .0.context_window
+)]
+pub struct ContextOverflow(pub ContextUsage);
+
+impl ContextOverflow {
+    pub fn class(&self) -> ErrorClass {
+        ErrorClass::ContextTooLong
+    }
+}
+
+/// Estimates the size of each request and refuses those that would overflow the model's
+/// context window, instead of sending them and getting a validation error back.
+///
+/// Counts are estimates, so a request close to the limit may still be rejected by the
+/// provider; that error is classified as `ContextTooLong` too.
+pub struct ContextGuard {
+    inner: Arc<dyn LlmClient>,
+    context_window: usize,
+    reply_tokens: usize,
+    overhead_tokens: usize,
+    window: Option<Arc<dyn ContextWindow>>,
+}
+
+impl ContextGuard {
+    /// Guard `inner`, leaving room for a reply of up to `reply_tokens` (the `max_tokens`
+    /// the client sends)
+    pub fn new(inner: Arc<dyn LlmClient>, context_window: usize, reply_tokens: usize) -> Self {
+        Self {
+            inner,
+            context_window,
+            reply_tokens,
+            overhead_tokens: 0,
+            window: None,
+        }
+    }
+
+    /// Count tokens the client adds to every request besides the conversation, such as its
+    /// own system prompt and tool descriptions
+    pub fn with_overhead(mut self, tokens: usize) -> Self {
+        self.overhead_tokens = tokens;
+        self
+    }
+
+    /// Measure only the messages this strategy sends, when the guarded client applies one
+    pub fn with_window(mut self, window: Arc<dyn ContextWindow>) -> Self {
+        self.window = Some(window);
+        self
+    }
+
+    /// How much of the context window a request for this conversation would take up
+    pub fn usage(&self, context: &ConversationContext) -> ContextUsage {
+        let prompt_tokens = match &self.window {
+            Some(window) => context.windowed(window.as_ref()).estimated_tokens(),
+            None => context.estimated_tokens(),
+        };
+        ContextUsage::new(
+            prompt_tokens + self.overhead_tokens,
+            self.reply_tokens,
+            self.context_window,
+        )
+    }
+
+    fn check(&self, context: &ConversationContext) -> Result<()> {
+        let usage = self.usage(context);
+        if usage.fits() {
+            return Ok(());
+        }
+
+        warn!(
+            "Refusing request of about {} tokens for a {}-token context window",
+            usage.prompt_tokens, usage.context_window
+        );
+        count!("llm.context_overflows");
+        Err(anyhow!(ContextOverflow(usage)))
+    }
+}
+
+#[async_trait]
+impl LlmClient for ContextGuard {
+    async fn send_message(&self, context: &ConversationContext) -> Result<LlmResponse> {
+        self.check(context)?;
+        self.inner.send_message(context).await
+    }
+
+    async fn stream_message(
+        &self,
+        context: &ConversationContext,
+    ) -> Result<Box<dyn Stream<Item = Result<StreamChunk>> + Unpin + Send>> {
+        self.check(context)?;
+        self.inner.stream_message(context).await
+    }
+
+    fn cancel_request(&self, request_id: &str) -> Result<()> {
+        self.inner.cancel_request(request_id)
+    }
+
+    async fn send_task(
+        &self,
+        task: ModelTask,
+        context: &ConversationContext,
+    ) -> Result<LlmResponse> {
+        self.check(context)?;
+        self.inner.send_task(task, context).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::retry::classify;
+    use mcp_core::context::SlidingWindow;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    // Counts the requests that get through
+    #[derive(Default)]
+    struct CountingClient {
+        calls: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl LlmClient for CountingClient {
+        async fn send_message(&self, _context: &ConversationContext) -> Result<LlmResponse> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            Ok(LlmResponse {
+                id: "msg".to_string(),
+                content: "ok".to_string(),
+                tool_calls: Vec::new(),
+                usage: None,
+                thinking: Vec::new(),
+            })
+        }
+
+        async fn stream_message(
+            &self,
+            _context: &ConversationContext,
+        ) -> Result<Box<dyn Stream<Item = Result<StreamChunk>> + Unpin + Send>> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            Ok(Box::new(futures::stream::empty()))
+        }
+
+        fn cancel_request(&self, _request_id: &str) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_overflowing_requests_are_not_sent() {
+        let inner = Arc::new(CountingClient::default());
+        let guard = ContextGuard::new(inner.clone(), 1_000, 200).with_overhead(100);
+
+        let mut context = ConversationContext::new();
+        context.add_user_message("hello");
+        guard.send_message(&context).await.unwrap();
+
+        // ~750 tokens of history plus overhead and the reply no longer fit
+        context.add_assistant_message(&"x".repeat(3_000));
+        assert!(!guard.usage(&context).fits());
+
+        let error = guard.stream_message(&context).await.err().unwrap();
+        assert_eq!(classify(&error), ErrorClass::ContextTooLong);
+        assert!(error.to_string().contains("1000-token context window"));
+        assert_eq!(inner.calls.load(Ordering::SeqCst), 1);
+
+        // Sent through a window that only keeps the last turn, the request fits again
+        context.add_user_message("and now?");
+        let guard = guard.with_window(Arc::new(SlidingWindow { max_turns: 1 }));
+        assert!(guard.usage(&context).fits());
+        guard.send_message(&context).await.unwrap();
+    }
+}
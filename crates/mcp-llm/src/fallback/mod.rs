@@ -0,0 +1,306 @@
+use crate::client_trait::{LlmClient, LlmResponse, StreamChunk};
+use crate::retry::{classify, ErrorClass};
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use futures::Stream;
+use mcp_core::context::ConversationContext;
+use mcp_core::ModelTask;
+use mcp_metrics::count;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use tracing::warn;
+
+/// One model/region pair in a fallback chain
+pub struct FallbackTarget {
+    /// Shown to the user when the chain moves to this target, e.g. `model-id (us-west-2)`
+    pub label: String,
+    pub client: Arc<dyn LlmClient>,
+}
+
+impl FallbackTarget {
+    pub fn new(label: impl Into<String>, client: Arc<dyn LlmClient>) -> Self {
+        Self {
+            label: label.into(),
+            client,
+        }
+    }
+}
+
+/// Notice that a request moved from one target to the next
+#[derive(Debug, Clone)]
+pub struct FallbackEvent {
+    pub from: String,
+    pub to: String,
+    pub class: ErrorClass,
+    pub error: String,
+}
+
+pub type FallbackListener = Arc<dyn Fn(&FallbackEvent) + Send + Sync>;
+
+/// Tries an ordered list of model/region pairs, moving on when a target is throttled, out
+/// of capacity or not available to the account.
+///
+/// The target that last succeeded stays active, so a saturated region isn't tried first on
+/// every request; later requests only return to earlier targets when the active one fails.
+/// Streams fall back only when they fail to start, since a half-streamed answer can't be
+/// replayed from another model.
+pub struct FallbackClient {
+    targets: Vec<FallbackTarget>,
+    active: Mutex<usize>,
+    listener: Option<FallbackListener>,
+}
+
+impl FallbackClient {
+    pub fn new(primary: FallbackTarget) -> Self {
+        Self {
+            targets: vec![primary],
+            active: Mutex::new(0),
+            listener: None,
+        }
+    }
+
+    pub fn with_fallback(mut self, target: FallbackTarget) -> Self {
+        self.targets.push(target);
+        self
+    }
+
+    /// Call `listener` whenever a request moves to another target
+    pub fn with_listener(mut self, listener: FallbackListener) -> Self {
+        self.listener = Some(listener);
+        self
+    }
+
+    /// Label of the target requests are currently sent to
+    pub fn active_model(&self) -> &str {
+        &self.targets[*self.active.lock().unwrap()].label
+    }
+
+    // Errors that another model or region may not run into
+    fn should_fall_back(class: ErrorClass) -> bool {
+        matches!(
+            class,
+            ErrorClass::Throttled
+                | ErrorClass::Transient
+                | ErrorClass::ModelNotAvailable
+                | ErrorClass::Auth
+        )
+    }
+
+    // Run a request against each target in turn, starting from the active one
+    async fn run<T, F, Fut>(&self, mut request: F) -> Result<T>
+    where
+        F: FnMut(Arc<dyn LlmClient>) -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let start = *self.active.lock().unwrap();
+        let mut last_error = None;
+
+        for offset in 0..self.targets.len() {
+            let index = (start + offset) % self.targets.len();
+            let target = &self.targets[index];
+
+            match request(target.client.clone()).await {
+                Ok(result) => {
+                    *self.active.lock().unwrap() = index;
+                    return Ok(result);
+                }
+                Err(e) => {
+                    let class = classify(&e);
+                    if !Self::should_fall_back(class) {
+                        return Err(e);
+                    }
+
+                    if offset + 1 < self.targets.len() {
+                        let next = &self.targets[(index + 1) % self.targets.len()];
+                        warn!(
+                            "{} failed ({}), falling back to {}: {:#}",
+                            target.label,
+                            class.as_str(),
+                            next.label,
+                            e
+                        );
+                        count!("llm.fallbacks");
+                        if let Some(listener) = &self.listener {
+                            listener(&FallbackEvent {
+                                from: target.label.clone(),
+                                to: next.label.clone(),
+                                class,
+                                error: format!("{:#}", e),
+                            });
+                        }
+                    }
+                    last_error = Some(e);
+                }
+            }
+        }
+
+        Err(last_error.unwrap_or_else(|| anyhow!("No models configured")))
+    }
+}
+
+#[async_trait]
+impl LlmClient for FallbackClient {
+    async fn send_message(&self, context: &ConversationContext) -> Result<LlmResponse> {
+        self.run(|client| async move { client.send_message(context).await })
+            .await
+    }
+
+    async fn stream_message(
+        &self,
+        context: &ConversationContext,
+    ) -> Result<Box<dyn Stream<Item = Result<StreamChunk>> + Unpin + Send>> {
+        self.run(|client| async move { client.stream_message(context).await })
+            .await
+    }
+
+    fn cancel_request(&self, request_id: &str) -> Result<()> {
+        for target in &self.targets {
+            target.client.cancel_request(request_id)?;
+        }
+        Ok(())
+    }
+
+    async fn send_task(
+        &self,
+        task: ModelTask,
+        context: &ConversationContext,
+    ) -> Result<LlmResponse> {
+        self.run(|client| async move { client.send_task(task, context).await })
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bedrock::BedrockError;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    // Fails with the given error, or answers with its name
+    struct TestClient {
+        name: &'static str,
+        error: Option<fn() -> BedrockError>,
+        calls: AtomicUsize,
+    }
+
+    impl TestClient {
+        fn ok(name: &'static str) -> Arc<Self> {
+            Arc::new(Self {
+                name,
+                error: None,
+                calls: AtomicUsize::new(0),
+            })
+        }
+
+        fn failing(name: &'static str, error: fn() -> BedrockError) -> Arc<Self> {
+            Arc::new(Self {
+                name,
+                error: Some(error),
+                calls: AtomicUsize::new(0),
+            })
+        }
+    }
+
+    #[async_trait]
+    impl LlmClient for TestClient {
+        async fn send_message(&self, _context: &ConversationContext) -> Result<LlmResponse> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            if let Some(error) = self.error {
+                return Err(error().into());
+            }
+            Ok(LlmResponse {
+                id: "msg".to_string(),
+                content: self.name.to_string(),
+                tool_calls: Vec::new(),
+                usage: None,
+                thinking: Vec::new(),
+            })
+        }
+
+        async fn stream_message(
+            &self,
+            _context: &ConversationContext,
+        ) -> Result<Box<dyn Stream<Item = Result<StreamChunk>> + Unpin + Send>> {
+            Ok(Box::new(futures::stream::empty()))
+        }
+
+        fn cancel_request(&self, _request_id: &str) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    fn throttled() -> BedrockError {
+        BedrockError::Throttled("Too many requests".to_string())
+    }
+
+    #[tokio::test]
+    async fn test_falls_back_and_stays_on_working_target() {
+        let primary = TestClient::failing("primary", throttled);
+        let secondary = TestClient::ok("secondary");
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let recorded = events.clone();
+
+        let client = FallbackClient::new(FallbackTarget::new("primary", primary.clone()))
+            .with_fallback(FallbackTarget::new("secondary", secondary.clone()))
+            .with_listener(Arc::new(move |event: &FallbackEvent| {
+                recorded.lock().unwrap().push(event.clone())
+            }));
+
+        let context = ConversationContext::new();
+        assert_eq!(
+            client.send_message(&context).await.unwrap().content,
+            "secondary"
+        );
+        assert_eq!(client.active_model(), "secondary");
+
+        let event = {
+            let events = events.lock().unwrap();
+            assert_eq!(events.len(), 1);
+            events[0].clone()
+        };
+        assert_eq!(event.from, "primary");
+        assert_eq!(event.to, "secondary");
+        assert_eq!(event.class, ErrorClass::Throttled);
+
+        // The saturated primary isn't tried again while the fallback works
+        client.send_message(&context).await.unwrap();
+        assert_eq!(primary.calls.load(Ordering::SeqCst), 1);
+        assert_eq!(secondary.calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_other_errors_do_not_fall_back() {
+        let primary = TestClient::failing("primary", || {
+            BedrockError::ApiError("Malformed input".to_string())
+        });
+        let secondary = TestClient::ok("secondary");
+
+        let client = FallbackClient::new(FallbackTarget::new("primary", primary))
+            .with_fallback(FallbackTarget::new("secondary", secondary.clone()));
+
+        assert!(client
+            .send_message(&ConversationContext::new())
+            .await
+            .is_err());
+        assert_eq!(secondary.calls.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn test_last_error_returned_when_all_fail() {
+        let client = FallbackClient::new(FallbackTarget::new(
+            "primary",
+            TestClient::failing("primary", throttled),
+        ))
+        .with_fallback(FallbackTarget::new(
+            "secondary",
+            TestClient::failing("secondary", throttled),
+        ));
+
+        let error = client
+            .send_message(&ConversationContext::new())
+            .await
+            .unwrap_err();
+        assert_eq!(classify(&error), ErrorClass::Throttled);
+        assert_eq!(client.active_model(), "primary");
+    }
+}
@@ -1,12 +1,44 @@
 pub mod anthropic;
 pub mod bedrock;
+pub mod cancel;
+pub mod cassette;
 pub mod client_trait;
+pub mod compaction;
+pub mod context_guard;
+pub mod fallback;
+pub mod middleware;
+pub mod openai;
+pub mod prompt_cache;
+pub mod rate_limit;
+pub mod retry;
+pub mod router;
 pub mod schema;
 pub mod streaming;
+pub mod structured;
+pub mod thinking;
 
-pub use client_trait::{LlmClient, LlmResponse, StreamChunk, ToolCall};
+pub use cancel::{ActiveRequests, RequestGuard};
+pub use client_trait::{
+    LlmClient, LlmResponse, StreamChunk, ThinkingBlock, TokenUsage, ToolCall, ToolDefinition,
+};
+pub use compaction::{Compaction, Compactor};
+pub use context_guard::{ContextGuard, ContextOverflow};
+pub use fallback::{FallbackClient, FallbackEvent, FallbackTarget};
+pub use middleware::{
+    AuditLayer, AuditListener, AuditRecord, Layer, LayerStack, LoggingLayer, MetricsLayer,
+    RateLimitLayer, RedactionLayer, RetryLayer, WindowLayer,
+};
+pub use rate_limit::{
+    RateLimitEvent, RateLimitListener, RateLimitPermit, RateLimitReason, RateLimiter, RateLimits,
+};
+pub use retry::{classify, ErrorClass, RetryPolicy};
+pub use router::RoutingClient;
 pub use schema::McpSchemaManager;
+pub use structured::{send_structured_as, OutputSchema, StructuredOutputError};
+pub use thinking::ThinkingConfig;
 
 // Re-export specific implementations
-pub use anthropic::AnthropicClient;
+pub use anthropic::{AnthropicClient, AnthropicConfig, AnthropicError};
 pub use bedrock::{BedrockClient, BedrockConfig, BedrockError};
+pub use cassette::{Cassette, RecordingClient, ReplayClient};
+pub use openai::{OpenAiClient, OpenAiConfig, OpenAiError};
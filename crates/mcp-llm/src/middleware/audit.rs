@@ -0,0 +1,244 @@
+use super::Layer;
+use crate::client_trait::{LlmClient, LlmResponse, StreamChunk, TokenUsage};
+use anyhow::Result;
+use async_trait::async_trait;
+use futures::{Stream, StreamExt};
+use mcp_core::context::ConversationContext;
+use mcp_core::ModelTask;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// What an `AuditLayer` knows about one finished request
+#[derive(Debug, Clone)]
+pub struct AuditRecord {
+    /// The name the layer was created with
+    pub client: String,
+    /// Set for requests made through `send_task`
+    pub task: Option<ModelTask>,
+    pub streamed: bool,
+    pub messages: usize,
+    pub estimated_tokens: usize,
+    /// Until the reply, or the end of the stream
+    pub duration: Duration,
+    pub usage: Option<TokenUsage>,
+    pub tool_calls: usize,
+    pub error: Option<String>,
+}
+
+/// Called once for every request that finishes, successfully or not
+pub type AuditListener = Arc<dyn Fn(&AuditRecord) + Send + Sync>;
+
+/// Reports every request to a listener, for request auditing or accounting that lives
+/// outside this crate. Streams are reported when their completion chunk or first error
+/// passes through; a stream dropped before either is not reported.
+#[derive(Clone)]
+pub struct AuditLayer {
+    name: String,
+    listener: AuditListener,
+}
+
+impl AuditLayer {
+    pub fn new(name: impl Into<String>, listener: AuditListener) -> Self {
+        Self {
+            name: name.into(),
+            listener,
+        }
+    }
+}
+
+impl Layer for AuditLayer {
+    fn layer(&self, inner: Arc<dyn LlmClient>) -> Arc<dyn LlmClient> {
+        Arc::new(Audit {
+            inner,
+            layer: self.clone(),
+        })
+    }
+}
+
+struct Audit {
+    inner: Arc<dyn LlmClient>,
+    layer: AuditLayer,
+}
+
+impl Audit {
+    fn record(
+        &self,
+        task: Option<ModelTask>,
+        streamed: bool,
+        context: &ConversationContext,
+    ) -> AuditRecord {
+        AuditRecord {
+            client: self.layer.name.clone(),
+            task,
+            streamed,
+            messages: context.messages.len(),
+            estimated_tokens: context.estimated_tokens(),
+            duration: Duration::ZERO,
+            usage: None,
+            tool_calls: 0,
+            error: None,
+        }
+    }
+
+    fn finish(&self, mut record: AuditRecord, start: Instant, result: &Result<LlmResponse>) {
+        record.duration = start.elapsed();
+        match result {
+            Ok(response) => {
+                record.usage = response.usage;
+                record.tool_calls = response.tool_calls.len();
+            }
+            Err(e) => record.error = Some(format!("{:#}", e)),
+        }
+        (self.layer.listener)(&record);
+    }
+}
+
+#[async_trait]
+impl LlmClient for Audit {
+    async fn send_message(&self, context: &ConversationContext) -> Result<LlmResponse> {
+        let record = self.record(None, false, context);
+        let start = Instant::now();
+        let result = self.inner.send_message(context).await;
+        self.finish(record, start, &result);
+        result
+    }
+
+    async fn stream_message(
+        &self,
+        context: &ConversationContext,
+    ) -> Result<Box<dyn Stream<Item = Result<StreamChunk>> + Unpin + Send>> {
+        let mut record = self.record(None, true, context);
+        let start = Instant::now();
+        let stream = match self.inner.stream_message(context).await {
+            Ok(stream) => stream,
+            Err(e) => {
+                record.duration = start.elapsed();
+                record.error = Some(format!("{:#}", e));
+                (self.layer.listener)(&record);
+                return Err(e);
+            }
+        };
+
+        let listener = self.layer.listener.clone();
+        let mut reported = false;
+        let stream = stream.inspect(move |chunk| {
+            if reported {
+                return;
+            }
+            let finished = match chunk {
+                Ok(chunk) => {
+                    if chunk.tool_call.is_some() {
+                        record.tool_calls += 1;
+                    }
+                    if chunk.is_complete {
+                        record.usage = chunk.usage;
+                    }
+                    chunk.is_complete
+                }
+                Err(e) => {
+                    record.error = Some(format!("{:#}", e));
+                    true
+                }
+            };
+            if finished {
+                record.duration = start.elapsed();
+                reported = true;
+                listener(&record);
+            }
+        });
+        Ok(Box::new(stream))
+    }
+
+    fn cancel_request(&self, request_id: &str) -> Result<()> {
+        self.inner.cancel_request(request_id)
+    }
+
+    async fn send_task(
+        &self,
+        task: ModelTask,
+        context: &ConversationContext,
+    ) -> Result<LlmResponse> {
+        let record = self.record(Some(task), false, context);
+        let start = Instant::now();
+        let result = self.inner.send_task(task, context).await;
+        self.finish(record, start, &result);
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    // Streams a tool call and a completion chunk
+    struct ToolCallingClient;
+
+    fn chunk(tool_call: bool, is_complete: bool) -> StreamChunk {
+        StreamChunk {
+            id: "msg".to_string(),
+            content: String::new(),
+            is_tool_call: tool_call,
+            tool_call: tool_call.then(|| crate::client_trait::ToolCall {
+                id: "call_1".to_string(),
+                tool: "shell".to_string(),
+                params: serde_json::json!({}),
+            }),
+            is_complete,
+            usage: is_complete.then(|| TokenUsage::new(10, 5)),
+            thinking: String::new(),
+            thinking_block: None,
+        }
+    }
+
+    #[async_trait]
+    impl LlmClient for ToolCallingClient {
+        async fn send_message(&self, _context: &ConversationContext) -> Result<LlmResponse> {
+            Err(anyhow::anyhow!("not used"))
+        }
+
+        async fn stream_message(
+            &self,
+            _context: &ConversationContext,
+        ) -> Result<Box<dyn Stream<Item = Result<StreamChunk>> + Unpin + Send>> {
+            let chunks = vec![Ok(chunk(true, false)), Ok(chunk(false, true))];
+            Ok(Box::new(futures::stream::iter(chunks)))
+        }
+
+        fn cancel_request(&self, _request_id: &str) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_audits_requests_and_streams() {
+        let records = Arc::new(Mutex::new(Vec::new()));
+        let sink = records.clone();
+        let client = AuditLayer::new(
+            "test",
+            Arc::new(move |record: &AuditRecord| sink.lock().unwrap().push(record.clone())),
+        )
+        .layer(Arc::new(ToolCallingClient));
+
+        let mut context = ConversationContext::new();
+        context.add_user_message("hello");
+        assert!(client.send_message(&context).await.is_err());
+        let chunks: Vec<_> = client
+            .stream_message(&context)
+            .await
+            .unwrap()
+            .collect()
+            .await;
+        assert_eq!(chunks.len(), 2);
+
+        let records = records.lock().unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].error.as_deref(), Some("not used"));
+        assert!(!records[0].streamed);
+        assert!(records[1].streamed);
+        assert_eq!(records[1].messages, 1);
+        assert_eq!(records[1].tool_calls, 1);
+        assert_eq!(records[1].usage.as_ref().unwrap().output_tokens, 5);
+        assert!(records[1].error.is_none());
+    }
+}
@@ -0,0 +1,115 @@
+use super::Layer;
+use crate::client_trait::{LlmClient, LlmResponse, StreamChunk};
+use anyhow::Result;
+use async_trait::async_trait;
+use futures::Stream;
+use mcp_core::context::ConversationContext;
+use mcp_core::ModelTask;
+use std::sync::Arc;
+use std::time::Instant;
+use tracing::{debug, warn};
+
+/// Logs the size of each request and how it went
+#[derive(Debug, Clone)]
+pub struct LoggingLayer {
+    name: String,
+}
+
+impl LoggingLayer {
+    /// `name` prefixes every log line, e.g. the provider or model
+    pub fn new(name: impl Into<String>) -> Self {
+        Self { name: name.into() }
+    }
+}
+
+impl Layer for LoggingLayer {
+    fn layer(&self, inner: Arc<dyn LlmClient>) -> Arc<dyn LlmClient> {
+        Arc::new(Logging {
+            inner,
+            name: self.name.clone(),
+        })
+    }
+}
+
+struct Logging {
+    inner: Arc<dyn LlmClient>,
+    name: String,
+}
+
+impl Logging {
+    fn log_request(&self, kind: &str, context: &ConversationContext) {
+        debug!(
+            "{}: {} with {} messages (~{} tokens)",
+            self.name,
+            kind,
+            context.messages.len(),
+            context.estimated_tokens()
+        );
+    }
+
+    fn log_response(&self, start: Instant, result: &Result<LlmResponse>) {
+        match result {
+            Ok(response) => debug!(
+                "{}: reply {} in {:?}, {} characters, {} tool calls",
+                self.name,
+                response.id,
+                start.elapsed(),
+                response.content.len(),
+                response.tool_calls.len()
+            ),
+            Err(e) => warn!(
+                "{}: request failed after {:?}: {:#}",
+                self.name,
+                start.elapsed(),
+                e
+            ),
+        }
+    }
+}
+
+#[async_trait]
+impl LlmClient for Logging {
+    async fn send_message(&self, context: &ConversationContext) -> Result<LlmResponse> {
+        self.log_request("request", context);
+        let start = Instant::now();
+        let result = self.inner.send_message(context).await;
+        self.log_response(start, &result);
+        result
+    }
+
+    async fn stream_message(
+        &self,
+        context: &ConversationContext,
+    ) -> Result<Box<dyn Stream<Item = Result<StreamChunk>> + Unpin + Send>> {
+        self.log_request("streaming request", context);
+        let start = Instant::now();
+        let result = self.inner.stream_message(context).await;
+        match &result {
+            Ok(_) => debug!("{}: stream opened in {:?}", self.name, start.elapsed()),
+            Err(e) => warn!(
+                "{}: stream failed to open after {:?}: {:#}",
+                self.name,
+                start.elapsed(),
+                e
+            ),
+        }
+        result
+    }
+
+    fn cancel_request(&self, request_id: &str) -> Result<()> {
+        debug!("{}: cancelling {}", self.name, request_id);
+        self.inner.cancel_request(request_id)
+    }
+
+    async fn send_task(
+        &self,
+        task: ModelTask,
+        context: &ConversationContext,
+    ) -> Result<LlmResponse> {
+        self.log_request(&format!("{} request", task.as_str()), context);
+        let start = Instant::now();
+        let result = self.inner.send_task(task, context).await;
+        self.log_response(start, &result);
+        result
+    }
+}
@@ -0,0 +1,118 @@
+use super::Layer;
+use crate::client_trait::{LlmClient, LlmResponse, StreamChunk};
+use anyhow::Result;
+use async_trait::async_trait;
+use futures::{Stream, StreamExt};
+use mcp_core::context::ConversationContext;
+use mcp_core::ModelTask;
+use mcp_metrics::{count, time};
+use std::sync::Arc;
+
+/// Counts calls, completions, errors and tool calls, and times responses, under the
+/// `llm.*` metrics with `name` naming the provider, e.g. `llm.calls.bedrock` and
+/// `llm.response_time.bedrock`.
+///
+/// The provider clients leave these to this layer. Put it outside any `RetryLayer` so a
+/// call that is retried counts once; each failed attempt is counted by the retry policy.
+#[derive(Debug, Clone)]
+pub struct MetricsLayer {
+    name: String,
+}
+
+impl MetricsLayer {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self { name: name.into() }
+    }
+}
+
+impl Layer for MetricsLayer {
+    fn layer(&self, inner: Arc<dyn LlmClient>) -> Arc<dyn LlmClient> {
+        Arc::new(Metrics {
+            inner,
+            name: self.name.clone(),
+        })
+    }
+}
+
+struct Metrics {
+    inner: Arc<dyn LlmClient>,
+    name: String,
+}
+
+// Count a failure, whether the request failed or its stream did part way
+fn record_error(name: &str) {
+    count!("llm.errors");
+    count!(&format!("llm.errors.{}", name));
+}
+
+fn record_tool_calls(count: usize) {
+    if count > 0 {
+        count!("llm.tool_calls", count as u64);
+    }
+}
+
+impl Metrics {
+    fn record_call(&self) {
+        count!("llm.calls.total");
+        count!(&format!("llm.calls.{}", self.name));
+    }
+
+    fn record_response(&self, result: &Result<LlmResponse>) {
+        match result {
+            Ok(response) => {
+                count!("llm.completions.success");
+                record_tool_calls(response.tool_calls.len());
+            }
+            Err(_) => record_error(&self.name),
+        }
+    }
+}
+
+#[async_trait]
+impl LlmClient for Metrics {
+    async fn send_message(&self, context: &ConversationContext) -> Result<LlmResponse> {
+        self.record_call();
+        let result = time!(&format!("llm.response_time.{}", self.name), {
+            self.inner.send_message(context).await
+        });
+        self.record_response(&result);
+        result
+    }
+
+    async fn stream_message(
+        &self,
+        context: &ConversationContext,
+    ) -> Result<Box<dyn Stream<Item = Result<StreamChunk>> + Unpin + Send>> {
+        self.record_call();
+        let result = time!(&format!("llm.stream_open_time.{}", self.name), {
+            self.inner.stream_message(context).await
+        });
+        let stream = result.inspect_err(|_| record_error(&self.name))?;
+
+        let name = self.name.clone();
+        Ok(Box::new(stream.inspect(move |chunk| match chunk {
+            Ok(chunk) if chunk.is_complete => count!("llm.completions.success"),
+            Ok(chunk) if chunk.is_tool_call => record_tool_calls(1),
+            Ok(_) => {}
+            Err(_) => record_error(&name),
+        })))
+    }
+
+    fn cancel_request(&self, request_id: &str) -> Result<()> {
+        self.inner.cancel_request(request_id)
+    }
+
+    async fn send_task(
+        &self,
+        task: ModelTask,
+        context: &ConversationContext,
+    ) -> Result<LlmResponse> {
+        self.record_call();
+        count!(&format!("llm.tasks.{}", task.as_str()));
+        let result = time!(&format!("llm.response_time.{}", self.name), {
+            self.inner.send_task(task, context).await
+        });
+        self.record_response(&result);
+        result
+    }
+}
@@ -0,0 +1,173 @@
+// Composable layers around LlmClient, in the style of tower's Layer and ServiceBuilder
+mod audit;
+mod logging;
+mod metrics;
+mod rate_limit;
+mod redact;
+mod retry;
+mod window;
+
+pub use audit::{AuditLayer, AuditListener, AuditRecord};
+pub use logging::LoggingLayer;
+pub use metrics::MetricsLayer;
+pub use rate_limit::RateLimitLayer;
+pub use redact::RedactionLayer;
+pub use retry::RetryLayer;
+pub use window::WindowLayer;
+
+use crate::client_trait::LlmClient;
+use std::sync::Arc;
+
+/// Wraps a client in another client that adds behaviour around its requests.
+///
+/// Closures taking and returning an `Arc<dyn LlmClient>` are layers too, so existing
+/// wrappers such as `RecordingClient` or `ContextGuard` can be stacked without a layer type
+/// of their own.
+pub trait Layer: Send + Sync {
+    fn layer(&self, inner: Arc<dyn LlmClient>) -> Arc<dyn LlmClient>;
+}
+
+impl<F> Layer for F
+where
+    F: Fn(Arc<dyn LlmClient>) -> Arc<dyn LlmClient> + Send + Sync,
+{
+    fn layer(&self, inner: Arc<dyn LlmClient>) -> Arc<dyn LlmClient> {
+        self(inner)
+    }
+}
+
+/// An ordered stack of layers. The first layer added is the outermost, so it sees each
+/// request first and each response last.
+///
+/// ```ignore
+/// let client = LayerStack::new()
+///     .layer(LoggingLayer::new("bedrock"))
+///     .layer(MetricsLayer::new("bedrock"))
+///     .layer(RetryLayer::new("bedrock", RetryPolicy::default()))
+///     .service(Arc::new(bedrock_client));
+/// ```
+#[derive(Clone, Default)]
+pub struct LayerStack {
+    layers: Vec<Arc<dyn Layer>>,
+}
+
+impl LayerStack {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a layer inside the ones already added
+    pub fn layer(mut self, layer: impl Layer + 'static) -> Self {
+        self.layers.push(Arc::new(layer));
+        self
+    }
+
+    /// Add a layer if there is one, for layers that depend on configuration
+    pub fn option_layer(self, layer: Option<impl Layer + 'static>) -> Self {
+        match layer {
+            Some(layer) => self.layer(layer),
+            None => self,
+        }
+    }
+
+    /// Add all the layers of another stack inside the ones already added
+    pub fn extend(mut self, other: LayerStack) -> Self {
+        self.layers.extend(other.layers);
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.layers.is_empty()
+    }
+
+    /// Wrap `client` in the stack
+    pub fn service(&self, client: Arc<dyn LlmClient>) -> Arc<dyn LlmClient> {
+        self.layers
+            .iter()
+            .rev()
+            .fold(client, |inner, layer| layer.layer(inner))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client_trait::{LlmResponse, StreamChunk};
+    use anyhow::Result;
+    use async_trait::async_trait;
+    use futures::Stream;
+    use mcp_core::context::ConversationContext;
+
+    // Answers with the system prompt, so tests can see what the layers did to the request
+    struct EchoClient;
+
+    #[async_trait]
+    impl LlmClient for EchoClient {
+        async fn send_message(&self, context: &ConversationContext) -> Result<LlmResponse> {
+            Ok(LlmResponse {
+                id: "msg".to_string(),
+                content: context.system_prompt.clone(),
+                tool_calls: Vec::new(),
+                usage: None,
+                thinking: Vec::new(),
+            })
+        }
+
+        async fn stream_message(
+            &self,
+            _context: &ConversationContext,
+        ) -> Result<Box<dyn Stream<Item = Result<StreamChunk>> + Unpin + Send>> {
+            Ok(Box::new(futures::stream::empty()))
+        }
+
+        fn cancel_request(&self, _request_id: &str) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    // Appends its name to the system prompt on the way in
+    struct Tag {
+        inner: Arc<dyn LlmClient>,
+        name: &'static str,
+    }
+
+    #[async_trait]
+    impl LlmClient for Tag {
+        async fn send_message(&self, context: &ConversationContext) -> Result<LlmResponse> {
+            let mut context = context.clone();
+            context.system_prompt.push_str(self.name);
+            self.inner.send_message(&context).await
+        }
+
+        async fn stream_message(
+            &self,
+            context: &ConversationContext,
+        ) -> Result<Box<dyn Stream<Item = Result<StreamChunk>> + Unpin + Send>> {
+            self.inner.stream_message(context).await
+        }
+
+        fn cancel_request(&self, request_id: &str) -> Result<()> {
+            self.inner.cancel_request(request_id)
+        }
+    }
+
+    fn tag(name: &'static str) -> impl Layer {
+        move |inner: Arc<dyn LlmClient>| -> Arc<dyn LlmClient> { Arc::new(Tag { inner, name }) }
+    }
+
+    #[tokio::test]
+    async fn test_first_layer_is_outermost() {
+        let client = LayerStack::new()
+            .layer(tag("a"))
+            .option_layer(Some(tag("b")))
+            .option_layer(None::<fn(Arc<dyn LlmClient>) -> Arc<dyn LlmClient>>)
+            .extend(LayerStack::new().layer(tag("c")))
+            .service(Arc::new(EchoClient));
+
+        let response = client
+            .send_message(&ConversationContext::new())
+            .await
+            .unwrap();
+        assert_eq!(response.content, "abc");
+    }
+}
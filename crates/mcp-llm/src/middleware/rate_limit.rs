@@ -0,0 +1,100 @@
+use super::Layer;
+use crate::client_trait::{LlmClient, LlmResponse, StreamChunk};
+use crate::rate_limit::RateLimiter;
+use anyhow::Result;
+use async_trait::async_trait;
+use futures::{Stream, StreamExt};
+use mcp_core::context::ConversationContext;
+use mcp_core::ModelTask;
+use std::sync::Arc;
+
+/// Holds requests back until the model's `RateLimiter` has budget for them. The prompt is
+/// counted up front from its estimated size, and the reply once its usage is known; a
+/// stream keeps its concurrency slot until it is dropped.
+#[derive(Clone)]
+pub struct RateLimitLayer {
+    limiter: RateLimiter,
+}
+
+impl RateLimitLayer {
+    pub fn new(limiter: RateLimiter) -> Self {
+        Self { limiter }
+    }
+}
+
+impl Layer for RateLimitLayer {
+    fn layer(&self, inner: Arc<dyn LlmClient>) -> Arc<dyn LlmClient> {
+        Arc::new(RateLimited {
+            inner,
+            limiter: self.limiter.clone(),
+        })
+    }
+}
+
+struct RateLimited {
+    inner: Arc<dyn LlmClient>,
+    limiter: RateLimiter,
+}
+
+impl RateLimited {
+    fn record(&self, result: &Result<LlmResponse>) {
+        if let Some(usage) = result.as_ref().ok().and_then(|r| r.usage) {
+            self.limiter.record_tokens(usage.output_tokens);
+        }
+    }
+}
+
+#[async_trait]
+impl LlmClient for RateLimited {
+    async fn send_message(&self, context: &ConversationContext) -> Result<LlmResponse> {
+        let _permit = self
+            .limiter
+            .acquire(context.estimated_tokens() as u64)
+            .await;
+        let result = self.inner.send_message(context).await;
+        self.record(&result);
+        result
+    }
+
+    async fn stream_message(
+        &self,
+        context: &ConversationContext,
+    ) -> Result<Box<dyn Stream<Item = Result<StreamChunk>> + Unpin + Send>> {
+        let permit = self
+            .limiter
+            .acquire(context.estimated_tokens() as u64)
+            .await;
+        let stream = self.inner.stream_message(context).await?;
+
+        let limiter = self.limiter.clone();
+        let stream = stream.inspect(move |chunk| {
+            // Keep the permit for as long as the stream lives
+            let _ = &permit;
+            if let Ok(StreamChunk {
+                usage: Some(usage), ..
+            }) = chunk
+            {
+                limiter.record_tokens(usage.output_tokens);
+            }
+        });
+        Ok(Box::new(stream))
+    }
+
+    fn cancel_request(&self, request_id: &str) -> Result<()> {
+        self.inner.cancel_request(request_id)
+    }
+
+    async fn send_task(
+        &self,
+        task: ModelTask,
+        context: &ConversationContext,
+    ) -> Result<LlmResponse> {
+        let _permit = self
+            .limiter
+            .acquire(context.estimated_tokens() as u64)
+            .await;
+        let result = self.inner.send_task(task, context).await;
+        self.record(&result);
+        result
+    }
+}
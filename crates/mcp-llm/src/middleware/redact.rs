@@ -0,0 +1,190 @@
+use super::Layer;
+use crate::client_trait::{LlmClient, LlmResponse, StreamChunk};
+use anyhow::Result;
+use async_trait::async_trait;
+use futures::Stream;
+use mcp_core::context::ConversationContext;
+use mcp_core::ModelTask;
+use mcp_metrics::count;
+use serde_json::Value;
+use std::sync::Arc;
+
+/// Replaces known secrets in outgoing requests, so they never reach the provider even when
+/// a tool result or pasted text contains them
+#[derive(Debug, Clone)]
+pub struct RedactionLayer {
+    secrets: Arc<Vec<String>>,
+    replacement: String,
+}
+
+impl RedactionLayer {
+    pub fn new(secrets: impl IntoIterator<Item = String>) -> Self {
+        Self {
+            secrets: Arc::new(secrets.into_iter().filter(|s| !s.is_empty()).collect()),
+            replacement: "[REDACTED]".to_string(),
+        }
+    }
+
+    /// Redact the values of these environment variables, such as API keys
+    pub fn from_env(names: &[&str]) -> Self {
+        Self::new(names.iter().filter_map(|name| std::env::var(name).ok()))
+    }
+
+    pub fn with_replacement(mut self, replacement: impl Into<String>) -> Self {
+        self.replacement = replacement.into();
+        self
+    }
+}
+
+impl Layer for RedactionLayer {
+    fn layer(&self, inner: Arc<dyn LlmClient>) -> Arc<dyn LlmClient> {
+        Arc::new(Redaction {
+            inner,
+            layer: self.clone(),
+        })
+    }
+}
+
+struct Redaction {
+    inner: Arc<dyn LlmClient>,
+    layer: RedactionLayer,
+}
+
+impl Redaction {
+    fn redact_text(&self, text: &mut String) -> bool {
+        let mut redacted = false;
+        for secret in self.layer.secrets.iter() {
+            if text.contains(secret.as_str()) {
+                *text = text.replace(secret.as_str(), &self.layer.replacement);
+                redacted = true;
+            }
+        }
+        redacted
+    }
+
+    fn redact_value(&self, value: &mut Value) -> bool {
+        match value {
+            Value::String(text) => self.redact_text(text),
+            // Every element has to be visited, so no short-circuiting any()
+            Value::Array(items) => items
+                .iter_mut()
+                .map(|item| self.redact_value(item))
+                .fold(false, |a, b| a | b),
+            Value::Object(fields) => fields
+                .values_mut()
+                .map(|field| self.redact_value(field))
+                .fold(false, |a, b| a | b),
+            _ => false,
+        }
+    }
+
+    // A copy of the context with every secret replaced
+    fn redact(&self, context: &ConversationContext) -> ConversationContext {
+        let mut context = context.clone();
+        let mut redacted = self.redact_text(&mut context.system_prompt);
+        for message in &mut context.messages {
+            redacted |= self.redact_text(&mut message.content);
+            for call in message.tool_calls.iter_mut().flatten() {
+                redacted |= self.redact_value(&mut call.parameters);
+            }
+            for result in message.tool_results.iter_mut().flatten() {
+                redacted |= self.redact_value(&mut result.result);
+            }
+        }
+        if redacted {
+            count!("llm.redactions");
+        }
+        context
+    }
+}
+
+#[async_trait]
+impl LlmClient for Redaction {
+    async fn send_message(&self, context: &ConversationContext) -> Result<LlmResponse> {
+        self.inner.send_message(&self.redact(context)).await
+    }
+
+    async fn stream_message(
+        &self,
+        context: &ConversationContext,
+    ) -> Result<Box<dyn Stream<Item = Result<StreamChunk>> + Unpin + Send>> {
+        self.inner.stream_message(&self.redact(context)).await
+    }
+
+    fn cancel_request(&self, request_id: &str) -> Result<()> {
+        self.inner.cancel_request(request_id)
+    }
+
+    async fn send_task(
+        &self,
+        task: ModelTask,
+        context: &ConversationContext,
+    ) -> Result<LlmResponse> {
+        self.inner.send_task(task, &self.redact(context)).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mcp_core::context::ToolResult;
+    use serde_json::json;
+    use std::sync::Mutex;
+
+    // Keeps the last context it was sent
+    #[derive(Default)]
+    struct CapturingClient {
+        last: Mutex<Option<ConversationContext>>,
+    }
+
+    #[async_trait]
+    impl LlmClient for CapturingClient {
+        async fn send_message(&self, context: &ConversationContext) -> Result<LlmResponse> {
+            *self.last.lock().unwrap() = Some(context.clone());
+            Ok(LlmResponse {
+                id: "msg".to_string(),
+                content: String::new(),
+                tool_calls: Vec::new(),
+                usage: None,
+                thinking: Vec::new(),
+            })
+        }
+
+        async fn stream_message(
+            &self,
+            _context: &ConversationContext,
+        ) -> Result<Box<dyn Stream<Item = Result<StreamChunk>> + Unpin + Send>> {
+            Ok(Box::new(futures::stream::empty()))
+        }
+
+        fn cancel_request(&self, _request_id: &str) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_secrets_do_not_reach_the_client() {
+        let inner = Arc::new(CapturingClient::default());
+        let client =
+            RedactionLayer::new(vec!["hunter2".to_string(), String::new()]).layer(inner.clone());
+
+        let mut context = ConversationContext::new();
+        context.add_user_message("The password is hunter2");
+        context.add_tool_result(
+            "",
+            ToolResult {
+                tool_id: "shell".to_string(),
+                result: json!({"stdout": ["PASSWORD=hunter2"]}),
+                call_id: "call_1".to_string(),
+            },
+        );
+        client.send_message(&context).await.unwrap();
+
+        let sent = inner.last.lock().unwrap().clone().unwrap();
+        assert_eq!(sent.messages[0].content, "The password is [REDACTED]");
+        let result = &sent.messages[1].tool_results.as_ref().unwrap()[0].result;
+        assert_eq!(result["stdout"][0], "PASSWORD=[REDACTED]");
+        // The caller's conversation keeps the original text
+        assert!(context.messages[0].content.contains("hunter2"));
+    }
+}
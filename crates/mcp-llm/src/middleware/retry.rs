@@ -0,0 +1,140 @@
+use super::Layer;
+use crate::client_trait::{LlmClient, LlmResponse, StreamChunk};
+use crate::retry::RetryPolicy;
+use anyhow::Result;
+use async_trait::async_trait;
+use futures::Stream;
+use mcp_core::context::ConversationContext;
+use mcp_core::ModelTask;
+use std::sync::Arc;
+
+/// Retries throttled and transient failures with a `RetryPolicy`.
+///
+/// The provider clients send each request once and leave retrying to this layer. Streams
+/// are only retried while opening, since chunks already forwarded can't be taken back.
+#[derive(Debug, Clone)]
+pub struct RetryLayer {
+    name: String,
+    policy: RetryPolicy,
+}
+
+impl RetryLayer {
+    /// `name` labels the `llm.retries.<name>` metric and log lines
+    pub fn new(name: impl Into<String>, policy: RetryPolicy) -> Self {
+        Self {
+            name: name.into(),
+            policy,
+        }
+    }
+}
+
+impl Layer for RetryLayer {
+    fn layer(&self, inner: Arc<dyn LlmClient>) -> Arc<dyn LlmClient> {
+        Arc::new(Retry {
+            inner,
+            name: self.name.clone(),
+            policy: self.policy.clone(),
+        })
+    }
+}
+
+struct Retry {
+    inner: Arc<dyn LlmClient>,
+    name: String,
+    policy: RetryPolicy,
+}
+
+#[async_trait]
+impl LlmClient for Retry {
+    async fn send_message(&self, context: &ConversationContext) -> Result<LlmResponse> {
+        self.policy
+            .run(&self.name, || self.inner.send_message(context))
+            .await
+    }
+
+    async fn stream_message(
+        &self,
+        context: &ConversationContext,
+    ) -> Result<Box<dyn Stream<Item = Result<StreamChunk>> + Unpin + Send>> {
+        self.policy
+            .run(&self.name, || self.inner.stream_message(context))
+            .await
+    }
+
+    fn cancel_request(&self, request_id: &str) -> Result<()> {
+        self.inner.cancel_request(request_id)
+    }
+
+    async fn send_task(
+        &self,
+        task: ModelTask,
+        context: &ConversationContext,
+    ) -> Result<LlmResponse> {
+        self.policy
+            .run(&self.name, || self.inner.send_task(task, context))
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bedrock::BedrockError;
+    use crate::middleware::LayerStack;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::time::Duration;
+
+    // Throttled for the first `failures` calls
+    struct FlakyClient {
+        failures: u32,
+        calls: AtomicU32,
+    }
+
+    #[async_trait]
+    impl LlmClient for FlakyClient {
+        async fn send_message(&self, _context: &ConversationContext) -> Result<LlmResponse> {
+            if self.calls.fetch_add(1, Ordering::SeqCst) < self.failures {
+                return Err(BedrockError::Throttled("Too many requests".to_string()).into());
+            }
+            Ok(LlmResponse {
+                id: "msg".to_string(),
+                content: "ok".to_string(),
+                tool_calls: Vec::new(),
+                usage: None,
+                thinking: Vec::new(),
+            })
+        }
+
+        async fn stream_message(
+            &self,
+            _context: &ConversationContext,
+        ) -> Result<Box<dyn Stream<Item = Result<StreamChunk>> + Unpin + Send>> {
+            Ok(Box::new(futures::stream::empty()))
+        }
+
+        fn cancel_request(&self, _request_id: &str) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_retries_throttled_requests() {
+        let inner = Arc::new(FlakyClient {
+            failures: 2,
+            calls: AtomicU32::new(0),
+        });
+        let policy = RetryPolicy::default()
+            .with_max_retries(3)
+            .with_backoff(Duration::from_millis(1), Duration::from_millis(1));
+        let client = LayerStack::new()
+            .layer(RetryLayer::new("test", policy))
+            .service(inner.clone());
+
+        let response = client
+            .send_message(&ConversationContext::new())
+            .await
+            .unwrap();
+        assert_eq!(response.content, "ok");
+        assert_eq!(inner.calls.load(Ordering::SeqCst), 3);
+    }
+}
@@ -0,0 +1,67 @@
+use super::Layer;
+use crate::client_trait::{LlmClient, LlmResponse, StreamChunk};
+use anyhow::Result;
+use async_trait::async_trait;
+use futures::Stream;
+use mcp_core::context::{ContextWindow, ConversationContext};
+use mcp_core::ModelTask;
+use std::sync::Arc;
+
+/// Sends each request with only the messages a `ContextWindow` strategy chooses. The
+/// caller's conversation is left whole, so the strategy can differ from model to model.
+#[derive(Clone)]
+pub struct WindowLayer {
+    window: Arc<dyn ContextWindow>,
+}
+
+impl WindowLayer {
+    pub fn new(window: Arc<dyn ContextWindow>) -> Self {
+        Self { window }
+    }
+}
+
+impl Layer for WindowLayer {
+    fn layer(&self, inner: Arc<dyn LlmClient>) -> Arc<dyn LlmClient> {
+        Arc::new(Windowed {
+            inner,
+            window: self.window.clone(),
+        })
+    }
+}
+
+struct Windowed {
+    inner: Arc<dyn LlmClient>,
+    window: Arc<dyn ContextWindow>,
+}
+
+#[async_trait]
+impl LlmClient for Windowed {
+    async fn send_message(&self, context: &ConversationContext) -> Result<LlmResponse> {
+        self.inner
+            .send_message(&context.windowed(self.window.as_ref()))
+            .await
+    }
+
+    async fn stream_message(
+        &self,
+        context: &ConversationContext,
+    ) -> Result<Box<dyn Stream<Item = Result<StreamChunk>> + Unpin + Send>> {
+        self.inner
+            .stream_message(&context.windowed(self.window.as_ref()))
+            .await
+    }
+
+    fn cancel_request(&self, request_id: &str) -> Result<()> {
+        self.inner.cancel_request(request_id)
+    }
+
+    async fn send_task(
+        &self,
+        task: ModelTask,
+        context: &ConversationContext,
+    ) -> Result<LlmResponse> {
+        self.inner
+            .send_task(task, &context.windowed(self.window.as_ref()))
+            .await
+    }
+}
@@ -0,0 +1,423 @@
+use crate::client_trait::{LlmResponse, TokenUsage, ToolCall as ClientToolCall, ToolDefinition};
+use mcp_core::context::{Attachment, AttachmentKind, ConversationContext, MessageRole};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Request body for `POST {base_url}/chat/completions`
+#[derive(Debug, Serialize)]
+pub struct ChatRequest<'a> {
+    pub model: &'a str,
+    pub messages: Vec<ChatMessage>,
+    pub max_tokens: usize,
+    pub temperature: f32,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tools: Vec<ChatTool<'a>>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stream_options: Option<StreamOptions>,
+}
+
+/// Asks the server to send a final usage event when streaming
+#[derive(Debug, Serialize)]
+pub struct StreamOptions {
+    pub include_usage: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ChatMessage {
+    pub role: String,
+    #[serde(default)]
+    pub content: Option<ChatContent>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tool_calls: Vec<ChatToolCall>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_call_id: Option<String>,
+}
+
+/// Message content: plain text, or a list of parts when images or files are attached
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(untagged)]
+pub enum ChatContent {
+    Text(String),
+    Parts(Vec<ChatContentPart>),
+}
+
+impl ChatContent {
+    /// The text of the content, joining text parts and skipping the rest
+    pub fn into_text(self) -> String {
+        match self {
+            ChatContent::Text(text) => text,
+            ChatContent::Parts(parts) => parts
+                .into_iter()
+                .filter_map(|part| match part {
+                    ChatContentPart::Text { text } => Some(text),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+                .join("\n"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ChatContentPart {
+    Text { text: String },
+    ImageUrl { image_url: ChatImageUrl },
+    File { file: ChatFile },
+}
+
+/// Image passed inline as a `data:` URL
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ChatImageUrl {
+    pub url: String,
+}
+
+/// Document passed inline as a `data:` URL
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ChatFile {
+    pub filename: String,
+    pub file_data: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ChatToolCall {
+    pub id: String,
+    #[serde(rename = "type", default = "function_type")]
+    pub call_type: String,
+    pub function: ChatFunctionCall,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ChatFunctionCall {
+    pub name: String,
+    /// JSON-encoded arguments, as the API sends them
+    pub arguments: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChatTool<'a> {
+    #[serde(rename = "type")]
+    pub tool_type: &'static str,
+    pub function: ChatFunction<'a>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChatFunction<'a> {
+    pub name: &'a str,
+    pub description: &'a str,
+    pub parameters: &'a Value,
+}
+
+/// Non-streaming response body
+#[derive(Debug, Deserialize)]
+pub struct ChatResponse {
+    #[serde(default)]
+    pub id: String,
+    pub choices: Vec<ChatChoice>,
+    #[serde(default)]
+    pub usage: Option<ChatUsage>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ChatChoice {
+    pub message: ChatMessage,
+}
+
+/// Token counts, in the non-streaming response and the final stream event
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct ChatUsage {
+    #[serde(default)]
+    pub prompt_tokens: u64,
+    #[serde(default)]
+    pub completion_tokens: u64,
+}
+
+impl From<ChatUsage> for TokenUsage {
+    fn from(usage: ChatUsage) -> Self {
+        TokenUsage::new(usage.prompt_tokens, usage.completion_tokens)
+    }
+}
+
+/// Error body returned with non-2xx statuses
+#[derive(Debug, Deserialize)]
+pub struct ErrorResponse {
+    pub error: ErrorDetail,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ErrorDetail {
+    pub message: String,
+}
+
+fn function_type() -> String {
+    "function".to_string()
+}
+
+/// Describe tool definitions as chat completion functions
+pub fn build_tools(tools: &[ToolDefinition]) -> Vec<ChatTool<'_>> {
+    tools
+        .iter()
+        .map(|tool| ChatTool {
+            tool_type: "function",
+            function: ChatFunction {
+                name: &tool.name,
+                description: &tool.description,
+                parameters: &tool.input_schema,
+            },
+        })
+        .collect()
+}
+
+/// Convert the conversation into chat completion messages.
+///
+/// Tool results with a call id become `tool` role messages answering the assistant's
+/// `tool_calls`; older tool messages without one are passed along as user text.
+pub fn build_messages(
+    context: &ConversationContext,
+    system_prompt: Option<&str>,
+) -> Vec<ChatMessage> {
+    let mut messages = Vec::new();
+
+    if let Some(system_prompt) = system_prompt.filter(|s| !s.trim().is_empty()) {
+        messages.push(text_message("system", system_prompt));
+    }
+
+    for message in &context.messages {
+        match message.role {
+            MessageRole::System => messages.push(text_message("system", &message.content)),
+            MessageRole::User => match &message.attachments {
+                Some(attachments) if !attachments.is_empty() => {
+                    messages.push(attachment_message(&message.content, attachments))
+                }
+                _ => messages.push(text_message("user", &message.content)),
+            },
+            MessageRole::Assistant => {
+                let tool_calls: Vec<_> = message
+                    .tool_calls
+                    .iter()
+                    .flatten()
+                    .filter(|call| !call.call_id.is_empty())
+                    .map(|call| ChatToolCall {
+                        id: call.call_id.clone(),
+                        call_type: function_type(),
+                        function: ChatFunctionCall {
+                            name: call.tool_id.clone(),
+                            arguments: call.parameters.to_string(),
+                        },
+                    })
+                    .collect();
+
+                messages.push(ChatMessage {
+                    role: "assistant".to_string(),
+                    content: (!message.content.is_empty())
+                        .then(|| ChatContent::Text(message.content.clone())),
+                    tool_calls,
+                    tool_call_id: None,
+                });
+            }
+            MessageRole::Tool => {
+                let results: Vec<_> = message
+                    .tool_results
+                    .iter()
+                    .flatten()
+                    .filter(|r| !r.call_id.is_empty())
+                    .collect();
+
+                if results.is_empty() {
+                    messages.push(text_message(
+                        "user",
+                        &format!("Tool result:\n{}", message.content),
+                    ));
+                }
+                for result in results {
+                    messages.push(ChatMessage {
+                        role: "tool".to_string(),
+                        content: Some(ChatContent::Text(result.result.to_string())),
+                        tool_calls: Vec::new(),
+                        tool_call_id: Some(result.call_id.clone()),
+                    });
+                }
+            }
+        }
+    }
+
+    messages
+}
+
+/// Convert a chat completion response into an `LlmResponse`
+pub fn parse_response(response: ChatResponse) -> Result<LlmResponse, String> {
+    let choice = response
+        .choices
+        .into_iter()
+        .next()
+        .ok_or_else(|| "Response contained no choices".to_string())?;
+
+    let tool_calls = choice
+        .message
+        .tool_calls
+        .into_iter()
+        .map(|call| {
+            Ok(ClientToolCall {
+                params: parse_arguments(&call.function.name, &call.function.arguments)?,
+                id: call.id,
+                tool: call.function.name,
+            })
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+
+    Ok(LlmResponse {
+        id: response.id,
+        content: choice
+            .message
+            .content
+            .map(ChatContent::into_text)
+            .unwrap_or_default(),
+        tool_calls,
+        usage: response.usage.map(TokenUsage::from),
+        thinking: Vec::new(),
+    })
+}
+
+/// Parse function call arguments, treating an empty string as no arguments
+pub fn parse_arguments(name: &str, arguments: &str) -> Result<Value, String> {
+    if arguments.trim().is_empty() {
+        return Ok(Value::Object(Default::default()));
+    }
+    serde_json::from_str(arguments)
+        .map_err(|e| format!("Invalid arguments for function {}: {}", name, e))
+}
+
+fn text_message(role: &str, content: &str) -> ChatMessage {
+    ChatMessage {
+        role: role.to_string(),
+        content: Some(ChatContent::Text(content.to_string())),
+        tool_calls: Vec::new(),
+        tool_call_id: None,
+    }
+}
+
+// A user message with attachments as image or file parts, followed by the text
+fn attachment_message(content: &str, attachments: &[Attachment]) -> ChatMessage {
+    let mut parts: Vec<_> = attachments
+        .iter()
+        .map(|attachment| match attachment.kind {
+            AttachmentKind::Image => ChatContentPart::ImageUrl {
+                image_url: ChatImageUrl {
+                    url: attachment.data_url(),
+                },
+            },
+            AttachmentKind::Document => ChatContentPart::File {
+                file: ChatFile {
+                    filename: attachment.name.clone(),
+                    file_data: attachment.data_url(),
+                },
+            },
+        })
+        .collect();
+    if !content.trim().is_empty() {
+        parts.push(ChatContentPart::Text {
+            text: content.to_string(),
+        });
+    }
+
+    ChatMessage {
+        role: "user".to_string(),
+        content: Some(ChatContent::Parts(parts)),
+        tool_calls: Vec::new(),
+        tool_call_id: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mcp_core::context::{ToolCall, ToolResult};
+    use serde_json::json;
+
+    #[test]
+    fn test_build_messages_with_tool_turns() {
+        let mut context = ConversationContext::new();
+        context.add_user_message("List files");
+        context.add_assistant_tool_calls(
+            "",
+            vec![ToolCall {
+                tool_id: "shell".to_string(),
+                parameters: json!({"command": "ls"}),
+                call_id: "call_1".to_string(),
+            }],
+        );
+        context.add_tool_result(
+            "{}",
+            ToolResult {
+                tool_id: "shell".to_string(),
+                result: json!({"status": "Success"}),
+                call_id: "call_1".to_string(),
+            },
+        );
+
+        let messages = build_messages(&context, Some("Be brief."));
+
+        assert_eq!(messages.len(), 4);
+        assert_eq!(messages[0].role, "system");
+        assert_eq!(messages[2].content, None);
+        assert_eq!(
+            messages[2].tool_calls[0].function.arguments,
+            r#"{"command":"ls"}"#
+        );
+        assert_eq!(messages[3].role, "tool");
+        assert_eq!(messages[3].tool_call_id.as_deref(), Some("call_1"));
+    }
+
+    #[test]
+    fn test_attachments_become_content_parts() {
+        let mut context = ConversationContext::new();
+        context.add_user_message_with_attachments(
+            "What is in this screenshot?",
+            vec![Attachment::new(
+                AttachmentKind::Image,
+                "image/png",
+                "shot.png",
+                b"png",
+            )],
+        );
+
+        let messages = build_messages(&context, None);
+        let body = serde_json::to_value(&messages[0]).unwrap();
+
+        assert_eq!(body["content"][0]["type"], "image_url");
+        assert!(body["content"][0]["image_url"]["url"]
+            .as_str()
+            .unwrap()
+            .starts_with("data:image/png;base64,"));
+        assert_eq!(body["content"][1]["type"], "text");
+        assert_eq!(body["content"][1]["text"], "What is in this screenshot?");
+    }
+
+    #[test]
+    fn test_parse_response_with_function_call() {
+        let response: ChatResponse = serde_json::from_value(json!({
+            "id": "chatcmpl-1",
+            "choices": [{
+                "index": 0,
+                "message": {
+                    "role": "assistant",
+                    "content": null,
+                    "tool_calls": [{
+                        "id": "call_1",
+                        "type": "function",
+                        "function": {"name": "shell", "arguments": "{\"command\":\"ls\"}"}
+                    }]
+                },
+                "finish_reason": "tool_calls"
+            }]
+        }))
+        .unwrap();
+
+        let response = parse_response(response).unwrap();
+        assert!(response.content.is_empty());
+        assert_eq!(response.tool_calls[0].id, "call_1");
+        assert_eq!(response.tool_calls[0].params, json!({"command": "ls"}));
+    }
+}
@@ -0,0 +1,359 @@
+use crate::cancel::ActiveRequests;
+use crate::client_trait::{LlmClient, LlmResponse, StreamChunk, TokenUsage, ToolDefinition};
+use crate::retry::ErrorClass;
+use crate::streaming::{OpenAiStreamDecoder, SseParser};
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use futures::{Stream, StreamExt};
+use mcp_core::context::ConversationContext;
+use mcp_metrics::count;
+use serde::{Deserialize, Serialize};
+use tokio_stream::wrappers::ReceiverStream;
+use tracing::{debug, error, trace, warn};
+
+mod messages;
+
+use messages::{ChatRequest, ChatResponse, ErrorResponse, StreamOptions};
+
+/// Ollama's OpenAI-compatible endpoint, the most common local setup
+pub const DEFAULT_BASE_URL: &str = "http://localhost:11434/v1";
+
+// OpenAI-compatible provider errors
+#[derive(Debug, thiserror::Error)]
+pub enum OpenAiError {
+    #[error("Chat completions API error ({status}): {message}")]
+    ApiError { status: u16, message: String },
+
+    #[error("Failed to parse chat completions response: {0}")]
+    ResponseParseError(String),
+
+    #[error("HTTP request failed: {0}")]
+    Http(String),
+
+    #[error("Request cancelled")]
+    Cancelled,
+}
+
+impl OpenAiError {
+    /// Classify the error for retry decisions and user-facing messages
+    pub fn class(&self) -> ErrorClass {
+        match self {
+            OpenAiError::ApiError { status, message } => {
+                ErrorClass::from_http_status(*status, message)
+            }
+            OpenAiError::Http(_) => ErrorClass::Transient,
+            _ => ErrorClass::Other,
+        }
+    }
+}
+
+fn default_base_url() -> String {
+    DEFAULT_BASE_URL.to_string()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OpenAiConfig {
+    pub model: String,
+    /// Base URL including the API version prefix, e.g. `http://localhost:8000/v1`
+    #[serde(default = "default_base_url")]
+    pub base_url: String,
+    /// Sent as a bearer token; local servers usually don't need one
+    #[serde(default)]
+    pub api_key: Option<String>,
+    pub max_tokens: usize,
+    pub temperature: f32,
+    #[serde(default)]
+    pub system_prompt: Option<String>,
+}
+
+impl OpenAiConfig {
+    pub fn new(model: String) -> Self {
+        Self {
+            model,
+            base_url: default_base_url(),
+            api_key: None,
+            max_tokens: 4096,
+            temperature: 0.7,
+            system_prompt: None,
+        }
+    }
+
+    pub fn with_base_url(mut self, base_url: String) -> Self {
+        self.base_url = base_url.trim_end_matches('/').to_string();
+        self
+    }
+
+    pub fn with_api_key(mut self, api_key: String) -> Self {
+        self.api_key = Some(api_key);
+        self
+    }
+
+    pub fn with_max_tokens(mut self, max_tokens: usize) -> Self {
+        self.max_tokens = max_tokens;
+        self
+    }
+
+    pub fn with_temperature(mut self, temperature: f32) -> Self {
+        self.temperature = temperature;
+        self
+    }
+
+    pub fn with_system_prompt(mut self, system_prompt: String) -> Self {
+        self.system_prompt = Some(system_prompt);
+        self
+    }
+}
+
+pub struct OpenAiClient {
+    config: OpenAiConfig,
+    http: reqwest::Client,
+    active_requests: ActiveRequests,
+    tool_definitions: Vec<ToolDefinition>,
+}
+
+impl OpenAiClient {
+    pub fn new(config: OpenAiConfig) -> Self {
+        debug!(
+            "Creating OpenAI-compatible client for model {} at {}",
+            config.model, config.base_url
+        );
+        Self {
+            config,
+            http: reqwest::Client::new(),
+            active_requests: ActiveRequests::new(),
+            tool_definitions: Vec::new(),
+        }
+    }
+
+    /// Attach tool definitions that are sent as function specs
+    pub fn with_tools(mut self, tools: Vec<ToolDefinition>) -> Self {
+        self.tool_definitions = tools;
+        self
+    }
+
+    // Serialize the conversation into a chat completions request body
+    fn build_request(&self, context: &ConversationContext, stream: bool) -> Result<Vec<u8>> {
+        let request = ChatRequest {
+            model: &self.config.model,
+            messages: messages::build_messages(context, self.config.system_prompt.as_deref()),
+            max_tokens: self.config.max_tokens,
+            temperature: self.config.temperature,
+            tools: messages::build_tools(&self.tool_definitions),
+            stream,
+            stream_options: stream.then_some(StreamOptions {
+                include_usage: true,
+            }),
+        };
+
+        trace!(
+            ">>> RAW REQUEST TO LLM >>>\n{}",
+            serde_json::to_string_pretty(&request).unwrap_or_default()
+        );
+
+        Ok(serde_json::to_vec(&request)?)
+    }
+
+    // POST the request and turn non-success statuses into API errors
+    async fn post_chat(&self, body: Vec<u8>) -> Result<reqwest::Response> {
+        let url = format!("{}/chat/completions", self.config.base_url);
+        debug!("Sending chat completions request: {}", url);
+
+        let mut request = self
+            .http
+            .post(&url)
+            .header("content-type", "application/json")
+            .body(body);
+        if let Some(api_key) = &self.config.api_key {
+            request = request.bearer_auth(api_key);
+        }
+
+        let response = request
+            .send()
+            .await
+            .map_err(|e| anyhow!(OpenAiError::Http(format!("{}: {}", url, e))))?;
+
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response);
+        }
+
+        // Prefer the structured error message when the body has one
+        let body = response.text().await.unwrap_or_default();
+        let message = serde_json::from_str::<ErrorResponse>(&body)
+            .map(|e| e.error.message)
+            .unwrap_or(body);
+
+        Err(anyhow!(OpenAiError::ApiError {
+            status: status.as_u16(),
+            message,
+        }))
+    }
+}
+
+#[async_trait]
+impl LlmClient for OpenAiClient {
+    async fn send_message(&self, context: &ConversationContext) -> Result<LlmResponse> {
+        debug!("Sending message to chat completions API");
+        let request = self.active_requests.register();
+
+        let body = self.build_request(context, false)?;
+        let result = request
+            .run(async {
+                match self.post_chat(body).await {
+                    Ok(response) => response
+                        .text()
+                        .await
+                        .map_err(|e| anyhow!(OpenAiError::Http(e.to_string()))),
+                    Err(e) => Err(e),
+                }
+            })
+            .await;
+
+        // A cancelled request has already been dropped, closing the connection
+        let Some(result) = result else {
+            debug!("Request {} was cancelled", request.id());
+            count!("llm.cancelled");
+            return Err(anyhow!(OpenAiError::Cancelled));
+        };
+
+        let response_str = result.inspect_err(|e| error!("Chat completions error: {}", e))?;
+        trace!("<<< RAW RESPONSE FROM LLM <<<\n{}", response_str);
+
+        let response = serde_json::from_str::<ChatResponse>(&response_str)
+            .map_err(|e| e.to_string())
+            .and_then(messages::parse_response)
+            .map_err(|e| {
+                count!("llm.errors.parsing");
+                anyhow!(OpenAiError::ResponseParseError(e))
+            })?;
+
+        // Fall back to an estimate for servers that don't report usage
+        response
+            .usage
+            .unwrap_or_else(|| TokenUsage::estimate(context, &response.content))
+            .record_metrics();
+
+        Ok(response)
+    }
+
+    async fn stream_message(
+        &self,
+        context: &ConversationContext,
+    ) -> Result<Box<dyn Stream<Item = Result<StreamChunk>> + Unpin + Send>> {
+        debug!("Streaming message from chat completions API");
+        let request = self.active_requests.register();
+
+        let body = self.build_request(context, true)?;
+        let Some(response) = request.run(self.post_chat(body)).await else {
+            count!("llm.cancelled");
+            return Err(anyhow!(OpenAiError::Cancelled));
+        };
+        let response = response?;
+
+        // Create a channel for the stream
+        let (tx, rx) = tokio::sync::mpsc::channel::<Result<StreamChunk>>(100);
+
+        // Spawn a task to read server-sent events and forward chunks as they arrive
+        tokio::spawn(async move {
+            let mut decoder = OpenAiStreamDecoder::new(request.id());
+            let mut parser = SseParser::new();
+            let mut body = response.bytes_stream();
+            let mut failed = false;
+
+            'events: loop {
+                // Dropping the body on cancellation closes the connection
+                let bytes = tokio::select! {
+                    biased;
+                    _ = request.cancelled() => {
+                        debug!("Request {} was cancelled", request.id());
+                        count!("llm.cancelled");
+                        let _ = tx.send(Err(anyhow!(OpenAiError::Cancelled))).await;
+                        failed = true;
+                        break;
+                    }
+                    _ = tx.closed() => {
+                        debug!("Stream receiver dropped, stopping");
+                        break;
+                    }
+                    bytes = body.next() => match bytes {
+                        Some(bytes) => bytes,
+                        None => break,
+                    },
+                };
+
+                let bytes = match bytes {
+                    Ok(bytes) => bytes,
+                    Err(e) => {
+                        error!("Chat completions stream error: {}", e);
+                        let _ = tx
+                            .send(Err(anyhow!(OpenAiError::Http(e.to_string()))))
+                            .await;
+                        failed = true;
+                        break;
+                    }
+                };
+
+                for data in parser.push(&bytes) {
+                    trace!("<<< RAW STREAM EVENT FROM LLM <<<\n{}", data);
+                    match decoder.decode(&data) {
+                        Ok(chunks) => {
+                            for chunk in chunks {
+                                if let Some(usage) = &chunk.usage {
+                                    usage.record_metrics();
+                                }
+                                if tx.send(Ok(chunk)).await.is_err() {
+                                    debug!("Stream receiver dropped, stopping");
+                                    break 'events;
+                                }
+                            }
+                        }
+                        Err(e) => {
+                            warn!("Failed to parse chat completions stream event: {}", e);
+                            count!("llm.errors.parsing");
+                            let _ = tx
+                                .send(Err(anyhow!(OpenAiError::ResponseParseError(e.to_string()))))
+                                .await;
+                            failed = true;
+                            break 'events;
+                        }
+                    }
+                }
+
+                if decoder.is_complete() {
+                    break;
+                }
+            }
+
+            // Servers that close the stream without [DONE] still get a final chunk
+            if !failed {
+                match decoder.finish() {
+                    Ok(chunks) => {
+                        for chunk in chunks {
+                            if let Some(usage) = &chunk.usage {
+                                usage.record_metrics();
+                            }
+                            let _ = tx.send(Ok(chunk)).await;
+                        }
+                    }
+                    Err(e) => {
+                        let _ = tx
+                            .send(Err(anyhow!(OpenAiError::ResponseParseError(e.to_string()))))
+                            .await;
+                    }
+                }
+            }
+        });
+
+        // Return the receiver as a stream
+        Ok(Box::new(ReceiverStream::new(rx)))
+    }
+
+    fn cancel_request(&self, request_id: &str) -> Result<()> {
+        if self.active_requests.cancel(request_id) {
+            debug!("Cancelled request {}", request_id);
+            Ok(())
+        } else {
+            Err(anyhow!("Request ID not found: {}", request_id))
+        }
+    }
+}
@@ -0,0 +1,54 @@
+use serde::{Deserialize, Serialize};
+
+/// Marks the end of a cacheable prompt prefix in Claude request bodies.
+///
+/// Everything before the marked block (tools, then system, then messages) is cached for a
+/// few minutes, so later requests that start with the same prefix read it from the cache.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct CacheControl {
+    #[serde(rename = "type")]
+    pub cache_type: String,
+}
+
+impl CacheControl {
+    pub fn ephemeral() -> Self {
+        Self {
+            cache_type: "ephemeral".to_string(),
+        }
+    }
+}
+
+/// Message indices that get a cache breakpoint, given the number of alternating turns.
+///
+/// The newest message is marked so the whole history is cached for the next request. The
+/// message that ended the previous request, two turns back, is marked as well: it is the
+/// prefix the previous request wrote, so it is read even when the new turns are too long
+/// for the provider to look back across.
+pub fn history_breakpoints(message_count: usize) -> Vec<usize> {
+    match message_count {
+        0 => Vec::new(),
+        1..=2 => vec![message_count - 1],
+        _ => vec![message_count - 3, message_count - 1],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_history_breakpoints() {
+        assert!(history_breakpoints(0).is_empty());
+        assert_eq!(history_breakpoints(1), vec![0]);
+        assert_eq!(history_breakpoints(3), vec![0, 2]);
+        assert_eq!(history_breakpoints(8), vec![5, 7]);
+    }
+
+    #[test]
+    fn test_cache_control_serializes_as_ephemeral() {
+        assert_eq!(
+            serde_json::to_value(CacheControl::ephemeral()).unwrap(),
+            serde_json::json!({"type": "ephemeral"})
+        );
+    }
+}
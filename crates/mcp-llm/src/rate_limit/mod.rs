@@ -0,0 +1,330 @@
+// Client-side request, token and concurrency budgets, so bursts queue locally instead of
+// being throttled by the provider
+use mcp_core::RateLimit;
+use mcp_metrics::count;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio::time::Instant;
+use tracing::debug;
+
+const WINDOW: Duration = Duration::from_secs(60);
+
+/// Which budget a request is waiting for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RateLimitReason {
+    Requests,
+    Tokens,
+    Concurrency,
+}
+
+impl RateLimitReason {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            RateLimitReason::Requests => "requests per minute",
+            RateLimitReason::Tokens => "tokens per minute",
+            RateLimitReason::Concurrency => "concurrent requests",
+        }
+    }
+}
+
+/// A request held back by a `RateLimiter`
+#[derive(Debug, Clone)]
+pub struct RateLimitEvent {
+    pub model: String,
+    pub reason: RateLimitReason,
+    /// How long until the budget allows the request; unknown while waiting for another
+    /// request to finish
+    pub wait: Option<Duration>,
+}
+
+/// Called each time a request has to wait
+pub type RateLimitListener = Arc<dyn Fn(&RateLimitEvent) + Send + Sync>;
+
+// Requests and tokens spent in the last minute
+#[derive(Debug, Default)]
+struct Window {
+    requests: VecDeque<Instant>,
+    tokens: VecDeque<(Instant, u64)>,
+}
+
+impl Window {
+    fn prune(&mut self, now: Instant) {
+        while self
+            .requests
+            .front()
+            .is_some_and(|&at| now.duration_since(at) >= WINDOW)
+        {
+            self.requests.pop_front();
+        }
+        while self
+            .tokens
+            .front()
+            .is_some_and(|&(at, _)| now.duration_since(at) >= WINDOW)
+        {
+            self.tokens.pop_front();
+        }
+    }
+
+    // How long until a request for `tokens` fits, and which budget it is waiting for
+    fn wait(
+        &self,
+        limit: &RateLimit,
+        tokens: u64,
+        now: Instant,
+    ) -> Option<(RateLimitReason, Duration)> {
+        if let Some(rpm) = limit.requests_per_minute {
+            if self.requests.len() >= rpm as usize {
+                let oldest = self.requests[self.requests.len() - rpm as usize];
+                return Some((RateLimitReason::Requests, oldest + WINDOW - now));
+            }
+        }
+
+        // A request larger than the whole budget goes through once the window is empty
+        let tpm = limit.tokens_per_minute?;
+        let mut spent: u64 = self.tokens.iter().map(|(_, tokens)| tokens).sum();
+        if spent + tokens <= tpm || self.tokens.is_empty() {
+            return None;
+        }
+        for &(at, expiring) in &self.tokens {
+            spent -= expiring;
+            if spent + tokens <= tpm || spent == 0 {
+                return Some((RateLimitReason::Tokens, at + WINDOW - now));
+            }
+        }
+        None
+    }
+}
+
+/// Budgets for one model. Clones share their budgets, so every client calling the model
+/// draws from the same quota.
+#[derive(Clone)]
+pub struct RateLimiter {
+    model: String,
+    limit: RateLimit,
+    window: Arc<Mutex<Window>>,
+    concurrency: Option<Arc<Semaphore>>,
+    listener: Option<RateLimitListener>,
+}
+
+/// Held for the duration of a request, to count it against the concurrency budget
+#[derive(Debug)]
+pub struct RateLimitPermit {
+    _concurrency: Option<OwnedSemaphorePermit>,
+}
+
+impl RateLimiter {
+    pub fn new(model: impl Into<String>, limit: RateLimit) -> Self {
+        Self {
+            model: model.into(),
+            limit,
+            window: Arc::new(Mutex::new(Window::default())),
+            concurrency: limit
+                .max_concurrent
+                .map(|max| Arc::new(Semaphore::new(max.max(1)))),
+            listener: None,
+        }
+    }
+
+    pub fn with_listener(mut self, listener: RateLimitListener) -> Self {
+        self.listener = Some(listener);
+        self
+    }
+
+    pub fn model(&self) -> &str {
+        &self.model
+    }
+
+    /// Wait until a request estimated at `tokens` prompt tokens fits every budget, then
+    /// count it against them
+    pub async fn acquire(&self, tokens: u64) -> RateLimitPermit {
+        let concurrency = match &self.concurrency {
+            Some(semaphore) => Some(match semaphore.clone().try_acquire_owned() {
+                Ok(permit) => permit,
+                Err(_) => {
+                    self.report(RateLimitReason::Concurrency, None);
+                    semaphore
+                        .clone()
+                        .acquire_owned()
+                        .await
+                        .expect("rate limit semaphore is never closed")
+                }
+            }),
+            None => None,
+        };
+
+        loop {
+            let (reason, delay) = {
+                let mut window = self.window.lock().unwrap();
+                let now = Instant::now();
+                window.prune(now);
+                match window.wait(&self.limit, tokens, now) {
+                    Some(wait) => wait,
+                    None => {
+                        window.requests.push_back(now);
+                        if tokens > 0 {
+                            window.tokens.push_back((now, tokens));
+                        }
+                        break;
+                    }
+                }
+            };
+
+            self.report(reason, Some(delay));
+            tokio::time::sleep(delay).await;
+        }
+
+        RateLimitPermit {
+            _concurrency: concurrency,
+        }
+    }
+
+    /// Count tokens only known once the reply is in, such as its output tokens
+    pub fn record_tokens(&self, tokens: u64) {
+        if tokens > 0 && self.limit.tokens_per_minute.is_some() {
+            let mut window = self.window.lock().unwrap();
+            window.tokens.push_back((Instant::now(), tokens));
+        }
+    }
+
+    fn report(&self, reason: RateLimitReason, wait: Option<Duration>) {
+        debug!(
+            "{} is at its {} budget, waiting {:?}",
+            self.model,
+            reason.as_str(),
+            wait
+        );
+        count!("llm.rate_limit.waits");
+        count!(&format!("llm.rate_limit.waits.{}", self.model));
+        if let Some(listener) = &self.listener {
+            listener(&RateLimitEvent {
+                model: self.model.clone(),
+                reason,
+                wait,
+            });
+        }
+    }
+}
+
+/// Rate limiters by model, so that clients created separately for the same model, such as
+/// the main client and a task route, share one set of budgets
+#[derive(Clone, Default)]
+pub struct RateLimits {
+    limiters: Arc<Mutex<HashMap<String, RateLimiter>>>,
+    listener: Option<RateLimitListener>,
+}
+
+impl RateLimits {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Listener given to every limiter created from here on
+    pub fn with_listener(mut self, listener: RateLimitListener) -> Self {
+        self.listener = Some(listener);
+        self
+    }
+
+    /// The limiter for `model`, created with `limit` the first time it is asked for
+    pub fn limiter(&self, model: &str, limit: RateLimit) -> RateLimiter {
+        let mut limiters = self.limiters.lock().unwrap();
+        limiters
+            .entry(model.to_string())
+            .or_insert_with(|| {
+                let limiter = RateLimiter::new(model, limit);
+                match &self.listener {
+                    Some(listener) => limiter.with_listener(listener.clone()),
+                    None => limiter,
+                }
+            })
+            .clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn events() -> (RateLimitListener, Arc<Mutex<Vec<RateLimitEvent>>>) {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let sink = events.clone();
+        let listener: RateLimitListener =
+            Arc::new(move |event: &RateLimitEvent| sink.lock().unwrap().push(event.clone()));
+        (listener, events)
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_requests_per_minute() {
+        let (listener, events) = events();
+        let limit = RateLimit {
+            requests_per_minute: Some(2),
+            ..RateLimit::default()
+        };
+        let limiter = RateLimiter::new("model", limit).with_listener(listener);
+
+        let start = Instant::now();
+        limiter.acquire(0).await;
+        limiter.acquire(0).await;
+        assert!(events.lock().unwrap().is_empty());
+
+        limiter.acquire(0).await;
+        assert_eq!(start.elapsed(), WINDOW);
+        let events = events.lock().unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].reason, RateLimitReason::Requests);
+        assert_eq!(events[0].wait, Some(WINDOW));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_tokens_per_minute_shared_by_model() {
+        let (listener, events) = events();
+        let limits = RateLimits::new().with_listener(listener);
+        let limit = RateLimit {
+            tokens_per_minute: Some(1000),
+            ..RateLimit::default()
+        };
+        let main = limits.limiter("model", limit);
+        let task = limits.limiter("model", limit);
+
+        let start = Instant::now();
+        main.acquire(600).await;
+        tokio::time::advance(Duration::from_secs(10)).await;
+        main.record_tokens(300);
+
+        // 900 tokens spent; waits for the first 600 to leave the window
+        task.acquire(200).await;
+        assert_eq!(start.elapsed(), WINDOW);
+        assert_eq!(events.lock().unwrap()[0].reason, RateLimitReason::Tokens);
+
+        // Larger than the whole budget: waits for the window to empty, then goes through
+        let start = Instant::now();
+        task.acquire(5000).await;
+        assert_eq!(start.elapsed(), WINDOW);
+    }
+
+    #[tokio::test]
+    async fn test_concurrency() {
+        let (listener, events) = events();
+        let limit = RateLimit {
+            max_concurrent: Some(1),
+            ..RateLimit::default()
+        };
+        let limiter = RateLimiter::new("model", limit).with_listener(listener);
+
+        let first = limiter.acquire(0).await;
+        let waiting = tokio::spawn({
+            let limiter = limiter.clone();
+            async move { limiter.acquire(0).await }
+        });
+        tokio::task::yield_now().await;
+        assert_eq!(
+            events.lock().unwrap()[0].reason,
+            RateLimitReason::Concurrency
+        );
+        assert!(!waiting.is_finished());
+
+        drop(first);
+        waiting.await.unwrap();
+    }
+}
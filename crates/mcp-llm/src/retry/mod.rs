@@ -0,0 +1,281 @@
+use crate::anthropic::AnthropicError;
+use crate::bedrock::BedrockError;
+use crate::context_guard::ContextOverflow;
+use crate::openai::OpenAiError;
+use anyhow::Result;
+use mcp_metrics::count;
+use std::future::Future;
+use std::time::Duration;
+use tracing::{debug, warn};
+
+/// Broad category of an LLM request failure, shared by all providers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorClass {
+    /// Rate limited or over quota; worth retrying after a pause
+    Throttled,
+    /// Network failures, timeouts and server-side 5xx errors
+    Transient,
+    /// Missing, invalid or expired credentials, or no access to the model
+    Auth,
+    /// The model id is unknown or not enabled in this account or region
+    ModelNotAvailable,
+    /// The request does not fit in the model's context window
+    ContextTooLong,
+    /// Anything else, including cancellations and malformed responses
+    Other,
+}
+
+impl ErrorClass {
+    /// Whether a request failing this way may succeed if sent again unchanged
+    pub fn is_retryable(self) -> bool {
+        matches!(self, ErrorClass::Throttled | ErrorClass::Transient)
+    }
+
+    /// Suffix used for the per-class `llm.errors.*` metrics
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ErrorClass::Throttled => "throttled",
+            ErrorClass::Transient => "transient",
+            ErrorClass::Auth => "auth",
+            ErrorClass::ModelNotAvailable => "model_not_available",
+            ErrorClass::ContextTooLong => "context_too_long",
+            ErrorClass::Other => "other",
+        }
+    }
+
+    /// Classify an HTTP error response from the Anthropic or OpenAI-compatible APIs
+    pub fn from_http_status(status: u16, message: &str) -> Self {
+        match status {
+            429 => ErrorClass::Throttled,
+            // 529 is Anthropic's "overloaded"
+            408 | 500 | 502 | 503 | 504 | 529 => ErrorClass::Transient,
+            401 | 403 => ErrorClass::Auth,
+            404 => ErrorClass::ModelNotAvailable,
+            400 | 413 if is_context_overflow(message) => ErrorClass::ContextTooLong,
+            _ => ErrorClass::Other,
+        }
+    }
+}
+
+/// Classify an error returned by any of the provider clients
+pub fn classify(error: &anyhow::Error) -> ErrorClass {
+    if let Some(e) = error.downcast_ref::<BedrockError>() {
+        e.class()
+    } else if let Some(e) = error.downcast_ref::<AnthropicError>() {
+        e.class()
+    } else if let Some(e) = error.downcast_ref::<OpenAiError>() {
+        e.class()
+    } else if let Some(e) = error.downcast_ref::<ContextOverflow>() {
+        e.class()
+    } else {
+        ErrorClass::Other
+    }
+}
+
+// Providers word this differently; these cover Bedrock, Anthropic, vLLM and Ollama
+pub(crate) fn is_context_overflow(message: &str) -> bool {
+    let message = message.to_lowercase();
+    [
+        "too long",
+        "too many tokens",
+        "too many input tokens",
+        "context length",
+        "context window",
+        "context_length_exceeded",
+        "maximum context",
+    ]
+    .iter()
+    .any(|pattern| message.contains(pattern))
+}
+
+/// Exponential backoff with full jitter for throttled and transient failures
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Retries after the first attempt; 0 disables retrying
+    pub max_retries: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 4,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(30),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// A policy that never retries
+    pub fn none() -> Self {
+        Self {
+            max_retries: 0,
+            ..Self::default()
+        }
+    }
+
+    pub fn with_max_retries(mut self, max_retries: u32) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    pub fn with_backoff(mut self, initial_backoff: Duration, max_backoff: Duration) -> Self {
+        self.initial_backoff = initial_backoff;
+        self.max_backoff = max_backoff;
+        self
+    }
+
+    /// Upper bound of the delay before retry number `attempt` (starting at 0)
+    pub fn backoff_ceiling(&self, attempt: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempt.min(16));
+        self.initial_backoff
+            .saturating_mul(factor)
+            .min(self.max_backoff)
+    }
+
+    /// Random delay in `[0, backoff_ceiling(attempt)]`, so concurrent sessions spread out
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        self.backoff_ceiling(attempt).mul_f64(fastrand::f64())
+    }
+
+    /// Run `operation`, retrying it while it fails with a retryable error.
+    ///
+    /// Retries and the class of every failure are counted under `llm.retries.*` and
+    /// `llm.errors.*` with the given provider name.
+    pub async fn run<T, F, Fut>(&self, provider: &str, mut operation: F) -> Result<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let mut attempt = 0;
+        loop {
+            let error = match operation().await {
+                Ok(value) => return Ok(value),
+                Err(error) => error,
+            };
+
+            let class = classify(&error);
+            count!(&format!("llm.errors.{}", class.as_str()));
+
+            if !class.is_retryable() || attempt >= self.max_retries {
+                if class.is_retryable() {
+                    warn!(
+                        "Giving up on {} request after {} retries: {}",
+                        provider, attempt, error
+                    );
+                    count!("llm.retries.exhausted");
+                }
+                return Err(error);
+            }
+
+            let delay = self.backoff(attempt);
+            attempt += 1;
+            warn!(
+                "{} request failed ({}), retry {} of {} in {:?}: {}",
+                provider,
+                class.as_str(),
+                attempt,
+                self.max_retries,
+                delay,
+                error
+            );
+            count!("llm.retries");
+            count!(&format!("llm.retries.{}", provider));
+            debug!("Backing off for {:?}", delay);
+            tokio::time::sleep(delay).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::anyhow;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    fn fast_policy() -> RetryPolicy {
+        RetryPolicy::default()
+            .with_max_retries(3)
+            .with_backoff(Duration::from_millis(1), Duration::from_millis(5))
+    }
+
+    #[test]
+    fn test_backoff_is_capped_and_jittered() {
+        let policy = RetryPolicy::default();
+        assert_eq!(policy.backoff_ceiling(0), Duration::from_millis(500));
+        assert_eq!(policy.backoff_ceiling(2), Duration::from_secs(2));
+        assert_eq!(policy.backoff_ceiling(30), Duration::from_secs(30));
+
+        for attempt in 0..10 {
+            assert!(policy.backoff(attempt) <= policy.backoff_ceiling(attempt));
+        }
+    }
+
+    #[test]
+    fn test_http_status_classification() {
+        assert_eq!(ErrorClass::from_http_status(429, ""), ErrorClass::Throttled);
+        assert_eq!(ErrorClass::from_http_status(529, ""), ErrorClass::Transient);
+        assert_eq!(ErrorClass::from_http_status(401, ""), ErrorClass::Auth);
+        assert_eq!(
+            ErrorClass::from_http_status(404, "model not found"),
+            ErrorClass::ModelNotAvailable
+        );
+        assert_eq!(
+            ErrorClass::from_http_status(400, "prompt is too long: 210000 tokens > 200000 maximum"),
+            ErrorClass::ContextTooLong
+        );
+        assert_eq!(
+            ErrorClass::from_http_status(400, "invalid temperature"),
+            ErrorClass::Other
+        );
+    }
+
+    #[tokio::test]
+    async fn test_retries_throttled_errors_until_success() {
+        let attempts = AtomicU32::new(0);
+        let result = fast_policy()
+            .run("test", || async {
+                if attempts.fetch_add(1, Ordering::SeqCst) < 2 {
+                    Err(anyhow!(BedrockError::Throttled("slow down".to_string())))
+                } else {
+                    Ok("done")
+                }
+            })
+            .await;
+
+        assert_eq!(result.unwrap(), "done");
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_does_not_retry_permanent_errors() {
+        let attempts = AtomicU32::new(0);
+        let result: Result<()> = fast_policy()
+            .run("test", || async {
+                attempts.fetch_add(1, Ordering::SeqCst);
+                Err(anyhow!(BedrockError::AccessDenied("no".to_string())))
+            })
+            .await;
+
+        assert_eq!(classify(&result.unwrap_err()), ErrorClass::Auth);
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_gives_up_after_max_retries() {
+        let attempts = AtomicU32::new(0);
+        let result: Result<()> = fast_policy()
+            .run("test", || async {
+                attempts.fetch_add(1, Ordering::SeqCst);
+                Err(anyhow!(BedrockError::Transient(
+                    "connection reset".to_string()
+                )))
+            })
+            .await;
+
+        assert!(result.is_err());
+        assert_eq!(attempts.load(Ordering::SeqCst), 4);
+    }
+}
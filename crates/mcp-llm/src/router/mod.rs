@@ -0,0 +1,130 @@
+use crate::client_trait::{LlmClient, LlmResponse, StreamChunk};
+use anyhow::Result;
+use async_trait::async_trait;
+use futures::Stream;
+use mcp_core::context::ConversationContext;
+use mcp_core::ModelTask;
+use mcp_metrics::count;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tracing::debug;
+
+/// Sends the agent loop to a primary client and auxiliary tasks to the clients routed for
+/// them, typically a cheaper and faster model.
+///
+/// `send_message` and `stream_message` always use the primary client; auxiliary requests go
+/// through `send_task`, falling back to the primary client for tasks without a route.
+pub struct RoutingClient {
+    primary: Arc<dyn LlmClient>,
+    routes: HashMap<ModelTask, Arc<dyn LlmClient>>,
+}
+
+impl RoutingClient {
+    pub fn new(primary: Arc<dyn LlmClient>) -> Self {
+        Self {
+            primary,
+            routes: HashMap::new(),
+        }
+    }
+
+    pub fn with_route(mut self, task: ModelTask, client: Arc<dyn LlmClient>) -> Self {
+        self.routes.insert(task, client);
+        self
+    }
+
+    /// The client that handles a task
+    pub fn client_for(&self, task: ModelTask) -> &Arc<dyn LlmClient> {
+        self.routes.get(&task).unwrap_or(&self.primary)
+    }
+}
+
+#[async_trait]
+impl LlmClient for RoutingClient {
+    async fn send_message(&self, context: &ConversationContext) -> Result<LlmResponse> {
+        self.primary.send_message(context).await
+    }
+
+    async fn stream_message(
+        &self,
+        context: &ConversationContext,
+    ) -> Result<Box<dyn Stream<Item = Result<StreamChunk>> + Unpin + Send>> {
+        self.primary.stream_message(context).await
+    }
+
+    fn cancel_request(&self, request_id: &str) -> Result<()> {
+        // Request ids are unique, so only the client that issued one will act on it
+        for client in self.routes.values() {
+            client.cancel_request(request_id)?;
+        }
+        self.primary.cancel_request(request_id)
+    }
+
+    async fn send_task(
+        &self,
+        task: ModelTask,
+        context: &ConversationContext,
+    ) -> Result<LlmResponse> {
+        match self.routes.get(&task) {
+            Some(client) => {
+                debug!("Routing {} request to its task model", task.as_str());
+                count!(&format!("llm.routed.{}", task.as_str()));
+                client.send_message(context).await
+            }
+            None => self.primary.send_task(task, context).await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Answers with its own name so tests can see which client handled a request
+    struct NamedClient(&'static str);
+
+    #[async_trait]
+    impl LlmClient for NamedClient {
+        async fn send_message(&self, _context: &ConversationContext) -> Result<LlmResponse> {
+            Ok(LlmResponse {
+                id: "msg".to_string(),
+                content: self.0.to_string(),
+                tool_calls: Vec::new(),
+                usage: None,
+                thinking: Vec::new(),
+            })
+        }
+
+        async fn stream_message(
+            &self,
+            _context: &ConversationContext,
+        ) -> Result<Box<dyn Stream<Item = Result<StreamChunk>> + Unpin + Send>> {
+            Ok(Box::new(futures::stream::empty()))
+        }
+
+        fn cancel_request(&self, _request_id: &str) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_tasks_are_routed() {
+        let router = RoutingClient::new(Arc::new(NamedClient("primary")))
+            .with_route(ModelTask::Summarize, Arc::new(NamedClient("fast")));
+
+        let mut context = ConversationContext::new();
+        context.add_user_message("hello");
+
+        let main = router.send_message(&context).await.unwrap();
+        assert_eq!(main.content, "primary");
+
+        let summary = router
+            .send_task(ModelTask::Summarize, &context)
+            .await
+            .unwrap();
+        assert_eq!(summary.content, "fast");
+
+        // Tasks without a route stay on the primary model
+        let title = router.send_task(ModelTask::Title, &context).await.unwrap();
+        assert_eq!(title.content, "primary");
+    }
+}
@@ -0,0 +1,570 @@
+use super::jsonrpc::jsonrpc_call_id;
+use crate::client_trait::{StreamChunk, ThinkingBlock, TokenUsage, ToolCall as ClientToolCall};
+use crate::retry::{self, ErrorClass};
+use anyhow::{anyhow, Result};
+use mcp_core::protocol::Request as McpRequest;
+use serde::Deserialize;
+use std::collections::HashMap;
+use tracing::{debug, trace, warn};
+
+// Claude streaming event, shared by the Anthropic SSE stream and Bedrock's response stream
+#[derive(Debug, Deserialize)]
+struct ClaudeStreamEvent {
+    #[serde(rename = "type")]
+    type_: String,
+    #[serde(default)]
+    index: Option<usize>,
+    #[serde(default)]
+    delta: Option<ClaudeDelta>,
+    #[serde(default)]
+    content_block: Option<ClaudeContentBlock>,
+    #[serde(default)]
+    error: Option<ClaudeStreamError>,
+    #[serde(default)]
+    message: Option<ClaudeStreamMessage>,
+    #[serde(default)]
+    usage: Option<ClaudeStreamUsage>,
+}
+
+// Message envelope of message_start, which carries the input token count
+#[derive(Debug, Deserialize)]
+struct ClaudeStreamMessage {
+    #[serde(default)]
+    usage: Option<ClaudeStreamUsage>,
+}
+
+// Usage in message_start and message_delta; output tokens in message_delta are cumulative
+#[derive(Debug, Deserialize)]
+struct ClaudeStreamUsage {
+    #[serde(default)]
+    input_tokens: Option<u64>,
+    #[serde(default)]
+    output_tokens: Option<u64>,
+    #[serde(default)]
+    cache_read_input_tokens: Option<u64>,
+    #[serde(default)]
+    cache_creation_input_tokens: Option<u64>,
+}
+
+// Delta payload of content_block_delta and message_delta events
+#[derive(Debug, Deserialize)]
+struct ClaudeDelta {
+    #[serde(default)]
+    text: String,
+    #[serde(default)]
+    partial_json: String,
+    #[serde(default)]
+    thinking: String,
+    #[serde(default)]
+    signature: String,
+    #[serde(default)]
+    stop_reason: Option<String>,
+}
+
+// Content block announced by content_block_start
+#[derive(Debug, Deserialize)]
+struct ClaudeContentBlock {
+    #[serde(rename = "type")]
+    type_: String,
+    #[serde(default)]
+    id: String,
+    #[serde(default)]
+    name: String,
+    // Opaque payload of a redacted_thinking block, sent whole in content_block_start
+    #[serde(default)]
+    data: String,
+}
+
+/// Error reported in-band by the stream, such as `overloaded_error` part way through a reply
+#[derive(Debug, Clone, Deserialize, thiserror::Error)]
+#[error("{error_type}: {message}")]
+pub struct ClaudeStreamError {
+    #[serde(rename = "type", default)]
+    pub error_type: String,
+    #[serde(default)]
+    pub message: String,
+}
+
+impl ClaudeStreamError {
+    /// Classify the error by its Claude error type
+    pub fn class(&self) -> ErrorClass {
+        match self.error_type.as_str() {
+            "rate_limit_error" => ErrorClass::Throttled,
+            "overloaded_error" | "api_error" | "timeout_error" => ErrorClass::Transient,
+            "authentication_error" | "permission_error" => ErrorClass::Auth,
+            "not_found_error" => ErrorClass::ModelNotAvailable,
+            "invalid_request_error" | "request_too_large"
+                if retry::is_context_overflow(&self.message) =>
+            {
+                ErrorClass::ContextTooLong
+            }
+            _ => ErrorClass::Other,
+        }
+    }
+}
+
+// A native tool_use block whose input is still arriving
+struct PendingToolUse {
+    id: String,
+    name: String,
+    input_json: String,
+}
+
+/// Turns Claude streaming events into `StreamChunk`s.
+///
+/// Text deltas are emitted as soon as they arrive. The accumulated text is also scanned for
+/// `mcp.tool_call` JSON-RPC objects, so a tool call split across several deltas is reported
+/// once, as soon as its closing brace arrives. Native `tool_use` blocks are reported when
+/// their block stops and the streamed input JSON is complete. Thinking deltas are emitted
+/// separately from the text, and each finished thinking block (with its signature) is
+/// reported when the block stops. Token usage from `message_start` and `message_delta` is
+/// attached to the final chunk.
+pub struct ClaudeStreamDecoder {
+    request_id: String,
+    text: String,
+    seen_tool_calls: Vec<String>,
+    pending_tool_uses: HashMap<usize, PendingToolUse>,
+    pending_thinking: HashMap<usize, ThinkingBlock>,
+    stop_reason: Option<String>,
+    usage: Option<TokenUsage>,
+    complete: bool,
+}
+
+impl ClaudeStreamDecoder {
+    pub fn new(request_id: &str) -> Self {
+        Self {
+            request_id: request_id.to_string(),
+            text: String::new(),
+            seen_tool_calls: Vec::new(),
+            pending_tool_uses: HashMap::new(),
+            pending_thinking: HashMap::new(),
+            stop_reason: None,
+            usage: None,
+            complete: false,
+        }
+    }
+
+    /// Decode one event payload, returning the chunks it produces.
+    ///
+    /// An in-band `error` event fails with a `ClaudeStreamError`; any other error means the
+    /// payload could not be parsed.
+    pub fn decode(&mut self, payload: &[u8]) -> Result<Vec<StreamChunk>> {
+        let event: ClaudeStreamEvent = serde_json::from_slice(payload)?;
+        trace!("Stream event: {}", event.type_);
+
+        let usage = event.usage.or_else(|| event.message.and_then(|m| m.usage));
+        if let Some(usage) = usage {
+            let total = self.usage.get_or_insert_with(TokenUsage::default);
+            if let Some(input_tokens) = usage.input_tokens {
+                total.input_tokens = input_tokens;
+            }
+            if let Some(output_tokens) = usage.output_tokens {
+                total.output_tokens = output_tokens;
+            }
+            if let Some(cache_read_tokens) = usage.cache_read_input_tokens {
+                total.cache_read_tokens = cache_read_tokens;
+            }
+            if let Some(cache_write_tokens) = usage.cache_creation_input_tokens {
+                total.cache_write_tokens = cache_write_tokens;
+            }
+        }
+
+        let mut chunks = Vec::new();
+        match event.type_.as_str() {
+            "content_block_start" => {
+                if let (Some(index), Some(block)) = (event.index, event.content_block) {
+                    match block.type_.as_str() {
+                        "tool_use" => {
+                            self.pending_tool_uses.insert(
+                                index,
+                                PendingToolUse {
+                                    id: block.id,
+                                    name: block.name,
+                                    input_json: String::new(),
+                                },
+                            );
+                        }
+                        "thinking" => {
+                            self.pending_thinking.insert(
+                                index,
+                                ThinkingBlock::Thinking {
+                                    thinking: String::new(),
+                                    signature: String::new(),
+                                },
+                            );
+                        }
+                        "redacted_thinking" => {
+                            self.pending_thinking.insert(
+                                index,
+                                ThinkingBlock::RedactedThinking { data: block.data },
+                            );
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            "content_block_delta" => {
+                let Some(delta) = event.delta else {
+                    return Ok(chunks);
+                };
+
+                if let Some(pending) = event
+                    .index
+                    .and_then(|index| self.pending_tool_uses.get_mut(&index))
+                {
+                    pending.input_json.push_str(&delta.partial_json);
+                } else if let Some(ThinkingBlock::Thinking {
+                    thinking,
+                    signature,
+                }) = event
+                    .index
+                    .and_then(|index| self.pending_thinking.get_mut(&index))
+                {
+                    // Thinking never joins the text, so it can't be mistaken for JSON-RPC
+                    thinking.push_str(&delta.thinking);
+                    signature.push_str(&delta.signature);
+                    if !delta.thinking.is_empty() {
+                        let mut chunk = self.chunk(String::new(), None, false);
+                        chunk.thinking = delta.thinking;
+                        chunks.push(chunk);
+                    }
+                } else if !delta.text.is_empty() {
+                    self.text.push_str(&delta.text);
+                    let closes_object = delta.text.contains('}');
+                    chunks.push(self.chunk(delta.text, None, false));
+
+                    // A tool call can only have completed if this delta closed an object
+                    if closes_object {
+                        chunks.extend(self.detect_tool_calls());
+                    }
+                }
+            }
+            "content_block_stop" => {
+                let index = event.index.unwrap_or_default();
+                if let Some(pending) = self.pending_tool_uses.remove(&index) {
+                    chunks.push(self.native_tool_call(pending)?);
+                } else if let Some(block) = self.pending_thinking.remove(&index) {
+                    let mut chunk = self.chunk(String::new(), None, false);
+                    chunk.thinking_block = Some(block);
+                    chunks.push(chunk);
+                }
+            }
+            "message_delta" => {
+                self.stop_reason = event.delta.and_then(|d| d.stop_reason);
+            }
+            "message_stop" => {
+                debug!(
+                    "Stream complete, stop reason: {}",
+                    self.stop_reason.as_deref().unwrap_or("unknown")
+                );
+                chunks.extend(self.finish());
+            }
+            "error" => {
+                let error = event.error.unwrap_or(ClaudeStreamError {
+                    error_type: "unknown_error".to_string(),
+                    message: String::new(),
+                });
+                return Err(anyhow!(error));
+            }
+            _ => {}
+        }
+
+        Ok(chunks)
+    }
+
+    /// Emit the final chunk if the stream has not already completed
+    pub fn finish(&mut self) -> Vec<StreamChunk> {
+        if self.complete {
+            return Vec::new();
+        }
+        self.complete = true;
+        let mut last = self.chunk(String::new(), None, true);
+        last.usage = self.usage;
+        vec![last]
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.complete
+    }
+
+    // Build the tool call chunk for a finished native tool_use block
+    fn native_tool_call(&self, pending: PendingToolUse) -> Result<StreamChunk> {
+        let params = if pending.input_json.trim().is_empty() {
+            serde_json::json!({})
+        } else {
+            serde_json::from_str(&pending.input_json)
+                .map_err(|e| anyhow!("Invalid input JSON for tool {}: {}", pending.name, e))?
+        };
+
+        debug!(
+            "Native tool use in stream: {} ({})",
+            pending.name, pending.id
+        );
+        Ok(self.chunk(
+            String::new(),
+            Some(ClientToolCall {
+                id: pending.id,
+                tool: pending.name,
+                params,
+            }),
+            false,
+        ))
+    }
+
+    // Report any tool calls in the accumulated text that have not been reported yet
+    fn detect_tool_calls(&mut self) -> Vec<StreamChunk> {
+        if !self.text.contains("mcp.tool_call") {
+            return Vec::new();
+        }
+
+        let mut chunks = Vec::new();
+        for json_value in mcp_core::extract_jsonrpc_objects(&self.text) {
+            let Ok(request) = serde_json::from_value::<McpRequest>(json_value.clone()) else {
+                continue;
+            };
+            if request.method != "mcp.tool_call" {
+                continue;
+            }
+
+            let key = json_value.to_string();
+            if self.seen_tool_calls.contains(&key) {
+                continue;
+            }
+
+            let (Some(name), Some(parameters)) = (
+                request.params.get("name").and_then(|n| n.as_str()),
+                request.params.get("parameters"),
+            ) else {
+                warn!("Ignoring malformed tool call in stream: {}", key);
+                continue;
+            };
+
+            let id = jsonrpc_call_id(&request.id);
+
+            debug!("Detected tool call in stream: {} ({})", name, id);
+            self.seen_tool_calls.push(key);
+            chunks.push(self.chunk(
+                String::new(),
+                Some(ClientToolCall {
+                    id,
+                    tool: name.to_string(),
+                    params: parameters.clone(),
+                }),
+                false,
+            ));
+        }
+
+        chunks
+    }
+
+    fn chunk(
+        &self,
+        content: String,
+        tool_call: Option<ClientToolCall>,
+        is_complete: bool,
+    ) -> StreamChunk {
+        StreamChunk {
+            id: self.request_id.clone(),
+            content,
+            is_tool_call: tool_call.is_some(),
+            tool_call,
+            is_complete,
+            usage: None,
+            thinking: String::new(),
+            thinking_block: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn event(value: serde_json::Value) -> Vec<u8> {
+        serde_json::to_vec(&value).unwrap()
+    }
+
+    fn delta(text: &str) -> Vec<u8> {
+        event(json!({
+            "type": "content_block_delta",
+            "index": 0,
+            "delta": {"type": "text_delta", "text": text}
+        }))
+    }
+
+    #[test]
+    fn test_text_deltas_stream_incrementally() {
+        let mut decoder = ClaudeStreamDecoder::new("req-1");
+
+        let start = json!({
+            "type": "message_start",
+            "message": {
+                "id": "msg_1", "type": "message", "role": "assistant", "content": [],
+                "model": "claude", "usage": {"input_tokens": 10, "output_tokens": 1}
+            }
+        });
+        assert!(decoder.decode(&event(start)).unwrap().is_empty());
+
+        let first = decoder.decode(&delta("Hello")).unwrap();
+        let second = decoder.decode(&delta(", world")).unwrap();
+        assert_eq!(first[0].content, "Hello");
+        assert_eq!(second[0].content, ", world");
+        assert!(!second[0].is_complete);
+
+        decoder
+            .decode(&event(json!({
+                "type": "message_delta",
+                "delta": {"stop_reason": "end_turn"},
+                "usage": {"output_tokens": 4}
+            })))
+            .unwrap();
+
+        let done = decoder.decode(br#"{"type":"message_stop"}"#).unwrap();
+        assert_eq!(done.len(), 1);
+        assert!(done[0].is_complete);
+        assert_eq!(done[0].usage, Some(TokenUsage::new(10, 4)));
+        assert!(decoder.finish().is_empty());
+    }
+
+    #[test]
+    fn test_tool_call_split_across_deltas() {
+        let mut decoder = ClaudeStreamDecoder::new("req-2");
+
+        let mut chunks = Vec::new();
+        for part in [
+            "Let me check.\n{\"jsonrpc\":\"2.0\",\"method\":\"mcp.",
+            "tool_call\",\"params\":{\"name\":\"shell\",\"parameters\":{\"command\":\"ls\"}}",
+            ",\"id\":\"call-1\"}",
+            "\nDone.",
+        ] {
+            chunks.extend(decoder.decode(&delta(part)).unwrap());
+        }
+
+        let tool_calls: Vec<_> = chunks.iter().filter(|c| c.is_tool_call).collect();
+        assert_eq!(tool_calls.len(), 1);
+
+        let tool_call = tool_calls[0].tool_call.as_ref().unwrap();
+        assert_eq!(tool_call.id, "call-1");
+        assert_eq!(tool_call.tool, "shell");
+        assert_eq!(tool_call.params, json!({"command": "ls"}));
+    }
+
+    #[test]
+    fn test_native_tool_use_block() {
+        let mut decoder = ClaudeStreamDecoder::new("req-3");
+
+        decoder
+            .decode(&event(json!({
+                "type": "content_block_start",
+                "index": 1,
+                "content_block": {"type": "tool_use", "id": "toolu_1", "name": "shell", "input": {}}
+            })))
+            .unwrap();
+        for part in ["{\"comm", "and\": \"ls\"}"] {
+            let chunks = decoder
+                .decode(&event(json!({
+                    "type": "content_block_delta",
+                    "index": 1,
+                    "delta": {"type": "input_json_delta", "partial_json": part}
+                })))
+                .unwrap();
+            assert!(chunks.is_empty());
+        }
+
+        let chunks = decoder
+            .decode(&event(json!({"type": "content_block_stop", "index": 1})))
+            .unwrap();
+        let tool_call = chunks[0].tool_call.as_ref().unwrap();
+        assert!(chunks[0].is_tool_call);
+        assert_eq!(tool_call.id, "toolu_1");
+        assert_eq!(tool_call.params, json!({"command": "ls"}));
+    }
+
+    #[test]
+    fn test_thinking_block_kept_out_of_text() {
+        let mut decoder = ClaudeStreamDecoder::new("req-5");
+
+        decoder
+            .decode(&event(json!({
+                "type": "content_block_start",
+                "index": 0,
+                "content_block": {"type": "thinking", "thinking": ""}
+            })))
+            .unwrap();
+        let mut chunks = Vec::new();
+        for delta in [
+            json!({"type": "thinking_delta", "thinking": "The user wants {\"jsonrpc\": "}),
+            json!({"type": "thinking_delta", "thinking": "\"2.0\"} maybe."}),
+            json!({"type": "signature_delta", "signature": "sig-1"}),
+        ] {
+            chunks.extend(
+                decoder
+                    .decode(&event(json!({
+                        "type": "content_block_delta",
+                        "index": 0,
+                        "delta": delta
+                    })))
+                    .unwrap(),
+            );
+        }
+        chunks.extend(
+            decoder
+                .decode(&event(json!({"type": "content_block_stop", "index": 0})))
+                .unwrap(),
+        );
+        chunks.extend(decoder.decode(&delta("Done.")).unwrap());
+
+        let thinking: String = chunks.iter().map(|c| c.thinking.as_str()).collect();
+        assert_eq!(thinking, "The user wants {\"jsonrpc\": \"2.0\"} maybe.");
+        assert!(chunks.iter().all(|c| !c.is_tool_call));
+
+        let block = chunks
+            .iter()
+            .find_map(|c| c.thinking_block.clone())
+            .unwrap();
+        assert_eq!(
+            block,
+            ThinkingBlock::Thinking {
+                thinking: thinking.clone(),
+                signature: "sig-1".to_string(),
+            }
+        );
+
+        let text: String = chunks.iter().map(|c| c.content.as_str()).collect();
+        assert_eq!(text, "Done.");
+    }
+
+    #[test]
+    fn test_error_event() {
+        let mut decoder = ClaudeStreamDecoder::new("req-4");
+        let err = decoder
+            .decode(&event(json!({
+                "type": "error",
+                "error": {"type": "overloaded_error", "message": "Overloaded"}
+            })))
+            .unwrap_err();
+        assert!(err.to_string().contains("overloaded_error"));
+    }
+
+    #[test]
+    fn test_error_event_is_classified() {
+        let mut decoder = ClaudeStreamDecoder::new("req-6");
+        decoder.decode(&delta("Half an ans")).unwrap();
+
+        let err = decoder
+            .decode(&event(json!({
+                "type": "error",
+                "error": {"type": "overloaded_error", "message": "Overloaded"}
+            })))
+            .unwrap_err();
+        let error = err.downcast_ref::<ClaudeStreamError>().unwrap();
+        assert_eq!(error.error_type, "overloaded_error");
+        assert_eq!(error.class(), ErrorClass::Transient);
+        assert!(!decoder.is_complete());
+
+        // A payload that isn't JSON is a parse failure, not an in-band error
+        let err = decoder.decode(b"{\"type\": ").unwrap_err();
+        assert!(err.downcast_ref::<ClaudeStreamError>().is_none());
+    }
+}
@@ -0,0 +1,456 @@
+use crate::client_trait::ToolCall;
+use serde_json::Value;
+use tracing::{debug, warn};
+use uuid::Uuid;
+
+// First keys a JSON-RPC object can start with; any other object is prose
+const JSONRPC_KEYS: [&str; 4] = ["jsonrpc", "method", "id", "params"];
+
+// Info strings of code fences that may wrap a tool call
+const JSON_FENCES: [&str; 4] = ["", "json", "jsonrpc", "json-rpc"];
+
+// A fence opener with a longer info string than this is not a JSON fence
+const MAX_FENCE_OPENER: usize = 16;
+
+/// What a `JsonRpcStreamParser` found in the text pushed so far
+#[derive(Debug, Clone, PartialEq)]
+pub enum JsonRpcStreamEvent {
+    /// Prose to display, never part of a tool call
+    Text(String),
+    /// An `mcp.tool_call` object has begun and names this tool
+    ToolCallStarted { name: String },
+    /// An `mcp.tool_call` object is complete
+    ToolCallComplete(ToolCall),
+}
+
+/// Incremental parser separating prose from `mcp.tool_call` JSON-RPC objects in streamed
+/// model text.
+///
+/// Text can be pushed in arbitrary pieces, including UTF-8 sequences split across
+/// `push_bytes` calls. Prose is emitted as soon as it can't be the start of a tool call; a
+/// `{` is held back until the object either turns out not to be JSON-RPC or closes. Code
+/// fences around a tool call are dropped with it, so neither the JSON nor an empty fence
+/// reaches the display. Other JSON, fenced or not, is passed through as text.
+#[derive(Debug, Default)]
+pub struct JsonRpcStreamParser {
+    // Text not yet emitted; a candidate object or fence always starts at 0
+    buffer: String,
+    // Incomplete UTF-8 sequence at the end of the last push_bytes
+    partial_utf8: Vec<u8>,
+    object: Option<ObjectScan>,
+    // A fenced tool call was taken, so its closing fence is dropped too
+    close_fence: bool,
+}
+
+// Progress through a candidate object starting at `brace` in the buffer
+#[derive(Debug)]
+struct ObjectScan {
+    brace: usize,
+    pos: usize,
+    depth: usize,
+    in_string: bool,
+    escaped: bool,
+    key_start: Option<usize>,
+    key_checked: bool,
+    started: bool,
+}
+
+enum Scan {
+    Incomplete,
+    Complete(usize),
+    Rejected,
+}
+
+impl ObjectScan {
+    fn new(brace: usize) -> Self {
+        Self {
+            brace,
+            pos: brace,
+            depth: 0,
+            in_string: false,
+            escaped: false,
+            key_start: None,
+            key_checked: false,
+            started: false,
+        }
+    }
+
+    // Scan from where the last call stopped. Braces, quotes and backslashes are ASCII, so
+    // bytes are safe to scan even inside multi-byte characters.
+    fn scan(&mut self, text: &str) -> Scan {
+        let bytes = text.as_bytes();
+        while self.pos < bytes.len() {
+            let i = self.pos;
+            let byte = bytes[i];
+            self.pos += 1;
+
+            if self.in_string {
+                if self.escaped {
+                    self.escaped = false;
+                } else if byte == b'\\' {
+                    self.escaped = true;
+                } else if byte == b'"' {
+                    self.in_string = false;
+                    if let Some(start) = self.key_start.take() {
+                        self.key_checked = true;
+                        if !JSONRPC_KEYS.contains(&&text[start + 1..i]) {
+                            return Scan::Rejected;
+                        }
+                    }
+                }
+                continue;
+            }
+
+            // The object must open with a string key, checked once it is complete
+            if self.depth == 1 && !self.key_checked && self.key_start.is_none() {
+                match byte {
+                    b'"' => {
+                        self.key_start = Some(i);
+                        self.in_string = true;
+                        continue;
+                    }
+                    b if b.is_ascii_whitespace() => continue,
+                    _ => return Scan::Rejected,
+                }
+            }
+
+            match byte {
+                b'"' => self.in_string = true,
+                b'{' | b'[' => self.depth += 1,
+                b'}' | b']' => {
+                    self.depth -= 1;
+                    if self.depth == 0 {
+                        return Scan::Complete(i + 1);
+                    }
+                }
+                _ => {}
+            }
+        }
+        Scan::Incomplete
+    }
+}
+
+impl JsonRpcStreamParser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add streamed text and return the events it completes
+    pub fn push(&mut self, text: &str) -> Vec<JsonRpcStreamEvent> {
+        self.buffer.push_str(text);
+        self.process()
+    }
+
+    /// Add raw bytes, which may end part-way through a UTF-8 sequence. Invalid sequences
+    /// become U+FFFD.
+    pub fn push_bytes(&mut self, bytes: &[u8]) -> Vec<JsonRpcStreamEvent> {
+        self.partial_utf8.extend_from_slice(bytes);
+        let pending = std::mem::take(&mut self.partial_utf8);
+        let mut rest = pending.as_slice();
+        loop {
+            match std::str::from_utf8(rest) {
+                Ok(text) => {
+                    self.buffer.push_str(text);
+                    break;
+                }
+                Err(e) => {
+                    let (valid, after) = rest.split_at(e.valid_up_to());
+                    // Safe: from_utf8 just validated this prefix
+                    self.buffer
+                        .push_str(std::str::from_utf8(valid).unwrap_or_default());
+                    match e.error_len() {
+                        Some(len) => {
+                            self.buffer.push(char::REPLACEMENT_CHARACTER);
+                            rest = &after[len..];
+                        }
+                        None => {
+                            self.partial_utf8 = after.to_vec();
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+        self.process()
+    }
+
+    /// End of the stream: emit whatever is still held back as text
+    pub fn finish(&mut self) -> Vec<JsonRpcStreamEvent> {
+        if !self.partial_utf8.is_empty() {
+            let tail = std::mem::take(&mut self.partial_utf8);
+            self.buffer.push_str(&String::from_utf8_lossy(&tail));
+        }
+        if self.object.take().is_some() {
+            warn!("Stream ended inside a JSON object, passing it through as text");
+        }
+        if std::mem::take(&mut self.close_fence) && self.buffer.trim() == "```" {
+            self.buffer.clear();
+        }
+
+        let text = std::mem::take(&mut self.buffer);
+        if text.is_empty() {
+            Vec::new()
+        } else {
+            vec![JsonRpcStreamEvent::Text(text)]
+        }
+    }
+
+    fn process(&mut self) -> Vec<JsonRpcStreamEvent> {
+        let mut events = Vec::new();
+        loop {
+            let progressed = if self.close_fence {
+                self.skip_closing_fence()
+            } else if self.object.is_some() {
+                self.scan_object(&mut events)
+            } else {
+                self.find_candidate(&mut events)
+            };
+            if !progressed {
+                return events;
+            }
+        }
+    }
+
+    // Emit prose up to the next `{` or code fence and start scanning there. Returns false
+    // when more text is needed to decide.
+    fn find_candidate(&mut self, events: &mut Vec<JsonRpcStreamEvent>) -> bool {
+        let brace = self.buffer.find('{');
+        let fence = self.buffer.find("```");
+        let start = match (brace, fence) {
+            (Some(b), Some(f)) => b.min(f),
+            (Some(b), None) => b,
+            (None, Some(f)) => f,
+            (None, None) => {
+                // Hold back backticks that may turn out to open a fence
+                let held = self.buffer.len() - self.buffer.trim_end_matches('`').len();
+                let end = self.buffer.len() - held;
+                self.emit(end, events);
+                return false;
+            }
+        };
+        self.emit(start, events);
+
+        if self.buffer.starts_with('{') {
+            self.object = Some(ObjectScan::new(0));
+            return true;
+        }
+
+        // A fence: only a JSON fence directly followed by an object is held back
+        let Some(newline) = self.buffer.find('\n') else {
+            if self.buffer.len() > MAX_FENCE_OPENER {
+                self.emit(3, events);
+                return true;
+            }
+            return false;
+        };
+        if !JSON_FENCES.contains(&self.buffer[3..newline].trim()) {
+            self.emit(newline + 1, events);
+            return true;
+        }
+        let after = &self.buffer[newline + 1..];
+        match after.trim_start().chars().next() {
+            None => false,
+            Some('{') => {
+                let brace = self.buffer.len() - after.trim_start().len();
+                self.object = Some(ObjectScan::new(brace));
+                true
+            }
+            Some(_) => {
+                self.emit(newline + 1, events);
+                true
+            }
+        }
+    }
+
+    fn scan_object(&mut self, events: &mut Vec<JsonRpcStreamEvent>) -> bool {
+        let Some(mut scan) = self.object.take() else {
+            return false;
+        };
+        match scan.scan(&self.buffer) {
+            Scan::Incomplete => {
+                if !scan.started {
+                    let partial = &self.buffer[scan.brace..];
+                    if partial.contains("\"mcp.tool_call\"") {
+                        if let Some(name) = partial_string_field(partial, "name") {
+                            debug!("Tool call started in stream: {}", name);
+                            events.push(JsonRpcStreamEvent::ToolCallStarted { name });
+                            scan.started = true;
+                        }
+                    }
+                }
+                self.object = Some(scan);
+                false
+            }
+            Scan::Rejected => {
+                self.emit(scan.brace + 1, events);
+                true
+            }
+            Scan::Complete(end) => {
+                let call = serde_json::from_str::<Value>(&self.buffer[scan.brace..end])
+                    .ok()
+                    .and_then(|value| tool_call_from_jsonrpc(&value));
+                match call {
+                    Some(call) => {
+                        if !scan.started {
+                            events.push(JsonRpcStreamEvent::ToolCallStarted {
+                                name: call.tool.clone(),
+                            });
+                        }
+                        debug!("Tool call complete in stream: {} ({})", call.tool, call.id);
+                        events.push(JsonRpcStreamEvent::ToolCallComplete(call));
+                        self.close_fence = scan.brace > 0;
+                        self.buffer.drain(..end);
+                    }
+                    None => self.emit(end, events),
+                }
+                true
+            }
+        }
+    }
+
+    // Drop the fence closing a fenced tool call, or give up on it at the first other text
+    fn skip_closing_fence(&mut self) -> bool {
+        let trimmed = self.buffer.trim_start();
+        if trimmed.is_empty() || "```".starts_with(trimmed) {
+            return false;
+        }
+        if let Some(rest) = trimmed.strip_prefix("```") {
+            let Some(newline) = rest.find('\n') else {
+                return false;
+            };
+            let end = self.buffer.len() - rest.len() + newline + 1;
+            self.buffer.drain(..end);
+        }
+        self.close_fence = false;
+        true
+    }
+
+    fn emit(&mut self, end: usize, events: &mut Vec<JsonRpcStreamEvent>) {
+        if end > 0 {
+            let text: String = self.buffer.drain(..end).collect();
+            events.push(JsonRpcStreamEvent::Text(text));
+        }
+    }
+}
+
+/// The tool call in an `mcp.tool_call` JSON-RPC request, if `value` is a well-formed one
+pub fn tool_call_from_jsonrpc(value: &Value) -> Option<ToolCall> {
+    if value.get("method")?.as_str()? != "mcp.tool_call" {
+        return None;
+    }
+    let params = value.get("params")?;
+    Some(ToolCall {
+        id: jsonrpc_call_id(value.get("id").unwrap_or(&Value::Null)),
+        tool: params.get("name")?.as_str()?.to_string(),
+        params: params.get("parameters")?.clone(),
+    })
+}
+
+// Reuse the JSON-RPC id so consumers can match a tool call to the text they received
+pub(super) fn jsonrpc_call_id(id: &Value) -> String {
+    match id {
+        Value::String(id) => id.clone(),
+        Value::Null => Uuid::new_v4().to_string(),
+        other => other.to_string(),
+    }
+}
+
+// The value of a string field that is already complete in a partial JSON object
+fn partial_string_field(partial: &str, key: &str) -> Option<String> {
+    let pattern = format!("\"{}\"", key);
+    let after_key = &partial[partial.find(&pattern)? + pattern.len()..];
+    let value = after_key.trim_start().strip_prefix(':')?.trim_start();
+    if !value.starts_with('"') {
+        return None;
+    }
+
+    let mut escaped = false;
+    for (i, c) in value.char_indices().skip(1) {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '"' => return serde_json::from_str(&value[..=i]).ok(),
+            _ => {}
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    // Push the text one character at a time, merging adjacent text events
+    fn parse_chars(text: &str) -> Vec<JsonRpcStreamEvent> {
+        let mut parser = JsonRpcStreamParser::new();
+        let mut events = Vec::new();
+        for c in text.chars() {
+            events.extend(parser.push(&c.to_string()));
+        }
+        events.extend(parser.finish());
+
+        let mut merged: Vec<JsonRpcStreamEvent> = Vec::new();
+        for event in events {
+            match (merged.last_mut(), event) {
+                (Some(JsonRpcStreamEvent::Text(last)), JsonRpcStreamEvent::Text(text)) => {
+                    last.push_str(&text)
+                }
+                (_, event) => merged.push(event),
+            }
+        }
+        merged
+    }
+
+    #[test]
+    fn test_tool_call_separated_from_prose() {
+        let events = parse_chars(
+            "Let me look {first}.\n```json\n{\"jsonrpc\":\"2.0\",\"method\":\"mcp.tool_call\",\
+             \"params\":{\"name\":\"shell\",\"parameters\":{\"command\":\"ls }\"}},\"id\":7}\n```\nDone.",
+        );
+
+        assert_eq!(
+            events,
+            vec![
+                JsonRpcStreamEvent::Text("Let me look {first}.\n".to_string()),
+                JsonRpcStreamEvent::ToolCallStarted {
+                    name: "shell".to_string()
+                },
+                JsonRpcStreamEvent::ToolCallComplete(ToolCall {
+                    id: "7".to_string(),
+                    tool: "shell".to_string(),
+                    params: json!({"command": "ls }"}),
+                }),
+                JsonRpcStreamEvent::Text("Done.".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_other_json_and_fences_pass_through() {
+        let text = "Config:\n```json\n{\"debug\": true}\n```\nand {\"id\": 1, \"result\": \"ok\"}";
+        assert_eq!(
+            parse_chars(text),
+            vec![JsonRpcStreamEvent::Text(text.to_string())]
+        );
+    }
+
+    #[test]
+    fn test_utf8_split_across_pushes() {
+        let mut parser = JsonRpcStreamParser::new();
+        let bytes = "héllo 🦀".as_bytes();
+        let mut events = parser.push_bytes(&bytes[..2]);
+        events.extend(parser.push_bytes(&bytes[2..bytes.len() - 1]));
+        events.extend(parser.push_bytes(&bytes[bytes.len() - 1..]));
+        events.extend(parser.finish());
+
+        let text: String = events
+            .into_iter()
+            .map(|event| match event {
+                JsonRpcStreamEvent::Text(text) => text,
+                other => panic!("unexpected event {:?}", other),
+            })
+            .collect();
+        assert_eq!(text, "héllo 🦀");
+    }
+}
@@ -1,2 +1,10 @@
-// This module will contain streaming response processing utilities
-// Placeholder for future implementation
+// Streaming response processing utilities shared by the provider clients
+mod claude;
+mod jsonrpc;
+mod openai;
+mod sse;
+
+pub use claude::{ClaudeStreamDecoder, ClaudeStreamError};
+pub use jsonrpc::{tool_call_from_jsonrpc, JsonRpcStreamEvent, JsonRpcStreamParser};
+pub use openai::OpenAiStreamDecoder;
+pub use sse::SseParser;
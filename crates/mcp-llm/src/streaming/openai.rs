@@ -0,0 +1,247 @@
+use crate::client_trait::{StreamChunk, TokenUsage, ToolCall as ClientToolCall};
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use tracing::{debug, trace};
+use uuid::Uuid;
+
+// One `chat.completion.chunk` event
+#[derive(Debug, Deserialize)]
+struct ChatStreamEvent {
+    #[serde(default)]
+    choices: Vec<ChatStreamChoice>,
+    // Only present on the final event, when usage was requested
+    #[serde(default)]
+    usage: Option<ChatStreamUsage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatStreamUsage {
+    #[serde(default)]
+    prompt_tokens: u64,
+    #[serde(default)]
+    completion_tokens: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatStreamChoice {
+    #[serde(default)]
+    delta: Option<ChatDelta>,
+    #[serde(default)]
+    finish_reason: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatDelta {
+    #[serde(default)]
+    content: Option<String>,
+    #[serde(default)]
+    tool_calls: Vec<ChatDeltaToolCall>,
+}
+
+// Function calls arrive in pieces keyed by index; only the first piece carries id and name
+#[derive(Debug, Deserialize)]
+struct ChatDeltaToolCall {
+    #[serde(default)]
+    index: usize,
+    #[serde(default)]
+    id: Option<String>,
+    #[serde(default)]
+    function: Option<ChatDeltaFunction>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatDeltaFunction {
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    arguments: Option<String>,
+}
+
+#[derive(Debug, Default)]
+struct PendingFunctionCall {
+    id: String,
+    name: String,
+    arguments: String,
+}
+
+/// Turns OpenAI-compatible `chat/completions` stream events into `StreamChunk`s.
+///
+/// Content deltas are emitted as they arrive. Function calls are accumulated by index and
+/// reported once the choice finishes, since their arguments are streamed as JSON fragments.
+pub struct OpenAiStreamDecoder {
+    request_id: String,
+    pending_calls: BTreeMap<usize, PendingFunctionCall>,
+    usage: Option<TokenUsage>,
+    complete: bool,
+}
+
+impl OpenAiStreamDecoder {
+    pub fn new(request_id: &str) -> Self {
+        Self {
+            request_id: request_id.to_string(),
+            pending_calls: BTreeMap::new(),
+            usage: None,
+            complete: false,
+        }
+    }
+
+    /// Decode the data of one server-sent event, returning the chunks it produces
+    pub fn decode(&mut self, data: &str) -> Result<Vec<StreamChunk>> {
+        if data.trim() == "[DONE]" {
+            return self.finish();
+        }
+
+        let event: ChatStreamEvent = serde_json::from_str(data)?;
+        let mut chunks = Vec::new();
+
+        if let Some(usage) = event.usage {
+            self.usage = Some(TokenUsage::new(
+                usage.prompt_tokens,
+                usage.completion_tokens,
+            ));
+        }
+
+        // Only the first choice is requested
+        let Some(choice) = event.choices.into_iter().next() else {
+            return Ok(chunks);
+        };
+
+        if let Some(delta) = choice.delta {
+            if let Some(content) = delta.content.filter(|c| !c.is_empty()) {
+                chunks.push(self.chunk(content, None, false));
+            }
+
+            for call in delta.tool_calls {
+                let pending = self.pending_calls.entry(call.index).or_default();
+                if let Some(id) = call.id {
+                    pending.id = id;
+                }
+                if let Some(function) = call.function {
+                    if let Some(name) = function.name {
+                        pending.name.push_str(&name);
+                    }
+                    if let Some(arguments) = function.arguments {
+                        pending.arguments.push_str(&arguments);
+                    }
+                }
+            }
+        }
+
+        if let Some(finish_reason) = choice.finish_reason {
+            trace!("Choice finished: {}", finish_reason);
+            chunks.extend(self.flush_tool_calls()?);
+        }
+
+        Ok(chunks)
+    }
+
+    /// Report any outstanding function calls and emit the final chunk, once
+    pub fn finish(&mut self) -> Result<Vec<StreamChunk>> {
+        if self.complete {
+            return Ok(Vec::new());
+        }
+
+        let mut chunks = self.flush_tool_calls()?;
+        self.complete = true;
+        let mut last = self.chunk(String::new(), None, true);
+        last.usage = self.usage;
+        chunks.push(last);
+        Ok(chunks)
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.complete
+    }
+
+    fn flush_tool_calls(&mut self) -> Result<Vec<StreamChunk>> {
+        let pending = std::mem::take(&mut self.pending_calls);
+
+        let mut chunks = Vec::with_capacity(pending.len());
+        for call in pending.into_values() {
+            let params = if call.arguments.trim().is_empty() {
+                Value::Object(Default::default())
+            } else {
+                serde_json::from_str(&call.arguments)
+                    .map_err(|e| anyhow!("Invalid arguments for function {}: {}", call.name, e))?
+            };
+
+            // Some local servers leave out the call id
+            let id = if call.id.is_empty() {
+                Uuid::new_v4().to_string()
+            } else {
+                call.id
+            };
+
+            debug!("Function call in stream: {} ({})", call.name, id);
+            chunks.push(self.chunk(
+                String::new(),
+                Some(ClientToolCall {
+                    id,
+                    tool: call.name,
+                    params,
+                }),
+                false,
+            ));
+        }
+
+        Ok(chunks)
+    }
+
+    fn chunk(
+        &self,
+        content: String,
+        tool_call: Option<ClientToolCall>,
+        is_complete: bool,
+    ) -> StreamChunk {
+        StreamChunk {
+            id: self.request_id.clone(),
+            content,
+            is_tool_call: tool_call.is_some(),
+            tool_call,
+            is_complete,
+            usage: None,
+            thinking: String::new(),
+            thinking_block: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_content_and_function_call_deltas() {
+        let mut decoder = OpenAiStreamDecoder::new("req-1");
+
+        let events = [
+            json!({"choices": [{"index": 0, "delta": {"role": "assistant", "content": "Checking"}}]}),
+            json!({"choices": [{"index": 0, "delta": {"tool_calls": [{"index": 0, "id": "call_1", "type": "function", "function": {"name": "shell", "arguments": ""}}]}}]}),
+            json!({"choices": [{"index": 0, "delta": {"tool_calls": [{"index": 0, "function": {"arguments": "{\"command\":"}}]}}]}),
+            json!({"choices": [{"index": 0, "delta": {"tool_calls": [{"index": 0, "function": {"arguments": "\"ls\"}"}}]}}]}),
+            json!({"choices": [{"index": 0, "delta": {}, "finish_reason": "tool_calls"}]}),
+            json!({"choices": [], "usage": {"prompt_tokens": 20, "completion_tokens": 7, "total_tokens": 27}}),
+        ];
+
+        let mut chunks = Vec::new();
+        for event in events {
+            chunks.extend(decoder.decode(&event.to_string()).unwrap());
+        }
+        chunks.extend(decoder.decode("[DONE]").unwrap());
+
+        assert_eq!(chunks.len(), 3);
+        assert_eq!(chunks[0].content, "Checking");
+
+        let tool_call = chunks[1].tool_call.as_ref().unwrap();
+        assert_eq!(tool_call.id, "call_1");
+        assert_eq!(tool_call.tool, "shell");
+        assert_eq!(tool_call.params, json!({"command": "ls"}));
+
+        assert!(chunks[2].is_complete);
+        assert_eq!(chunks[2].usage, Some(TokenUsage::new(20, 7)));
+        assert!(decoder.finish().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,58 @@
+/// Incremental parser for `text/event-stream` bodies.
+///
+/// Bytes can be pushed in arbitrary pieces; the `data:` payload of each complete event is
+/// returned once its terminating blank line has arrived.
+#[derive(Debug, Default)]
+pub struct SseParser {
+    buffer: Vec<u8>,
+}
+
+impl SseParser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add bytes from the response body and return the data of any completed events
+    pub fn push(&mut self, bytes: &[u8]) -> Vec<String> {
+        // Normalize CRLF line endings, JSON payloads never contain a raw CR
+        self.buffer.extend(bytes.iter().filter(|b| **b != b'\r'));
+
+        let mut events = Vec::new();
+        while let Some(end) = self.buffer.windows(2).position(|w| w == b"\n\n") {
+            let block: Vec<u8> = self.buffer.drain(..end + 2).collect();
+            let block = String::from_utf8_lossy(&block);
+
+            let data = block
+                .lines()
+                .filter_map(|line| line.strip_prefix("data:"))
+                .map(|data| data.strip_prefix(' ').unwrap_or(data))
+                .collect::<Vec<_>>()
+                .join("\n");
+
+            if !data.is_empty() {
+                events.push(data);
+            }
+        }
+
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_events_split_across_pushes() {
+        let mut parser = SseParser::new();
+
+        assert!(parser.push(b"event: ping\r\ndata: {\"type\":").is_empty());
+        let events =
+            parser.push(b"\"ping\"}\r\n\r\n: comment\n\nevent: message_stop\ndata: {}\n\n");
+
+        assert_eq!(
+            events,
+            vec!["{\"type\":\"ping\"}".to_string(), "{}".to_string()]
+        );
+    }
+}
@@ -0,0 +1,302 @@
+use crate::client_trait::LlmClient;
+use crate::schema::SchemaError;
+use anyhow::{anyhow, Result};
+use jsonschema::{Draft, Validator};
+use mcp_core::context::{ConversationContext, MessageRole};
+use mcp_metrics::count;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use tracing::{debug, warn};
+
+/// A JSON Schema that a structured reply has to conform to
+pub struct OutputSchema {
+    schema: Value,
+    validator: Validator,
+    max_retries: usize,
+}
+
+impl OutputSchema {
+    /// Compile `schema` (draft 7), allowing two re-prompts for a non-conforming reply
+    pub fn new(schema: Value) -> Result<Self, SchemaError> {
+        let validator = Validator::options()
+            .with_draft(Draft::Draft7)
+            .build(&schema)
+            .map_err(|e| SchemaError::CompilationError(e.to_string()))?;
+
+        Ok(Self {
+            schema,
+            validator,
+            max_retries: 2,
+        })
+    }
+
+    /// How many times to re-prompt with the validation errors before giving up
+    pub fn with_max_retries(mut self, max_retries: usize) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    pub fn schema(&self) -> &Value {
+        &self.schema
+    }
+
+    /// Everything wrong with `value`, one message per error, or nothing if it conforms
+    pub fn validate(&self, value: &Value) -> Vec<String> {
+        self.validator
+            .iter_errors(value)
+            .map(|error| {
+                let path = error.instance_path.to_string();
+                if path.is_empty() {
+                    error.to_string()
+                } else {
+                    format!("{}: {}", path, error)
+                }
+            })
+            .collect()
+    }
+
+    /// Parse a reply and check it against the schema
+    pub fn check(&self, reply: &str) -> std::result::Result<Value, Vec<String>> {
+        let value =
+            extract_json(reply).map_err(|e| vec![format!("The reply is not valid JSON: {}", e)])?;
+        let errors = self.validate(&value);
+        if errors.is_empty() {
+            Ok(value)
+        } else {
+            Err(errors)
+        }
+    }
+
+    // Instructions appended to the request so the model knows what to produce
+    fn instructions(&self) -> String {
+        format!(
+            "Respond with a single JSON value that conforms to the following JSON Schema. \
+             Do not include any other text.\n\n{}",
+            serde_json::to_string_pretty(&self.schema).unwrap_or_default()
+        )
+    }
+}
+
+/// A reply that still did not conform to the schema once the retries ran out
+#[derive(Debug, thiserror::Error)]
+#[error(
+    "The reply did not conform to the schema after {attempts} attempts: {}",
+    .errors.join("; ")
+)]
+pub struct StructuredOutputError {
+    pub attempts: usize,
+    /// Validation errors for the last reply
+    pub errors: Vec<String>,
+    pub last_reply: String,
+}
+
+/// Request a reply that conforms to `schema`, re-prompting with the validation errors when
+/// it doesn't. `context` itself is left unchanged.
+///
+/// This is what [`LlmClient::send_structured`] does unless a client overrides it.
+pub async fn send_structured<C: LlmClient + ?Sized>(
+    client: &C,
+    context: &ConversationContext,
+    schema: &OutputSchema,
+) -> Result<Value> {
+    let mut request = context.clone();
+    add_user_text(&mut request, &schema.instructions());
+
+    let attempts = schema.max_retries + 1;
+    let mut attempt = 1;
+    loop {
+        let response = client.send_message(&request).await?;
+        let errors = match schema.check(&response.content) {
+            Ok(value) => {
+                debug!("Structured reply conformed on attempt {}", attempt);
+                return Ok(value);
+            }
+            Err(errors) => errors,
+        };
+
+        count!("llm.structured.invalid");
+        if attempt == attempts {
+            return Err(anyhow!(StructuredOutputError {
+                attempts,
+                errors,
+                last_reply: response.content,
+            }));
+        }
+
+        warn!(
+            "Structured reply did not conform to the schema (attempt {} of {}): {}",
+            attempt,
+            attempts,
+            errors.join("; ")
+        );
+        request.add_assistant_message(&response.content);
+        request.add_user_message(&format!(
+            "That reply does not conform to the schema:\n- {}\n\nRespond again with only the corrected JSON.",
+            errors.join("\n- ")
+        ));
+        attempt += 1;
+    }
+}
+
+/// Like [`send_structured`], deserializing the reply into `T`
+pub async fn send_structured_as<T, C>(
+    client: &C,
+    context: &ConversationContext,
+    schema: &OutputSchema,
+) -> Result<T>
+where
+    T: DeserializeOwned,
+    C: LlmClient + ?Sized,
+{
+    let value = client.send_structured(context, schema).await?;
+    Ok(serde_json::from_value(value)?)
+}
+
+// Add text to the last user message, or as a new one, so roles keep alternating
+fn add_user_text(context: &mut ConversationContext, text: &str) {
+    match context.messages.last_mut() {
+        Some(message) if message.role == MessageRole::User => {
+            message.content = format!("{}\n\n{}", message.content, text);
+        }
+        _ => context.add_user_message(text),
+    }
+}
+
+// Pull the JSON value out of a reply, which models sometimes wrap in a code fence or a
+// sentence of explanation
+fn extract_json(reply: &str) -> serde_json::Result<Value> {
+    let trimmed = reply.trim();
+    if let Ok(value) = serde_json::from_str(trimmed) {
+        return Ok(value);
+    }
+
+    let unfenced = trimmed
+        .strip_prefix("```json")
+        .or_else(|| trimmed.strip_prefix("```"))
+        .and_then(|rest| rest.trim_end().strip_suffix("```"))
+        .map(str::trim);
+    if let Some(value) = unfenced.and_then(|body| serde_json::from_str(body).ok()) {
+        return Ok(value);
+    }
+
+    // Fall back to the outermost object or array
+    let start = trimmed.find(['{', '[']);
+    let end = trimmed.rfind(['}', ']']);
+    match (start, end) {
+        (Some(start), Some(end)) if start < end => serde_json::from_str(&trimmed[start..=end]),
+        _ => serde_json::from_str(trimmed),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client_trait::{LlmResponse, StreamChunk};
+    use async_trait::async_trait;
+    use futures::Stream;
+    use serde::Deserialize;
+    use serde_json::json;
+    use std::sync::Mutex;
+
+    // Answers with the queued replies in order, keeping the requests it was sent
+    struct ScriptedClient {
+        replies: Mutex<Vec<&'static str>>,
+        requests: Mutex<Vec<ConversationContext>>,
+    }
+
+    impl ScriptedClient {
+        fn new(replies: &[&'static str]) -> Self {
+            Self {
+                replies: Mutex::new(replies.iter().rev().copied().collect()),
+                requests: Mutex::new(Vec::new()),
+            }
+        }
+    }
+
+    #[async_trait]
+    impl LlmClient for ScriptedClient {
+        async fn send_message(&self, context: &ConversationContext) -> Result<LlmResponse> {
+            self.requests.lock().unwrap().push(context.clone());
+            Ok(LlmResponse {
+                id: "msg".to_string(),
+                content: self.replies.lock().unwrap().pop().unwrap().to_string(),
+                tool_calls: Vec::new(),
+                usage: None,
+                thinking: Vec::new(),
+            })
+        }
+
+        async fn stream_message(
+            &self,
+            _context: &ConversationContext,
+        ) -> Result<Box<dyn Stream<Item = Result<StreamChunk>> + Unpin + Send>> {
+            Ok(Box::new(futures::stream::empty()))
+        }
+
+        fn cancel_request(&self, _request_id: &str) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Finding {
+        file: String,
+        line: u32,
+    }
+
+    fn finding_schema() -> OutputSchema {
+        OutputSchema::new(json!({
+            "type": "object",
+            "required": ["file", "line"],
+            "properties": {
+                "file": { "type": "string" },
+                "line": { "type": "integer", "minimum": 1 }
+            }
+        }))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_reprompts_with_validation_errors() {
+        let client = ScriptedClient::new(&[
+            r#"{"file": "src/lib.rs", "line": 0}"#,
+            "```json\n{\"file\": \"src/lib.rs\", \"line\": 12}\n```",
+        ]);
+        let mut context = ConversationContext::new();
+        context.add_user_message("Review this change");
+
+        let finding: Finding = send_structured_as(&client, &context, &finding_schema())
+            .await
+            .unwrap();
+        assert_eq!(
+            finding,
+            Finding {
+                file: "src/lib.rs".to_string(),
+                line: 12
+            }
+        );
+
+        let requests = client.requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        // The schema goes with the caller's message rather than in a turn of its own
+        assert_eq!(requests[0].messages.len(), 1);
+        assert!(requests[0].messages[0].content.contains("\"minimum\""));
+        assert!(requests[1].messages[2].content.contains("/line"));
+        assert_eq!(context.messages.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_gives_up_after_max_retries() {
+        let client = ScriptedClient::new(&["not json", "still not json"]);
+        let schema = finding_schema().with_max_retries(1);
+
+        let error = client
+            .send_structured(&ConversationContext::new(), &schema)
+            .await
+            .unwrap_err();
+        let error = error.downcast_ref::<StructuredOutputError>().unwrap();
+        assert_eq!(error.attempts, 2);
+        assert_eq!(error.last_reply, "still not json");
+        assert!(error.errors[0].starts_with("The reply is not valid JSON"));
+    }
+}
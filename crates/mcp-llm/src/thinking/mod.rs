@@ -0,0 +1,47 @@
+use serde::{Deserialize, Serialize};
+
+/// Smallest thinking budget Claude accepts
+pub const MIN_BUDGET_TOKENS: usize = 1024;
+
+/// The `thinking` field of Claude request bodies, enabling extended thinking.
+///
+/// Thinking counts towards `max_tokens`, so the budget has to stay below it. Claude also
+/// rejects requests that set a temperature or a low `top_p` while thinking is on.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ThinkingConfig {
+    #[serde(rename = "type")]
+    pub thinking_type: String,
+    pub budget_tokens: usize,
+}
+
+impl ThinkingConfig {
+    /// Enable thinking, keeping the budget within what Claude accepts for `max_tokens`
+    pub fn enabled(budget_tokens: usize, max_tokens: usize) -> Self {
+        Self {
+            thinking_type: "enabled".to_string(),
+            budget_tokens: budget_tokens
+                .min(max_tokens.saturating_sub(1))
+                .max(MIN_BUDGET_TOKENS),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_budget_is_clamped() {
+        assert_eq!(ThinkingConfig::enabled(8000, 16000).budget_tokens, 8000);
+        assert_eq!(ThinkingConfig::enabled(8000, 4096).budget_tokens, 4095);
+        assert_eq!(ThinkingConfig::enabled(100, 4096).budget_tokens, 1024);
+    }
+
+    #[test]
+    fn test_thinking_config_serializes_as_enabled() {
+        assert_eq!(
+            serde_json::to_value(ThinkingConfig::enabled(2048, 4096)).unwrap(),
+            serde_json::json!({"type": "enabled", "budget_tokens": 2048})
+        );
+    }
+}
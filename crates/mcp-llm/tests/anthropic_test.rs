@@ -0,0 +1,273 @@
+use anyhow::Result;
+use futures::StreamExt;
+use mcp_core::context::ConversationContext;
+use mcp_llm::anthropic::{AnthropicClient, AnthropicConfig};
+use mcp_llm::client_trait::{LlmClient, TokenUsage, ToolDefinition};
+use mcp_llm::retry::{classify, ErrorClass, RetryPolicy};
+use mcp_llm::{LayerStack, RetryLayer};
+use serde_json::json;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+
+mod common;
+
+use common::{mock_server, mock_server_sequence, request_body};
+
+fn client_for(base_url: String) -> AnthropicClient {
+    let config = AnthropicConfig::new("test-key".to_string(), "claude-test".to_string())
+        .with_base_url(base_url)
+        .with_system_prompt("You are a test assistant.".to_string());
+    AnthropicClient::new(config).with_tools(vec![ToolDefinition {
+        name: "shell".to_string(),
+        description: "Execute shell commands".to_string(),
+        input_schema: json!({"type": "object", "properties": {"command": {"type": "string"}}}),
+    }])
+}
+
+#[tokio::test]
+async fn test_send_message_parses_text_and_tool_use() -> Result<()> {
+    let body = json!({
+        "id": "msg_123",
+        "type": "message",
+        "role": "assistant",
+        "model": "claude-test",
+        "content": [
+            {"type": "text", "text": "Let me look."},
+            {"type": "tool_use", "id": "toolu_1", "name": "shell", "input": {"command": "ls"}}
+        ],
+        "stop_reason": "tool_use",
+        "usage": {"input_tokens": 12, "output_tokens": 8}
+    });
+    let (base_url, request) = mock_server("200 OK", "application/json", body.to_string()).await;
+
+    let mut context = ConversationContext::new();
+    context.add_user_message("What files are here?");
+
+    let response = client_for(base_url).send_message(&context).await?;
+    assert_eq!(response.id, "msg_123");
+    assert_eq!(response.content, "Let me look.");
+    assert_eq!(response.tool_calls.len(), 1);
+    assert_eq!(response.tool_calls[0].id, "toolu_1");
+    assert_eq!(response.tool_calls[0].params, json!({"command": "ls"}));
+
+    // The request carries the auth headers, system prompt and tool specs
+    let request = request.await?;
+    assert!(request.starts_with("POST /v1/messages"));
+    assert!(request.contains("x-api-key: test-key"));
+    assert!(request.contains("anthropic-version: 2023-06-01"));
+    let body = request_body(&request);
+    assert_eq!(body["system"][0]["text"], "You are a test assistant.");
+    assert_eq!(body["tools"][0]["name"], "shell");
+    assert!(body.get("stream").is_none());
+
+    // Tools, system prompt and history each end a cached prefix
+    assert_eq!(body["tools"][0]["cache_control"]["type"], "ephemeral");
+    assert_eq!(body["system"][0]["cache_control"]["type"], "ephemeral");
+    assert_eq!(
+        body["messages"][0]["content"][0]["cache_control"]["type"],
+        "ephemeral"
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_stream_message_over_sse() -> Result<()> {
+    let events = [
+        json!({"type": "message_start", "message": {"id": "msg_1", "type": "message", "role": "assistant", "content": [], "model": "claude-test", "usage": {"input_tokens": 5, "output_tokens": 1}}}),
+        json!({"type": "content_block_start", "index": 0, "content_block": {"type": "text", "text": ""}}),
+        json!({"type": "ping"}),
+        json!({"type": "content_block_delta", "index": 0, "delta": {"type": "text_delta", "text": "Hello"}}),
+        json!({"type": "content_block_delta", "index": 0, "delta": {"type": "text_delta", "text": " there"}}),
+        json!({"type": "content_block_stop", "index": 0}),
+        json!({"type": "message_delta", "delta": {"stop_reason": "end_turn"}, "usage": {"output_tokens": 2}}),
+        json!({"type": "message_stop"}),
+    ];
+    let body = events
+        .iter()
+        .map(|e| format!("event: {}\ndata: {}\n\n", e["type"].as_str().unwrap(), e))
+        .collect::<String>();
+    let (base_url, request) = mock_server("200 OK", "text/event-stream", body).await;
+
+    let mut context = ConversationContext::new();
+    context.add_user_message("Say hello");
+
+    let mut stream = client_for(base_url).stream_message(&context).await?;
+    let mut content = String::new();
+    let mut completed = false;
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk?;
+        content.push_str(&chunk.content);
+        if chunk.is_complete {
+            completed = true;
+            break;
+        }
+    }
+
+    assert_eq!(content, "Hello there");
+    assert!(completed, "Stream should end with a complete chunk");
+    assert!(request.await?.contains("\"stream\":true"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_cache_usage_is_reported() -> Result<()> {
+    let body = json!({
+        "id": "msg_cached",
+        "type": "message",
+        "role": "assistant",
+        "model": "claude-test",
+        "content": [{"type": "text", "text": "Cached."}],
+        "stop_reason": "end_turn",
+        "usage": {
+            "input_tokens": 12,
+            "output_tokens": 4,
+            "cache_read_input_tokens": 2048,
+            "cache_creation_input_tokens": 256
+        }
+    });
+    let (base_url, request) = mock_server("200 OK", "application/json", body.to_string()).await;
+
+    let mut context = ConversationContext::new();
+    context.add_user_message("Hello again");
+
+    let client = client_for(base_url);
+    let usage = client.send_message(&context).await?.usage.unwrap();
+    assert_eq!(usage, TokenUsage::new(12, 4).with_cache(2048, 256));
+    assert_eq!(usage.prompt_tokens(), 2316);
+    request.await?;
+
+    // Without caching the request carries no breakpoints
+    let (base_url, request) = mock_server("200 OK", "application/json", body.to_string()).await;
+    let config = AnthropicConfig::new("test-key".to_string(), "claude-test".to_string())
+        .with_base_url(base_url)
+        .with_system_prompt("You are a test assistant.".to_string())
+        .with_prompt_caching(false);
+    AnthropicClient::new(config).send_message(&context).await?;
+    assert!(!request.await?.contains("cache_control"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_api_error_is_reported() -> Result<()> {
+    let body = json!({
+        "type": "error",
+        "error": {"type": "authentication_error", "message": "invalid x-api-key"}
+    });
+    let (base_url, _request) =
+        mock_server("401 Unauthorized", "application/json", body.to_string()).await;
+
+    let mut context = ConversationContext::new();
+    context.add_user_message("Hello");
+
+    let err = client_for(base_url)
+        .send_message(&context)
+        .await
+        .expect_err("A 401 should be reported as an error");
+    let message = err.to_string();
+    assert!(message.contains("401"), "Unexpected error: {}", message);
+    assert!(
+        message.contains("invalid x-api-key"),
+        "Unexpected error: {}",
+        message
+    );
+    assert_eq!(classify(&err), ErrorClass::Auth);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_throttled_request_is_retried() -> Result<()> {
+    let throttled = json!({
+        "type": "error",
+        "error": {"type": "rate_limit_error", "message": "Number of requests has exceeded your rate limit"}
+    });
+    let overloaded = json!({
+        "type": "error",
+        "error": {"type": "overloaded_error", "message": "Overloaded"}
+    });
+    let success = json!({
+        "id": "msg_retry",
+        "type": "message",
+        "role": "assistant",
+        "model": "claude-test",
+        "content": [{"type": "text", "text": "Made it."}],
+        "stop_reason": "end_turn",
+        "usage": {"input_tokens": 3, "output_tokens": 2}
+    });
+    let (base_url, requests) = mock_server_sequence(vec![
+        (
+            "429 Too Many Requests",
+            "application/json",
+            throttled.to_string(),
+        ),
+        ("529 Overloaded", "application/json", overloaded.to_string()),
+        ("200 OK", "application/json", success.to_string()),
+    ])
+    .await;
+
+    let mut context = ConversationContext::new();
+    context.add_user_message("Hello");
+
+    let client = LayerStack::new()
+        .layer(RetryLayer::new(
+            "anthropic",
+            RetryPolicy::default().with_backoff(Duration::from_millis(1), Duration::from_millis(5)),
+        ))
+        .service(Arc::new(client_for(base_url)));
+    let response = client.send_message(&context).await?;
+    assert_eq!(response.content, "Made it.");
+    assert_eq!(requests.load(Ordering::SeqCst), 3);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_retries_give_up_after_max_retries() -> Result<()> {
+    let throttled = json!({
+        "type": "error",
+        "error": {"type": "rate_limit_error", "message": "Slow down"}
+    })
+    .to_string();
+    let (base_url, requests) = mock_server_sequence(vec![
+        (
+            "429 Too Many Requests",
+            "application/json",
+            throttled.clone(),
+        ),
+        ("429 Too Many Requests", "application/json", throttled),
+    ])
+    .await;
+
+    let mut context = ConversationContext::new();
+    context.add_user_message("Hello");
+
+    let client = LayerStack::new()
+        .layer(RetryLayer::new(
+            "anthropic",
+            RetryPolicy::default()
+                .with_max_retries(1)
+                .with_backoff(Duration::from_millis(1), Duration::from_millis(5)),
+        ))
+        .service(Arc::new(client_for(base_url)));
+    let err = client
+        .send_message(&context)
+        .await
+        .expect_err("Throttling should be reported once retries run out");
+    assert_eq!(classify(&err), ErrorClass::Throttled);
+    assert_eq!(requests.load(Ordering::SeqCst), 2);
+
+    Ok(())
+}
+
+#[test]
+fn test_cancel_unknown_request() {
+    let client = AnthropicClient::new(AnthropicConfig::new(
+        "test-key".to_string(),
+        "claude-test".to_string(),
+    ));
+    assert!(client.cancel_request("missing").is_err());
+}
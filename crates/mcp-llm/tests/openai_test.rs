@@ -0,0 +1,133 @@
+use anyhow::Result;
+use futures::StreamExt;
+use mcp_core::context::ConversationContext;
+use mcp_llm::client_trait::{LlmClient, ToolDefinition};
+use mcp_llm::openai::{OpenAiClient, OpenAiConfig};
+use mcp_llm::retry::{classify, ErrorClass};
+use serde_json::json;
+
+mod common;
+
+use common::{mock_server, request_body};
+
+fn client_for(base_url: String) -> OpenAiClient {
+    let config = OpenAiConfig::new("llama3.1".to_string())
+        .with_base_url(format!("{}/v1", base_url))
+        .with_system_prompt("You are a test assistant.".to_string());
+    OpenAiClient::new(config).with_tools(vec![ToolDefinition {
+        name: "shell".to_string(),
+        description: "Execute shell commands".to_string(),
+        input_schema: json!({"type": "object", "properties": {"command": {"type": "string"}}}),
+    }])
+}
+
+#[tokio::test]
+async fn test_send_message_maps_function_calls() -> Result<()> {
+    let body = json!({
+        "id": "chatcmpl-1",
+        "object": "chat.completion",
+        "choices": [{
+            "index": 0,
+            "message": {
+                "role": "assistant",
+                "content": "",
+                "tool_calls": [{
+                    "id": "call_1",
+                    "type": "function",
+                    "function": {"name": "shell", "arguments": "{\"command\":\"ls\"}"}
+                }]
+            },
+            "finish_reason": "tool_calls"
+        }]
+    });
+    let (base_url, request) = mock_server("200 OK", "application/json", body.to_string()).await;
+
+    let mut context = ConversationContext::new();
+    context.add_user_message("What files are here?");
+
+    let response = client_for(base_url).send_message(&context).await?;
+    assert_eq!(response.tool_calls.len(), 1);
+    assert_eq!(response.tool_calls[0].id, "call_1");
+    assert_eq!(response.tool_calls[0].tool, "shell");
+    assert_eq!(response.tool_calls[0].params, json!({"command": "ls"}));
+
+    // No API key is configured, so no Authorization header is sent
+    let request = request.await?;
+    assert!(request.starts_with("POST /v1/chat/completions"));
+    assert!(!request.to_ascii_lowercase().contains("authorization:"));
+
+    let body = request_body(&request);
+    assert_eq!(body["model"], "llama3.1");
+    assert_eq!(body["messages"][0]["role"], "system");
+    assert_eq!(body["tools"][0]["type"], "function");
+    assert_eq!(body["tools"][0]["function"]["name"], "shell");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_stream_message_with_function_call() -> Result<()> {
+    let events = [
+        json!({"choices": [{"index": 0, "delta": {"role": "assistant", "content": "Let me "}}]}),
+        json!({"choices": [{"index": 0, "delta": {"content": "check."}}]}),
+        json!({"choices": [{"index": 0, "delta": {"tool_calls": [{"index": 0, "id": "call_9", "type": "function", "function": {"name": "shell", "arguments": "{\"command\""}}]}}]}),
+        json!({"choices": [{"index": 0, "delta": {"tool_calls": [{"index": 0, "function": {"arguments": ":\"pwd\"}"}}]}}]}),
+        json!({"choices": [{"index": 0, "delta": {}, "finish_reason": "tool_calls"}]}),
+    ];
+    let mut body = events
+        .iter()
+        .map(|e| format!("data: {}\n\n", e))
+        .collect::<String>();
+    body.push_str("data: [DONE]\n\n");
+    let (base_url, request) = mock_server("200 OK", "text/event-stream", body).await;
+
+    let mut context = ConversationContext::new();
+    context.add_user_message("Where am I?");
+
+    let mut stream = client_for(base_url).stream_message(&context).await?;
+    let mut content = String::new();
+    let mut tool_calls = Vec::new();
+    let mut completed = false;
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk?;
+        content.push_str(&chunk.content);
+        tool_calls.extend(chunk.tool_call);
+        if chunk.is_complete {
+            completed = true;
+            break;
+        }
+    }
+
+    assert_eq!(content, "Let me check.");
+    assert_eq!(tool_calls.len(), 1);
+    assert_eq!(tool_calls[0].id, "call_9");
+    assert_eq!(tool_calls[0].params, json!({"command": "pwd"}));
+    assert!(completed, "Stream should end with a complete chunk");
+    assert_eq!(request_body(&request.await?)["stream"], true);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_api_error_is_reported() -> Result<()> {
+    let body = json!({"error": {"message": "model 'llama3.1' not found", "type": "api_error"}});
+    let (base_url, _request) =
+        mock_server("404 Not Found", "application/json", body.to_string()).await;
+
+    let mut context = ConversationContext::new();
+    context.add_user_message("Hello");
+
+    let err = client_for(base_url)
+        .stream_message(&context)
+        .await
+        .err()
+        .expect("A 404 should be reported as an error");
+    assert!(
+        err.to_string().contains("not found"),
+        "Unexpected error: {}",
+        err
+    );
+    assert_eq!(classify(&err), ErrorClass::ModelNotAvailable);
+
+    Ok(())
+}
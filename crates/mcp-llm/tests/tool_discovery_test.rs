@@ -61,6 +61,8 @@ impl LlmClient for MockLlmClient {
             id: "mock-response-id".to_string(),
             content: "This is a mock response".to_string(),
             tool_calls: vec![],
+            usage: None,
+            thinking: Vec::new(),
         })
     }
 
@@ -86,6 +88,9 @@ impl LlmClient for MockLlmClient {
                 is_tool_call: false,
                 tool_call: None,
                 is_complete: true,
+                usage: None,
+                thinking: String::new(),
+                thinking_block: None,
             };
 
             tx.send(Ok(chunk)).await.unwrap();
@@ -1,5 +1,6 @@
 use anyhow::{anyhow, Result};
 use crossbeam_channel::{self, bounded, Receiver, Sender};
+use mcp_llm::{RateLimitEvent, RateLimitListener};
 use std::sync::{Arc, Mutex};
 use tracing::{debug, info, warn};
 
@@ -75,6 +76,20 @@ impl EventBus {
         self.api_tx.clone()
     }
 
+    /// A rate limit listener that reports waiting requests as `ApiEvent::RateLimited`, for
+    /// limiters created with `RateLimiter::with_listener` or `RateLimits::with_listener`
+    pub fn rate_limit_listener(&self) -> RateLimitListener {
+        let api_tx = self.api_tx.clone();
+        Arc::new(move |event: &RateLimitEvent| {
+            if api_tx
+                .try_send(ApiEvent::RateLimited(event.clone()))
+                .is_err()
+            {
+                warn!("Dropped rate limit event for {}", event.model);
+            }
+        })
+    }
+
     /// Register a handler for UI events
     pub fn register_ui_handler(&self, handler: EventHandler<UiEvent>) -> Result<()> {
         match self.ui_handlers.lock() {
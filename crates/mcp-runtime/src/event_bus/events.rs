@@ -1,5 +1,6 @@
 use anyhow::Result;
-use mcp_core::context::ConversationContext;
+use mcp_core::context::{Attachment, ConversationContext};
+use mcp_llm::RateLimitEvent;
 use serde_json::Value;
 use std::future::Future;
 use std::pin::Pin;
@@ -82,6 +83,9 @@ pub enum UiEvent {
     /// User submitted input
     UserInput(String),
 
+    /// User submitted input with files attached
+    UserInputWithAttachments(String, Vec<Attachment>),
+
     /// User requested cancellation of current operation
     RequestCancellation,
 
@@ -116,6 +120,9 @@ pub enum ModelEvent {
     /// Stream chunk received from LLM
     LlmStreamChunk(String),
 
+    /// Reasoning streamed by the LLM ahead of its answer
+    LlmThinkingChunk(String),
+
     /// Request to update the conversation context
     UpdateContext(Arc<ConversationContext>),
 
@@ -138,6 +145,9 @@ pub enum ApiEvent {
     /// Cancel an ongoing request
     CancelRequest(String),
 
+    /// A request was cancelled and its connection closed; no more output will arrive for it
+    RequestCancelled(String),
+
     /// API connection established
     ConnectionEstablished,
 
@@ -146,6 +156,9 @@ pub enum ApiEvent {
 
     /// API error occurred
     Error(String),
+
+    /// A request is waiting locally for the model's rate limit budget
+    RateLimited(RateLimitEvent),
 }
 
 /// Direction for scrolling
@@ -7,4 +7,6 @@ pub use event_bus::{
     KeyCode, KeyEvent, KeyModifiers, ModelEvent, ScrollDirection, UiEvent,
 };
 pub use executor::ToolExecutor;
-pub use session::{Session, SessionManager};
+pub use session::{
+    CancelPolicy, Session, SessionManager, SessionRecord, SessionStore, SessionStoreError,
+};
@@ -2,13 +2,17 @@ use crate::event_bus::{self, ApiEvent, EventBus, EventHandler, ModelEvent, UiEve
 use crate::executor::ToolExecutor;
 use anyhow::{anyhow, Result};
 use futures::StreamExt;
-use mcp_core::context::ConversationContext;
+use mcp_core::context::{Attachment, ConversationContext};
+use mcp_llm::cancel::ActiveRequests;
 use mcp_llm::client_trait::{LlmClient, LlmResponse, StreamChunk};
-use std::collections::HashMap;
-use std::sync::{Arc, Mutex, RwLock};
+use std::sync::{Arc, RwLock};
 use tracing::{debug, error};
 use uuid::Uuid;
 
+mod store;
+
+pub use store::{SessionRecord, SessionStore, SessionStoreError};
+
 // Session manages the state of a conversation
 pub struct Session {
     id: String,
@@ -24,6 +28,14 @@ impl Session {
         }
     }
 
+    /// Carry on with a saved conversation, under its original id
+    pub fn restore(record: &SessionRecord) -> Self {
+        Self {
+            id: record.id.clone(),
+            context: Arc::new(RwLock::new(record.context())),
+        }
+    }
+
     pub fn get_id(&self) -> &str {
         &self.id
     }
@@ -38,6 +50,12 @@ impl Session {
         }
     }
 
+    pub fn add_user_message_with_attachments(&self, content: &str, attachments: Vec<Attachment>) {
+        if let Ok(mut context) = self.context.write() {
+            context.add_user_message_with_attachments(content, attachments);
+        }
+    }
+
     pub fn add_assistant_message(&self, content: &str) {
         if let Ok(mut context) = self.context.write() {
             context.add_assistant_message(content);
@@ -63,13 +81,24 @@ impl Default for Session {
     }
 }
 
+/// What happens to a streamed answer that is cut short by cancellation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CancelPolicy {
+    /// Keep the text received so far as the assistant's reply
+    #[default]
+    KeepPartial,
+    /// Discard the partial reply, leaving the conversation as it was before the request
+    DropPartial,
+}
+
 // SessionManager handles interactions between the UI, model, and tools
 pub struct SessionManager<L: LlmClient> {
     session: Arc<Session>,
     llm_client: Arc<L>,
     tool_executor: Arc<ToolExecutor>,
     event_bus: Arc<EventBus>,
-    active_requests: Arc<Mutex<HashMap<String, bool>>>,
+    active_requests: ActiveRequests,
+    cancel_policy: CancelPolicy,
 }
 
 impl<L: LlmClient + 'static> SessionManager<L> {
@@ -79,10 +108,23 @@ impl<L: LlmClient + 'static> SessionManager<L> {
             llm_client: Arc::new(llm_client),
             tool_executor: Arc::new(tool_executor),
             event_bus: Arc::new(event_bus),
-            active_requests: Arc::new(Mutex::new(HashMap::new())),
+            active_requests: ActiveRequests::new(),
+            cancel_policy: CancelPolicy::default(),
         }
     }
 
+    /// Choose whether a cancelled answer's partial text stays in the conversation
+    pub fn with_cancel_policy(mut self, policy: CancelPolicy) -> Self {
+        self.cancel_policy = policy;
+        self
+    }
+
+    /// Use `session` in place of a new, empty one, e.g. one restored from a `SessionStore`
+    pub fn with_session(mut self, session: Session) -> Self {
+        self.session = Arc::new(session);
+        self
+    }
+
     pub fn get_session(&self) -> Arc<Session> {
         self.session.clone()
     }
@@ -132,13 +174,20 @@ impl<L: LlmClient + 'static> SessionManager<L> {
                         session.add_user_message(&content);
                         let _ = model_tx.send(ModelEvent::ProcessUserMessage(content));
                     }
+                    UiEvent::UserInputWithAttachments(content, attachments) => {
+                        debug!(
+                            "Received user input with {} attachments: {}",
+                            attachments.len(),
+                            content
+                        );
+                        session.add_user_message_with_attachments(&content, attachments);
+                        let _ = model_tx.send(ModelEvent::ProcessUserMessage(content));
+                    }
                     UiEvent::RequestCancellation => {
                         debug!("Request cancellation received");
-                        // Mark all active requests as cancelled
-                        let mut requests = active_requests.lock().unwrap();
-                        for (_, cancelled) in requests.iter_mut() {
-                            *cancelled = true;
-                        }
+                        // Stop every request in flight
+                        let cancelled = active_requests.cancel_all();
+                        debug!("Cancelled {} active requests", cancelled);
                     }
                     UiEvent::ClearConversation => {
                         debug!("Clearing conversation");
@@ -161,6 +210,7 @@ impl<L: LlmClient + 'static> SessionManager<L> {
         let api_tx = self.event_bus.api_sender();
         let model_tx = self.event_bus.model_sender();
         let active_requests = self.active_requests.clone();
+        let cancel_policy = self.cancel_policy;
 
         event_bus::create_handler(move |event: ModelEvent| {
             let session = session.clone();
@@ -184,106 +234,61 @@ impl<L: LlmClient + 'static> SessionManager<L> {
                             }
                         };
 
-                        // Generate request ID
-                        let request_id = Uuid::new_v4().to_string();
-
                         // Register request for possible cancellation
-                        {
-                            let mut requests = active_requests.lock().unwrap();
-                            requests.insert(request_id.clone(), false);
-                        }
+                        let request = active_requests.register();
+
+                        // Text streamed so far that isn't in the conversation yet
+                        let mut partial = String::new();
 
                         // Decide between streaming or regular API based on config/preference
                         let use_streaming = true; // This could be a config option
 
-                        if use_streaming {
-                            // Start streaming response
-                            match llm_client.stream_message(&context).await {
-                                Ok(mut stream) => {
-                                    while let Some(chunk_result) = stream.next().await {
-                                        match chunk_result {
-                                            Ok(chunk) => {
-                                                // Check if request was cancelled
-                                                {
-                                                    let requests = active_requests.lock().unwrap();
-                                                    if let Some(cancelled) =
-                                                        requests.get(&request_id)
-                                                    {
-                                                        if *cancelled {
-                                                            debug!(
-                                                                "Request {} was cancelled",
-                                                                request_id
-                                                            );
-                                                            break;
-                                                        }
-                                                    }
-                                                }
-
-                                                // Store is_complete flag before moving chunk
-                                                let is_complete = chunk.is_complete;
-
-                                                // Process the chunk
-                                                Self::process_stream_chunk(
-                                                    chunk,
-                                                    &session,
-                                                    &model_tx,
-                                                    &tool_executor,
-                                                )
-                                                .await?;
-
-                                                // If this was the completion chunk, we're done
-                                                if is_complete {
-                                                    let _ = model_tx
-                                                        .send(ModelEvent::LlmResponseComplete);
-                                                    break;
-                                                }
-                                            }
-                                            Err(e) => {
-                                                error!("Error in stream: {:?}", e);
-                                                let _ = api_tx.send(ApiEvent::Error(format!(
-                                                    "Stream error: {}",
-                                                    e
-                                                )));
-                                                break;
-                                            }
-                                        }
-                                    }
-                                }
-                                Err(e) => {
-                                    error!("Failed to start streaming: {:?}", e);
-                                    let _ = api_tx.send(ApiEvent::Error(format!(
-                                        "Failed to start streaming: {}",
-                                        e
-                                    )));
-                                }
-                            }
-                        } else {
-                            // Use regular response
-                            match llm_client.send_message(&context).await {
-                                Ok(response) => {
-                                    Self::process_llm_response(
-                                        response,
+                        // Cancelling drops this future, which closes the connection to the
+                        // provider and stops any tool it was running
+                        let finished = request
+                            .run(async {
+                                if use_streaming {
+                                    Self::stream_response(
+                                        &context,
+                                        &*llm_client,
                                         &session,
                                         &model_tx,
+                                        &api_tx,
                                         &tool_executor,
+                                        &mut partial,
                                     )
-                                    .await?;
-
-                                    let _ = model_tx.send(ModelEvent::LlmResponseComplete);
+                                    .await
+                                } else {
+                                    Self::send_request(
+                                        &context,
+                                        &*llm_client,
+                                        &session,
+                                        &model_tx,
+                                        &api_tx,
+                                        &tool_executor,
+                                    )
+                                    .await
                                 }
-                                Err(e) => {
-                                    error!("Error sending message to LLM: {:?}", e);
-                                    let _ =
-                                        api_tx.send(ApiEvent::Error(format!("LLM error: {}", e)));
+                            })
+                            .await;
+
+                        match finished {
+                            Some(result) => {
+                                Self::flush_partial(&session, &mut partial);
+                                result?;
+                            }
+                            None => {
+                                debug!("Request {} was cancelled", request.id());
+                                match cancel_policy {
+                                    CancelPolicy::KeepPartial => {
+                                        Self::flush_partial(&session, &mut partial)
+                                    }
+                                    CancelPolicy::DropPartial => partial.clear(),
                                 }
+                                let _ = api_tx
+                                    .send(ApiEvent::RequestCancelled(request.id().to_string()));
                             }
                         }
-
-                        // Remove from active requests
-                        {
-                            let mut requests = active_requests.lock().unwrap();
-                            requests.remove(&request_id);
-                        }
                     }
                     ModelEvent::ToolResult(tool_id, result) => {
                         debug!("Received tool result from {}: {:?}", tool_id, result);
@@ -324,18 +329,13 @@ impl<L: LlmClient + 'static> SessionManager<L> {
                 if let ApiEvent::CancelRequest(request_id) = event {
                     debug!("Cancelling request: {}", request_id);
 
-                    // Mark the request as cancelled
-                    {
-                        let mut requests = active_requests.lock().unwrap();
-                        if let Some(cancelled) = requests.get_mut(&request_id) {
-                            *cancelled = true;
+                    // Requests started by this session stop themselves; any other id
+                    // belongs to the LLM client
+                    if !active_requests.cancel(&request_id) {
+                        if let Err(e) = llm_client.cancel_request(&request_id) {
+                            error!("Failed to cancel request {}: {:?}", request_id, e);
                         }
                     }
-
-                    // Try to cancel it in the LLM client
-                    if let Err(e) = llm_client.cancel_request(&request_id) {
-                        error!("Failed to cancel request {}: {:?}", request_id, e);
-                    }
                 }
 
                 Ok(())
@@ -343,14 +343,102 @@ impl<L: LlmClient + 'static> SessionManager<L> {
         })
     }
 
+    // Stream a response, forwarding chunks to the UI as they arrive
+    async fn stream_response(
+        context: &ConversationContext,
+        llm_client: &L,
+        session: &Session,
+        model_tx: &crossbeam_channel::Sender<ModelEvent>,
+        api_tx: &crossbeam_channel::Sender<ApiEvent>,
+        tool_executor: &ToolExecutor,
+        partial: &mut String,
+    ) -> Result<()> {
+        let mut stream = match llm_client.stream_message(context).await {
+            Ok(stream) => stream,
+            Err(e) => {
+                error!("Failed to start streaming: {:?}", e);
+                let _ = api_tx.send(ApiEvent::Error(format!("Failed to start streaming: {}", e)));
+                return Ok(());
+            }
+        };
+
+        while let Some(chunk_result) = stream.next().await {
+            match chunk_result {
+                Ok(chunk) => {
+                    // Store is_complete flag before moving chunk
+                    let is_complete = chunk.is_complete;
+
+                    // Process the chunk
+                    Self::process_stream_chunk(chunk, session, model_tx, tool_executor, partial)
+                        .await?;
+
+                    // If this was the completion chunk, we're done
+                    if is_complete {
+                        Self::flush_partial(session, partial);
+                        let _ = model_tx.send(ModelEvent::LlmResponseComplete);
+                        break;
+                    }
+                }
+                Err(e) => {
+                    error!("Error in stream: {:?}", e);
+                    let _ = api_tx.send(ApiEvent::Error(format!("Stream error: {}", e)));
+                    break;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // Send a request and process the full response
+    async fn send_request(
+        context: &ConversationContext,
+        llm_client: &L,
+        session: &Session,
+        model_tx: &crossbeam_channel::Sender<ModelEvent>,
+        api_tx: &crossbeam_channel::Sender<ApiEvent>,
+        tool_executor: &ToolExecutor,
+    ) -> Result<()> {
+        match llm_client.send_message(context).await {
+            Ok(response) => {
+                Self::process_llm_response(response, session, model_tx, tool_executor).await?;
+
+                let _ = model_tx.send(ModelEvent::LlmResponseComplete);
+            }
+            Err(e) => {
+                error!("Error sending message to LLM: {:?}", e);
+                let _ = api_tx.send(ApiEvent::Error(format!("LLM error: {}", e)));
+            }
+        }
+
+        Ok(())
+    }
+
+    // Add streamed text to the conversation as one assistant message
+    fn flush_partial(session: &Session, partial: &mut String) {
+        if !partial.is_empty() {
+            session.add_assistant_message(partial);
+            partial.clear();
+        }
+    }
+
     // Process a streaming chunk from the LLM
     async fn process_stream_chunk(
         chunk: StreamChunk,
         session: &Session,
         model_tx: &crossbeam_channel::Sender<ModelEvent>,
         tool_executor: &ToolExecutor,
+        partial: &mut String,
     ) -> Result<()> {
+        // Thinking is shown as it streams but isn't part of the answer
+        if !chunk.thinking.is_empty() {
+            let _ = model_tx.send(ModelEvent::LlmThinkingChunk(chunk.thinking));
+        }
+
         if chunk.is_tool_call {
+            // The text leading up to a tool call comes before its result
+            Self::flush_partial(session, partial);
+
             if let Some(tool_call) = chunk.tool_call {
                 debug!(
                     "Received tool call for {}: {:?}",
@@ -389,8 +477,8 @@ impl<L: LlmClient + 'static> SessionManager<L> {
             // Handle normal content
             debug!("Received content: {}", chunk.content);
 
-            // Update assistant message
-            session.add_assistant_message(&chunk.content);
+            // Collect the text until the response ends
+            partial.push_str(&chunk.content);
 
             // Send event for UI update
             let _ = model_tx.send(ModelEvent::LlmStreamChunk(chunk.content));
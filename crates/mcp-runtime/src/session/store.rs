@@ -0,0 +1,204 @@
+// Conversations saved to disk, one JSON file per session, so they can be resumed later
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use mcp_core::context::{ConversationContext, ConversationTree, Message, MessageRole};
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::fs;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+use tracing::{debug, warn};
+use uuid::Uuid;
+
+const EXTENSION: &str = "json";
+const TITLE_CHARS: usize = 60;
+
+/// A conversation as it is saved: its messages, with their tool calls and results, and
+/// enough about where it was held to find it again
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionRecord {
+    pub id: String,
+    /// Model the conversation was last held with, after any fallback
+    pub model: String,
+    /// Region that model was used in, where the provider has regions
+    #[serde(default)]
+    pub region: Option<String>,
+    /// Working directory the session was started in
+    pub directory: PathBuf,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    #[serde(default)]
+    pub system_prompt: String,
+    #[serde(default)]
+    pub messages: Vec<Message>,
+    /// Branches left behind by rewinding, along with the current one
+    #[serde(default)]
+    pub tree: ConversationTree,
+}
+
+impl SessionRecord {
+    pub fn new(model: impl Into<String>, directory: impl Into<PathBuf>) -> Self {
+        let now = Utc::now();
+        Self {
+            id: Uuid::new_v4().to_string(),
+            model: model.into(),
+            region: None,
+            directory: directory.into(),
+            created_at: now,
+            updated_at: now,
+            system_prompt: String::new(),
+            messages: Vec::new(),
+            tree: ConversationTree::new(),
+        }
+    }
+
+    /// Take the conversation as it stands now
+    pub fn update(&mut self, context: &ConversationContext) {
+        self.system_prompt = context.system_prompt.clone();
+        self.messages = context.messages.clone();
+        self.updated_at = Utc::now();
+    }
+
+    /// The saved conversation, ready to carry on with
+    pub fn context(&self) -> ConversationContext {
+        let mut context = ConversationContext::new();
+        context.system_prompt = self.system_prompt.clone();
+        context.messages = self.messages.clone();
+        context
+    }
+
+    /// The first user message on one line, cut short, to tell sessions apart in a list
+    pub fn title(&self) -> String {
+        let Some(message) = self
+            .messages
+            .iter()
+            .find(|message| message.role == MessageRole::User)
+        else {
+            return String::new();
+        };
+        let line = message
+            .content
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ");
+        match line.char_indices().nth(TITLE_CHARS) {
+            Some((at, _)) => format!("{}...", &line[..at]),
+            None => line,
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum SessionStoreError {
+    #[error("No saved session matches '{0}'")]
+    NotFound(String),
+    #[error("'{0}' matches more than one session: {ids}", ids = .1.join(", "))]
+    Ambiguous(String, Vec<String>),
+}
+
+/// The sessions directory
+#[derive(Debug, Clone)]
+pub struct SessionStore {
+    dir: PathBuf,
+}
+
+impl SessionStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// `sessions` in the mcpterm config directory
+    pub fn default_dir() -> PathBuf {
+        let mut dir = dirs::config_dir().unwrap_or_else(|| PathBuf::from("."));
+        dir.push("mcpterm");
+        dir.push("sessions");
+        dir
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Write `record` to its file. The file is replaced in one step, so a crash mid-write
+    /// leaves the previous save intact.
+    pub fn save(&self, record: &SessionRecord) -> Result<PathBuf> {
+        fs::create_dir_all(&self.dir).with_context(|| {
+            format!("Failed to create sessions directory {}", self.dir.display())
+        })?;
+        let path = self.path(&record.id);
+        let partial = path.with_extension("json.partial");
+        fs::write(&partial, serde_json::to_vec_pretty(record)?)
+            .with_context(|| format!("Failed to write {}", partial.display()))?;
+        fs::rename(&partial, &path)
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        debug!("Saved session {} to {}", record.id, path.display());
+        Ok(path)
+    }
+
+    /// Load a session by its id, or by the start of an id that no other session shares
+    pub fn load(&self, id: &str) -> Result<SessionRecord> {
+        let mut matches: Vec<String> = self
+            .ids()?
+            .into_iter()
+            .filter(|candidate| candidate.starts_with(id))
+            .collect();
+        if id.is_empty() || matches.is_empty() {
+            return Err(SessionStoreError::NotFound(id.to_string()).into());
+        }
+        if matches.len() > 1 && !matches.iter().any(|candidate| candidate == id) {
+            matches.sort();
+            return Err(SessionStoreError::Ambiguous(id.to_string(), matches).into());
+        }
+        let id = if matches.len() == 1 { &matches[0] } else { id };
+        self.read(&self.path(id))
+    }
+
+    /// Every readable session, most recently updated first
+    pub fn list(&self) -> Result<Vec<SessionRecord>> {
+        let mut records = Vec::new();
+        for id in self.ids()? {
+            match self.read(&self.path(&id)) {
+                Ok(record) => records.push(record),
+                // One damaged file shouldn't hide the others
+                Err(e) => warn!("Skipping session {}: {:#}", id, e),
+            }
+        }
+        records.sort_by_key(|record| Reverse(record.updated_at));
+        Ok(records)
+    }
+
+    /// The most recently updated session started in `directory`
+    pub fn latest_in(&self, directory: &Path) -> Result<Option<SessionRecord>> {
+        Ok(self
+            .list()?
+            .into_iter()
+            .find(|record| record.directory == directory))
+    }
+
+    fn path(&self, id: &str) -> PathBuf {
+        self.dir.join(format!("{}.{}", id, EXTENSION))
+    }
+
+    fn read(&self, path: &Path) -> Result<SessionRecord> {
+        let data = fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+        serde_json::from_slice(&data).with_context(|| format!("Failed to parse {}", path.display()))
+    }
+
+    // Ids of the saved sessions; a directory that doesn't exist yet has none
+    fn ids(&self) -> Result<Vec<String>> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => {
+                return Err(e).with_context(|| {
+                    format!("Failed to read sessions directory {}", self.dir.display())
+                })
+            }
+        };
+        Ok(entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == EXTENSION))
+            .filter_map(|path| Some(path.file_stem()?.to_str()?.to_string()))
+            .collect())
+    }
+}
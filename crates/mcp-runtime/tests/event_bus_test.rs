@@ -1,5 +1,9 @@
 use anyhow::Result;
-use mcp_runtime::{create_handler, EventBus, KeyCode, KeyEvent, KeyModifiers, ModelEvent, UiEvent};
+use mcp_core::RateLimit;
+use mcp_llm::RateLimiter;
+use mcp_runtime::{
+    create_handler, ApiEvent, EventBus, KeyCode, KeyEvent, KeyModifiers, ModelEvent, UiEvent,
+};
 use std::sync::{
     atomic::{AtomicUsize, Ordering},
     Arc,
@@ -173,3 +177,41 @@ async fn test_key_event_handling() -> Result<()> {
 
     Ok(())
 }
+
+#[tokio::test]
+async fn test_rate_limit_waits_reported_on_bus() -> Result<()> {
+    let bus = EventBus::new();
+    let waits = Arc::new(AtomicUsize::new(0));
+
+    let waits_clone = waits.clone();
+    let handler = create_handler(move |event: ApiEvent| {
+        let waits = waits_clone.clone();
+        Box::pin(async move {
+            if let ApiEvent::RateLimited(event) = event {
+                assert_eq!(event.model, "model");
+                waits.fetch_add(1, Ordering::SeqCst);
+            }
+            Ok(())
+        })
+    });
+    bus.register_api_handler(handler)?;
+    bus.start_event_distribution()?;
+
+    let limit = RateLimit {
+        max_concurrent: Some(1),
+        ..RateLimit::default()
+    };
+    let limiter = RateLimiter::new("model", limit).with_listener(bus.rate_limit_listener());
+    let first = limiter.acquire(0).await;
+    let second = tokio::spawn({
+        let limiter = limiter.clone();
+        async move { limiter.acquire(0).await }
+    });
+
+    sleep(Duration::from_millis(50)).await;
+    assert_eq!(waits.load(Ordering::SeqCst), 1);
+
+    drop(first);
+    second.await?;
+    Ok(())
+}
@@ -1,12 +1,15 @@
 use anyhow::{anyhow, Result};
 use async_trait::async_trait;
 use futures::{stream, Stream, StreamExt};
-use mcp_core::context::ConversationContext;
+use mcp_core::context::{Attachment, AttachmentKind, ConversationContext};
 use mcp_llm::client_trait::{LlmClient, LlmResponse, StreamChunk, ToolCall};
-use mcp_runtime::{EventBus, ModelEvent, SessionManager, ToolExecutor};
+use mcp_runtime::{
+    ApiEvent, CancelPolicy, EventBus, ModelEvent, SessionManager, ToolExecutor, UiEvent,
+};
 use mcp_tools::ToolManager;
 use serde_json::json;
 use std::clone::Clone;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
 use tokio::time::{sleep, Duration};
 
@@ -39,7 +42,6 @@ impl MockLlmClient {
         stream_responses.push(chunk);
     }
 
-    #[allow(dead_code)]
     fn get_last_context(&self) -> Option<ConversationContext> {
         let context = self.last_context.lock().unwrap();
         context.clone()
@@ -146,6 +148,8 @@ async fn test_session_manager_text_response() {
         id: "resp1".to_string(),
         content: "Hello, I'm an AI assistant!".to_string(),
         tool_calls: Vec::new(),
+        usage: None,
+        thinking: Vec::new(),
     });
 
     // Create session manager
@@ -221,6 +225,9 @@ async fn test_session_manager_streaming_response() {
         is_tool_call: false,
         tool_call: None,
         is_complete: false,
+        usage: None,
+        thinking: String::new(),
+        thinking_block: None,
     });
 
     mock_client.add_stream_chunk(StreamChunk {
@@ -229,6 +236,9 @@ async fn test_session_manager_streaming_response() {
         is_tool_call: false,
         tool_call: None,
         is_complete: false,
+        usage: None,
+        thinking: String::new(),
+        thinking_block: None,
     });
 
     mock_client.add_stream_chunk(StreamChunk {
@@ -237,6 +247,9 @@ async fn test_session_manager_streaming_response() {
         is_tool_call: false,
         tool_call: None,
         is_complete: true,
+        usage: None,
+        thinking: String::new(),
+        thinking_block: None,
     });
 
     // Create session manager
@@ -335,6 +348,8 @@ async fn test_session_manager_tool_calls() {
             tool: "search".to_string(),
             params: json!({"query": "weather"}),
         }],
+        usage: None,
+        thinking: Vec::new(),
     });
 
     // Add response for after tool execution
@@ -342,6 +357,8 @@ async fn test_session_manager_tool_calls() {
         id: "resp2".to_string(),
         content: "Here's the weather information.".to_string(),
         tool_calls: Vec::new(),
+        usage: None,
+        thinking: Vec::new(),
     });
 
     // Create session manager
@@ -447,6 +464,9 @@ async fn test_session_manager_cancellation() {
             is_tool_call: false,
             tool_call: None,
             is_complete: i == 19,
+            usage: None,
+            thinking: String::new(),
+            thinking_block: None,
         });
     }
 
@@ -470,3 +490,242 @@ async fn test_session_manager_cancellation() {
     assert!(!cancelled_requests.is_empty());
     assert_eq!(cancelled_requests[0], request_id);
 }
+
+// Streams one chunk and then stalls, noting when the stream is dropped
+struct StallingLlmClient {
+    stream_dropped: Arc<AtomicBool>,
+}
+
+// Sets its flag when dropped along with the stream that owns it
+struct DropFlag(Arc<AtomicBool>);
+
+impl Drop for DropFlag {
+    fn drop(&mut self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+}
+
+#[async_trait]
+impl LlmClient for StallingLlmClient {
+    async fn send_message(&self, _context: &ConversationContext) -> Result<LlmResponse> {
+        Err(anyhow!("Only streaming is supported"))
+    }
+
+    async fn stream_message(
+        &self,
+        _context: &ConversationContext,
+    ) -> Result<Box<dyn Stream<Item = Result<StreamChunk>> + Unpin + Send>> {
+        let flag = DropFlag(self.stream_dropped.clone());
+        let chunk = StreamChunk {
+            id: "resp1".to_string(),
+            content: "Partial answer".to_string(),
+            is_tool_call: false,
+            tool_call: None,
+            is_complete: false,
+            usage: None,
+            thinking: String::new(),
+            thinking_block: None,
+        };
+        Ok(Box::new(
+            stream::iter(vec![Ok(chunk)])
+                .chain(stream::pending())
+                .map(move |chunk| {
+                    let _ = &flag;
+                    chunk
+                }),
+        ))
+    }
+
+    fn cancel_request(&self, _request_id: &str) -> Result<()> {
+        Ok(())
+    }
+}
+
+// Start a stalled streaming request, cancel it from the UI and return the conversation
+// along with the cancelled request ids
+async fn cancel_stalled_request(policy: CancelPolicy) -> (ConversationContext, Vec<String>) {
+    let stream_dropped = Arc::new(AtomicBool::new(false));
+    let client = StallingLlmClient {
+        stream_dropped: stream_dropped.clone(),
+    };
+    let tool_executor = ToolExecutor::new(ToolManager::new());
+    let event_bus = EventBus::new();
+    event_bus.start_event_distribution().unwrap();
+
+    let cancelled = Arc::new(Mutex::new(Vec::new()));
+    let recorded = cancelled.clone();
+    event_bus
+        .register_api_handler(mcp_runtime::create_handler(move |event: ApiEvent| {
+            let recorded = recorded.clone();
+            Box::pin(async move {
+                if let ApiEvent::RequestCancelled(id) = event {
+                    recorded.lock().unwrap().push(id);
+                }
+                Ok(())
+            })
+        }))
+        .unwrap();
+
+    let session_manager =
+        SessionManager::new(client, tool_executor, event_bus).with_cancel_policy(policy);
+    session_manager.register_handlers().unwrap();
+
+    let event_bus = session_manager.get_event_bus();
+    event_bus
+        .ui_sender()
+        .send(UiEvent::UserInput("Tell me a long story".to_string()))
+        .unwrap();
+    sleep(Duration::from_millis(200)).await;
+    assert!(!stream_dropped.load(Ordering::SeqCst));
+
+    event_bus
+        .ui_sender()
+        .send(UiEvent::RequestCancellation)
+        .unwrap();
+    sleep(Duration::from_millis(200)).await;
+    assert!(
+        stream_dropped.load(Ordering::SeqCst),
+        "cancelling should drop the in-flight stream"
+    );
+
+    let context = session_manager
+        .get_session()
+        .get_context()
+        .read()
+        .unwrap()
+        .clone();
+    let cancelled = cancelled.lock().unwrap().clone();
+    (context, cancelled)
+}
+
+#[tokio::test]
+async fn test_cancellation_stops_stream_and_keeps_partial_answer() {
+    let (context, cancelled) = cancel_stalled_request(CancelPolicy::KeepPartial).await;
+
+    assert_eq!(cancelled.len(), 1);
+    assert_eq!(context.messages.len(), 2);
+    assert_eq!(context.messages[0].content, "Tell me a long story");
+    assert_eq!(context.messages[1].content, "Partial answer");
+}
+
+#[tokio::test]
+async fn test_cancellation_can_drop_partial_answer() {
+    let (context, cancelled) = cancel_stalled_request(CancelPolicy::DropPartial).await;
+
+    assert_eq!(cancelled.len(), 1);
+    assert_eq!(context.messages.len(), 1);
+    assert_eq!(context.messages[0].content, "Tell me a long story");
+}
+
+#[tokio::test]
+async fn test_user_input_attachments_reach_the_model() {
+    let mock_client = MockLlmClient::new();
+    let tool_executor = ToolExecutor::new(ToolManager::new());
+    let event_bus = EventBus::new();
+    event_bus.start_event_distribution().unwrap();
+
+    mock_client.add_stream_chunk(StreamChunk {
+        id: "resp1".to_string(),
+        content: "A red square".to_string(),
+        is_tool_call: false,
+        tool_call: None,
+        is_complete: true,
+        usage: None,
+        thinking: String::new(),
+        thinking_block: None,
+    });
+
+    let session_manager = SessionManager::new(mock_client.clone(), tool_executor, event_bus);
+    session_manager.register_handlers().unwrap();
+
+    let attachment = Attachment::new(AttachmentKind::Image, "image/png", "shot.png", b"png");
+    session_manager
+        .get_event_bus()
+        .ui_sender()
+        .send(UiEvent::UserInputWithAttachments(
+            "What is in shot.png?".to_string(),
+            vec![attachment.clone()],
+        ))
+        .unwrap();
+    sleep(Duration::from_millis(200)).await;
+
+    // The model is asked with the file, and the conversation keeps it
+    let sent = mock_client
+        .get_last_context()
+        .expect("the model was called");
+    assert_eq!(sent.messages[0].content, "What is in shot.png?");
+    assert_eq!(sent.messages[0].attachments, Some(vec![attachment.clone()]));
+
+    let context = session_manager
+        .get_session()
+        .get_context()
+        .read()
+        .unwrap()
+        .clone();
+    assert_eq!(context.messages.len(), 2);
+    assert_eq!(context.messages[0].attachments, Some(vec![attachment]));
+    assert_eq!(context.messages[1].content, "A red square");
+}
+
+#[tokio::test]
+async fn test_streamed_thinking_is_sent_to_the_ui() {
+    let mock_client = MockLlmClient::new();
+    let tool_executor = ToolExecutor::new(ToolManager::new());
+    let event_bus = EventBus::new();
+    event_bus.start_event_distribution().unwrap();
+    let collector = EventCollector::new(&event_bus);
+
+    mock_client.add_stream_chunk(StreamChunk {
+        id: "resp1".to_string(),
+        content: String::new(),
+        is_tool_call: false,
+        tool_call: None,
+        is_complete: false,
+        usage: None,
+        thinking: "The user said hello".to_string(),
+        thinking_block: None,
+    });
+    mock_client.add_stream_chunk(StreamChunk {
+        id: "resp1".to_string(),
+        content: "Hi".to_string(),
+        is_tool_call: false,
+        tool_call: None,
+        is_complete: true,
+        usage: None,
+        thinking: String::new(),
+        thinking_block: None,
+    });
+
+    let session_manager = SessionManager::new(mock_client.clone(), tool_executor, event_bus);
+    session_manager.register_handlers().unwrap();
+    session_manager
+        .get_event_bus()
+        .ui_sender()
+        .send(UiEvent::UserInput("Hello!".to_string()))
+        .unwrap();
+    sleep(Duration::from_millis(200)).await;
+
+    // The thinking reaches the UI ahead of the answer, and only the answer is kept
+    let streamed: Vec<_> = collector
+        .get_model_events()
+        .into_iter()
+        .filter_map(|event| match event {
+            ModelEvent::LlmThinkingChunk(text) => Some(format!("thinking: {}", text)),
+            ModelEvent::LlmStreamChunk(text) => Some(format!("answer: {}", text)),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(
+        streamed,
+        vec!["thinking: The user said hello", "answer: Hi"]
+    );
+
+    let context = session_manager
+        .get_session()
+        .get_context()
+        .read()
+        .unwrap()
+        .clone();
+    assert_eq!(context.messages.len(), 2);
+    assert_eq!(context.messages[1].content, "Hi");
+}
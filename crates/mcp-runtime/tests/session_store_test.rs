@@ -0,0 +1,136 @@
+use chrono::{Duration, Utc};
+use mcp_core::context::{ConversationContext, ToolCall, ToolResult};
+use mcp_runtime::{Session, SessionRecord, SessionStore, SessionStoreError};
+use serde_json::json;
+use std::path::Path;
+use tempfile::TempDir;
+
+fn conversation() -> ConversationContext {
+    let mut context = ConversationContext::new();
+    context.system_prompt = "Be brief.".to_string();
+    context.add_user_message("Why does   the build\nfail on CI?");
+    context.add_assistant_tool_calls(
+        "",
+        vec![ToolCall {
+            tool_id: "shell".to_string(),
+            parameters: json!({"command": "cargo build"}),
+            call_id: "call-1".to_string(),
+        }],
+    );
+    context.add_tool_result(
+        "",
+        ToolResult {
+            tool_id: "shell".to_string(),
+            result: json!({"exit_code": 101}),
+            call_id: "call-1".to_string(),
+        },
+    );
+    context.add_assistant_message("A missing feature flag.");
+    context
+}
+
+fn record(directory: &str, hours_ago: i64) -> SessionRecord {
+    let mut record = SessionRecord::new("claude-sonnet", directory);
+    record.update(&conversation());
+    record.updated_at = Utc::now() - Duration::hours(hours_ago);
+    record
+}
+
+#[test]
+fn test_save_and_load_round_trip() {
+    let dir = TempDir::new().unwrap();
+    let store = SessionStore::new(dir.path().join("sessions"));
+
+    let record = record("/work/project", 0);
+    let path = store.save(&record).unwrap();
+    assert_eq!(
+        path,
+        dir.path()
+            .join("sessions")
+            .join(format!("{}.json", record.id))
+    );
+
+    let loaded = store.load(&record.id).unwrap();
+    assert_eq!(loaded.model, "claude-sonnet");
+    assert_eq!(loaded.directory, Path::new("/work/project"));
+    assert_eq!(loaded.created_at, record.created_at);
+    assert_eq!(loaded.title(), "Why does the build fail on CI?");
+
+    // Tool calls and results come back with the messages
+    let context = loaded.context();
+    assert_eq!(context.system_prompt, "Be brief.");
+    assert_eq!(context.messages.len(), 4);
+    assert_eq!(
+        context.messages[1].tool_calls.as_ref().unwrap()[0].parameters,
+        json!({"command": "cargo build"})
+    );
+    assert_eq!(
+        context.messages[2].tool_results.as_ref().unwrap()[0].result,
+        json!({"exit_code": 101})
+    );
+
+    let session = Session::restore(&loaded);
+    assert_eq!(session.get_id(), record.id);
+    assert_eq!(session.get_context().read().unwrap().messages.len(), 4);
+}
+
+#[test]
+fn test_load_by_prefix() {
+    let dir = TempDir::new().unwrap();
+    let store = SessionStore::new(dir.path());
+
+    let mut first = record("/work", 0);
+    first.id = "abc123".to_string();
+    let mut second = record("/work", 0);
+    second.id = "abd456".to_string();
+    store.save(&first).unwrap();
+    store.save(&second).unwrap();
+
+    assert_eq!(store.load("abc").unwrap().id, "abc123");
+    let error = store.load("ab").unwrap_err();
+    assert!(matches!(
+        error.downcast_ref::<SessionStoreError>(),
+        Some(SessionStoreError::Ambiguous(_, ids)) if ids.len() == 2
+    ));
+    assert!(matches!(
+        store
+            .load("xyz")
+            .unwrap_err()
+            .downcast_ref::<SessionStoreError>(),
+        Some(SessionStoreError::NotFound(_))
+    ));
+}
+
+#[test]
+fn test_list_and_latest_in_directory() {
+    let dir = TempDir::new().unwrap();
+    let store = SessionStore::new(dir.path());
+    assert!(store.list().unwrap().is_empty());
+
+    let yesterday = record("/work/project", 24);
+    let this_morning = record("/work/project", 2);
+    let elsewhere = record("/work/other", 1);
+    for record in [&yesterday, &this_morning, &elsewhere] {
+        store.save(record).unwrap();
+    }
+    // A damaged file is skipped rather than failing the listing
+    std::fs::write(dir.path().join("broken.json"), "{").unwrap();
+
+    let ids: Vec<_> = store
+        .list()
+        .unwrap()
+        .into_iter()
+        .map(|record| record.id)
+        .collect();
+    assert_eq!(
+        ids,
+        [&elsewhere.id, &this_morning.id, &yesterday.id].map(String::clone)
+    );
+
+    let latest = store
+        .latest_in(Path::new("/work/project"))
+        .unwrap()
+        .unwrap();
+    assert_eq!(latest.id, this_morning.id);
+    assert!(store.latest_in(Path::new("/tmp")).unwrap().is_none());
+}
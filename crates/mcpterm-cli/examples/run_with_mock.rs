@@ -25,6 +25,8 @@ async fn main() -> Result<()> {
             enable_tools: true,
             require_tool_confirmation: false, // Don't require confirmation in tests
             auto_approve_tools: true,         // Auto-approve tools in tests
+            native_tools: false,
+            ..CliConfig::default()
         });
 
     // Run the app with a test prompt
@@ -1,4 +1,7 @@
 use crate::json_filter::JsonRpcFilter;
+use mcp_core::context::ConversationTree;
+use mcp_core::ContextUsage;
+use mcp_runtime::SessionRecord;
 use mcp_tools::{ToolResult, ToolStatus};
 use serde_json::{json, Value};
 use std::fmt::Write;
@@ -714,6 +717,93 @@ impl ResponseFormatter {
     }
 }
 
+/// Format the model's thinking dimmed, so it reads as an aside to the answer
+pub fn format_thinking(text: &str) -> String {
+    format!("{}{}{}", Colors::dim(), text, Colors::reset())
+}
+
+/// Format the context window gauge, dimmed until the window is getting full
+pub fn format_context_gauge(usage: &ContextUsage) -> String {
+    let color = if !usage.fits() || usage.fraction() >= 0.9 {
+        Colors::red()
+    } else if usage.fraction() >= 0.75 {
+        Colors::yellow()
+    } else {
+        Colors::dim()
+    };
+    format!("{}{}{}", color, usage.gauge(20), Colors::reset())
+}
+
+/// When a session was last active, in local time
+pub fn format_session_time(record: &SessionRecord) -> String {
+    record
+        .updated_at
+        .with_timezone(&chrono::Local)
+        .format("%Y-%m-%d %H:%M")
+        .to_string()
+}
+
+/// Format saved sessions one per line: short id, last active, size, directory and the
+/// opening question
+pub fn format_session_list(records: &[SessionRecord]) -> String {
+    if records.is_empty() {
+        return "No saved sessions".to_string();
+    }
+    let mut out = String::new();
+    for record in records {
+        let _ = writeln!(
+            out,
+            "{}{}{}  {}  {:>4} messages  {}{}{}  {}",
+            Colors::bold(),
+            record.id.get(..8).unwrap_or(&record.id),
+            Colors::reset(),
+            format_session_time(record),
+            record.messages.len(),
+            Colors::dim(),
+            record.directory.display(),
+            Colors::reset(),
+            record.title()
+        );
+    }
+    out.pop();
+    out
+}
+
+/// Format a conversation's branches one per line, marking the current one: id, where it
+/// forked from, size and the message it starts with
+pub fn format_branch_list(tree: &ConversationTree) -> String {
+    let mut out = String::new();
+    for branch in tree.branches() {
+        let marker = if branch.id == tree.current() {
+            "*"
+        } else {
+            " "
+        };
+        let origin = match branch.parent {
+            Some(parent) => format!("from {} at message {}", parent, branch.forked_at + 1),
+            None => "start".to_string(),
+        };
+        let _ = writeln!(
+            out,
+            "{} {}{}{}  {}{:<22}{}  {:>4} messages  {}",
+            marker,
+            Colors::bold(),
+            branch.id,
+            Colors::reset(),
+            Colors::dim(),
+            origin,
+            Colors::reset(),
+            branch.messages.len(),
+            branch
+                .label()
+                .and_then(|label| label.lines().next())
+                .unwrap_or("(nothing asked yet)")
+        );
+    }
+    out.pop();
+    out
+}
+
 /// Format LLM responses to enhance readability
 /// Our architecture has several formats:
 /// 1. LlmResponse with a "content" field from mcp-llm
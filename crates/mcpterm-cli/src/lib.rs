@@ -1,13 +1,29 @@
 use anyhow::{anyhow, Result};
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use futures::{Stream, StreamExt};
 use mcp_core::commands::mcp::{ToolInfo, ToolProvider};
-use mcp_core::context::{ConversationContext, MessageRole};
+use mcp_core::config::inference_profile_region;
+use mcp_core::context::{
+    estimate_tokens, Attachment, ConversationContext, ConversationTree, MessageRole, Rewind,
+    ToolCall as ContextToolCall, ToolResult as ContextToolResult,
+};
 use mcp_core::{
-    api_log, debug_log, init_tracing, set_verbose_logging, Config, SlashCommand, ValidationResult,
+    api_log, debug_log, init_tracing, set_verbose_logging, AwsConfig, CompactionConfig, Config,
+    ContextStrategy, ContextUsage, ExportFormat, MiddlewareConfig, ModelConfig, ModelFallback,
+    ModelPricing, ModelProvider, ModelTask, RateLimit, SessionUsage, SlashCommand, Transcript,
+    UsageCommand, ValidationResult,
+};
+use mcp_llm::streaming::{JsonRpcStreamEvent, JsonRpcStreamParser};
+use mcp_llm::{
+    classify, AnthropicClient, AnthropicConfig, BedrockClient, BedrockConfig, Compaction,
+    Compactor, ContextGuard, ContextOverflow, ErrorClass, FallbackClient, FallbackEvent,
+    FallbackTarget, Layer, LayerStack, LlmClient, LoggingLayer, MetricsLayer, OpenAiClient,
+    OpenAiConfig, RateLimitEvent, RateLimitLayer, RateLimitListener, RateLimits, RecordingClient,
+    RedactionLayer, ReplayClient, RetryLayer, RetryPolicy, RoutingClient, StreamChunk,
+    ThinkingBlock, TokenUsage, ToolDefinition, WindowLayer,
 };
-use mcp_llm::{BedrockClient, BedrockConfig, LlmClient, StreamChunk};
 use mcp_metrics::{count, gauge, time, LogDestination, MetricsDestination, MetricsRegistry};
+use mcp_runtime::{SessionRecord, SessionStore};
 use mcp_tools::{
     analysis::LanguageAnalyzerTool,
     filesystem::{FilesystemConfig, ListDirectoryTool, ReadFileTool, WriteFileTool},
@@ -17,10 +33,10 @@ use mcp_tools::{
     ToolManager, ToolResult, ToolStatus,
 };
 use serde_json::Value;
-use std::fmt::Display;
+use std::collections::BTreeMap;
 use std::io::{Read, Write};
 use std::path::PathBuf;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 use tokio::time::sleep;
 use tracing::{debug, error, trace};
@@ -44,9 +60,9 @@ pub struct Cli {
     #[clap(long, short = 'o', value_name = "FILE")]
     output: Option<String>,
 
-    /// LLM model to use
-    #[clap(long, default_value = "us.anthropic.claude-3-7-sonnet-20250219-v1:0")]
-    model: String,
+    /// LLM model to use; a resumed session carries on with the model it last used
+    #[clap(long)]
+    model: Option<String>,
 
     /// Enable MCP protocol
     #[clap(long)]
@@ -87,6 +103,164 @@ pub struct Cli {
     /// Automatically approve all tool executions
     #[clap(long, short = 'y')]
     yes: bool,
+
+    /// Use native Bedrock tool use (Converse API) instead of JSON-RPC in text
+    #[clap(long)]
+    native_tools: bool,
+
+    /// Disable prompt caching even if the model config enables it
+    #[clap(long)]
+    no_prompt_cache: bool,
+
+    /// Token budget for extended thinking, overriding the model config
+    #[clap(long, value_name = "TOKENS")]
+    thinking_budget: Option<usize>,
+
+    /// Don't print the model's thinking (it is still kept for tool-use turns)
+    #[clap(long)]
+    hide_thinking: bool,
+
+    /// Record model requests and responses to a cassette file
+    #[clap(long, value_name = "FILE", conflicts_with = "replay")]
+    record: Option<PathBuf>,
+
+    /// Replay model responses from a cassette file instead of calling the provider
+    #[clap(long, value_name = "FILE")]
+    replay: Option<PathBuf>,
+
+    /// Attach an image (PNG, JPEG, GIF, WebP) or PDF to the first prompt; may be repeated
+    #[clap(long, short = 'a', value_name = "FILE")]
+    attach: Vec<PathBuf>,
+
+    /// Resume a saved session by id, or by the start of its id
+    #[clap(long, value_name = "ID", conflicts_with = "continue_session")]
+    resume: Option<String>,
+
+    /// Continue the most recent session started in this directory
+    #[clap(long = "continue")]
+    continue_session: bool,
+
+    #[clap(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Manage saved sessions
+    Sessions {
+        #[clap(subcommand)]
+        command: SessionsCommand,
+    },
+}
+
+#[derive(Subcommand)]
+enum SessionsCommand {
+    /// List saved sessions, most recent first
+    List,
+    /// Export a saved session as a transcript
+    Export {
+        /// Session id, or the start of it
+        id: String,
+
+        /// markdown, html or jsonl
+        #[clap(long, short, default_value = "markdown", value_parser = parse_export_format)]
+        format: ExportFormat,
+
+        /// File to write; the transcript is printed otherwise
+        #[clap(long, short, value_name = "FILE")]
+        output: Option<PathBuf>,
+    },
+}
+
+fn parse_export_format(name: &str) -> Result<ExportFormat, String> {
+    ExportFormat::from_name(name).ok_or_else(|| {
+        format!(
+            "unknown format '{}', expected markdown, html or jsonl",
+            name
+        )
+    })
+}
+
+// `sessions` subcommands, which work on saved sessions without starting a model
+fn run_sessions_command(store: &SessionStore, command: &SessionsCommand) -> Result<()> {
+    match command {
+        SessionsCommand::List => {
+            println!("{}", formatter::format_session_list(&store.list()?));
+        }
+        SessionsCommand::Export { id, format, output } => {
+            let record = store.load(id)?;
+            let context = record.context();
+            let transcript = Transcript::new(&context)
+                .with_model(&record.model)
+                .render(*format);
+            match output {
+                Some(path) => {
+                    std::fs::write(path, transcript)?;
+                    eprintln!("Exported session {} to {}", record.id, path.display());
+                }
+                None => print!("{}", transcript),
+            }
+        }
+    }
+    Ok(())
+}
+
+const DEFAULT_MODEL: &str = "us.anthropic.claude-3-7-sonnet-20250219-v1:0";
+
+// Read a provider API key from the named environment variable
+fn api_key_from_env(var: &str) -> Result<String> {
+    std::env::var(var).map_err(|_| anyhow!("API key environment variable {} is not set", var))
+}
+
+// Name a model for the user, with its region where the provider has regions
+fn model_label(config: &CliConfig) -> String {
+    match (&config.provider, &config.region) {
+        (ModelProvider::Bedrock, Some(region)) => format!("{} ({})", config.model, region),
+        _ => config.model.clone(),
+    }
+}
+
+// The max_tokens the clients are created with; thinking counts towards it
+fn reply_tokens(config: &CliConfig) -> usize {
+    4096 + config.thinking_budget_tokens.unwrap_or(0)
+}
+
+// The optional layers the config asks for around the whole client
+fn middleware_layers(middleware: &MiddlewareConfig) -> LayerStack {
+    let secrets: Vec<&str> = middleware.redact_env.iter().map(String::as_str).collect();
+    LayerStack::new()
+        .option_layer(middleware.logging.then(|| LoggingLayer::new("mcpterm")))
+        .option_layer((!secrets.is_empty()).then(|| RedactionLayer::from_env(&secrets)))
+}
+
+// One line telling the user what a compaction did
+fn compaction_summary(compaction: &Compaction) -> String {
+    format!(
+        "Compacted {} earlier messages into a summary (about {} tokens, down from {})",
+        compaction.messages, compaction.tokens_after, compaction.tokens_before
+    )
+}
+
+// Suggest what to do about a failed LLM request, based on how it was classified
+fn llm_error_hint(e: &anyhow::Error) -> Option<&'static str> {
+    match classify(e) {
+        ErrorClass::Throttled => {
+            Some("The provider is still rate limiting after several retries. Please wait and try again.")
+        }
+        ErrorClass::Transient => {
+            Some("The provider could not be reached or returned a server error. Please try again.")
+        }
+        ErrorClass::Auth => {
+            Some("Please check your credentials and that your account has access to this model.")
+        }
+        ErrorClass::ModelNotAvailable => Some(
+            "The model was not found. Please check the model ID and region, and that the model is enabled for your account.",
+        ),
+        ErrorClass::ContextTooLong => Some(
+            "The conversation no longer fits in the model's context window. Please start a new conversation or shorten the input.",
+        ),
+        ErrorClass::Other => None,
+    }
 }
 
 /// Main function to run the CLI application
@@ -96,7 +270,10 @@ pub async fn run_cli() -> Result<()> {
 
     // Initialize our tracing-based logging system only
     let log_file = init_tracing();
-    println!("Log file: {}", log_file.display());
+    // Subcommands keep stdout for their own output, e.g. an exported transcript
+    if cli.command.is_none() {
+        println!("Log file: {}", log_file.display());
+    }
 
     // Set verbose logging if requested
     if cli.verbose {
@@ -126,17 +303,54 @@ pub async fn run_cli() -> Result<()> {
 
     // Load configuration
     debug!("Loading configuration");
-    let config = match Config::load(cli.config.as_ref(), Some(&cli.model), cli.region.as_deref()) {
-        Ok(config) => {
-            debug!("Configuration loaded successfully");
-            config
-        }
-        Err(e) => {
-            debug!("Error loading config: {}", e);
-            eprintln!("Warning: Could not load configuration: {}", e);
-            // Create a default config
-            Config::default()
+    let load_config =
+        |model: &str| match Config::load(cli.config.as_ref(), Some(model), cli.region.as_deref()) {
+            Ok(config) => {
+                debug!("Configuration loaded successfully");
+                config
+            }
+            Err(e) => {
+                debug!("Error loading config: {}", e);
+                eprintln!("Warning: Could not load configuration: {}", e);
+                // Create a default config
+                Config::default()
+            }
+        };
+    let mut config = load_config(cli.model.as_deref().unwrap_or(DEFAULT_MODEL));
+
+    // Sessions are listed and resumed from here even when saving is turned off
+    let store = SessionStore::new(
+        config
+            .sessions
+            .dir
+            .as_ref()
+            .map(PathBuf::from)
+            .unwrap_or_else(SessionStore::default_dir),
+    );
+    if let Some(Command::Sessions { command }) = &cli.command {
+        return run_sessions_command(&store, command);
+    }
+
+    let directory = std::env::current_dir()?;
+    let resumed = if let Some(id) = &cli.resume {
+        Some(store.load(id)?)
+    } else if cli.continue_session {
+        let latest = store.latest_in(&directory)?;
+        let missing = || anyhow!("No saved session to continue in {}", directory.display());
+        Some(latest.ok_or_else(missing)?)
+    } else {
+        None
+    };
+
+    // A resumed session carries on with the model and region it last used, which may be a
+    // fallback, unless another model is asked for
+    let resumed_region = match (&resumed, &cli.model) {
+        (Some(record), None) => {
+            debug!("Resuming with model {}", record.model);
+            config = load_config(&record.model);
+            record.region.clone()
         }
+        _ => None,
     };
 
     // Get the active model
@@ -164,9 +378,13 @@ pub async fn run_cli() -> Result<()> {
 
     // Create CLI configuration
     let cli_config = CliConfig {
-        model: model_config.model_id.clone(),
         use_mcp: cli.mcp || config.mcp.enabled,
-        region: Some(config.aws.region.clone()),
+        region: Some(
+            cli.region
+                .clone()
+                .or(resumed_region)
+                .unwrap_or_else(|| config.region_for(&model_config)),
+        ),
         streaming: !cli.no_streaming,
         enable_tools: if cli.no_tools {
             false
@@ -180,17 +398,58 @@ pub async fn run_cli() -> Result<()> {
         },
         require_tool_confirmation: !cli.no_tool_confirmation,
         auto_approve_tools: cli.yes,
+        // Providers other than Bedrock only support native function calling
+        native_tools: cli.native_tools || model_config.provider != ModelProvider::Bedrock,
+        prompt_caching: if cli.no_prompt_cache {
+            Some(false)
+        } else {
+            model_config.prompt_caching
+        },
+        thinking_budget_tokens: cli.thinking_budget.or(model_config.thinking_budget_tokens),
+        show_thinking: !cli.hide_thinking,
+        record_path: cli.record.clone(),
+        replay_path: cli.replay.clone(),
+        ..CliConfig::from_config(&config, &model_config)
     };
 
     debug!("CLI config: {:#?}", cli_config);
 
+    // Read attachments up front so a bad path fails before anything is sent
+    let attachments = cli
+        .attach
+        .iter()
+        .map(Attachment::from_path)
+        .collect::<Result<Vec<_>>>()?;
+    if !attachments.is_empty() {
+        debug!("Attaching {} files to the first prompt", attachments.len());
+    }
+
     // Create CLI application with configuration
-    let mut app = CliApp::new().with_config(cli_config);
+    let layers = middleware_layers(&cli_config.middleware);
+    let mut app = CliApp::new().with_config(cli_config).with_layers(layers);
+    app.attach(attachments);
+    if let Some(record) = &resumed {
+        println!(
+            "Resumed session {} ({} messages, last active {})",
+            record.id,
+            record.messages.len(),
+            formatter::format_session_time(record)
+        );
+    }
+    let record =
+        resumed.unwrap_or_else(|| SessionRecord::new(model_config.model_id.clone(), &directory));
+    app = app.with_session(record);
+    if config.sessions.enabled {
+        app = app.with_session_store(store);
+    }
 
     // Initialize the application
     debug!("Initializing CLI application");
     if let Err(e) = app.initialize().await {
         debug!("Failed to initialize app: {}", e);
+        if let Some(hint) = llm_error_hint(&e) {
+            eprintln!("{}", hint);
+        }
         return Err(e);
     }
 
@@ -286,6 +545,10 @@ pub async fn run_cli() -> Result<()> {
 
     // Log metrics report at info level before exiting
     debug!("Generating metrics summary for this CLI execution");
+    if app.session_usage().requests > 0 {
+        // On stderr, so the report never mixes with a reply piped from stdout
+        eprintln!("{}", app.usage_summary());
+    }
     let log_destination = LogDestination;
     let report = MetricsRegistry::global().generate_report();
     if let Err(e) = log_destination.send_report(&report) {
@@ -304,8 +567,8 @@ async fn handle_slash_command(app: &mut CliApp, input: &str) {
         input
     );
 
-    // Get the slash command handler
-    let handler = app.get_slash_command_handler();
+    // Get the slash command handlers
+    let handlers = app.get_slash_command_handlers();
 
     // Parse the command
     let parts: Vec<&str> = input.split_whitespace().collect();
@@ -316,12 +579,50 @@ async fn handle_slash_command(app: &mut CliApp, input: &str) {
     // Extract the command name without the slash
     let command_name = parts[0].trim_start_matches('/');
 
-    // Check if this handler can process this command
-    if command_name != handler.name() {
+    // These change the conversation itself, so the app handles them rather than a handler
+    match command_name {
+        "compact" => {
+            handle_compact_command(app, &parts[1..]).await;
+            return;
+        }
+        "pin" => {
+            match app.pin_last_user_message() {
+                Some(content) => println!("Pinned: {}", content),
+                None => println!("Nothing to pin: no message has been sent yet"),
+            }
+            return;
+        }
+        "rewind" => {
+            handle_rewind_command(app, &parts[1..]);
+            return;
+        }
+        "branch" => {
+            handle_branch_command(app, &parts[1..]);
+            return;
+        }
+        "export" => {
+            handle_export_command(app, &parts[1..]);
+            return;
+        }
+        _ => {}
+    }
+
+    // Find the handler that can process this command
+    let Some(handler) = handlers.iter().find(|h| h.name() == command_name) else {
+        let supported = handlers
+            .iter()
+            .map(|h| format!("/{}", h.name()))
+            .chain(
+                ["/compact", "/pin", "/rewind", "/branch", "/export"]
+                    .into_iter()
+                    .map(String::from),
+            )
+            .collect::<Vec<_>>()
+            .join(", ");
         println!("Unknown command: /{}", command_name);
-        println!("Currently supported commands: /mcp");
+        println!("Currently supported commands: {}", supported);
         return;
-    }
+    };
 
     // Execute the command with args
     let args = &parts[1..];
@@ -351,6 +652,155 @@ async fn handle_slash_command(app: &mut CliApp, input: &str) {
     }
 }
 
+// Summarise older turns on request; any arguments say what the summary should focus on
+async fn handle_compact_command(app: &mut CliApp, args: &[&str]) {
+    if args == ["help"] {
+        println!(
+            r#"
+=== Compact Commands ===
+/compact           - Summarise all but the most recent turns to free up context
+/compact <focus>   - Same, telling the summariser what to keep in most detail
+/compact help      - Show this help message
+"#
+        );
+        return;
+    }
+
+    let focus = args.join(" ");
+    match app
+        .compact(Some(focus.as_str()).filter(|focus| !focus.is_empty()))
+        .await
+    {
+        Ok(Some(compaction)) => println!("{}", compaction_summary(&compaction)),
+        Ok(None) => println!("Nothing to compact: the conversation only has recent turns"),
+        Err(e) => println!("Error: Could not compact the conversation: {:#}", e),
+    }
+}
+
+// Go back a number of user messages, one by default, to ask again differently
+fn handle_rewind_command(app: &mut CliApp, args: &[&str]) {
+    let turns = match args {
+        [] => 1,
+        ["help"] => {
+            println!(
+                r#"
+=== Rewind Commands ===
+/rewind            - Remove your last message and everything after it
+/rewind <n>        - Go back to before your nth most recent message
+/rewind help       - Show this help message
+
+Nothing is lost: the conversation as it was is kept as a branch. See /branch.
+"#
+            );
+            return;
+        }
+        [turns] => match turns.parse::<usize>() {
+            Ok(turns) if turns > 0 => turns,
+            _ => {
+                println!("Error: /rewind takes a number of messages to go back, e.g. /rewind 2");
+                return;
+            }
+        },
+        _ => {
+            println!("Error: /rewind takes at most one argument");
+            return;
+        }
+    };
+
+    match app.rewind(turns) {
+        Some(rewind) => {
+            println!("Rewound to before: {}", rewind.message.content);
+            if let Some(kept) = rewind.kept {
+                println!(
+                    "The conversation as it was is kept as branch {}; /branch {} returns to it",
+                    kept, kept
+                );
+            }
+        }
+        None => println!("Nothing to rewind: no message has been sent yet"),
+    }
+}
+
+// List the conversation's branches, or carry on from one of them
+fn handle_branch_command(app: &mut CliApp, args: &[&str]) {
+    match args {
+        [] | ["list"] => println!("{}", formatter::format_branch_list(app.branches())),
+        ["help"] => println!(
+            r#"
+=== Branch Commands ===
+/branch            - List the branches of the conversation
+/branch <id>       - Carry on from branch <id>, keeping the current one
+/branch help       - Show this help message
+
+A branch is made each time /rewind goes back past messages that had answers.
+"#
+        ),
+        [id] => match id.parse::<usize>() {
+            Ok(id) if app.switch_branch(id) => {
+                println!("Switched to branch {}", id);
+                if let Some(label) = app.branches().branch(id).and_then(|b| b.label()) {
+                    println!("It continues from: {}", label);
+                }
+            }
+            _ => println!("Error: No branch {}. /branch lists them.", id),
+        },
+        _ => println!("Error: /branch takes at most one argument"),
+    }
+}
+
+// Print the conversation as a transcript, or write it to a file. The format is named, or
+// else taken from the file's extension.
+fn handle_export_command(app: &CliApp, args: &[&str]) {
+    let named = args.first().and_then(|name| ExportFormat::from_name(name));
+    let (format, path) = match (args, named) {
+        (["help"], _) => {
+            println!(
+                r#"
+=== Export Commands ===
+/export                    - Print the conversation as Markdown
+/export <format>           - Print it as markdown, html or jsonl
+/export <format> <file>    - Write it to a file
+/export <file>             - Write it to a file, in the format its extension names
+/export help               - Show this help message
+"#
+            );
+            return;
+        }
+        ([], _) => (ExportFormat::Markdown, None),
+        ([_], Some(format)) => (format, None),
+        ([_, path], Some(format)) => (format, Some(*path)),
+        ([path], None) => {
+            let extension = std::path::Path::new(path)
+                .extension()
+                .and_then(|extension| extension.to_str())
+                .and_then(ExportFormat::from_name);
+            match extension {
+                Some(format) => (format, Some(*path)),
+                None => {
+                    println!(
+                        "Error: Can't tell the format of {}; name one: markdown, html or jsonl",
+                        path
+                    );
+                    return;
+                }
+            }
+        }
+        _ => {
+            println!("Error: Usage: /export [markdown|html|jsonl] [file]");
+            return;
+        }
+    };
+
+    let transcript = app.export(format);
+    match path {
+        Some(path) => match std::fs::write(path, transcript) {
+            Ok(()) => println!("Exported the conversation to {}", path),
+            Err(e) => println!("Error: Could not write {}: {}", path, e),
+        },
+        None => print!("{}", transcript),
+    }
+}
+
 // Interactive chat session with the model
 async fn run_interactive_mode(app: &mut CliApp) -> Result<()> {
     println!("Starting interactive chat session. Type 'exit' or 'quit' to end.");
@@ -385,6 +835,10 @@ async fn run_interactive_mode(app: &mut CliApp) -> Result<()> {
                 // Add a delay for tool responses in interactive mode
                 sleep(Duration::from_secs(3)).await;
 
+                if let Some(usage) = app.context_usage() {
+                    println!("\n{}", formatter::format_context_gauge(&usage));
+                }
+
                 // Log context size and roles for debugging
                 debug!(
                     "Context size after command: {} messages",
@@ -399,6 +853,9 @@ async fn run_interactive_mode(app: &mut CliApp) -> Result<()> {
     }
 
     println!("Chat session ended.");
+    if let Some(id) = app.saved_session_id() {
+        println!("Session saved. Resume it with --resume {}", id);
+    }
     Ok(())
 }
 
@@ -459,23 +916,224 @@ async fn process_input_file(
     Ok(())
 }
 
+/// The tools the CLI offers the model, with its shell and filesystem restrictions
+pub fn default_tool_manager() -> ToolManager {
+    // Create a new tool manager
+    let mut tool_manager = ToolManager::new();
+
+    // Register the shell tool with configuration
+    let shell_config = ShellConfig {
+        default_timeout_ms: 30000, // 30 seconds default timeout
+        max_timeout_ms: 300000,    // 5 minutes maximum timeout
+        allowed_commands: None,    // No specific whitelist
+        denied_commands: Some(vec![
+            "rm -rf".to_string(),   // Prevent dangerous recursive deletion
+            "sudo".to_string(),     // Prevent sudo commands
+            "chmod".to_string(),    // Prevent permission changes
+            "chown".to_string(),    // Prevent ownership changes
+            "mkfs".to_string(),     // Prevent formatting
+            "dd".to_string(),       // Prevent raw disk operations
+            "shutdown".to_string(), // Prevent shutdown
+            "reboot".to_string(),   // Prevent reboot
+            "halt".to_string(),     // Prevent halt
+        ]),
+    };
+
+    let shell_tool = ShellTool::with_config(shell_config);
+    tool_manager.register_tool(Box::new(shell_tool));
+
+    // Register filesystem tools with default configuration
+    let filesystem_config = FilesystemConfig {
+        // Use default denied paths to protect sensitive areas
+        denied_paths: Some(vec![
+            "/etc/".to_string(),
+            "/var/".to_string(),
+            "/usr/".to_string(),
+            "/bin/".to_string(),
+            "/sbin/".to_string(),
+            "/.ssh/".to_string(),
+            "/.aws/".to_string(),
+            "/.config/".to_string(),
+            "C:\\Windows\\".to_string(),
+            "C:\\Program Files\\".to_string(),
+            "C:\\Program Files (x86)\\".to_string(),
+        ]),
+        allowed_paths: None,             // Allow all paths not explicitly denied
+        max_file_size: 10 * 1024 * 1024, // 10 MB max file size
+    };
+
+    let read_file_tool = ReadFileTool::with_config(filesystem_config.clone());
+    tool_manager.register_tool(Box::new(read_file_tool));
+
+    let write_file_tool = WriteFileTool::with_config(filesystem_config.clone());
+    tool_manager.register_tool(Box::new(write_file_tool));
+
+    let list_dir_tool = ListDirectoryTool::with_config(filesystem_config.clone());
+    tool_manager.register_tool(Box::new(list_dir_tool));
+
+    // Register search tools
+    let grep_config = GrepConfig {
+        denied_paths: filesystem_config.denied_paths.clone(),
+        allowed_paths: filesystem_config.allowed_paths.clone(),
+        ..GrepConfig::default()
+    };
+    let grep_tool = GrepTool::with_config(grep_config);
+    tool_manager.register_tool(Box::new(grep_tool));
+
+    let find_config = FindConfig {
+        denied_paths: filesystem_config.denied_paths.clone(),
+        allowed_paths: filesystem_config.allowed_paths.clone(),
+        ..FindConfig::default()
+    };
+    let find_tool = FindTool::with_config(find_config);
+    tool_manager.register_tool(Box::new(find_tool));
+
+    // Register diff and patch tools
+    let diff_tool = mcp_tools::diff::DiffTool::new();
+    tool_manager.register_tool(Box::new(diff_tool));
+
+    // Register patch tool with explicit identifier matching the prompt
+    let patch_tool = mcp_tools::diff::PatchTool::new();
+    // Ensure tool_id is "patch" to match what the LLM is using
+    tool_manager.register_tool(Box::new(patch_tool));
+
+    // Register project navigator tool
+    let project_navigator = mcp_tools::analysis::ProjectNavigator::new();
+    tool_manager.register_tool(Box::new(project_navigator));
+
+    // Register language analyzer tool
+    let language_analyzer = LanguageAnalyzerTool::new();
+    tool_manager.register_tool(Box::new(language_analyzer));
+
+    // Register test runner tool
+    let test_runner = TestRunnerTool::new();
+    tool_manager.register_tool(Box::new(test_runner));
+
+    tool_manager
+}
+
+/// The model client the CLI would build for `model`, with its fallbacks, routing,
+/// rate limits and middleware, for front ends that keep the conversation themselves.
+/// Requests held back by the rate limits are reported to `rate_limit_listener`.
+pub async fn build_llm_client(
+    config: &Config,
+    model: &ModelConfig,
+    rate_limit_listener: RateLimitListener,
+) -> Result<Arc<dyn LlmClient>> {
+    let cli_config = CliConfig::from_config(config, model);
+    let layers = middleware_layers(&cli_config.middleware);
+    let mut app = CliApp::new()
+        .with_config(cli_config)
+        .with_layers(layers)
+        .with_rate_limit_listener(rate_limit_listener);
+    app.initialize().await?;
+    app.llm_client
+        .ok_or_else(|| anyhow!("No model client was built for {}", model.model_id))
+}
+
 #[derive(Default)]
 pub struct CliApp {
     context: ConversationContext,
     llm_client: Option<Arc<dyn LlmClient>>,
     config: CliConfig,
     tool_manager: ToolManager,
+    usage: SessionUsage,
+    pending_attachments: Vec<Attachment>,
+    // Model/region the fallback chain last moved to, if it has moved off the configured one
+    active_model: Arc<Mutex<Option<CliConfig>>>,
+    // Size check in front of the main model, also used for the context gauge
+    context_guard: Option<Arc<ContextGuard>>,
+    // Middleware wrapped around whatever client initialize() builds
+    layers: LayerStack,
+    // Budgets shared by all clients for the same model
+    rate_limits: RateLimits,
+    // The conversation as saved, and where to save it; unset store means it isn't saved
+    session: Option<SessionRecord>,
+    session_store: Option<SessionStore>,
+    // Branches the conversation has taken through /rewind
+    tree: ConversationTree,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct CliConfig {
     pub model: String,
     pub use_mcp: bool,
     pub region: Option<String>,
+    /// Bedrock profile, role and endpoint settings
+    pub aws: AwsConfig,
     pub streaming: bool,
     pub enable_tools: bool,
     pub require_tool_confirmation: bool,
     pub auto_approve_tools: bool,
+    pub native_tools: bool,
+    pub provider: ModelProvider,
+    /// Endpoint override for the Anthropic and OpenAI-compatible providers
+    pub base_url: Option<String>,
+    /// Environment variable holding the provider API key
+    pub api_key_env: Option<String>,
+    /// Token prices for the model, used for session cost accounting
+    pub pricing: Option<ModelPricing>,
+    /// Token prices of the other configured models, for pricing a fallback by model id
+    pub model_pricing: BTreeMap<String, ModelPricing>,
+    /// Prompt caching override; unset uses the provider default
+    pub prompt_caching: Option<bool>,
+    /// Extended thinking budget; unset leaves thinking off
+    pub thinking_budget_tokens: Option<usize>,
+    /// Print the model's thinking, dimmed, while streaming
+    pub show_thinking: bool,
+    /// Cassette file to record model traffic to
+    pub record_path: Option<PathBuf>,
+    /// Cassette file to replay model responses from, in place of the provider
+    pub replay_path: Option<PathBuf>,
+    /// Models for auxiliary tasks routed away from the main model
+    pub task_models: BTreeMap<ModelTask, ModelConfig>,
+    /// Models or regions to fall back to when the main model is unavailable
+    pub fallbacks: Vec<ModelFallback>,
+    /// Context window of the main model; unset skips the pre-flight size check
+    pub context_window: Option<usize>,
+    /// Client-side quotas by model id, shared by every client for that model
+    pub rate_limits: BTreeMap<String, RateLimit>,
+    /// When to summarise older turns of the conversation
+    pub compaction: CompactionConfig,
+    /// Which messages to send the model with each request; unset sends them all
+    pub context_strategy: Option<ContextStrategy>,
+    /// Retries, logging and redaction around the model client
+    pub middleware: MiddlewareConfig,
+}
+
+impl CliConfig {
+    /// Settings for `model` taken from the configuration file alone, as for a run without flags
+    pub fn from_config(config: &Config, model: &ModelConfig) -> Self {
+        Self {
+            model: model.model_id.clone(),
+            use_mcp: config.mcp.enabled,
+            region: Some(config.region_for(model)),
+            aws: config.aws.clone(),
+            streaming: true,
+            enable_tools: true,
+            require_tool_confirmation: true,
+            auto_approve_tools: false,
+            // Providers other than Bedrock only support native function calling
+            native_tools: model.provider != ModelProvider::Bedrock,
+            provider: model.provider,
+            base_url: model.base_url.clone(),
+            api_key_env: model.api_key_env.clone(),
+            pricing: model.pricing,
+            model_pricing: config.model_pricing(),
+            prompt_caching: model.prompt_caching,
+            thinking_budget_tokens: model.thinking_budget_tokens,
+            show_thinking: true,
+            record_path: None,
+            replay_path: None,
+            task_models: config.task_models(),
+            fallbacks: model.fallbacks.clone(),
+            context_window: model.context_window,
+            rate_limits: config.rate_limits(),
+            compaction: config.compaction.clone(),
+            context_strategy: model.context_strategy,
+            middleware: config.middleware.clone(),
+        }
+    }
 }
 
 impl Default for CliConfig {
@@ -484,10 +1142,29 @@ impl Default for CliConfig {
             model: "us.anthropic.claude-3-sonnet-20240229-v1:0".to_string(),
             use_mcp: true,
             region: None,
+            aws: AwsConfig::default(),
             streaming: true,
             enable_tools: true,
             require_tool_confirmation: false,
             auto_approve_tools: false,
+            native_tools: false,
+            provider: ModelProvider::Bedrock,
+            base_url: None,
+            api_key_env: None,
+            pricing: None,
+            model_pricing: BTreeMap::new(),
+            prompt_caching: None,
+            thinking_budget_tokens: None,
+            show_thinking: true,
+            record_path: None,
+            replay_path: None,
+            task_models: BTreeMap::new(),
+            fallbacks: Vec::new(),
+            context_window: None,
+            rate_limits: BTreeMap::new(),
+            compaction: CompactionConfig::default(),
+            context_strategy: None,
+            middleware: MiddlewareConfig::default(),
         }
     }
 }
@@ -529,102 +1206,36 @@ impl ToolProvider for CliApp {
 
 impl CliApp {
     pub fn new() -> Self {
-        // Create a new tool manager
-        let mut tool_manager = ToolManager::new();
-
-        // Register the shell tool with configuration
-        let shell_config = ShellConfig {
-            default_timeout_ms: 30000, // 30 seconds default timeout
-            max_timeout_ms: 300000,    // 5 minutes maximum timeout
-            allowed_commands: None,    // No specific whitelist
-            denied_commands: Some(vec![
-                "rm -rf".to_string(),   // Prevent dangerous recursive deletion
-                "sudo".to_string(),     // Prevent sudo commands
-                "chmod".to_string(),    // Prevent permission changes
-                "chown".to_string(),    // Prevent ownership changes
-                "mkfs".to_string(),     // Prevent formatting
-                "dd".to_string(),       // Prevent raw disk operations
-                "shutdown".to_string(), // Prevent shutdown
-                "reboot".to_string(),   // Prevent reboot
-                "halt".to_string(),     // Prevent halt
-            ]),
-        };
-
-        let shell_tool = ShellTool::with_config(shell_config);
-        tool_manager.register_tool(Box::new(shell_tool));
-
-        // Register filesystem tools with default configuration
-        let filesystem_config = FilesystemConfig {
-            // Use default denied paths to protect sensitive areas
-            denied_paths: Some(vec![
-                "/etc/".to_string(),
-                "/var/".to_string(),
-                "/usr/".to_string(),
-                "/bin/".to_string(),
-                "/sbin/".to_string(),
-                "/.ssh/".to_string(),
-                "/.aws/".to_string(),
-                "/.config/".to_string(),
-                "C:\\Windows\\".to_string(),
-                "C:\\Program Files\\".to_string(),
-                "C:\\Program Files (x86)\\".to_string(),
-            ]),
-            allowed_paths: None, // Allow all paths not explicitly denied
-            max_file_size: 10 * 1024 * 1024, // 10 MB max file size
-        };
-
-        let read_file_tool = ReadFileTool::with_config(filesystem_config.clone());
-        tool_manager.register_tool(Box::new(read_file_tool));
-
-        let write_file_tool = WriteFileTool::with_config(filesystem_config.clone());
-        tool_manager.register_tool(Box::new(write_file_tool));
-
-        let list_dir_tool = ListDirectoryTool::with_config(filesystem_config.clone());
-        tool_manager.register_tool(Box::new(list_dir_tool));
-
-        // Register search tools
-        let grep_config = GrepConfig {
-            denied_paths: filesystem_config.denied_paths.clone(),
-            allowed_paths: filesystem_config.allowed_paths.clone(),
-            ..GrepConfig::default()
-        };
-        let grep_tool = GrepTool::with_config(grep_config);
-        tool_manager.register_tool(Box::new(grep_tool));
-
-        let find_config = FindConfig {
-            denied_paths: filesystem_config.denied_paths.clone(),
-            allowed_paths: filesystem_config.allowed_paths.clone(),
-            ..FindConfig::default()
-        };
-        let find_tool = FindTool::with_config(find_config);
-        tool_manager.register_tool(Box::new(find_tool));
-
-        // Register diff and patch tools
-        let diff_tool = mcp_tools::diff::DiffTool::new();
-        tool_manager.register_tool(Box::new(diff_tool));
-
-        // Register patch tool with explicit identifier matching the prompt
-        let patch_tool = mcp_tools::diff::PatchTool::new();
-        // Ensure tool_id is "patch" to match what the LLM is using
-        tool_manager.register_tool(Box::new(patch_tool));
-
-        // Register project navigator tool
-        let project_navigator = mcp_tools::analysis::ProjectNavigator::new();
-        tool_manager.register_tool(Box::new(project_navigator));
-
-        // Register language analyzer tool
-        let language_analyzer = LanguageAnalyzerTool::new();
-        tool_manager.register_tool(Box::new(language_analyzer));
-
-        // Register test runner tool
-        let test_runner = TestRunnerTool::new();
-        tool_manager.register_tool(Box::new(test_runner));
+        let tool_manager = default_tool_manager();
 
         Self {
             context: ConversationContext::new(),
             llm_client: None,
             config: CliConfig::default(),
             tool_manager,
+            usage: SessionUsage::new(),
+            pending_attachments: Vec::new(),
+            active_model: Arc::new(Mutex::new(None)),
+            context_guard: None,
+            layers: LayerStack::new(),
+            rate_limits: RateLimits::new().with_listener(Arc::new(|event: &RateLimitEvent| {
+                match event.wait {
+                    Some(wait) => eprintln!(
+                        "{} is at its {} limit, waiting {}s",
+                        event.model,
+                        event.reason.as_str(),
+                        wait.as_secs().max(1)
+                    ),
+                    None => eprintln!(
+                        "{} is at its {} limit, waiting for a request to finish",
+                        event.model,
+                        event.reason.as_str()
+                    ),
+                }
+            })),
+            session: None,
+            session_store: None,
+            tree: ConversationTree::new(),
         }
     }
 
@@ -727,12 +1338,76 @@ impl CliApp {
         result
     }
 
+    /// Queue attachments to send with the next prompt passed to `run`
+    pub fn attach(&mut self, attachments: Vec<Attachment>) {
+        self.pending_attachments.extend(attachments);
+    }
+
     // Method to use a custom LLM client (e.g., for testing)
     pub fn with_llm_client(mut self, client: impl LlmClient + 'static) -> Self {
         self.llm_client = Some(Arc::new(client));
         self
     }
 
+    /// Report requests held back by the rate limits to `listener` rather than on stderr
+    pub fn with_rate_limit_listener(mut self, listener: RateLimitListener) -> Self {
+        self.rate_limits = RateLimits::new().with_listener(listener);
+        self
+    }
+
+    /// Wrap the client built by `initialize` in these layers, e.g. for request auditing.
+    /// The layers sit outside recording, so a recorded session shows what they sent.
+    pub fn with_layers(mut self, layers: LayerStack) -> Self {
+        self.layers = layers;
+        self
+    }
+
+    /// Carry on with a saved conversation, or start `record` off empty for a new one
+    pub fn with_session(mut self, record: SessionRecord) -> Self {
+        if !record.messages.is_empty() {
+            self.context = record.context();
+        }
+        self.tree = record.tree.clone();
+        self.session = Some(record);
+        self
+    }
+
+    /// Save the session to `store` after each turn
+    pub fn with_session_store(mut self, store: SessionStore) -> Self {
+        self.session_store = Some(store);
+        self
+    }
+
+    /// Id of the session, once something has been saved to it
+    pub fn saved_session_id(&self) -> Option<&str> {
+        self.session_store.as_ref()?;
+        self.session
+            .as_ref()
+            .filter(|record| !record.messages.is_empty())
+            .map(|record| record.id.as_str())
+    }
+
+    // Write the conversation to its session file. Saving is best effort: a full disk
+    // shouldn't end the conversation.
+    fn save_session(&mut self) {
+        let (Some(store), Some(record)) = (&self.session_store, &mut self.session) else {
+            return;
+        };
+        if self.context.messages.is_empty() {
+            return;
+        }
+        let active = self.active_model.lock().unwrap();
+        let active = active.as_ref().unwrap_or(&self.config);
+        record.model = active.model.clone();
+        record.region = active.region.clone();
+        record.update(&self.context);
+        self.tree.snapshot(&self.context);
+        record.tree = self.tree.clone();
+        if let Err(e) = store.save(record) {
+            eprintln!("Warning: Could not save the session: {:#}", e);
+        }
+    }
+
     // Get a slash command handler for the CLI
     pub fn get_slash_command_handler(&self) -> Box<dyn SlashCommand> {
         // Create a new MCP command handler
@@ -740,44 +1415,177 @@ impl CliApp {
         let app_clone = CliApp {
             context: self.context.clone(),
             llm_client: self.llm_client.clone(),
-            config: CliConfig {
-                model: self.config.model.clone(),
-                use_mcp: self.config.use_mcp,
-                region: self.config.region.clone(),
-                streaming: self.config.streaming,
-                enable_tools: self.config.enable_tools,
-                require_tool_confirmation: self.config.require_tool_confirmation,
-                auto_approve_tools: self.config.auto_approve_tools,
-            },
+            config: self.config.clone(),
             tool_manager: ToolManager::new(), // Create a new tool manager
+            usage: self.usage.clone(),
+            pending_attachments: Vec::new(),
+            active_model: self.active_model.clone(),
+            context_guard: self.context_guard.clone(),
+            layers: self.layers.clone(),
+            rate_limits: self.rate_limits.clone(),
+            session: None,
+            session_store: None,
+            tree: ConversationTree::new(),
         };
         Box::new(mcp_core::commands::mcp::McpCommand::new(app_clone))
     }
 
-    // Helper to convert Bedrock errors to user-friendly messages
-    fn handle_bedrock_client_error(&self, e: impl Display) -> Result<()> {
-        error!("Bedrock client error: {}", e);
-        // Check for common error patterns and provide better messages
-        let error_msg = e.to_string();
-        if error_msg.contains("ResourceNotFoundException") && error_msg.contains("model-id") {
-            return Err(anyhow!("The specified model was not found. Please check the model ID and region. You may need to request access to this model in your AWS account."));
-        } else if error_msg.contains("AccessDeniedException") {
-            return Err(anyhow!("Access denied to the Bedrock service. Please check your AWS credentials and permissions."));
-        } else if error_msg.contains("ValidationException") {
-            return Err(anyhow!(
-                "Invalid request parameters. Please check the model ID and request configuration."
-            ));
-        } else if error_msg.contains("ThrottlingException") {
-            return Err(anyhow!(
-                "Request throttled by AWS. Please try again later or reduce the request rate."
-            ));
-        } else if error_msg.contains("ServiceQuotaExceededException") {
-            return Err(anyhow!(
-                "AWS service quota exceeded. You may need to request a quota increase for Bedrock."
-            ));
+    // Get all slash command handlers for the CLI
+    pub fn get_slash_command_handlers(&self) -> Vec<Box<dyn SlashCommand>> {
+        vec![
+            self.get_slash_command_handler(),
+            Box::new(match self.context_usage() {
+                Some(context) => UsageCommand::new(&self.active_model(), self.usage.clone())
+                    .with_context(context),
+                None => UsageCommand::new(&self.active_model(), self.usage.clone()),
+            }),
+        ]
+    }
+
+    /// Token usage and cost accumulated so far in this session
+    pub fn session_usage(&self) -> &SessionUsage {
+        &self.usage
+    }
+
+    pub fn usage_summary(&self) -> String {
+        self.usage.summary(&self.active_model())
+    }
+
+    /// How much of the main model's context window the conversation takes up, when the
+    /// window is known
+    pub fn context_usage(&self) -> Option<ContextUsage> {
+        self.context_guard
+            .as_ref()
+            .map(|guard| guard.usage(&self.context))
+    }
+
+    /// Replace all but the most recent turns of the conversation with a summary of them, to
+    /// free up context. `focus` tells the summariser what to keep in most detail.
+    pub async fn compact(&mut self, focus: Option<&str>) -> Result<Option<Compaction>> {
+        let client = self
+            .llm_client
+            .clone()
+            .ok_or_else(|| anyhow!("LLM client not initialized"))?;
+        let compaction = Compactor::new(&self.config.compaction)
+            .compact(client.as_ref(), &mut self.context, focus)
+            .await?;
+        if let Some(compaction) = &compaction {
+            self.record_usage(compaction.usage.as_ref());
+            self.save_session();
+        }
+        Ok(compaction)
+    }
+
+    /// Pin the most recent user message, so its turn is sent whatever the context strategy
+    /// and kept verbatim by compaction. Returns the pinned text.
+    pub fn pin_last_user_message(&mut self) -> Option<String> {
+        let index = self
+            .context
+            .messages
+            .iter()
+            .rposition(|message| message.role == MessageRole::User)?;
+        self.context.pin(index);
+        self.save_session();
+        Some(self.context.messages[index].content.clone())
+    }
+
+    /// Rewind the conversation to just before the `turns`th most recent user message, or
+    /// the first one if there are fewer. Where it was is kept as a branch.
+    pub fn rewind(&mut self, turns: usize) -> Option<Rewind> {
+        let index = self.context.recent_turns_start(turns.max(1));
+        let rewind = self.tree.rewind(&mut self.context, index)?;
+        self.save_session();
+        Some(rewind)
+    }
+
+    /// Carry on from branch `id` instead, keeping the current one. Returns false if there
+    /// is no such branch.
+    pub fn switch_branch(&mut self, id: usize) -> bool {
+        if !self.tree.switch(&mut self.context, id) {
+            return false;
+        }
+        self.save_session();
+        true
+    }
+
+    /// The conversation so far as a transcript, with the model it is being held with
+    pub fn export(&self, format: ExportFormat) -> String {
+        Transcript::new(&self.context)
+            .with_model(self.active_model())
+            .render(format)
+    }
+
+    /// The branches of the conversation, with the current one up to date
+    pub fn branches(&mut self) -> &ConversationTree {
+        self.tree.snapshot(&self.context);
+        &self.tree
+    }
+
+    // Compact once the conversation passes the configured share of the context window
+    async fn auto_compact(&mut self) {
+        if !self.config.compaction.auto {
+            return;
+        }
+        let Some(threshold) = self
+            .config
+            .compaction
+            .threshold_for(self.config.context_window)
+        else {
+            return;
+        };
+        let tokens = match self.context_usage() {
+            Some(usage) => usage.prompt_tokens,
+            None => self.context.estimated_tokens(),
+        };
+        if tokens < threshold {
+            return;
+        }
+
+        debug!(
+            "Conversation is about {} tokens, over the {}-token compaction threshold",
+            tokens, threshold
+        );
+        match self.compact(None).await {
+            Ok(Some(compaction)) => eprintln!("{}", compaction_summary(&compaction)),
+            Ok(None) => debug!("Nothing old enough to compact"),
+            Err(e) => eprintln!("Could not compact the conversation: {:#}", e),
         }
-        // Default case
-        Err(anyhow!("Error connecting to AWS Bedrock: {}", e))
+    }
+
+    /// The model currently answering, which differs from the configured one after a fallback
+    pub fn active_model(&self) -> String {
+        self.active_model
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(model_label)
+            .unwrap_or_else(|| model_label(&self.config))
+    }
+
+    // Add a response's token usage to the session totals
+    fn record_usage(&mut self, usage: Option<&TokenUsage>) {
+        let Some(usage) = usage else {
+            return;
+        };
+
+        // Price the request at the rates of the model that answered it
+        let active = self.active_model.lock().unwrap();
+        let cost = self.usage.record_with_cache(
+            usage.input_tokens,
+            usage.output_tokens,
+            usage.cache_read_tokens,
+            usage.cache_write_tokens,
+            active.as_ref().unwrap_or(&self.config).pricing.as_ref(),
+        );
+        drop(active);
+        if let Some(cost) = cost {
+            // Counters are integers, so cost is tracked in millionths of a dollar
+            count!(
+                "session.cost.micro_usd",
+                (cost * 1_000_000.0).round() as u64
+            );
+        }
+        gauge!("session.tokens.total", self.usage.total_tokens() as i64);
     }
 
     pub async fn initialize(&mut self) -> Result<()> {
@@ -787,42 +1595,315 @@ impl CliApp {
             return Ok(());
         }
 
+        // A replayed session never reaches the provider, so needs no credentials
+        if let Some(path) = &self.config.replay_path {
+            debug_log(&format!(
+                "Replaying model responses from {}",
+                path.display()
+            ));
+            let client = Arc::new(ReplayClient::from_file(path)?);
+            self.llm_client = Some(self.layers.service(client));
+            return Ok(());
+        }
+
+        // Add a system prompt based on whether MCP is enabled
+        let system_prompt = if self.config.use_mcp {
+            "You are Claude, a helpful AI assistant by Anthropic. You will follow the Model Context Protocol (MCP) for structured communication.".to_string()
+        } else {
+            "You are Claude, a helpful AI assistant by Anthropic.".to_string()
+        };
+
+        // Record metrics
+        count!("app.initialization");
+        gauge!("app.mcp_enabled", if self.config.use_mcp { 1 } else { 0 });
+
+        // In native tool mode the tool schemas travel as provider tool specs
+        let tools = if self.config.native_tools && self.config.enable_tools {
+            let tools = self.tool_definitions();
+            debug_log(&format!(
+                "Using native tool use with {} tool definitions",
+                tools.len()
+            ));
+            tools
+        } else {
+            Vec::new()
+        };
+
+        let client = self
+            .create_client(&self.config, system_prompt.clone(), tools.clone())
+            .await?;
+
+        // Other models or regions to move to when this one is throttled or unavailable
+        let client: Arc<dyn LlmClient> = if self.config.fallbacks.is_empty() {
+            client
+        } else {
+            let mut chain =
+                FallbackClient::new(FallbackTarget::new(model_label(&self.config), client));
+            let mut targets = vec![self.config.clone()];
+            for fallback in &self.config.fallbacks {
+                let model = fallback
+                    .model_id
+                    .clone()
+                    .unwrap_or_else(|| self.config.model.clone());
+                let pricing = if model == self.config.model {
+                    self.config.pricing
+                } else {
+                    self.config.model_pricing.get(&model).copied()
+                };
+                let fallback_config = CliConfig {
+                    model,
+                    pricing,
+                    region: fallback
+                        .region
+                        .clone()
+                        .or_else(|| self.config.region.clone()),
+                    ..self.config.clone()
+                };
+                let label = model_label(&fallback_config);
+                debug_log(&format!("Adding fallback model {}", label));
+                let fallback_client = self
+                    .create_client(&fallback_config, system_prompt.clone(), tools.clone())
+                    .await?;
+                chain = chain.with_fallback(FallbackTarget::new(label, fallback_client));
+                targets.push(fallback_config);
+            }
+
+            // Tell the user, and keep the session's idea of the active model current
+            let active_model = self.active_model.clone();
+            Arc::new(chain.with_listener(Arc::new(move |event: &FallbackEvent| {
+                eprintln!(
+                    "{} is unavailable ({}), switching to {}",
+                    event.from,
+                    event.class.as_str().replace('_', " "),
+                    event.to
+                );
+                *active_model.lock().unwrap() = targets
+                    .iter()
+                    .find(|target| model_label(target) == event.to)
+                    .cloned();
+            })))
+        };
+
+        // Refuse requests that can't fit before they reach the provider
+        let client: Arc<dyn LlmClient> = match self.config.context_window {
+            Some(context_window) => {
+                let guard =
+                    self.guard_client(&self.config, context_window, client, &system_prompt, &tools);
+                self.context_guard = Some(guard.clone());
+                guard
+            }
+            None => client,
+        };
+
+        // Auxiliary tasks with a model of their own get a plain text client for it
+        let client: Arc<dyn LlmClient> = if self.config.task_models.is_empty() {
+            client
+        } else {
+            let mut router = RoutingClient::new(client);
+            for (task, model) in &self.config.task_models {
+                debug_log(&format!(
+                    "Routing {} requests to {}",
+                    task.as_str(),
+                    model.model_id
+                ));
+                let task_config = CliConfig {
+                    model: model.model_id.clone(),
+                    use_mcp: false,
+                    native_tools: false,
+                    provider: model.provider,
+                    base_url: model.base_url.clone(),
+                    api_key_env: model.api_key_env.clone(),
+                    pricing: model.pricing,
+                    prompt_caching: model.prompt_caching,
+                    thinking_budget_tokens: model.thinking_budget_tokens,
+                    context_strategy: model.context_strategy,
+                    region: Some(
+                        model
+                            .region
+                            .clone()
+                            .or_else(|| {
+                                inference_profile_region(&model.model_id).map(str::to_string)
+                            })
+                            .unwrap_or_else(|| self.config.aws.region.clone()),
+                    ),
+                    ..self.config.clone()
+                };
+                let task_prompt = "You are Claude, a helpful AI assistant by Anthropic.";
+                let task_client = self
+                    .create_client(&task_config, task_prompt.to_string(), Vec::new())
+                    .await?;
+                let task_client: Arc<dyn LlmClient> = match model.context_window {
+                    Some(context_window) => self.guard_client(
+                        &task_config,
+                        context_window,
+                        task_client,
+                        task_prompt,
+                        &[],
+                    ),
+                    None => task_client,
+                };
+                router = router.with_route(*task, task_client);
+            }
+            Arc::new(router)
+        };
+
+        let client: Arc<dyn LlmClient> = match &self.config.record_path {
+            Some(path) => {
+                debug_log(&format!("Recording model traffic to {}", path.display()));
+                Arc::new(RecordingClient::new(client, path))
+            }
+            None => client,
+        };
+
+        self.llm_client = Some(self.layers.service(client));
+        Ok(())
+    }
+
+    // Create the client for a provider and model
+    async fn create_client(
+        &self,
+        config: &CliConfig,
+        system_prompt: String,
+        tools: Vec<ToolDefinition>,
+    ) -> Result<Arc<dyn LlmClient>> {
+        let client: Arc<dyn LlmClient> = match config.provider {
+            ModelProvider::Bedrock => Arc::new(
+                self.create_bedrock_client(config, system_prompt)
+                    .await?
+                    .with_tools(tools),
+            ),
+            ModelProvider::Anthropic => {
+                let api_key =
+                    api_key_from_env(config.api_key_env.as_deref().unwrap_or("ANTHROPIC_API_KEY"))?;
+                let mut anthropic_config = AnthropicConfig::new(api_key, config.model.clone())
+                    .with_system_prompt(system_prompt);
+                if let Some(prompt_caching) = config.prompt_caching {
+                    anthropic_config = anthropic_config.with_prompt_caching(prompt_caching);
+                }
+                // Thinking counts towards max_tokens, so leave the usual room for the answer
+                if let Some(budget) = config.thinking_budget_tokens {
+                    let max_tokens = anthropic_config.max_tokens + budget;
+                    anthropic_config = anthropic_config
+                        .with_max_tokens(max_tokens)
+                        .with_thinking_budget(budget);
+                }
+                if let Some(base_url) = &config.base_url {
+                    anthropic_config = anthropic_config.with_base_url(base_url.clone());
+                }
+                debug_log(&format!(
+                    "Initializing Anthropic client with model: {}",
+                    config.model
+                ));
+                Arc::new(AnthropicClient::new(anthropic_config).with_tools(tools))
+            }
+            ModelProvider::OpenAi => {
+                let mut openai_config =
+                    OpenAiConfig::new(config.model.clone()).with_system_prompt(system_prompt);
+                if let Some(base_url) = &config.base_url {
+                    openai_config = openai_config.with_base_url(base_url.clone());
+                }
+                // Local servers usually run without authentication
+                if let Some(var) = &config.api_key_env {
+                    openai_config = openai_config.with_api_key(api_key_from_env(var)?);
+                }
+                debug_log(&format!(
+                    "Initializing OpenAI-compatible client with model {} at {}",
+                    config.model, openai_config.base_url
+                ));
+                Arc::new(OpenAiClient::new(openai_config).with_tools(tools))
+            }
+        };
+
+        // Queue locally rather than be throttled when the model has quotas configured
+        let client = match config.rate_limits.get(&config.model) {
+            Some(limit) => {
+                debug_log(&format!("Rate limiting {}: {:?}", config.model, limit));
+                let limiter = self.rate_limits.limiter(&config.model, *limit);
+                RateLimitLayer::new(limiter).layer(client)
+            }
+            None => client,
+        };
+
+        // Each retry waits on the rate limit again, and metrics count a retried call once
+        let mut policy = RetryPolicy::default();
+        if let Some(max_retries) = config.middleware.max_retries {
+            policy = policy.with_max_retries(max_retries);
+        }
+        let provider = config.provider.as_str();
+        let client = LayerStack::new()
+            .layer(MetricsLayer::new(provider))
+            .layer(RetryLayer::new(provider, policy))
+            .service(client);
+
+        // Outside the rate limit, so only what is actually sent counts against its budget
+        let client = match config.context_strategy {
+            Some(strategy) => {
+                debug_log(&format!(
+                    "Windowing context for {}: {:?}",
+                    config.model, strategy
+                ));
+                WindowLayer::new(strategy.window()).layer(client)
+            }
+            None => client,
+        };
+
+        Ok(client)
+    }
+
+    async fn create_bedrock_client(
+        &self,
+        config: &CliConfig,
+        system_prompt: String,
+    ) -> Result<BedrockClient> {
         // Create a BedrockConfig
-        let mut bedrock_config = BedrockConfig::new(&self.config.model)
+        let mut bedrock_config = BedrockConfig::new(&config.model)
             .with_max_tokens(4096)
             .with_temperature(0.7);
 
         // Add region if provided
-        if let Some(region) = &self.config.region {
+        if let Some(region) = &config.region {
             debug_log(&format!("Using AWS region: {}", region));
             bedrock_config = bedrock_config.with_region(region.clone());
         } else {
             debug_log("No AWS region specified, using default from AWS config");
         }
 
-        // Add a system prompt based on whether MCP is enabled
-        let system_prompt = if self.config.use_mcp {
-            "You are Claude, a helpful AI assistant by Anthropic. You will follow the Model Context Protocol (MCP) for structured communication.".to_string()
-        } else {
-            "You are Claude, a helpful AI assistant by Anthropic.".to_string()
-        };
+        // Profile, role and endpoint overrides from the aws settings
+        if let Some(profile) = &config.aws.profile {
+            debug_log(&format!("Using AWS profile: {}", profile));
+            bedrock_config = bedrock_config.with_profile(profile.clone());
+        }
+        if let Some(role_arn) = &config.aws.role_arn {
+            bedrock_config =
+                bedrock_config.with_assume_role(role_arn.clone(), config.aws.external_id.clone());
+        }
+        if let Some(endpoint_url) = &config.aws.endpoint_url {
+            debug_log(&format!("Using Bedrock endpoint: {}", endpoint_url));
+            bedrock_config = bedrock_config.with_endpoint_url(endpoint_url.clone());
+        }
+
+        bedrock_config = bedrock_config
+            .with_system_prompt(system_prompt)
+            .with_native_tools(config.native_tools)
+            .with_prompt_caching(config.prompt_caching.unwrap_or(false));
 
-        bedrock_config = bedrock_config.with_system_prompt(system_prompt);
+        // Thinking counts towards max_tokens, so leave the usual room for the answer
+        if let Some(budget) = config.thinking_budget_tokens {
+            bedrock_config = bedrock_config
+                .with_max_tokens(4096 + budget)
+                .with_thinking_budget(budget);
+        }
 
         // Initialize the AWS SDK and create the Bedrock client
         debug_log(&format!(
             "Initializing Bedrock client with model: {}",
-            self.config.model
+            config.model
         ));
         api_log(&format!("Bedrock config: {:?}", bedrock_config));
 
-        // Record metrics
-        count!("app.initialization");
-        gauge!("app.mcp_enabled", if self.config.use_mcp { 1 } else { 0 });
-
         // Create the Bedrock client with dynamic tool documentation if MCP is enabled
         debug_log("Creating BedrockClient");
-        let client = if self.config.use_mcp {
+        let client = if config.use_mcp {
             // Generate tool documentation from the tool manager
             let tools_doc = self.tool_manager.generate_tool_documentation();
             debug_log(&format!(
@@ -838,8 +1919,7 @@ impl CliApp {
                 }
                 Err(e) => {
                     debug_log(&format!("Failed to create BedrockClient: {}", e));
-                    self.handle_bedrock_client_error(e)?;
-                    unreachable!(); // This line won't be reached as handle_bedrock_client_error always returns Err
+                    return Err(e);
                 }
             }
         } else {
@@ -851,30 +1931,125 @@ impl CliApp {
                 }
                 Err(e) => {
                     debug_log(&format!("Failed to create BedrockClient: {}", e));
-                    self.handle_bedrock_client_error(e)?;
-                    unreachable!(); // This line won't be reached as handle_bedrock_client_error always returns Err
+                    return Err(e);
                 }
             }
         };
 
-        self.llm_client = Some(Arc::new(client));
+        Ok(client)
+    }
+
+    // Put a context window check in front of a client, counting what the client adds to
+    // each request besides the conversation
+    fn guard_client(
+        &self,
+        config: &CliConfig,
+        context_window: usize,
+        client: Arc<dyn LlmClient>,
+        system_prompt: &str,
+        tools: &[ToolDefinition],
+    ) -> Arc<ContextGuard> {
+        let mut overhead = estimate_tokens(system_prompt);
+        // Bedrock in MCP mode describes the tools in the system prompt
+        if config.use_mcp && config.provider == ModelProvider::Bedrock {
+            overhead += estimate_tokens(&self.tool_manager.generate_tool_documentation());
+        }
+        overhead += tools
+            .iter()
+            .map(|tool| estimate_tokens(&serde_json::to_string(tool).unwrap_or_default()))
+            .sum::<usize>();
+
+        let guard =
+            ContextGuard::new(client, context_window, reply_tokens(config)).with_overhead(overhead);
+        Arc::new(match config.context_strategy {
+            Some(strategy) => guard.with_window(strategy.window()),
+            None => guard,
+        })
+    }
+
+    // Describe the registered tools for providers that support native tool use
+    fn tool_definitions(&self) -> Vec<ToolDefinition> {
+        self.tool_manager
+            .get_tools()
+            .into_iter()
+            .map(|tool| ToolDefinition {
+                name: tool.id,
+                description: tool.description,
+                input_schema: tool.input_schema,
+            })
+            .collect()
+    }
+
+    // Record the assistant turn that requested native tool calls as one message,
+    // so the provider can match every tool result to it, then run the calls.
+    // The turn's thinking has to be kept with it for the model to continue
+    // after the tool results.
+    async fn run_native_tool_calls(
+        &mut self,
+        content: &str,
+        tool_calls: &[mcp_llm::ToolCall],
+        thinking: Vec<ThinkingBlock>,
+    ) -> Result<()> {
+        if self.config.native_tools {
+            let calls = tool_calls
+                .iter()
+                .map(|tool_call| ContextToolCall {
+                    tool_id: tool_call.tool.clone(),
+                    parameters: tool_call.params.clone(),
+                    call_id: tool_call.id.clone(),
+                })
+                .collect();
+            self.context
+                .add_assistant_tool_calls_with_thinking(content, calls, thinking);
+        }
+
+        for tool_call in tool_calls {
+            self.handle_tool_call(tool_call).await?;
+        }
         Ok(())
     }
 
+    // Show streamed thinking dimmed, unless it has been hidden
+    fn print_thinking(&self, text: &str) {
+        if !self.config.show_thinking || text.is_empty() {
+            return;
+        }
+
+        print!("{}", formatter::format_thinking(text));
+        let _ = std::io::stdout().flush();
+    }
+
     // Run the CLI application with the given input
     pub async fn run(&mut self, input: &str) -> Result<String> {
         // Add the user message to the conversation
         debug!("Adding user message to context: {}", input);
-        self.context.add_user_message(input);
+        let attachments = std::mem::take(&mut self.pending_attachments);
+        if attachments.is_empty() {
+            self.context.add_user_message(input);
+        } else {
+            self.context
+                .add_user_message_with_attachments(input, attachments);
+        }
+
+        // Make room by summarising older turns before the check below
+        self.auto_compact().await;
+
+        // Refuse a message that doesn't fit, leaving the conversation as it was
+        if let Some(usage) = self.context_usage().filter(|usage| !usage.fits()) {
+            self.context.messages.pop();
+            return Err(anyhow!(ContextOverflow(usage)));
+        }
 
         debug_log("Sending request to LLM");
 
         // Process the response based on whether streaming is enabled
-        if self.config.streaming {
+        let response = if self.config.streaming {
             self.handle_streaming_response().await
         } else {
             self.handle_non_streaming_response().await
-        }
+        };
+        self.save_session();
+        response
     }
 
     // ========== Streaming response handling ==========
@@ -962,10 +2137,6 @@ impl CliApp {
                                                     tool_name
                                                 ));
 
-                                                // Add the assistant's mixed content message to the context
-                                                self.context
-                                                    .add_assistant_message(&response_content);
-
                                                 // Execute the tool call
                                                 if let Err(e) = self
                                                     .handle_tool_call_execution(
@@ -1011,15 +2182,17 @@ impl CliApp {
                 }
             }
             Err(e) => {
-                debug_log(&format!("Error from Bedrock: {}", e));
+                debug_log(&format!("Error from LLM provider: {}", e));
                 count!("llm.errors", 1);
 
                 // Print a user-friendly error message
-                error!("Error communicating with Bedrock: {}", e);
-                eprintln!("Error communicating with Bedrock: {}", e);
-                eprintln!("Please check your AWS credentials and model availability.");
+                error!("Error communicating with the LLM provider: {}", e);
+                eprintln!("Error communicating with the LLM provider: {}", e);
+                if let Some(hint) = llm_error_hint(&e) {
+                    eprintln!("{}", hint);
+                }
 
-                Err(anyhow!("Error from Bedrock: {}", e))
+                Err(e.context("Error from LLM provider"))
             }
         }
     }
@@ -1046,200 +2219,86 @@ impl CliApp {
         let mut response_content = String::new();
         let mut had_tool_call = false;
 
-        // Buffer to accumulate chunks until we know if they're part of a tool call
-        let mut content_buffer = String::new();
-        let mut is_current_buffer_tool_call = false;
+        // Separates prose from JSON-RPC tool calls as the text streams in
+        let mut parser = JsonRpcStreamParser::new();
 
         // Keep track of complete JSON-RPC objects we've already processed
         // to avoid executing the same tool call twice
         let mut processed_jsonrpc_ids = Vec::new();
 
+        // Native tool calls of this turn, recorded together and run once the turn ends
+        let mut native_tool_calls = Vec::new();
+
+        // Finished thinking blocks, recorded with the turn's tool calls
+        let mut thinking_blocks = Vec::new();
+
         while let Some(chunk_result) = stream.next().await {
             match chunk_result {
                 Ok(chunk) => {
                     debug_log(&format!("Received chunk, {} bytes", chunk.content.len()));
                     api_log(&format!("Chunk content: {}", chunk.content));
 
+                    // Thinking is only displayed, never scanned for JSON-RPC
+                    self.print_thinking(&chunk.thinking);
+                    if let Some(block) = chunk.thinking_block {
+                        if self.config.show_thinking && block.text().is_some() {
+                            println!();
+                        }
+                        thinking_blocks.push(block);
+                    }
+
                     if !chunk.content.is_empty() {
                         received_content = true;
                         response_content.push_str(&chunk.content);
                         count!("llm.stream_chunks", 1);
 
-                        // Check if this content looks like a tool call JSON-RPC (do this before buffering)
-                        let content_is_likely_tool_call = chunk.content.contains("\"jsonrpc\"")
-                            && (chunk.content.contains("\"method\"")
-                                || chunk.content.contains("\"mcp.tool_call\""));
-
-                        if content_is_likely_tool_call {
-                            // Mark as tool call preemptively to avoid displaying JSON-RPC
-                            is_current_buffer_tool_call = true;
-                            debug_log(&format!(
-                                "Detected likely tool call in content: {}",
-                                chunk.content
-                            ));
-                            // Don't add to buffer to avoid printing JSON-RPC
-                        } else {
-                            // Add to buffer only if not a likely tool call
-                            content_buffer.push_str(&chunk.content);
+                        for event in parser.push(&chunk.content) {
+                            self.handle_stream_event(
+                                event,
+                                &mut processed_jsonrpc_ids,
+                                &mut had_tool_call,
+                            )
+                            .await?;
                         }
                     }
 
                     // Check if this chunk is marked as a tool call by Bedrock
                     if chunk.is_tool_call {
-                        is_current_buffer_tool_call = true;
                         had_tool_call = true;
-
-                        // Empty the buffer without printing, since it's a tool call
-                        content_buffer.clear();
+                        received_content = true;
 
                         if let Some(tool_call) = &chunk.tool_call {
-                            self.handle_tool_call(tool_call).await?;
-                        }
-                    }
-                    // If we've accumulated enough content, try to extract JSON-RPC objects
-                    // This handles the case where Bedrock didn't mark the chunk as a tool call
-                    else if response_content.contains("\"jsonrpc\":\"2.0\"") {
-                        // Try to extract JSON-RPC objects from the accumulated content
-                        debug_log("Attempting to extract JSON-RPC objects from response content");
-                        let json_objects = mcp_core::extract_jsonrpc_objects(&response_content);
-
-                        // If we found JSON-RPC objects, we need to extract the surrounding text
-                        if !json_objects.is_empty() {
-                            let mut remaining_text = response_content.clone();
-
-                            // Create a serialized version of each JSON object to find it in the text
-                            for json_obj in &json_objects {
-                                // Skip if already processed
-                                if let Some(id) = json_obj.get("id").and_then(|v| v.as_str()) {
-                                    if processed_jsonrpc_ids.contains(&id.to_string()) {
-                                        debug_log(&format!("Skipping already processed JSON-RPC object with id: {}", id));
-                                        continue;
-                                    }
-                                }
-
-                                // Serialize the object to find it in the text
-                                let serialized =
-                                    serde_json::to_string(json_obj).unwrap_or_default();
-
-                                // Find the JSON object in the text
-                                if let Some(idx) = remaining_text.find(&serialized) {
-                                    // Everything before this JSON object is text to display
-                                    let text_before = remaining_text[..idx].trim();
-                                    if !text_before.is_empty() {
-                                        debug_log(&format!(
-                                            "Found text to display before JSON-RPC: {}",
-                                            text_before
-                                        ));
-                                        println!("{}", text_before);
-                                        // Also clear it from content_buffer if it's there
-                                        if content_buffer.contains(text_before) {
-                                            content_buffer =
-                                                content_buffer.replace(text_before, "");
-                                        }
-                                    }
-
-                                    // Remove the processed part (text + JSON) from remaining text
-                                    remaining_text =
-                                        remaining_text[idx + serialized.len()..].to_string();
-                                }
-
-                                // Check if this is a regular message with a result field
-                                if let Some(result) = json_obj.get("result") {
-                                    if let Some(text) = result.as_str() {
-                                        debug_log(&format!(
-                                            "Found result text in JSON-RPC: {} chars",
-                                            text.len()
-                                        ));
-                                        println!("{}", text);
-
-                                        // Remember that we've processed this message
-                                        if let Some(id) =
-                                            json_obj.get("id").and_then(|v| v.as_str())
-                                        {
-                                            processed_jsonrpc_ids.push(id.to_string());
-                                        }
-                                    }
-                                }
-
-                                // Process the JSON object if it's a tool call
-                                if let Some(method) =
-                                    json_obj.get("method").and_then(|v| v.as_str())
-                                {
-                                    if method == "mcp.tool_call" {
-                                        if let Some(params) = json_obj.get("params") {
-                                            if let Some(tool_name) =
-                                                params.get("name").and_then(|v| v.as_str())
-                                            {
-                                                if let Some(parameters) = params.get("parameters") {
-                                                    if let Some(id) =
-                                                        json_obj.get("id").and_then(|v| v.as_str())
-                                                    {
-                                                        had_tool_call = true;
-                                                        is_current_buffer_tool_call = true;
-
-                                                        debug_log(&format!(
-                                                            "Extracted tool call: {} with id: {}",
-                                                            tool_name, id
-                                                        ));
-
-                                                        // Execute the tool call
-                                                        self.handle_tool_call_execution(
-                                                            tool_name,
-                                                            parameters.clone(),
-                                                        )
-                                                        .await?;
-
-                                                        // Remember that we've processed this tool call
-                                                        processed_jsonrpc_ids.push(id.to_string());
-                                                    }
-                                                }
-                                            }
-                                        }
-                                    }
-                                }
-                            }
-
-                            // After processing all JSON objects, display any remaining text
-                            let remaining_text = remaining_text.trim();
-                            if !remaining_text.is_empty() {
+                            // The same call may already have been extracted from the text
+                            if processed_jsonrpc_ids.contains(&tool_call.id) {
                                 debug_log(&format!(
-                                    "Found text to display after all JSON-RPC: {}",
-                                    remaining_text
+                                    "Skipping already processed tool call with id: {}",
+                                    tool_call.id
                                 ));
-                                println!("{}", remaining_text);
-                                // Also clear it from content_buffer if it's there
-                                if content_buffer.contains(remaining_text) {
-                                    content_buffer = content_buffer.replace(remaining_text, "");
-                                }
+                            } else {
+                                processed_jsonrpc_ids.push(tool_call.id.clone());
+                                native_tool_calls.push(tool_call.clone());
                             }
                         }
                     }
 
-                    // If we have completed a chunk or this is the final chunk, process the buffer
-                    if chunk.is_complete
-                        || (!is_current_buffer_tool_call && chunk.content.contains("\n"))
-                    {
-                        // Only print if it's NOT part of a tool call
-                        if !is_current_buffer_tool_call && !content_buffer.is_empty() {
-                            debug_log(&format!(
-                                "Printing chunk content (not a tool call): {}",
-                                content_buffer
-                            ));
-                            self.print_chunk_content(&content_buffer);
-                            content_buffer.clear();
-                        } else if is_current_buffer_tool_call {
-                            // Clear the buffer but don't print it if it's a tool call
-                            debug_log("Skipping printing tool call JSON-RPC");
-                            content_buffer.clear();
-                        }
-
-                        // Reset the flag for the next buffer
-                        is_current_buffer_tool_call = false;
-                    }
-
                     // If this is the final chunk, we're done
                     if chunk.is_complete {
                         debug!("Final chunk received");
+                        for event in parser.finish() {
+                            self.handle_stream_event(
+                                event,
+                                &mut processed_jsonrpc_ids,
+                                &mut had_tool_call,
+                            )
+                            .await?;
+                        }
+                        self.record_usage(chunk.usage.as_ref());
+                        self.run_native_tool_calls(
+                            &response_content,
+                            &std::mem::take(&mut native_tool_calls),
+                            std::mem::take(&mut thinking_blocks),
+                        )
+                        .await?;
                         println!(); // Add a newline after completion
 
                         // Make one final attempt to extract any JSON-RPC objects from the full response
@@ -1256,16 +2315,7 @@ impl CliApp {
                                         continue;
                                     }
 
-                                    // Check if this is a regular message with a result field
-                                    if let Some(result) = json_obj.get("result") {
-                                        if let Some(text) = result.as_str() {
-                                            debug_log(&format!("Found result text in final JSON-RPC extraction: {} chars", text.len()));
-                                            println!("{}", text);
-
-                                            // Remember that we've processed this message
-                                            processed_jsonrpc_ids.push(id.to_string());
-                                        }
-                                    }
+                                    // Responses were already displayed by the stream parser
 
                                     // If it's a tool call, extract the tool name and parameters
                                     if let Some(method) =
@@ -1320,12 +2370,21 @@ impl CliApp {
             }
         }
 
+        // A stream that ended without a final chunk may still have requested tools
+        if !native_tool_calls.is_empty() {
+            self.run_native_tool_calls(&response_content, &native_tool_calls, thinking_blocks)
+                .await?;
+            return self.get_streaming_follow_up_response().await;
+        }
+
         // If we haven't received any content, that's an error
         if !received_content {
             debug_log("No content received from stream");
             return Err(anyhow!("No content received from Bedrock. Please check your AWS credentials and model availability."));
         }
 
+        // A reply without tool calls ends the turn, so keep it in the conversation
+        self.context.add_assistant_message(&response_content);
         Ok(response_content)
     }
 
@@ -1344,14 +2403,45 @@ impl CliApp {
     async fn handle_non_streaming_response(&mut self) -> Result<String> {
         // Get the initial response
         let client = self.llm_client.as_ref().unwrap();
-        let response = client.send_message(&self.context).await?;
-
-        // Add the response to the conversation context
-        self.context.add_assistant_message(&response.content);
+        let response = client.send_message(&self.context).await.inspect_err(|e| {
+            if let Some(hint) = llm_error_hint(e) {
+                eprintln!("{}", hint);
+            }
+        })?;
+        self.record_usage(response.usage.as_ref());
+
+        for block in &response.thinking {
+            if let Some(text) = block.text() {
+                self.print_thinking(text);
+                if self.config.show_thinking {
+                    println!();
+                }
+            }
+        }
 
         // Get any tool calls
         let has_tool_calls = !response.tool_calls.is_empty();
 
+        // Add the response to the conversation context
+        if has_tool_calls && self.config.native_tools {
+            let tool_calls = response
+                .tool_calls
+                .iter()
+                .map(|tool_call| ContextToolCall {
+                    tool_id: tool_call.tool.clone(),
+                    parameters: tool_call.params.clone(),
+                    call_id: tool_call.id.clone(),
+                })
+                .collect();
+            self.context.add_assistant_tool_calls_with_thinking(
+                &response.content,
+                tool_calls,
+                response.thinking.clone(),
+            );
+        } else {
+            self.context.add_assistant_message(&response.content);
+        }
+
         // If no tool calls, we're done
         if !has_tool_calls {
             return Ok(response.content);
@@ -1371,10 +2461,7 @@ impl CliApp {
 
     // Helper method to process a single tool call
     async fn process_tool_call(&mut self, tool_call: &mcp_llm::ToolCall) -> Result<()> {
-        if let Err(e) = self
-            .handle_tool_call_execution(&tool_call.tool, tool_call.params.clone())
-            .await
-        {
+        if let Err(e) = self.handle_tool_call(tool_call).await {
             debug_log(&format!("Tool execution error: {}", e));
         }
         Ok(())
@@ -1390,6 +2477,7 @@ impl CliApp {
         // Get the follow-up response
         let client = self.llm_client.as_ref().unwrap();
         let follow_up_result = client.send_message(&self.context).await?;
+        self.record_usage(follow_up_result.usage.as_ref());
         debug_log(&format!(
             "Received follow-up response: {} chars",
             follow_up_result.content.len()
@@ -1403,13 +2491,47 @@ impl CliApp {
         Ok(follow_up_result.content)
     }
 
+    // Display prose from the stream parser and execute the tool calls it completes
+    async fn handle_stream_event(
+        &mut self,
+        event: JsonRpcStreamEvent,
+        processed_jsonrpc_ids: &mut Vec<String>,
+        had_tool_call: &mut bool,
+    ) -> Result<()> {
+        match event {
+            JsonRpcStreamEvent::Text(text) => self.print_chunk_content(&text),
+            JsonRpcStreamEvent::ToolCallStarted { name } => {
+                debug_log(&format!("Tool call started in stream: {}", name));
+            }
+            JsonRpcStreamEvent::ToolCallComplete(tool_call) => {
+                if processed_jsonrpc_ids.contains(&tool_call.id) {
+                    debug_log(&format!(
+                        "Skipping already processed tool call with id: {}",
+                        tool_call.id
+                    ));
+                    return Ok(());
+                }
+                *had_tool_call = true;
+                debug_log(&format!(
+                    "Extracted tool call: {} with id: {}",
+                    tool_call.tool, tool_call.id
+                ));
+                processed_jsonrpc_ids.push(tool_call.id);
+                self.handle_tool_call_execution(&tool_call.tool, tool_call.params)
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
     // Function to handle tool calls
     async fn handle_tool_call(&mut self, tool_call: &mcp_llm::ToolCall) -> Result<()> {
-        // Not implemented yet - stub to fix compilation
         // ToolCall has tool, id and params fields
         let tool_name = &tool_call.tool;
         let parameters = serde_json::to_value(&tool_call.params).unwrap_or_default();
-        self.handle_tool_call_execution(tool_name, parameters).await
+        let call_id = self.config.native_tools.then_some(tool_call.id.as_str());
+        self.execute_and_record_tool(tool_name, parameters, call_id)
+            .await
     }
 
     // Function to execute tool calls
@@ -1417,6 +2539,18 @@ impl CliApp {
         &mut self,
         tool_name: &str,
         parameters: Value,
+    ) -> Result<()> {
+        self.execute_and_record_tool(tool_name, parameters, None)
+            .await
+    }
+
+    // Execute a tool and add its result to the conversation; native tool calls
+    // also record the structured result keyed by the provider's call id
+    async fn execute_and_record_tool(
+        &mut self,
+        tool_name: &str,
+        parameters: Value,
+        call_id: Option<&str>,
     ) -> Result<()> {
         // Execute the tool and capture the result
         match self.execute_tool(tool_name, parameters).await {
@@ -1434,7 +2568,17 @@ impl CliApp {
                     "Adding tool result to conversation context: {}",
                     tool_name
                 ));
-                self.context.add_tool_message(&result_json);
+                match call_id {
+                    Some(call_id) => self.context.add_tool_result(
+                        &result_json,
+                        ContextToolResult {
+                            tool_id: tool_name.to_string(),
+                            result: serde_json::to_value(&result).unwrap_or_default(),
+                            call_id: call_id.to_string(),
+                        },
+                    ),
+                    None => self.context.add_tool_message(&result_json),
+                }
 
                 Ok(())
             }
@@ -1723,9 +2867,28 @@ impl CliApp {
                 let mut chunk_buffer = String::new();
                 let mut had_tool_call = false;
                 let mut received_content = false;
+                let mut native_tool_calls = Vec::new();
+                let mut thinking_blocks = Vec::new();
 
                 while let Some(follow_up_chunk_result) = follow_up_stream.next().await {
                     if let Ok(follow_up_chunk) = follow_up_chunk_result {
+                        self.print_thinking(&follow_up_chunk.thinking);
+                        if let Some(block) = &follow_up_chunk.thinking_block {
+                            if self.config.show_thinking && block.text().is_some() {
+                                println!();
+                            }
+                            thinking_blocks.push(block.clone());
+                        }
+
+                        // Native tool calls arrive as structured chunks rather than text
+                        if self.config.native_tools {
+                            if let Some(tool_call) = &follow_up_chunk.tool_call {
+                                received_content = true;
+                                had_tool_call = true;
+                                native_tool_calls.push(tool_call.clone());
+                            }
+                        }
+
                         if !follow_up_chunk.content.is_empty() {
                             received_content = true;
                             follow_up_content.push_str(&follow_up_chunk.content);
@@ -1763,12 +2926,38 @@ impl CliApp {
                         // If this is the final chunk, we're done
                         if follow_up_chunk.is_complete {
                             debug!("Final follow-up chunk received");
+                            self.record_usage(follow_up_chunk.usage.as_ref());
                             println!(); // Add a newline after completion
                             break;
                         }
                     }
                 }
 
+                // Native tool calls need no JSON-RPC validation; record and run them
+                // together, then ask the model to continue
+                if !native_tool_calls.is_empty() {
+                    self.run_native_tool_calls(
+                        &follow_up_content,
+                        &native_tool_calls,
+                        thinking_blocks,
+                    )
+                    .await?;
+
+                    debug_log("Native tool calls executed, getting another follow-up");
+                    self.context
+                        .add_user_message("Please continue helping the user with their request.");
+
+                    let recursive_response = self.get_streaming_follow_up_response().await?;
+                    if recursive_response.is_empty() {
+                        return Ok(follow_up_content);
+                    }
+
+                    let mut combined_response = follow_up_content;
+                    combined_response.push_str("\n\n");
+                    combined_response.push_str(&recursive_response);
+                    return Ok(combined_response);
+                }
+
                 // If we didn't receive any content, log this fact and display a message
                 if !received_content {
                     debug_log("Received empty follow-up response from LLM");
@@ -1829,14 +3018,11 @@ impl CliApp {
                     _ => {}
                 }
 
-                // Process the tool calls in the validation result
+                // Process the tool calls in the validation result; this also adds the
+                // assistant's response to the context
                 self.handle_follow_up_validation_result(&validation_result, &follow_up_content)
                     .await?;
 
-                // Add the assistant's response to the context for future reference
-                debug!("Adding assistant response to context");
-                self.context.add_assistant_message(&follow_up_content);
-
                 // If we found a tool call in the response, we need to add a tool result message
                 // and then get another follow-up response
                 if has_tool_call || had_tool_call {
@@ -2,17 +2,34 @@ use anyhow::Result;
 use async_trait::async_trait;
 use futures::Stream;
 use mcp_core::context::ConversationContext;
-use mcp_llm::{LlmClient, LlmResponse, StreamChunk, ToolCall};
+use mcp_llm::{LlmClient, LlmResponse, StreamChunk, TokenUsage, ToolCall};
 use tokio::sync::mpsc;
 use tokio_stream::wrappers::ReceiverStream;
 
+// Tool calls the mock requests: one, or two for a parallel turn
+fn mock_tool_calls(parallel: bool) -> Vec<ToolCall> {
+    let count = if parallel { 2 } else { 1 };
+    (1..=count)
+        .map(|n| ToolCall {
+            id: format!("mock-tool-call-{}", n),
+            tool: "mock-tool".to_string(),
+            params: serde_json::json!({
+                "param1": "value1",
+                "param2": 42
+            }),
+        })
+        .collect()
+}
+
 // Mock LLM client for testing
 pub struct MockLlmClient {
     // Configuration options
     pub response_content: String,
     pub add_tool_call: bool,
+    pub parallel_tool_calls: bool,
     pub follow_up_response: Option<String>,
     pub use_jsonrpc_format: bool,
+    pub usage: Option<TokenUsage>,
 }
 
 impl Default for MockLlmClient {
@@ -20,10 +37,12 @@ impl Default for MockLlmClient {
         Self {
             response_content: "This is a mock response from the LLM".to_string(),
             add_tool_call: false,
+            parallel_tool_calls: false,
             follow_up_response: Some(
                 "This is a follow-up response after tool execution".to_string(),
             ),
             use_jsonrpc_format: true, // Use JSON-RPC format by default
+            usage: None,
         }
     }
 }
@@ -33,10 +52,12 @@ impl MockLlmClient {
         Self {
             response_content: response.to_string(),
             add_tool_call: false,
+            parallel_tool_calls: false,
             follow_up_response: Some(
                 "This is a follow-up response after tool execution".to_string(),
             ),
             use_jsonrpc_format: true, // Use JSON-RPC format by default
+            usage: None,
         }
     }
 
@@ -45,6 +66,13 @@ impl MockLlmClient {
         self
     }
 
+    /// Request two tool calls in the same turn instead of one
+    pub fn with_parallel_tool_calls(mut self) -> Self {
+        self.add_tool_call = true;
+        self.parallel_tool_calls = true;
+        self
+    }
+
     pub fn with_follow_up(mut self, follow_up: &str) -> Self {
         self.follow_up_response = Some(follow_up.to_string());
         self
@@ -54,6 +82,11 @@ impl MockLlmClient {
         self.follow_up_response = None;
         self
     }
+
+    pub fn with_usage(mut self, input_tokens: u64, output_tokens: u64) -> Self {
+        self.usage = Some(TokenUsage::new(input_tokens, output_tokens));
+        self
+    }
 }
 
 #[async_trait]
@@ -87,6 +120,8 @@ impl LlmClient for MockLlmClient {
                 id: "mock-empty-follow-up-id".to_string(),
                 content: String::new(), // Empty content to end the recursion
                 tool_calls: vec![],     // No tool calls
+                usage: None,
+                thinking: Vec::new(),
             });
         }
 
@@ -98,6 +133,8 @@ impl LlmClient for MockLlmClient {
                 id: "mock-follow-up-id".to_string(),
                 content: follow_up_text,
                 tool_calls: vec![], // No tool calls in follow-up response
+                usage: None,
+                thinking: Vec::new(),
             });
         }
 
@@ -132,14 +169,7 @@ impl LlmClient for MockLlmClient {
 
         // Create tool calls if requested
         let tool_calls = if self.add_tool_call {
-            vec![ToolCall {
-                id: "mock-tool-call-1".to_string(),
-                tool: "mock-tool".to_string(),
-                params: serde_json::json!({
-                    "param1": "value1",
-                    "param2": 42
-                }),
-            }]
+            mock_tool_calls(self.parallel_tool_calls)
         } else {
             vec![]
         };
@@ -148,6 +178,8 @@ impl LlmClient for MockLlmClient {
             id: "mock-response-id".to_string(),
             content: response_text,
             tool_calls,
+            usage: self.usage,
+            thinking: Vec::new(),
         })
     }
 
@@ -189,6 +221,9 @@ impl LlmClient for MockLlmClient {
                     is_tool_call: false,
                     tool_call: None,
                     is_complete: true,
+                    usage: None,
+                    thinking: String::new(),
+                    thinking_block: None,
                 };
 
                 let _ = tx.send(Ok(final_chunk)).await;
@@ -233,11 +268,13 @@ impl LlmClient for MockLlmClient {
 
         // Clone data for the async task
         let response_text_clone = response_text.clone();
+        let usage = self.usage;
         let add_tool_call = if is_follow_up_request {
             false // No tool calls in follow-up responses
         } else {
             self.add_tool_call
         };
+        let parallel_tool_calls = self.parallel_tool_calls;
 
         // Spawn a task to simulate streaming the response
         tokio::spawn(async move {
@@ -254,6 +291,9 @@ impl LlmClient for MockLlmClient {
                         is_tool_call: false,
                         tool_call: None,
                         is_complete: false,
+                        usage: None,
+                        thinking: String::new(),
+                        thinking_block: None,
                     };
 
                     if let Err(e) = tx.send(Ok(stream_chunk)).await {
@@ -266,19 +306,26 @@ impl LlmClient for MockLlmClient {
                 }
             }
 
-            // Send final chunk with tool call if needed
-            let tool_call = if add_tool_call {
-                Some(ToolCall {
-                    id: "mock-tool-call-1".to_string(),
-                    tool: "mock-tool".to_string(),
-                    params: serde_json::json!({
-                        "param1": "value1",
-                        "param2": 42
-                    }),
-                })
+            // Send final chunk with tool call if needed, any earlier ones on their own
+            let mut tool_calls = if add_tool_call {
+                mock_tool_calls(parallel_tool_calls)
             } else {
-                None
+                vec![]
             };
+            let tool_call = tool_calls.pop();
+            for earlier in tool_calls {
+                let stream_chunk = StreamChunk {
+                    id: "mock-stream-chunk".to_string(),
+                    content: String::new(),
+                    is_tool_call: true,
+                    tool_call: Some(earlier),
+                    is_complete: false,
+                    usage: None,
+                    thinking: String::new(),
+                    thinking_block: None,
+                };
+                let _ = tx.send(Ok(stream_chunk)).await;
+            }
 
             // Send completion message
             let final_chunk = StreamChunk {
@@ -287,6 +334,9 @@ impl LlmClient for MockLlmClient {
                 is_tool_call: add_tool_call,
                 tool_call,
                 is_complete: true,
+                usage,
+                thinking: String::new(),
+                thinking_block: None,
             };
 
             let _ = tx.send(Ok(final_chunk)).await;
@@ -1,7 +1,12 @@
 #[cfg(test)]
 mod tests {
+    use mcp_core::{CompactionConfig, ExportFormat, ModelPricing};
+    use mcp_llm::RecordingClient;
+    use mcp_runtime::{SessionRecord, SessionStore};
     use mcpterm_cli::{mock::MockLlmClient, CliApp, CliConfig};
+    use std::sync::Arc;
     use std::time::Duration;
+    use tempfile::TempDir;
 
     // Helper function to create a simple basic config with no streaming
     fn test_config() -> CliConfig {
@@ -14,6 +19,13 @@ mod tests {
         }
     }
 
+    // Plain text replies, so streamed runs don't treat the answer as a JSON-RPC tool call
+    fn plain_mock(response: &str) -> MockLlmClient {
+        let mut mock_client = MockLlmClient::new(response);
+        mock_client.use_jsonrpc_format = false;
+        mock_client
+    }
+
     #[tokio::test]
     async fn test_cli_app_with_mock() {
         // Use a timeout wrapper to prevent test from hanging
@@ -119,4 +131,217 @@ mod tests {
         // Make sure the test completed within the timeout
         assert!(result.is_ok(), "Test timed out");
     }
+
+    #[tokio::test]
+    async fn test_streamed_parallel_tool_calls_share_one_turn() {
+        let mut app = CliApp::new()
+            .with_llm_client(plain_mock("Parallel tool response").with_parallel_tool_calls())
+            .with_config(CliConfig {
+                streaming: true,
+                native_tools: true,
+                enable_tools: false,
+                ..test_config()
+            });
+        app.run("parallel prompt").await.unwrap();
+
+        // Both calls belong to one assistant turn, each answered by its own result
+        assert_eq!(
+            app.debug_last_message_roles(10),
+            "user,assistant,tool,tool,assistant"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_session_usage_is_accumulated() {
+        let mock_client = MockLlmClient::new("Usage test response").with_usage(1000, 200);
+
+        let mut app = CliApp::new()
+            .with_llm_client(mock_client)
+            .with_config(CliConfig {
+                pricing: Some(ModelPricing::new(3.0, 15.0)),
+                ..test_config()
+            });
+
+        app.run("first prompt").await.unwrap();
+        app.run("second prompt").await.unwrap();
+
+        let usage = app.session_usage();
+        assert_eq!(usage.requests, 2);
+        assert_eq!(usage.input_tokens, 2000);
+        assert_eq!(usage.output_tokens, 400);
+        assert!((usage.cost_usd.unwrap() - 0.012).abs() < 1e-9);
+        assert!(app.usage_summary().contains("$0.0120"));
+    }
+
+    #[tokio::test]
+    async fn test_recorded_session_replays_offline() {
+        let dir = TempDir::new().unwrap();
+        let cassette = dir.path().join("cassette.json");
+
+        // Record a two-turn session against the mock
+        let recorder =
+            RecordingClient::new(Arc::new(MockLlmClient::new("Recorded response")), &cassette);
+        let mut app = CliApp::new()
+            .with_llm_client(recorder)
+            .with_config(test_config());
+        let first = app.run("first prompt").await.unwrap();
+        let second = app.run("second prompt").await.unwrap();
+
+        // Replay it without the mock, through the same initialization as --replay
+        let mut app = CliApp::new().with_config(CliConfig {
+            replay_path: Some(cassette.clone()),
+            ..test_config()
+        });
+        app.initialize().await.unwrap();
+        assert_eq!(app.run("first prompt").await.unwrap(), first);
+        assert_eq!(app.run("second prompt").await.unwrap(), second);
+
+        // A conversation that wasn't recorded has nothing to replay
+        assert!(app.run("third prompt").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_conversation_is_compacted_past_threshold() {
+        let mut app = CliApp::new()
+            .with_llm_client(MockLlmClient::new("Compaction test response"))
+            .with_config(CliConfig {
+                compaction: CompactionConfig {
+                    threshold_tokens: Some(1),
+                    keep_recent_turns: 1,
+                    ..CompactionConfig::default()
+                },
+                ..test_config()
+            });
+
+        // The only turn is the one being answered, so there is nothing to compact yet
+        app.run("first prompt").await.unwrap();
+        assert_eq!(app.debug_last_message_roles(10), "user,assistant");
+
+        // Each later turn folds everything before it into one summary
+        app.run("second prompt").await.unwrap();
+        app.run("third prompt").await.unwrap();
+        assert_eq!(app.debug_last_message_roles(10), "system,user,assistant");
+
+        // Nothing is left to compact by hand either
+        assert!(app.compact(None).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_session_is_saved_and_resumed() {
+        for streaming in [false, true] {
+            let dir = TempDir::new().unwrap();
+            let store = SessionStore::new(dir.path());
+            let config = CliConfig {
+                streaming,
+                ..test_config()
+            };
+
+            // Nothing is written until there is a conversation to save
+            let mut app = CliApp::new()
+                .with_llm_client(plain_mock("Session test response"))
+                .with_config(config.clone())
+                .with_session(SessionRecord::new("test-model", "/work/project"))
+                .with_session_store(store.clone());
+            assert!(app.saved_session_id().is_none());
+            app.run("first prompt").await.unwrap();
+            let id = app.saved_session_id().unwrap().to_string();
+
+            let record = store.latest_in("/work/project".as_ref()).unwrap().unwrap();
+            assert_eq!(record.id, id);
+            assert_eq!(record.title(), "first prompt");
+            assert_eq!(record.messages.len(), 2);
+            assert!(record.messages[1].content.contains("Session test response"));
+            assert_eq!(record.model, test_config().model);
+
+            // A resumed session picks up the conversation and keeps saving under its id
+            let mut app = CliApp::new()
+                .with_llm_client(plain_mock("Session test response"))
+                .with_config(config)
+                .with_session(store.load(&id[..8]).unwrap())
+                .with_session_store(store.clone());
+            app.run("second prompt").await.unwrap();
+            assert_eq!(
+                app.debug_last_message_roles(10),
+                "user,assistant,user,assistant"
+            );
+            assert_eq!(store.load(&id).unwrap().messages.len(), 4);
+            assert_eq!(store.list().unwrap().len(), 1);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_rewind_and_switch_branches() {
+        let dir = TempDir::new().unwrap();
+        let store = SessionStore::new(dir.path());
+        let mut app = CliApp::new()
+            .with_llm_client(MockLlmClient::new("Branch test response"))
+            .with_config(test_config())
+            .with_session(SessionRecord::new("test-model", "/work/project"))
+            .with_session_store(store.clone());
+        app.run("first prompt").await.unwrap();
+        app.run("wrong turn").await.unwrap();
+
+        let rewind = app.rewind(1).unwrap();
+        assert_eq!(rewind.message.content, "wrong turn");
+        assert_eq!(rewind.kept, Some(0));
+        assert_eq!(app.debug_last_message_roles(10), "user,assistant");
+
+        app.run("right turn").await.unwrap();
+        assert_eq!(app.branches().branches().len(), 2);
+        assert_eq!(
+            app.branches().branch(1).unwrap().label(),
+            Some("right turn")
+        );
+
+        // The abandoned path is still there to go back to
+        assert!(app.switch_branch(0));
+        assert_eq!(
+            app.debug_last_message_roles(10),
+            "user,assistant,user,assistant"
+        );
+        assert!(!app.switch_branch(7));
+
+        // The tree is saved with the session
+        let id = app.saved_session_id().unwrap().to_string();
+        let record = store.load(&id).unwrap();
+        assert_eq!(record.tree.current(), 0);
+        assert_eq!(record.tree.branches().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_export_transcript() {
+        let mut app = CliApp::new()
+            .with_llm_client(MockLlmClient::new("Export test response"))
+            .with_config(test_config());
+        app.run("What changed?").await.unwrap();
+
+        let markdown = app.export(ExportFormat::Markdown);
+        assert!(markdown.starts_with("# What changed?"));
+        assert!(markdown.contains("### Assistant"));
+
+        let jsonl = app.export(ExportFormat::Jsonl);
+        let roles: Vec<String> = jsonl
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+            .filter_map(|step| step["role"].as_str().map(String::from))
+            .collect();
+        assert_eq!(roles.last().unwrap(), "assistant");
+        assert!(roles.contains(&"user".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_export_streamed_transcript() {
+        let mut app = CliApp::new()
+            .with_llm_client(plain_mock("Streamed export response"))
+            .with_config(CliConfig {
+                streaming: true,
+                ..test_config()
+            });
+        app.run("What streamed?").await.unwrap();
+
+        let markdown = app.export(ExportFormat::Markdown);
+        assert!(markdown.starts_with("# What streamed?"));
+        assert!(markdown.contains("### Assistant"));
+        assert!(markdown.contains("Streamed export response"));
+    }
 }
@@ -1,21 +1,29 @@
 use anyhow::Result;
 use chrono::{DateTime, Utc};
+use crossbeam_channel::{unbounded, Receiver, Sender};
 use crossterm::{
     event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
 use edtui::{EditorMode as EdtuiMode, EditorState, EditorTheme, EditorView, Lines};
+use mcp_core::context::{
+    extract_attachment_refs, Attachment, ConversationContext, ConversationTree, MessageRole,
+};
+use mcp_core::{ContextUsage, ModelConfig};
+use mcp_llm::{LlmClient, RateLimitEvent};
+use mcp_runtime::{create_handler, ApiEvent, EventBus, ModelEvent, SessionManager, UiEvent};
 use ratatui::{
     backend::CrosstermBackend,
     buffer::Buffer,
     layout::{Constraint, Direction, Layout, Rect},
     style::{Color, Style},
     text::{Line, Span, Text},
-    widgets::{Block, Borders, Paragraph, Widget},
+    widgets::{Block, Borders, Clear, Paragraph, Widget},
     Terminal,
 };
 use std::io;
+use std::sync::Arc;
 use std::time::Duration;
 
 // Focus management
@@ -41,6 +49,14 @@ enum MessageType {
     Response,
     Error,
     Tool,
+    Thinking,
+}
+
+// What the session sends back to the UI loop
+#[derive(Debug, Clone)]
+enum SessionEvent {
+    Model(ModelEvent),
+    RateLimited(RateLimitEvent),
 }
 
 // A message in the conversation
@@ -90,12 +106,20 @@ struct AppState {
     focus: Focus,
     mode: EditorMode,
     running: bool,
-    scroll: usize,                // Index of first message to show (0 = oldest)
+    scroll: usize,                      // Index of first message to show (0 = oldest)
     auto_scroll: bool, // Whether to automatically scroll to the bottom when new messages are added
     visible_message_count: usize, // Approximate number of messages that can be shown
     last_key: String,  // For debugging
     history: Vec<String>, // Command history
     history_index: usize, // Current position in history
+    expand_thinking: bool, // Show the model's thinking in full rather than one line
+    context_window: Option<usize>, // Context window of the model, for the usage gauge
+    reply_tokens: usize, // Room the model's reply takes in the window
+    conversation: ConversationContext, // The exchange itself, without UI notices
+    tree: ConversationTree, // Branches left behind by /rewind
+    branch_picker: Option<usize>, // Selected branch while the picker is open
+    ui_events: Option<Sender<UiEvent>>, // Where prompts go when attached to a session
+    request_pending: bool, // A prompt is waiting on the model
 }
 
 impl AppState {
@@ -125,6 +149,10 @@ impl AppState {
                 MessageType::System,
             ),
             Message::new("a: Toggle auto-scroll".to_string(), MessageType::System),
+            Message::new(
+                "t: Expand/collapse model thinking".to_string(),
+                MessageType::System,
+            ),
             Message::new(
                 "Up/Down: Navigate input history (in input area)".to_string(),
                 MessageType::System,
@@ -133,6 +161,22 @@ impl AppState {
                 "Enter: Submit message (in input area)".to_string(),
                 MessageType::System,
             ),
+            Message::new(
+                "@path: Attach an image or PDF to the message".to_string(),
+                MessageType::System,
+            ),
+            Message::new(
+                "/rewind [n]: Go back before your last (or nth last) message".to_string(),
+                MessageType::System,
+            ),
+            Message::new(
+                "b or /branch: Pick a branch of the conversation".to_string(),
+                MessageType::System,
+            ),
+            Message::new(
+                "Esc or Ctrl-C: Cancel the request in flight".to_string(),
+                MessageType::System,
+            ),
             Message::new("q: Quit (in normal mode)".to_string(), MessageType::System),
             // Example messages of different types
             Message::new(
@@ -143,6 +187,10 @@ impl AppState {
                 "This is an example tool output message".to_string(),
                 MessageType::Tool,
             ),
+            Message::new(
+                "This is an example of the model's thinking.\nIt stays collapsed to its first line until expanded.".to_string(),
+                MessageType::Thinking,
+            ),
         ];
 
         Self {
@@ -157,7 +205,31 @@ impl AppState {
             last_key: "None".to_string(),
             history: Vec::new(),
             history_index: 0,
+            expand_thinking: false,
+            context_window: None,
+            reply_tokens: 0,
+            conversation: ConversationContext::new(),
+            tree: ConversationTree::new(),
+            branch_picker: None,
+            ui_events: None,
+            request_pending: false,
         }
+        .with_model(&ModelConfig::default())
+    }
+
+    // Size the usage gauge for `model`; thinking counts towards its max_tokens
+    fn with_model(mut self, model: &ModelConfig) -> Self {
+        self.context_window = model.context_window;
+        self.reply_tokens = model.max_tokens + model.thinking_budget_tokens.unwrap_or(0);
+        self
+    }
+
+    // How much of the context window the next request would take up, counted the way the
+    // client's context guard counts it
+    fn context_usage(&self) -> Option<ContextUsage> {
+        let prompt_tokens = self.conversation.estimated_tokens();
+        self.context_window
+            .map(|window| ContextUsage::new(prompt_tokens, self.reply_tokens, window))
     }
 
     // Add a message to the list
@@ -217,18 +289,169 @@ impl AppState {
                 self.history.push(input_text.clone());
             }
             self.history_index = self.history.len();
+            self.input.clear();
+
+            // Rewinding and branching act on the conversation rather than sending anything
+            if let Some(command) = input_text.strip_prefix('/') {
+                self.run_command(command);
+                return;
+            }
+
+            // Pull @path references out of the text and load them as attachments
+            let (text, paths) = extract_attachment_refs(&input_text);
+            let attachments = self.load_attachments(&paths);
 
             // Add user message
-            self.add_message(input_text.clone(), MessageType::User);
+            self.add_message(text.clone(), MessageType::User);
+
+            // With a session attached the response arrives as model events
+            if let Some(ui_events) = &self.ui_events {
+                let event = if attachments.is_empty() {
+                    UiEvent::UserInput(text.clone())
+                } else {
+                    UiEvent::UserInputWithAttachments(text.clone(), attachments.clone())
+                };
+                if ui_events.send(event).is_ok() {
+                    self.request_pending = true;
+                    self.record_user_message(&text, attachments);
+                }
+                return;
+            }
 
             // Add a simulated response
-            self.add_message(format!("Echo: {}", input_text), MessageType::Response);
+            let response = format!("Echo: {} ({} attachments)", text, attachments.len());
+            self.add_message(response.clone(), MessageType::Response);
 
-            // Clear the input
-            self.input.clear();
+            self.record_user_message(&text, attachments);
+            self.conversation.add_assistant_message(&response);
+        }
+    }
+
+    fn record_user_message(&mut self, text: &str, attachments: Vec<Attachment>) {
+        if attachments.is_empty() {
+            self.conversation.add_user_message(text);
+        } else {
+            self.conversation
+                .add_user_message_with_attachments(text, attachments);
+        }
+    }
+
+    // Handle /rewind and /branch
+    fn run_command(&mut self, command: &str) {
+        let parts: Vec<&str> = command.split_whitespace().collect();
+        match parts.as_slice() {
+            ["rewind"] => self.rewind(1),
+            ["rewind", turns] => match turns.parse::<usize>() {
+                Ok(turns) if turns > 0 => self.rewind(turns),
+                _ => self.add_message(
+                    "/rewind takes a number of messages to go back".to_string(),
+                    MessageType::Error,
+                ),
+            },
+            ["branch"] => self.open_branch_picker(),
+            ["branch", id] => match id.parse::<usize>() {
+                Ok(id) => self.switch_branch(id),
+                Err(_) => self.add_message(format!("No branch {}", id), MessageType::Error),
+            },
+            _ => self.add_message(format!("Unknown command: /{}", command), MessageType::Error),
+        }
+    }
+
+    // Go back to before the nth most recent user message, keeping the old path as a branch
+    fn rewind(&mut self, turns: usize) {
+        let index = self.conversation.recent_turns_start(turns);
+        match self.tree.rewind(&mut self.conversation, index) {
+            Some(rewind) => {
+                self.show_conversation();
+                let kept = rewind
+                    .kept
+                    .map(|id| format!(" (the previous path is branch {})", id))
+                    .unwrap_or_default();
+                self.add_message(
+                    format!("Rewound to before: {}{}", rewind.message.content, kept),
+                    MessageType::System,
+                );
+            }
+            None => self.add_message("Nothing to rewind".to_string(), MessageType::Error),
+        }
+    }
+
+    fn open_branch_picker(&mut self) {
+        self.tree.snapshot(&self.conversation);
+        self.branch_picker = Some(self.tree.current());
+    }
+
+    fn switch_branch(&mut self, id: usize) {
+        if self.tree.switch(&mut self.conversation, id) {
+            self.show_conversation();
+            self.add_message(format!("Switched to branch {}", id), MessageType::System);
+        } else {
+            self.add_message(format!("No branch {}", id), MessageType::Error);
+        }
+    }
+
+    // Show the branch the conversation is now on in place of the exchange shown before
+    fn show_conversation(&mut self) {
+        self.messages
+            .retain(|m| !matches!(m.message_type, MessageType::User | MessageType::Response));
+        let shown: Vec<Message> = self
+            .conversation
+            .messages
+            .iter()
+            .filter_map(|m| match m.role {
+                MessageRole::User => Some(Message::new(m.content.clone(), MessageType::User)),
+                MessageRole::Assistant => {
+                    Some(Message::new(m.content.clone(), MessageType::Response))
+                }
+                _ => None,
+            })
+            .collect();
+        self.messages.extend(shown);
+        self.scroll_to_bottom();
+    }
+
+    // Keys while the branch picker is open: move, switch to the selected branch, or close
+    fn handle_picker_key(&mut self, key: KeyEvent) {
+        let Some(selected) = self.branch_picker else {
+            return;
+        };
+        let last = self.tree.branches().len().saturating_sub(1);
+        match key.code {
+            KeyCode::Char('j') | KeyCode::Down => {
+                self.branch_picker = Some((selected + 1).min(last))
+            }
+            KeyCode::Char('k') | KeyCode::Up => {
+                self.branch_picker = Some(selected.saturating_sub(1))
+            }
+            KeyCode::Enter => {
+                self.branch_picker = None;
+                if selected != self.tree.current() {
+                    self.switch_branch(selected);
+                }
+            }
+            KeyCode::Esc | KeyCode::Char('b') | KeyCode::Char('q') => self.branch_picker = None,
+            _ => {}
         }
     }
 
+    // Read attached files, reporting each one (or why it couldn't be read)
+    fn load_attachments(&mut self, paths: &[String]) -> Vec<Attachment> {
+        let mut attachments = Vec::new();
+        for path in paths {
+            match Attachment::from_path(path) {
+                Ok(attachment) => {
+                    self.add_message(
+                        format!("Attached {} ({})", path, attachment.media_type),
+                        MessageType::System,
+                    );
+                    attachments.push(attachment);
+                }
+                Err(e) => self.add_message(format!("{:#}", e), MessageType::Error),
+            }
+        }
+        attachments
+    }
+
     // Navigate history with up/down keys
     fn navigate_history(&mut self, direction: KeyCode) {
         if self.history.is_empty() {
@@ -255,11 +478,85 @@ impl AppState {
         }
     }
 
+    // Stop the request in flight, if there is one, so it stops spending tokens
+    fn cancel_request(&mut self) -> bool {
+        let Some(ui_events) = &self.ui_events else {
+            return false;
+        };
+        if !self.request_pending || ui_events.send(UiEvent::RequestCancellation).is_err() {
+            return false;
+        }
+        self.request_pending = false;
+        self.add_message("Request cancelled".to_string(), MessageType::System);
+        true
+    }
+
+    // Say why a request is waiting rather than leave it looking stuck
+    fn show_rate_limit(&mut self, event: &RateLimitEvent) {
+        let notice = match event.wait {
+            Some(wait) => format!(
+                "{} is at its {} limit, waiting {}s",
+                event.model,
+                event.reason.as_str(),
+                wait.as_secs().max(1)
+            ),
+            None => format!(
+                "{} is at its {} limit, waiting for a request to finish",
+                event.model,
+                event.reason.as_str()
+            ),
+        };
+        self.add_message(notice, MessageType::System);
+    }
+
+    // Show what the session sends back for a prompt
+    fn handle_model_event(&mut self, event: ModelEvent) {
+        match event {
+            ModelEvent::LlmStreamChunk(chunk) if self.request_pending => {
+                match self.messages.last_mut() {
+                    Some(last) if last.message_type == MessageType::Response => {
+                        last.content.push_str(&chunk)
+                    }
+                    _ => self.add_message(chunk, MessageType::Response),
+                }
+            }
+            ModelEvent::LlmThinkingChunk(chunk) if self.request_pending => {
+                match self.messages.last_mut() {
+                    Some(last) if last.message_type == MessageType::Thinking => {
+                        last.content.push_str(&chunk)
+                    }
+                    _ => self.add_message(chunk, MessageType::Thinking),
+                }
+            }
+            ModelEvent::LlmMessage(content) if self.request_pending => {
+                self.add_message(content, MessageType::Response);
+            }
+            ModelEvent::LlmResponseComplete if self.request_pending => {
+                self.request_pending = false;
+                if let Some(last) = self
+                    .messages
+                    .last()
+                    .filter(|m| m.message_type == MessageType::Response)
+                {
+                    let response = last.content.clone();
+                    self.conversation.add_assistant_message(&response);
+                }
+            }
+            _ => {}
+        }
+    }
+
     // Handle a key event
     fn handle_key(&mut self, key: KeyEvent) {
         // Update last key for debugging
         self.last_key = format!("{:?}", key);
 
+        // The branch picker takes every key while it is open
+        if self.branch_picker.is_some() {
+            self.handle_picker_key(key);
+            return;
+        }
+
         // Handle global keys first
         match key.code {
             KeyCode::Tab => {
@@ -271,8 +568,10 @@ impl AppState {
                 return;
             }
             KeyCode::Esc => {
-                // Escape always returns to normal mode regardless of focus
-                self.mode = EditorMode::Normal;
+                // Escape cancels a pending request, otherwise returns to normal mode
+                if !self.cancel_request() {
+                    self.mode = EditorMode::Normal;
+                }
                 return;
             }
             KeyCode::Char('q') if self.mode == EditorMode::Normal => {
@@ -280,7 +579,10 @@ impl AppState {
                 return;
             }
             KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
-                self.running = false;
+                // Ctrl-C cancels a pending request before it quits
+                if !self.cancel_request() {
+                    self.running = false;
+                }
                 return;
             }
             _ => {}
@@ -322,6 +624,14 @@ impl AppState {
                             self.scroll_to_bottom();
                         }
                     }
+                    KeyCode::Char('t') => {
+                        // Toggle between collapsed and full thinking
+                        self.expand_thinking = !self.expand_thinking;
+                    }
+                    KeyCode::Char('b') => {
+                        // Pick a branch of the conversation
+                        self.open_branch_picker();
+                    }
                     KeyCode::Enter => {
                         // Enter switches focus to input
                         self.focus = Focus::Input;
@@ -456,10 +766,15 @@ fn ui(f: &mut ratatui::Frame, state: &mut AppState) {
         .len()
         .saturating_sub(state.visible_message_count);
 
+    let context_gauge = state
+        .context_usage()
+        .map(|usage| format!(" - {}", usage.gauge(10)))
+        .unwrap_or_default();
+
     let messages_block = Block::default()
         .title(format!(
-            "Messages ({}/{}) - {}",
-            state.scroll, max_scroll, auto_scroll_indicator
+            "Messages ({}/{}) - {}{}",
+            state.scroll, max_scroll, auto_scroll_indicator, context_gauge
         ))
         .borders(Borders::ALL)
         .border_style(message_border_style);
@@ -485,6 +800,25 @@ fn ui(f: &mut ratatui::Frame, state: &mut AppState) {
                 MessageType::Response => ("Assistant: ", Style::default().fg(Color::Green)),
                 MessageType::Error => ("Error: ", Style::default().fg(Color::Red)),
                 MessageType::Tool => ("Tool: ", Style::default().fg(Color::Magenta)),
+                MessageType::Thinking => ("Thinking: ", Style::default().fg(Color::DarkGray)),
+            };
+
+            // Thinking is dimmed, and collapsed to its first line unless expanded
+            let (content, content_style) = if m.message_type == MessageType::Thinking {
+                let dim = Style::default().fg(Color::DarkGray);
+                if state.expand_thinking {
+                    (m.content.clone(), dim)
+                } else {
+                    let first_line = m.content.lines().next().unwrap_or_default();
+                    let more = if m.content.trim_end().contains('\n') {
+                        " ... (t to expand)"
+                    } else {
+                        ""
+                    };
+                    (format!("{}{}", first_line, more), dim)
+                }
+            } else {
+                (m.content.clone(), Style::default())
             };
 
             // Create a line with multiple spans for formatted output
@@ -494,7 +828,7 @@ fn ui(f: &mut ratatui::Frame, state: &mut AppState) {
                     Style::default().fg(Color::DarkGray),
                 ),
                 Span::styled(prefix, style),
-                Span::styled(&m.content, Style::default()),
+                Span::styled(content, content_style),
             ])
         })
         .collect();
@@ -545,13 +879,129 @@ fn ui(f: &mut ratatui::Frame, state: &mut AppState) {
             chunks[1].y + 1,                            // +1 for the block border
         ));
     }
+
+    if let Some(selected) = state.branch_picker {
+        render_branch_picker(f, &state.tree, selected);
+    }
 }
 
-pub struct App {}
+// The branch picker, drawn over the middle of the screen
+fn render_branch_picker(f: &mut ratatui::Frame, tree: &ConversationTree, selected: usize) {
+    let area = f.area();
+    let width = area.width.saturating_sub(8).min(80);
+    let height = (tree.branches().len() as u16 + 2).min(area.height.saturating_sub(4));
+    let popup = Rect::new(
+        area.x + (area.width - width) / 2,
+        area.y + (area.height - height) / 2,
+        width,
+        height,
+    );
+
+    let lines: Vec<Line> = tree
+        .branches()
+        .iter()
+        .map(|branch| {
+            let marker = if branch.id == tree.current() {
+                "* "
+            } else {
+                "  "
+            };
+            let origin = match branch.parent {
+                Some(parent) => format!("from {} at message {}", parent, branch.forked_at + 1),
+                None => "start".to_string(),
+            };
+            let label = branch
+                .label()
+                .and_then(|label| label.lines().next())
+                .unwrap_or("(nothing asked yet)");
+            let style = if branch.id == selected {
+                Style::default().fg(Color::Black).bg(Color::Green)
+            } else {
+                Style::default()
+            };
+            Line::styled(
+                format!(
+                    "{}{}  {}  {} messages  {}",
+                    marker,
+                    branch.id,
+                    origin,
+                    branch.messages.len(),
+                    label
+                ),
+                style,
+            )
+        })
+        .collect();
+
+    let block = Block::default()
+        .title("Branches - j/k to move, Enter to switch, Esc to close")
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(Color::Green));
+    f.render_widget(Clear, popup);
+    f.render_widget(Paragraph::new(Text::from(lines)).block(block), popup);
+}
+
+pub struct App {
+    event_bus: Option<Arc<EventBus>>,
+    model: ModelConfig,
+}
 
 impl App {
     pub fn new() -> Result<Self> {
-        Ok(App {})
+        Ok(App {
+            event_bus: None,
+            model: ModelConfig::default(),
+        })
+    }
+
+    /// Measure the context gauge against `model`, the one answering the prompts
+    pub fn with_model(mut self, model: ModelConfig) -> Self {
+        self.model = model;
+        self
+    }
+
+    /// Send prompts to the session on `event_bus` rather than echoing them locally
+    pub fn with_event_bus(mut self, event_bus: Arc<EventBus>) -> Self {
+        self.event_bus = Some(event_bus);
+        self
+    }
+
+    /// Have `session_manager` answer prompts, starting its event bus
+    pub fn with_session<L: LlmClient + 'static>(
+        self,
+        session_manager: SessionManager<L>,
+    ) -> Result<Self> {
+        session_manager.register_handlers()?;
+        let event_bus = session_manager.get_event_bus();
+        event_bus.start_event_distribution()?;
+        Ok(self.with_event_bus(event_bus))
+    }
+
+    // Send the state's prompts to the session, if there is one, and hand back what the
+    // session sends; the UI loop owns the state, so these events wait for it on a channel
+    fn attach(&self, state: &mut AppState) -> Result<Receiver<SessionEvent>> {
+        let (session_tx, session_rx) = unbounded();
+        if let Some(event_bus) = &self.event_bus {
+            let model_tx = session_tx.clone();
+            event_bus.register_model_handler(create_handler(move |event: ModelEvent| {
+                let model_tx = model_tx.clone();
+                Box::pin(async move {
+                    let _ = model_tx.send(SessionEvent::Model(event));
+                    Ok(())
+                })
+            }))?;
+            event_bus.register_api_handler(create_handler(move |event: ApiEvent| {
+                let api_tx = session_tx.clone();
+                Box::pin(async move {
+                    if let ApiEvent::RateLimited(event) = event {
+                        let _ = api_tx.send(SessionEvent::RateLimited(event));
+                    }
+                    Ok(())
+                })
+            }))?;
+            state.ui_events = Some(event_bus.ui_sender());
+        }
+        Ok(session_rx)
     }
 
     pub fn run(&mut self) -> Result<()> {
@@ -579,10 +1029,18 @@ impl App {
         };
 
         // Create app state
-        let mut state = AppState::new();
+        let mut state = AppState::new().with_model(&self.model);
+        let session_rx = self.attach(&mut state)?;
 
         // Main loop
         while state.running {
+            while let Ok(event) = session_rx.try_recv() {
+                match event {
+                    SessionEvent::Model(event) => state.handle_model_event(event),
+                    SessionEvent::RateLimited(event) => state.show_rate_limit(&event),
+                }
+            }
+
             // Render the UI - passing mutable state to update visible message count
             terminal.draw(|f| ui(f, &mut state))?;
 
@@ -612,3 +1070,274 @@ impl App {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+    use futures::{stream, Stream, StreamExt};
+    use mcp_llm::{LlmResponse, RateLimitReason, RateLimiter, StreamChunk};
+    use mcp_runtime::ToolExecutor;
+    use mcp_tools::ToolManager;
+    use std::sync::Mutex;
+
+    fn attached() -> (AppState, Receiver<UiEvent>) {
+        let (ui_tx, ui_rx) = unbounded();
+        let mut state = AppState::new();
+        state.ui_events = Some(ui_tx);
+        (state, ui_rx)
+    }
+
+    fn press(state: &mut AppState, code: KeyCode, modifiers: KeyModifiers) {
+        state.handle_key(KeyEvent::new(code, modifiers));
+    }
+
+    #[test]
+    fn test_attachments_are_sent_to_the_session() {
+        let path = std::env::temp_dir().join(format!("mcpterm-tui-{}.png", std::process::id()));
+        std::fs::write(&path, b"png").unwrap();
+        let expected = Attachment::from_path(&path).unwrap();
+
+        let (mut state, ui_rx) = attached();
+        state.mode = EditorMode::Insert;
+        state.input = format!("what is in @{}", path.display());
+        press(&mut state, KeyCode::Enter, KeyModifiers::NONE);
+        std::fs::remove_file(&path).unwrap();
+
+        match ui_rx.try_recv() {
+            Ok(UiEvent::UserInputWithAttachments(text, attachments)) => {
+                assert_eq!(text, format!("what is in {}", path.display()));
+                assert_eq!(attachments, vec![expected.clone()]);
+            }
+            other => panic!("expected input with attachments, got {:?}", other),
+        }
+        let message = state.conversation.messages.last().unwrap();
+        assert_eq!(message.attachments, Some(vec![expected]));
+    }
+
+    #[test]
+    fn test_escape_cancels_pending_request() {
+        let (mut state, ui_rx) = attached();
+        state.mode = EditorMode::Insert;
+        state.input = "explain this crate".to_string();
+        press(&mut state, KeyCode::Enter, KeyModifiers::NONE);
+        assert!(
+            matches!(ui_rx.try_recv(), Ok(UiEvent::UserInput(text)) if text == "explain this crate")
+        );
+        assert!(state.request_pending);
+
+        // The first Escape stops the request and leaves the editor as it was
+        press(&mut state, KeyCode::Esc, KeyModifiers::NONE);
+        assert!(matches!(ui_rx.try_recv(), Ok(UiEvent::RequestCancellation)));
+        assert!(!state.request_pending);
+        assert_eq!(state.mode, EditorMode::Insert);
+
+        // With nothing in flight it goes back to normal mode
+        press(&mut state, KeyCode::Esc, KeyModifiers::NONE);
+        assert!(ui_rx.try_recv().is_err());
+        assert_eq!(state.mode, EditorMode::Normal);
+    }
+
+    #[test]
+    fn test_ctrl_c_cancels_before_quitting() {
+        let (mut state, ui_rx) = attached();
+        state.input = "write a long essay".to_string();
+        press(&mut state, KeyCode::Enter, KeyModifiers::NONE);
+        state.handle_model_event(ModelEvent::LlmStreamChunk("Once".to_string()));
+        let _ = ui_rx.try_recv();
+
+        press(&mut state, KeyCode::Char('c'), KeyModifiers::CONTROL);
+        assert!(matches!(ui_rx.try_recv(), Ok(UiEvent::RequestCancellation)));
+        assert!(state.running);
+
+        // Output still arriving for the cancelled request is dropped
+        state.handle_model_event(ModelEvent::LlmStreamChunk(" upon".to_string()));
+        assert_eq!(state.messages.last().unwrap().content, "Request cancelled");
+
+        press(&mut state, KeyCode::Char('c'), KeyModifiers::CONTROL);
+        assert!(!state.running);
+    }
+
+    #[test]
+    fn test_streamed_response_completes_request() {
+        let (mut state, _ui_rx) = attached();
+        state.input = "hello".to_string();
+        press(&mut state, KeyCode::Enter, KeyModifiers::NONE);
+        state.handle_model_event(ModelEvent::LlmStreamChunk("Hi ".to_string()));
+        state.handle_model_event(ModelEvent::LlmStreamChunk("there".to_string()));
+        state.handle_model_event(ModelEvent::LlmResponseComplete);
+
+        assert!(!state.request_pending);
+        assert_eq!(state.messages.last().unwrap().content, "Hi there");
+        assert_eq!(
+            state.conversation.messages.last().unwrap().content,
+            "Hi there"
+        );
+    }
+
+    #[test]
+    fn test_context_gauge_counts_the_conversation_against_the_model() {
+        let model = ModelConfig {
+            max_tokens: 1000,
+            thinking_budget_tokens: Some(1000),
+            context_window: Some(10_000),
+            ..ModelConfig::default()
+        };
+        let mut state = AppState::new().with_model(&model);
+        state.input = "hello".to_string();
+        press(&mut state, KeyCode::Enter, KeyModifiers::NONE);
+
+        // UI notices such as the welcome text aren't sent, so they don't count
+        let usage = state.context_usage().unwrap();
+        assert_eq!(state.conversation.messages.len(), 2);
+        assert_eq!(usage.prompt_tokens, state.conversation.estimated_tokens());
+        assert_eq!(usage.reply_tokens, 2000);
+        assert_eq!(usage.context_window, 10_000);
+
+        // Without a known window there is nothing to measure against
+        let model = ModelConfig {
+            context_window: None,
+            ..ModelConfig::default()
+        };
+        assert!(AppState::new().with_model(&model).context_usage().is_none());
+    }
+
+    #[test]
+    fn test_streamed_thinking_is_shown_before_the_answer() {
+        let (mut state, _ui_rx) = attached();
+        state.input = "hello".to_string();
+        press(&mut state, KeyCode::Enter, KeyModifiers::NONE);
+        state.handle_model_event(ModelEvent::LlmThinkingChunk("The user ".to_string()));
+        state.handle_model_event(ModelEvent::LlmThinkingChunk("said hello".to_string()));
+        state.handle_model_event(ModelEvent::LlmStreamChunk("Hi".to_string()));
+        state.handle_model_event(ModelEvent::LlmResponseComplete);
+
+        let shown: Vec<_> = state
+            .messages
+            .iter()
+            .rev()
+            .take(2)
+            .map(|m| (m.message_type, m.content.as_str()))
+            .collect();
+        assert_eq!(
+            shown,
+            vec![
+                (MessageType::Response, "Hi"),
+                (MessageType::Thinking, "The user said hello"),
+            ]
+        );
+
+        // Only the answer goes into the conversation
+        assert_eq!(state.conversation.messages.last().unwrap().content, "Hi");
+    }
+
+    // Streams the start of an answer and then stalls until the request is cancelled
+    struct StallingClient;
+
+    #[async_trait]
+    impl LlmClient for StallingClient {
+        async fn send_message(&self, _context: &ConversationContext) -> Result<LlmResponse> {
+            Err(anyhow::anyhow!("Only streaming is supported"))
+        }
+
+        async fn stream_message(
+            &self,
+            _context: &ConversationContext,
+        ) -> Result<Box<dyn Stream<Item = Result<StreamChunk>> + Unpin + Send>> {
+            let chunk = StreamChunk {
+                id: "resp1".to_string(),
+                content: "Once upon".to_string(),
+                is_tool_call: false,
+                tool_call: None,
+                is_complete: false,
+                usage: None,
+                thinking: String::new(),
+                thinking_block: None,
+            };
+            Ok(Box::new(
+                stream::iter(vec![Ok(chunk)]).chain(stream::pending()),
+            ))
+        }
+
+        fn cancel_request(&self, _request_id: &str) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_escape_cancels_through_the_session() {
+        let session_manager = SessionManager::new(
+            StallingClient,
+            ToolExecutor::new(ToolManager::new()),
+            EventBus::new(),
+        );
+        let app = App::new().unwrap().with_session(session_manager).unwrap();
+        let mut state = AppState::new();
+        let session_rx = app.attach(&mut state).unwrap();
+
+        let cancelled = Arc::new(Mutex::new(Vec::new()));
+        let recorded = cancelled.clone();
+        let event_bus = app.event_bus.as_ref().unwrap();
+        event_bus
+            .register_api_handler(create_handler(move |event: ApiEvent| {
+                let recorded = recorded.clone();
+                Box::pin(async move {
+                    if let ApiEvent::RequestCancelled(id) = event {
+                        recorded.lock().unwrap().push(id);
+                    }
+                    Ok(())
+                })
+            }))
+            .unwrap();
+
+        state.input = "tell me a story".to_string();
+        press(&mut state, KeyCode::Enter, KeyModifiers::NONE);
+        // The session is answering once the first of the reply streams back
+        let chunk = loop {
+            if let SessionEvent::Model(ModelEvent::LlmStreamChunk(text)) =
+                session_rx.recv_timeout(Duration::from_secs(5)).unwrap()
+            {
+                break text;
+            }
+        };
+        assert_eq!(chunk, "Once upon");
+
+        // Escape reaches the session manager, which cancels the request in flight
+        press(&mut state, KeyCode::Esc, KeyModifiers::NONE);
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert_eq!(cancelled.lock().unwrap().len(), 1);
+        assert!(!state.request_pending);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_rate_limited_request_is_shown() {
+        let app = App::new()
+            .unwrap()
+            .with_event_bus(Arc::new(EventBus::new()));
+        let event_bus = app.event_bus.clone().unwrap();
+        event_bus.start_event_distribution().unwrap();
+        let mut state = AppState::new();
+        let session_rx = app.attach(&mut state).unwrap();
+
+        // A second request in the same minute waits, and the bus tells the UI why
+        let limit = mcp_core::RateLimit {
+            requests_per_minute: Some(1),
+            ..mcp_core::RateLimit::default()
+        };
+        let limiter =
+            RateLimiter::new("model", limit).with_listener(event_bus.rate_limit_listener());
+        let _first = limiter.acquire(0).await;
+        let second = tokio::time::timeout(Duration::from_millis(50), limiter.acquire(0)).await;
+        assert!(second.is_err());
+
+        match session_rx.recv_timeout(Duration::from_secs(5)).unwrap() {
+            SessionEvent::RateLimited(event) => {
+                assert_eq!(event.reason, RateLimitReason::Requests);
+                state.show_rate_limit(&event);
+            }
+            other => panic!("expected a rate limit event, got {:?}", other),
+        }
+        let notice = &state.messages.last().unwrap().content;
+        assert!(notice.starts_with("model is at its requests per minute limit, waiting"));
+    }
+}